@@ -1,9 +1,19 @@
-use lru::http::axum_serve;
-use lru::load_from_file;
-use std::path::PathBuf;
+use lru::http::axum_serve_watching;
+use lru::load_config;
 
 #[tokio::main]
 async fn main() {
-    let config = load_from_file(PathBuf::from("config/config.toml"));
-    axum_serve(config).await;
+    tracing_subscriber::fmt::init();
+    let path = "config/config.toml";
+    let config = match load_config(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = axum_serve_watching(config, path.into()).await {
+        eprintln!("failed to start the server: {}", e);
+        std::process::exit(1);
+    }
 }
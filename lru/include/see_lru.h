@@ -0,0 +1,52 @@
+/* C interface of the SEE LRU cache (cargo feature `capi`).
+ *
+ * Keys and values are arbitrary byte strings and are copied in and out of the cache.
+ * Every function tolerates a NULL handle and NULL buffers of length 0. */
+#ifndef SEE_LRU_H
+#define SEE_LRU_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A NULL handle or a NULL buffer with a non-zero length. */
+#define SEE_LRU_EINVAL (-1)
+/* The key is not in the cache. */
+#define SEE_LRU_ENOTFOUND (-2)
+
+typedef struct SeeLru see_lru_t;
+
+/* Creates a cache of at most `cap` entries; NULL when `cap` is 0 or too large to allocate. */
+see_lru_t *see_lru_new(size_t cap);
+
+/* Destroys the cache. */
+void see_lru_free(see_lru_t *handle);
+
+/* Stores a copy of the key and value. Returns 0 or SEE_LRU_EINVAL. */
+int64_t see_lru_put(see_lru_t *handle, const uint8_t *key, size_t key_len, const uint8_t *val, size_t val_len);
+
+/* Copies up to `buf_len` bytes of the value into `buf` and returns the full value length,
+ * SEE_LRU_ENOTFOUND or SEE_LRU_EINVAL. */
+int64_t see_lru_get(see_lru_t *handle, const uint8_t *key, size_t key_len, uint8_t *buf, size_t buf_len);
+
+/* Returns a copy of the value in `*out`/`*out_len`, released with see_lru_buf_free.
+ * Returns 0, SEE_LRU_ENOTFOUND or SEE_LRU_EINVAL. */
+int64_t see_lru_get_owned(see_lru_t *handle, const uint8_t *key, size_t key_len, uint8_t **out, size_t *out_len);
+
+/* Releases a buffer from see_lru_get_owned. */
+void see_lru_buf_free(uint8_t *buf, size_t len);
+
+/* Removes the key. Returns 1 if it was present, 0 if not, or SEE_LRU_EINVAL. */
+int64_t see_lru_pop(see_lru_t *handle, const uint8_t *key, size_t key_len);
+
+/* Number of entries; 0 for a NULL handle. */
+size_t see_lru_len(const see_lru_t *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SEE_LRU_H */
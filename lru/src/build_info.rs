@@ -0,0 +1,24 @@
+/// Crate version from `Cargo.toml`.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git hash of the checkout the binary was built from, `unknown` outside a git checkout.
+pub const GIT_HASH: &str = match option_env!("SEE_GIT_HASH") {
+    Some(hash) => hash,
+    None => "unknown",
+};
+
+/// Unix timestamp (seconds) of the build, honoring `SOURCE_DATE_EPOCH`.
+pub const BUILD_TIMESTAMP: &str = match option_env!("SEE_BUILD_TIMESTAMP") {
+    Some(ts) => ts,
+    None => "0",
+};
+
+const FEATURES: &str = match option_env!("SEE_FEATURES") {
+    Some(features) => features,
+    None => "",
+};
+
+/// Cargo features enabled for this build.
+pub fn features() -> Vec<&'static str> {
+    FEATURES.split(',').filter(|f| !f.is_empty()).collect()
+}
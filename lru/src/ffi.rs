@@ -0,0 +1,212 @@
+//! C bindings for `LRUCache`, specialized to byte-string keys and values.
+//!
+//! The declarations live in `include/see_lru.h`, written by hand; `tests/capi.rs` checks them
+//! against the signatures here. Every function accepts null handles and null buffers of length
+//! 0; a null buffer with a non-zero length is an error, never a crash. Keys and values are
+//! opaque bytes, so no UTF-8 validation happens anywhere.
+
+use crate::lru::cache::Cache;
+use crate::lru::lru_cache::LRUCache;
+use std::ptr;
+use std::slice;
+
+/// Returned by functions that fail because of a null handle or buffer.
+pub const SEE_LRU_EINVAL: i64 = -1;
+/// Returned by lookups of a missing key.
+pub const SEE_LRU_ENOTFOUND: i64 = -2;
+
+/// Opaque handle, `see_lru_t` on the C side.
+pub struct SeeLru {
+    cache: LRUCache<Vec<u8>, Vec<u8>>,
+}
+
+// Borrows `len` bytes at `ptr`; a null `ptr` is only accepted for an empty buffer.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(slice::from_raw_parts(ptr, len)),
+    }
+}
+
+/// Creates a cache holding at most `cap` entries. Returns null when `cap` is 0 or too large for
+/// the map sized after it to be allocated.
+#[no_mangle]
+pub extern "C" fn see_lru_new(cap: usize) -> *mut SeeLru {
+    match LRUCache::try_new(cap) {
+        Ok(cache) => Box::into_raw(Box::new(SeeLru { cache })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Destroys a cache created by `see_lru_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or come from `see_lru_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_free(handle: *mut SeeLru) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Copies the key and value into the cache, evicting the least recently used entry when full.
+/// Returns 0, or `SEE_LRU_EINVAL`.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle; each buffer must be null or valid for its length.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_put(handle: *mut SeeLru, key: *const u8, key_len: usize, val: *const u8, val_len: usize) -> i64 {
+    let (Some(lru), Some(key), Some(val)) = (handle.as_mut(), bytes(key, key_len), bytes(val, val_len)) else {
+        return SEE_LRU_EINVAL;
+    };
+    lru.cache.put(key.to_vec(), val.to_vec());
+    0
+}
+
+/// Looks up `key`, marking it most recently used, and copies up to `buf_len` bytes of the value
+/// into `buf`. Returns the full value length (call with a null `buf` and 0 to size a buffer),
+/// `SEE_LRU_ENOTFOUND` or `SEE_LRU_EINVAL`.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle; each buffer must be null or valid for its length.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_get(handle: *mut SeeLru, key: *const u8, key_len: usize, buf: *mut u8, buf_len: usize) -> i64 {
+    let (Some(lru), Some(key)) = (handle.as_mut(), bytes(key, key_len)) else {
+        return SEE_LRU_EINVAL;
+    };
+    if buf.is_null() && buf_len > 0 {
+        return SEE_LRU_EINVAL;
+    }
+    match lru.cache.get(key) {
+        Some(val) => {
+            let n = val.len().min(buf_len);
+            if n > 0 {
+                ptr::copy_nonoverlapping(val.as_ptr(), buf, n);
+            }
+            val.len() as i64
+        }
+        None => SEE_LRU_ENOTFOUND,
+    }
+}
+
+/// Like `see_lru_get`, but hands out a copy of the whole value in a new buffer stored in
+/// `*out`/`*out_len`, to be released with `see_lru_buf_free`. Returns 0, `SEE_LRU_ENOTFOUND`
+/// or `SEE_LRU_EINVAL`.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle; `key` must be null or valid for `key_len` bytes;
+/// `out` and `out_len` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_get_owned(handle: *mut SeeLru, key: *const u8, key_len: usize, out: *mut *mut u8, out_len: *mut usize) -> i64 {
+    let (Some(lru), Some(key)) = (handle.as_mut(), bytes(key, key_len)) else {
+        return SEE_LRU_EINVAL;
+    };
+    if out.is_null() || out_len.is_null() {
+        return SEE_LRU_EINVAL;
+    }
+    match lru.cache.get(key) {
+        Some(val) => {
+            let copy: Box<[u8]> = val.clone().into_boxed_slice();
+            *out_len = copy.len();
+            *out = Box::into_raw(copy) as *mut u8;
+            0
+        }
+        None => SEE_LRU_ENOTFOUND,
+    }
+}
+
+/// Releases a buffer returned by `see_lru_get_owned`. Null is ignored.
+///
+/// # Safety
+///
+/// `buf` and `len` must be exactly what `see_lru_get_owned` returned, and `buf` must not be
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_buf_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)));
+    }
+}
+
+/// Removes `key`. Returns 1 if it was present, 0 if not, or `SEE_LRU_EINVAL`.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle; `key` must be null or valid for `key_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_pop(handle: *mut SeeLru, key: *const u8, key_len: usize) -> i64 {
+    let (Some(lru), Some(key)) = (handle.as_mut(), bytes(key, key_len)) else {
+        return SEE_LRU_EINVAL;
+    };
+    lru.cache.pop(key).is_some() as i64
+}
+
+/// Number of entries; 0 for a null handle.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn see_lru_len(handle: *const SeeLru) -> usize { handle.as_ref().map_or(0, |lru| lru.cache.len()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        unsafe {
+            let h = see_lru_new(2);
+            assert_eq!(see_lru_put(h, b"a".as_ptr(), 1, b"apple".as_ptr(), 5), 0);
+            assert_eq!(see_lru_put(h, b"b".as_ptr(), 1, b"banana".as_ptr(), 6), 0);
+
+            let mut buf = [0u8; 3];
+            assert_eq!(see_lru_get(h, b"a".as_ptr(), 1, buf.as_mut_ptr(), buf.len()), 5);
+            assert_eq!(&buf, b"app");
+            assert_eq!(see_lru_get(h, b"a".as_ptr(), 1, ptr::null_mut(), 0), 5);
+
+            // "b" is least recently used now
+            assert_eq!(see_lru_put(h, b"c".as_ptr(), 1, ptr::null(), 0), 0);
+            assert_eq!(see_lru_get(h, b"b".as_ptr(), 1, ptr::null_mut(), 0), SEE_LRU_ENOTFOUND);
+            assert_eq!(see_lru_get(h, b"c".as_ptr(), 1, ptr::null_mut(), 0), 0);
+
+            let (mut out, mut out_len) = (ptr::null_mut(), 0);
+            assert_eq!(see_lru_get_owned(h, b"a".as_ptr(), 1, &mut out, &mut out_len), 0);
+            assert_eq!(slice::from_raw_parts(out, out_len), b"apple");
+            see_lru_buf_free(out, out_len);
+
+            assert_eq!(see_lru_pop(h, b"a".as_ptr(), 1), 1);
+            assert_eq!(see_lru_pop(h, b"a".as_ptr(), 1), 0);
+            assert_eq!(see_lru_len(h), 1);
+            see_lru_free(h);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            assert!(see_lru_new(0).is_null());
+            assert!(see_lru_new(usize::MAX).is_null());
+            assert_eq!(see_lru_put(ptr::null_mut(), b"a".as_ptr(), 1, b"x".as_ptr(), 1), SEE_LRU_EINVAL);
+            assert_eq!(see_lru_len(ptr::null()), 0);
+            see_lru_free(ptr::null_mut());
+            see_lru_buf_free(ptr::null_mut(), 0);
+
+            let h = see_lru_new(1);
+            assert_eq!(see_lru_put(h, ptr::null(), 1, b"x".as_ptr(), 1), SEE_LRU_EINVAL);
+            assert_eq!(see_lru_put(h, b"a".as_ptr(), 1, ptr::null(), 3), SEE_LRU_EINVAL);
+            assert_eq!(see_lru_get(h, b"a".as_ptr(), 1, ptr::null_mut(), 4), SEE_LRU_EINVAL);
+            assert_eq!(see_lru_get_owned(h, b"a".as_ptr(), 1, ptr::null_mut(), ptr::null_mut()), SEE_LRU_EINVAL);
+            assert_eq!(see_lru_pop(h, ptr::null(), 2), SEE_LRU_EINVAL);
+            // an empty key is a valid key
+            assert_eq!(see_lru_put(h, ptr::null(), 0, b"x".as_ptr(), 1), 0);
+            assert_eq!(see_lru_get(h, ptr::null(), 0, ptr::null_mut(), 0), 1);
+            see_lru_free(h);
+        }
+    }
+}
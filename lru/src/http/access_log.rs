@@ -0,0 +1,235 @@
+use crate::http::middleware::REQUEST_ID_HEADER;
+use axum::body::HttpBody;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+/// Format used when `access_log_format` is not set.
+pub const DEFAULT_ACCESS_LOG_FORMAT: &str = "$remote_addr $method $path $status $bytes_sent $duration_ms $request_id $cache_status";
+
+/// Lines buffered between handlers and the writer task; more are dropped rather than waited for.
+const CHANNEL_CAPACITY: usize = 8192;
+
+/// Set by handlers as a response extension to fill `$cache_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    RemoteAddr,
+    Method,
+    Path,
+    Status,
+    BytesSent,
+    DurationMs,
+    RequestId,
+    CacheStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// A parsed access-log format string. `$name` is replaced by the named field, `$$` is a literal
+/// `$` and everything else is copied verbatim; fields that are unknown for a request render as `-`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFormat {
+    segments: Vec<Segment>,
+}
+
+impl LogFormat {
+    /// Parses `format`, rejecting unknown `$fields` so typos surface at startup.
+    pub fn parse(format: &str) -> Result<LogFormat, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(pos) = rest.find('$') {
+            literal.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            let field = match name {
+                "" => {
+                    literal.push('$');
+                    continue;
+                }
+                "remote_addr" => Field::RemoteAddr,
+                "method" => Field::Method,
+                "path" => Field::Path,
+                "status" => Field::Status,
+                "bytes_sent" => Field::BytesSent,
+                "duration_ms" => Field::DurationMs,
+                "request_id" => Field::RequestId,
+                "cache_status" => Field::CacheStatus,
+                _ => return Err(format!("unknown access log field `${}`", name)),
+            };
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Field(field));
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(LogFormat { segments })
+    }
+
+    fn render(&self, entry: &Entry) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            let _ = match segment {
+                Segment::Literal(s) => write!(line, "{}", s),
+                Segment::Field(Field::RemoteAddr) => write!(line, "{}", or_dash(entry.remote_addr.map(|a| a.ip().to_string()))),
+                Segment::Field(Field::Method) => write!(line, "{}", entry.method),
+                Segment::Field(Field::Path) => write!(line, "{}", entry.path),
+                Segment::Field(Field::Status) => write!(line, "{}", entry.status),
+                Segment::Field(Field::BytesSent) => write!(line, "{}", or_dash(entry.bytes_sent.map(|n| n.to_string()))),
+                Segment::Field(Field::DurationMs) => write!(line, "{}", entry.duration_ms),
+                Segment::Field(Field::RequestId) => write!(line, "{}", or_dash(entry.request_id.clone())),
+                Segment::Field(Field::CacheStatus) => write!(line, "{}", or_dash(entry.cache_status.map(|s| s.as_str().to_string()))),
+            };
+        }
+        line
+    }
+}
+
+fn or_dash(value: Option<String>) -> String { value.unwrap_or_else(|| "-".to_string()) }
+
+// what one request contributes to its log line
+struct Entry {
+    remote_addr: Option<SocketAddr>,
+    method: String,
+    path: String,
+    status: u16,
+    bytes_sent: Option<u64>,
+    duration_ms: u128,
+    request_id: Option<String>,
+    cache_status: Option<CacheStatus>,
+}
+
+/// Formats one line per request and hands it to a writer task over a bounded channel.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    format: Arc<LogFormat>,
+    tx: mpsc::Sender<String>,
+}
+
+impl AccessLog {
+    /// Starts the writer task appending to `path`, or writing to stdout when `None`.
+    pub async fn spawn(format: LogFormat, path: Option<&str>) -> io::Result<AccessLog> {
+        let out: Box<dyn AsyncWrite + Send + Unpin> = match path {
+            Some(path) => Box::new(tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?),
+            None => Box::new(tokio::io::stdout()),
+        };
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(write_lines(rx, BufWriter::new(out)));
+        Ok(AccessLog::with_sender(format, tx))
+    }
+
+    fn with_sender(format: LogFormat, tx: mpsc::Sender<String>) -> Self { AccessLog { format: Arc::new(format), tx } }
+}
+
+// flushes whenever the queue runs dry, so a burst costs one write and a quiet log is never stale
+async fn write_lines(mut rx: mpsc::Receiver<String>, mut out: BufWriter<Box<dyn AsyncWrite + Send + Unpin>>) {
+    while let Some(line) = rx.recv().await {
+        if out.write_all(line.as_bytes()).await.is_err() || out.write_all(b"\n").await.is_err() {
+            continue;
+        }
+        if rx.is_empty() {
+            let _ = out.flush().await;
+        }
+    }
+    let _ = out.flush().await;
+}
+
+/// Middleware writing the access log line once the response head is ready.
+pub async fn access_log(State(log): State<AccessLog>, req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let remote_addr = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0);
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+
+    let res = next.run(req).await;
+    let entry = Entry {
+        remote_addr,
+        method,
+        path,
+        status: res.status().as_u16(),
+        bytes_sent: res.body().size_hint().exact(),
+        duration_ms: started.elapsed().as_millis(),
+        request_id: res.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()).map(String::from),
+        cache_status: res.extensions().get::<CacheStatus>().copied(),
+    };
+    // never wait on the writer: a full queue means the line is dropped
+    let _ = log.tx.try_send(log.format.render(&entry));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{access_log, AccessLog, CacheStatus, LogFormat, DEFAULT_ACCESS_LOG_FORMAT};
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::Request;
+    use axum::middleware::from_fn_with_state;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::Router;
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse_format() {
+        assert!(LogFormat::parse(DEFAULT_ACCESS_LOG_FORMAT).is_ok());
+        assert!(LogFormat::parse("[$method] cost $$ $duration_ms ms").is_ok());
+        assert_eq!(LogFormat::parse("$method $nope").unwrap_err(), "unknown access log field `$nope`");
+    }
+
+    #[tokio::test]
+    async fn test_renders_request_line() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let format = LogFormat::parse("$remote_addr \"$method $path\" $status $bytes_sent $request_id $cache_status $$").unwrap();
+        let log = AccessLog::with_sender(format, tx);
+        let app = Router::new()
+            .route("/hit", get(|| async { ([("x-request-id", "r-1")], axum::Extension(CacheStatus::Hit), "hello").into_response() }))
+            .route("/plain", get(|| async { "ok" }))
+            .layer(from_fn_with_state(log, access_log));
+
+        let mut req = Request::get("/hit?key=1").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(ConnectInfo("10.0.0.7:5555".parse::<SocketAddr>().unwrap()));
+        app.clone().oneshot(req).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "10.0.0.7 \"GET /hit\" 200 5 r-1 HIT $");
+
+        app.oneshot(Request::get("/plain").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "- \"GET /plain\" 200 2 - - $");
+    }
+}
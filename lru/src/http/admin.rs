@@ -0,0 +1,243 @@
+use crate::build_info;
+use crate::http::stats;
+use crate::http::common::build_status_error;
+use crate::http::store::{resize_shards, sharded_stats};
+use crate::http::Tools;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use super::common::{StandardApiJsonBody, StandardApiResult};
+use super::dtos;
+
+pub async fn info(Extension(tools): Extension<Tools>) -> StandardApiResult<dtos::InfoResponse> {
+    let shards = tools.read_stores().await?;
+    let store = shards[0].default_store();
+    let cache_mode = store.usage().cache_mode;
+    let res = dtos::InfoResponse {
+        version: build_info::VERSION.to_string(),
+        git_hash: build_info::GIT_HASH.to_string(),
+        build_timestamp: build_info::BUILD_TIMESTAMP.parse().unwrap_or(0),
+        features: build_info::features().into_iter().map(String::from).collect(),
+        cache_mode: cache_mode.to_string(),
+        cache_capacity: shards
+            .iter()
+            .map(|stores| stores.default_store())
+            .map(|store| store.byte_budget().unwrap_or(store.index().cap().get()))
+            .sum(),
+        cache_len: shards.iter().map(|stores| stores.default_store().len()).sum(),
+    };
+    Ok(res.into())
+}
+
+pub async fn config(Extension(tools): Extension<Tools>) -> StandardApiResult<serde_json::Value> {
+    Ok(tools.config.redacted().into())
+}
+
+pub async fn stats(Extension(tools): Extension<Tools>) -> StandardApiResult<dtos::StatsResponse> {
+    let res = dtos::StatsResponse {
+        server: tools.stats.snapshot(),
+        store: sharded_stats(&tools.read_stores().await?),
+    };
+    Ok(res.into())
+}
+
+/// `GET /lru/metrics`: the stats in the Prometheus text format.
+pub async fn metrics(Extension(tools): Extension<Tools>) -> Response {
+    let store = match tools.read_stores().await {
+        Ok(shards) => sharded_stats(&shards),
+        Err(busy) => return busy.into_response(),
+    };
+    let body = stats::render_prometheus(&tools.stats.snapshot(), &store);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+/// `POST /lru/admin/shutdown`: starts the same graceful shutdown as SIGTERM and answers 202
+/// right away. `drain_secs` overrides `shutdown_drain_secs`.
+pub async fn shutdown(
+    Extension(tools): Extension<Tools>,
+    req: Option<Json<dtos::ShutdownRequest>>,
+) -> (StatusCode, StandardApiJsonBody<dtos::ShutdownResponse>) {
+    let drain_secs = req
+        .and_then(|Json(req)| req.drain_secs)
+        .unwrap_or(tools.config.shutdown_drain_secs);
+    tracing::info!(drain_secs, "shutdown requested over the admin API");
+    tools.shutdown.shutdown(Duration::from_secs(drain_secs));
+    (StatusCode::ACCEPTED, dtos::ShutdownResponse { drain_secs }.into())
+}
+
+/// `POST /lru/admin/resize`: changes the bound of the default cache, or of `namespace`'s own
+/// cache, in the units of its mode. Keys that no longer fit are evicted. Each shard gets its share
+/// of `size`, which must leave every shard room for one key.
+pub async fn resize(
+    Extension(tools): Extension<Tools>,
+    Json(req): Json<dtos::ResizeRequest>,
+) -> StandardApiResult<dtos::ResizeResponse> {
+    let Some(size) = NonZeroUsize::new(req.size) else {
+        return Err(build_status_error(StatusCode::BAD_REQUEST, "INVALID_SIZE", "size must be positive"));
+    };
+    if size.get() < tools.store.shards().len() {
+        return Err(build_status_error(StatusCode::BAD_REQUEST, "INVALID_SIZE", "size must be at least cache_shards"));
+    }
+    let mut shards = tools.write_stores().await?;
+    let Some(usage) = shards[0].namespace_mut(req.namespace.as_deref()).map(|store| store.usage()) else {
+        return Err(build_status_error(StatusCode::NOT_FOUND, "NOT_FOUND", "Namespace has no cache of its own"));
+    };
+    let Some(evicted) = resize_shards(&mut shards, req.namespace.as_deref(), size) else {
+        return Err(build_status_error(StatusCode::CONFLICT, "UNBOUNDED", "An unlimited cache has no size"));
+    };
+    tracing::info!(namespace = ?req.namespace, size = req.size, evicted, "cache resized over the admin API");
+    let res = dtos::ResizeResponse {
+        namespace: req.namespace,
+        cache_mode: usage.cache_mode.to_string(),
+        capacity: req.size,
+        evicted,
+    };
+    Ok(res.into())
+}
+
+/// Panics on purpose so tests can exercise the panic responder.
+#[cfg(any(test, feature = "test-routes"))]
+pub async fn panic() -> StandardApiResult<()> {
+    panic!("panic route hit: secret internal detail")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::router::axum_router;
+    use crate::http::Tools;
+    use crate::settings::{NamespaceConfig, ServerConfig};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    async fn get_json(uri: &str) -> Value {
+        let app = axum_router(Tools::for_test(ServerConfig::default()));
+        let res = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_info() {
+        let body = get_json("/api/lru/admin/info").await;
+        assert_eq!(body["code"], "00000");
+        assert_eq!(body["data"]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!body["data"]["gitHash"].as_str().unwrap().is_empty());
+        assert!(body["data"]["features"].is_array());
+        assert_eq!(body["data"]["cacheMode"], "item");
+        assert_eq!(body["data"]["cacheCapacity"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_panic_returns_structured_500() {
+        let tools = Tools::for_test(ServerConfig::default());
+        let app = axum_router(tools.clone());
+        let req = Request::get("/api/lru/__panic")
+            .header("x-request-id", "req-42")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.headers()["x-request-id"], "req-42");
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains("secret internal detail"));
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "INTERNAL_PANIC");
+        assert_eq!(tools.stats.snapshot().panics_total, 1);
+
+        let body = get_json("/api/lru/stats").await;
+        assert_eq!(body["data"]["panicsTotal"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_effective_config() {
+        let body = get_json("/api/lru/admin/config").await;
+        assert_eq!(body["code"], "00000");
+        assert_eq!(body["data"]["cache_size"], 5);
+        assert_eq!(body["data"]["server_port"], 2345);
+    }
+
+    async fn post_json(tools: &Tools, uri: &str, body: &str) -> (StatusCode, Value) {
+        let req = Request::post(uri).header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+        let res = axum_router(tools.clone()).oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_resize_namespace_cache() {
+        let mut config = ServerConfig::default();
+        config.namespaces.insert("meta".to_string(), NamespaceConfig { cache_size: 10, ..NamespaceConfig::default() });
+        let unlimited = NamespaceConfig { cache_mode: "unlimited".to_string(), ..NamespaceConfig::default() };
+        config.namespaces.insert("logs".to_string(), unlimited);
+        let tools = Tools::for_test(config);
+
+        let (status, body) = post_json(&tools, "/api/lru/admin/resize", r#"{"namespace":"meta","size":3}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["cacheMode"], "item");
+        assert_eq!(body["data"]["capacity"], 3);
+        assert_eq!(tools.store.shard("meta/x").write().await.for_key_mut("meta/x").usage().capacity, Some(3));
+        // the default cache is untouched
+        assert_eq!(tools.store.shards()[0].read().await.default_store().usage().capacity, Some(5));
+
+        let (status, _) = post_json(&tools, "/api/lru/admin/resize", r#"{"namespace":"nope","size":3}"#).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(&tools, "/api/lru/admin/resize", r#"{"namespace":"logs","size":3}"#).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = post_json(&tools, "/api/lru/admin/resize", r#"{"size":0}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = post_json(&tools, "/api/lru/admin/resize", r#"{"size":2}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tools.store.shards()[0].read().await.default_store().usage().capacity, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_resize_splits_across_shards() {
+        let tools = Tools::for_test(ServerConfig { cache_size: 10, cache_shards: 3, ..ServerConfig::default() });
+        let capacities = || async {
+            let mut capacities = Vec::new();
+            for shard in tools.store.shards() {
+                capacities.push(shard.read().await.default_store().usage().capacity.unwrap());
+            }
+            capacities
+        };
+        assert_eq!(capacities().await, [4, 3, 3]);
+
+        let (status, body) = post_json(&tools, "/api/lru/admin/resize", r#"{"size":2}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "INVALID_SIZE");
+        let (status, body) = post_json(&tools, "/api/lru/admin/resize", r#"{"size":4}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["capacity"], 4);
+        assert_eq!(capacities().await, [2, 1, 1]);
+
+        let info = Request::get("/api/lru/admin/info").body(Body::empty()).unwrap();
+        let res = axum_router(tools.clone()).oneshot(info).await.unwrap();
+        let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["data"]["cacheCapacity"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_exposes_size_histogram() {
+        let app = axum_router(Tools::for_test(ServerConfig::default()));
+        let res = app.oneshot(Request::get("/api/lru/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert!(res.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("see_value_size_bytes_bucket{le=\"1024\"} 0\n"));
+        assert!(text.contains("see_value_size_bytes_bucket{le=\"+Inf\"} 0\n"));
+
+        let body = get_json("/api/lru/stats").await;
+        assert_eq!(body["data"]["valueSizes"]["buckets"].as_array().unwrap().len(), 6);
+        assert_eq!(body["data"]["valueSizes"]["buckets"][5]["le"], "+Inf");
+    }
+}
@@ -0,0 +1,120 @@
+use crate::http::common::build_status_error;
+use crate::http::Tools;
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Returns true when no `api_token` is configured or the request carries it as a bearer token.
+pub fn is_authorized(headers: &HeaderMap, api_token: Option<&str>) -> bool {
+    let Some(expected) = api_token else {
+        return true;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| token.as_bytes().ct_eq(expected.as_bytes()).into())
+}
+
+/// Rejects requests without a valid bearer token with a 401.
+pub async fn require_auth(Extension(tools): Extension<Tools>, req: Request, next: Next) -> Response {
+    if is_authorized(req.headers(), tools.config.api_token.as_deref()) {
+        next.run(req).await
+    } else {
+        unauthorized().into_response()
+    }
+}
+
+pub fn unauthorized() -> impl IntoResponse {
+    build_status_error(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", "Missing or invalid credentials")
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn mac_for(signing_key: &str, key: &str, exp: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(signing_key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(key.as_bytes());
+    mac.update(b"\n");
+    mac.update(exp.to_string().as_bytes());
+    mac
+}
+
+/// Signs `key` so that it can be downloaded until `exp` (unix seconds). Returns the hex signature.
+pub fn sign(signing_key: &str, key: &str, exp: u64) -> String {
+    hex::encode(mac_for(signing_key, key, exp).finalize().into_bytes())
+}
+
+/// Checks a pre-signed URL: the signature must match `key` and `exp` exactly (compared in
+/// constant time), and `now` must not be later than `exp + skew_secs`.
+pub fn verify(signing_key: &str, key: &str, exp: u64, sig: &str, now: u64, skew_secs: u64) -> bool {
+    if now > exp.saturating_add(skew_secs) {
+        return false;
+    }
+    let Ok(sig) = hex::decode(sig) else {
+        return false;
+    };
+    mac_for(signing_key, key, exp).verify_slice(&sig).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_authorized, sign, verify};
+    use axum::http::{header, HeaderMap};
+
+    const SIGNING_KEY: &str = "test-signing-key";
+
+    #[test]
+    fn test_valid_signature() {
+        let sig = sign(SIGNING_KEY, "apple", 1_000);
+        assert!(verify(SIGNING_KEY, "apple", 1_000, &sig, 900, 0));
+        assert!(verify(SIGNING_KEY, "apple", 1_000, &sig, 1_000, 0));
+    }
+
+    #[test]
+    fn test_expired_signature() {
+        let sig = sign(SIGNING_KEY, "apple", 1_000);
+        assert!(!verify(SIGNING_KEY, "apple", 1_000, &sig, 1_001, 0));
+        // within the skew window it is still accepted
+        assert!(verify(SIGNING_KEY, "apple", 1_000, &sig, 1_020, 30));
+        assert!(!verify(SIGNING_KEY, "apple", 1_000, &sig, 1_031, 30));
+    }
+
+    #[test]
+    fn test_tampered_signature() {
+        let sig = sign(SIGNING_KEY, "apple", 1_000);
+        // other key, other expiry, other signing key
+        assert!(!verify(SIGNING_KEY, "banana", 1_000, &sig, 900, 0));
+        assert!(!verify(SIGNING_KEY, "apple", 2_000, &sig, 900, 0));
+        assert!(!verify("other-key", "apple", 1_000, &sig, 900, 0));
+
+        let mut flipped = sig.clone().into_bytes();
+        flipped[0] = if flipped[0] == b'0' { b'1' } else { b'0' };
+        assert!(!verify(SIGNING_KEY, "apple", 1_000, &String::from_utf8(flipped).unwrap(), 900, 0));
+        assert!(!verify(SIGNING_KEY, "apple", 1_000, "not-hex", 900, 0));
+        assert!(!verify(SIGNING_KEY, "apple", 1_000, &sig[..10], 900, 0));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(is_authorized(&headers, None));
+        assert!(!is_authorized(&headers, Some("secret")));
+
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(!is_authorized(&headers, Some("secret")));
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(is_authorized(&headers, Some("secret")));
+    }
+}
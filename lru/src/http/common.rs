@@ -19,6 +19,28 @@ pub fn build_error_response(code: String, message: String) -> (StatusCode, Stand
     (StatusCode::OK, res)
 }
 
+/// Like `build_error_response`, but for failures that must carry a real HTTP status.
+pub fn build_status_error(status: StatusCode, code: &str, message: &str) -> (StatusCode, StandardApiJsonBody<()>) {
+    let res = StandardApiJsonBody {
+        code: code.to_string(),
+        message: message.to_string(),
+        data: (),
+    };
+    (status, res)
+}
+
+/// The store lock was not granted within `lock_wait_ms` (or `read_lock_wait_ms`).
+#[derive(Debug)]
+pub struct LockBusy;
+
+impl From<LockBusy> for (StatusCode, StandardApiJsonBody<()>) {
+    fn from(_: LockBusy) -> Self { build_status_error(StatusCode::SERVICE_UNAVAILABLE, "LOCK_BUSY", "The cache is busy, retry later") }
+}
+
+impl IntoResponse for LockBusy {
+    fn into_response(self) -> Response { <(StatusCode, StandardApiJsonBody<()>)>::from(self).into_response() }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StandardApiJsonBody<T: Serialize> {
     pub code: String,
@@ -1,22 +1,88 @@
+use crate::http::access_log::CacheStatus;
+use crate::http::auth;
+use crate::http::common::build_status_error;
+use crate::http::range::{self, ByteRange};
+use crate::http::store::{BlobMeta, CachedBlob, StoreError, TtlMode};
 use crate::http::Tools;
-use crate::lru::cache::Cache;
-use axum::body::Bytes;
-use axum::extract::{Multipart, Query};
-use axum::http::{header, HeaderMap, StatusCode};
-use axum::response::IntoResponse;
-use axum::Extension;
+use crate::lru::chunked_bytes::ChunkedBytesBuilder;
+use axum::body::{Body, Bytes};
+use axum::extract::multipart::MultipartError;
+use axum::extract::{Multipart, Path, Query};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures_util::stream;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
 use std::hash::{DefaultHasher, Hasher};
+use std::time::Duration;
 
-use super::common::{build_error_response, StandardApiResult};
+use super::common::{build_error_response, StandardApiJsonBody, StandardApiResult};
 use super::dtos;
 
+/// `GET /lru?key=`, and `HEAD` with the same headers, see `download_response`.
 pub async fn download(
     Extension(tools): Extension<Tools>,
+    method: Method,
     Query(req): Query<dtos::DownloadRequest>,
-) -> impl IntoResponse {
-    let key = req.key;
-    let mut lru_cache = tools.lru_cache.write().await;
-    let res = lru_cache.get(&key);
+    headers: HeaderMap,
+) -> Response {
+    download_response(&tools, req.key, method == Method::HEAD, &headers).await
+}
+
+/// `GET /lru/{key}` (and `HEAD`): accepts either the normal bearer token or a pre-signed `sig`/`exp` pair
+/// issued for exactly this key.
+pub async fn download_by_key(
+    Extension(tools): Extension<Tools>,
+    method: Method,
+    Path(key): Path<String>,
+    Query(req): Query<dtos::SignedDownloadQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let signed = match (&tools.config.signing_key, req.sig, req.exp) {
+        (Some(signing_key), Some(sig), Some(exp)) => {
+            let skew = tools.config.presign_clock_skew_secs;
+            auth::verify(signing_key, &key, exp, &sig, auth::unix_now(), skew)
+        }
+        _ => false,
+    };
+    if !signed && !auth::is_authorized(&headers, tools.config.api_token.as_deref()) {
+        return auth::unauthorized().into_response();
+    }
+    download_response(&tools, key, method == Method::HEAD, &headers).await
+}
+
+/// Serves the value of `key`, honoring `Range` (one range per request) and `If-Range`.
+///
+/// `HEAD` gets the headers of a full `GET` and an empty body. It is only a look: the key is
+/// not promoted, a sliding TTL is not extended and `Range` is ignored.
+async fn download_response(tools: &Tools, key: String, head: bool, req_headers: &HeaderMap) -> Response {
+    // cloning only bumps the segments' reference counts
+    let res = if head {
+        match tools.read_store(&key).await {
+            Ok(stores) => stores.for_key(&key).peek(&key),
+            Err(busy) => return busy.into_response(),
+        }
+    } else {
+        let mut stores = match tools.write_store(&key).await {
+            Ok(stores) => stores,
+            Err(busy) => return busy.into_response(),
+        };
+        let store = stores.for_key_mut(&key);
+        let res = store.get(&key);
+        if let Some((_, CachedBlob { meta: BlobMeta { ttl: Some(ttl), ttl_mode: TtlMode::Sliding, .. }, .. })) = res.as_ref() {
+            // slide the expiry, but never further than max_ttl_secs from now
+            let max_ttl = Duration::from_secs(tools.config.max_ttl_secs);
+            let remaining = store.ttl(&key).unwrap_or_default();
+            store.extend_ttl(&key, (*ttl).min(max_ttl.saturating_sub(remaining)));
+        }
+        res
+    };
+    let Some((data, blob)) = res else {
+        return (StatusCode::NOT_FOUND, Extension(CacheStatus::Miss), "Data not found".to_string()).into_response();
+    };
+
+    let etag = blob.etag();
     let disposition_val = format!("attachment; filename=\"{}\"", key);
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -27,25 +93,112 @@ pub async fn download(
         header::CONTENT_DISPOSITION,
         disposition_val.parse().unwrap(),
     );
-    match res {
-        Some(buf) => Ok((headers, Bytes::from(buf.to_vec()))),
-        None => Err((StatusCode::NOT_FOUND, "Data not found".to_string())),
+    headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    headers.insert(header::ETAG, etag.parse().unwrap());
+    headers.insert(header::LAST_MODIFIED, range::http_date(blob.meta.last_modified).parse().unwrap());
+
+    let requested = req_headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    // a Range is only honored while the If-Range validator, if any, still matches
+    let validator_matches = match req_headers.get(header::IF_RANGE) {
+        Some(value) => value.to_str().is_ok_and(|v| range::if_range_matches(v, &etag, blob.meta.last_modified)),
+        None => true,
+    };
+    let byte_range = match requested {
+        Some(requested) if validator_matches && !head => range::parse_range(requested, data.len()),
+        _ => ByteRange::Full,
+    };
+    match byte_range {
+        ByteRange::Full if head => {
+            headers.insert(header::CONTENT_LENGTH, data.len().into());
+            (headers, Extension(CacheStatus::Hit), Body::empty()).into_response()
+        }
+        ByteRange::Full => {
+            headers.insert(header::CONTENT_LENGTH, data.len().into());
+            (headers, Extension(CacheStatus::Hit), stream_body(data.chunks().cloned().collect())).into_response()
+        }
+        ByteRange::Partial(r) => {
+            let content_range = format!("bytes {}-{}/{}", r.start, r.end - 1, data.len());
+            headers.insert(header::CONTENT_RANGE, content_range.parse().unwrap());
+            headers.insert(header::CONTENT_LENGTH, r.len().into());
+            (StatusCode::PARTIAL_CONTENT, headers, Extension(CacheStatus::Hit), stream_body(data.slice(r))).into_response()
+        }
+        ByteRange::Unsatisfiable => {
+            headers.insert(header::CONTENT_RANGE, format!("bytes */{}", data.len()).parse().unwrap());
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers, Extension(CacheStatus::Hit)).into_response()
+        }
     }
 }
 
+/// Streams segments without joining them.
+fn stream_body(chunks: Vec<Bytes>) -> Body {
+    Body::from_stream(stream::iter(chunks.into_iter().map(Ok::<_, Infallible>)))
+}
+
+/// Issues a time-limited download URL for `key` that works without the API token.
+pub async fn presign(
+    Extension(tools): Extension<Tools>,
+    Json(req): Json<dtos::PresignRequest>,
+) -> StandardApiResult<dtos::PresignResponse> {
+    let Some(signing_key) = &tools.config.signing_key else {
+        return Err(build_error_response(
+            "10002".to_string(),
+            "Pre-signed URLs are not enabled".to_string(),
+        ));
+    };
+    let expires_at = auth::unix_now().saturating_add(req.expires_in_secs);
+    let sig = auth::sign(signing_key, &req.key, expires_at);
+    let res = dtos::PresignResponse {
+        url: format!("/api/lru/{}?sig={}&exp={}", req.key, sig, expires_at),
+        expires_at,
+    };
+    Ok(res.into())
+}
+
+/// `POST /lru?key=&ttl_secs=&ttl_mode=fixed|sliding`: stores the multipart field named
+/// `upload_field_name`, which must be the only field and must not be empty.
+/// Without `key`, the value is stored under a key derived from its content.
 pub async fn upload(
     Extension(tools): Extension<Tools>,
+    Query(query): Query<dtos::UploadQuery>,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> StandardApiResult<dtos::UploadResponse> {
-    let mut lru_cache = tools.lru_cache.write().await;
-    if let Some(field) = multipart.next_field().await.unwrap() {
-        let buf = field.bytes().await.unwrap();
-        let buf = buf.to_vec();
-        let size = buf.len();
+    // with a known body size and key, refuse before reading a body that could not be stored;
+    // content-derived keys are only known after the body was read
+    if let (true, Some(key)) = (headers.contains_key(header::CONTENT_LENGTH), &query.key) {
+        tools.write_store(key).await?.for_key_mut(key).admits(key).map_err(store_error)?;
+    }
+    if let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        let expected = tools.config.upload_field_name.as_str();
+        if field.name() != Some(expected) {
+            let message = format!("Expected the value in field \"{}\", got \"{}\"", expected, field.name().unwrap_or_default());
+            return Err(build_status_error(StatusCode::BAD_REQUEST, "UNEXPECTED_FIELD", &message));
+        }
+        // assemble segment by segment so a huge upload never needs one giant allocation
+        let mut builder = ChunkedBytesBuilder::new(tools.config.chunk_size_bytes);
         let mut hasher = DefaultHasher::new();
-        hasher.write(&buf);
-        let key = hasher.finish().to_string();
-        lru_cache.put(key.clone(), buf);
+        let mut content_hasher = Sha256::new();
+        while let Some(piece) = field.chunk().await.map_err(multipart_error)? {
+            hasher.write(&piece);
+            content_hasher.update(&piece);
+            builder.extend_from_slice(&piece);
+        }
+        let buf = builder.finish();
+        let size = buf.len();
+        if size == 0 {
+            return Err(build_status_error(StatusCode::BAD_REQUEST, "EMPTY_FIELD", "The uploaded value is empty"));
+        }
+        drop(field);
+        if multipart.next_field().await.map_err(multipart_error)?.is_some() {
+            return Err(build_status_error(StatusCode::BAD_REQUEST, "EXTRA_FIELD", "Send exactly one field per upload"));
+        }
+        let key = query.key.unwrap_or_else(|| hasher.finish().to_string());
+        let ttl = query
+            .ttl_secs
+            .map(|secs| Duration::from_secs(secs.min(tools.config.max_ttl_secs)));
+        let meta = BlobMeta { ttl, ttl_mode: query.ttl_mode, last_modified: auth::unix_now() };
+        let mut stores = tools.write_store(&key).await?;
+        stores.for_key_mut(&key).insert(key.clone(), content_hasher.finalize().into(), buf, meta).map_err(store_error)?;
 
         let res = dtos::UploadResponse { key, size };
         Ok(res.into())
@@ -57,9 +210,554 @@ pub async fn upload(
     }
 }
 
+// A body that is not valid multipart, cut short or not, is the client's to fix.
+fn multipart_error(err: MultipartError) -> (StatusCode, StandardApiJsonBody<()>) {
+    build_status_error(StatusCode::BAD_REQUEST, "MALFORMED_MULTIPART", &err.body_text())
+}
+
+fn store_error(err: StoreError) -> (StatusCode, StandardApiJsonBody<()>) {
+    match err {
+        StoreError::TooLarge { .. } => build_status_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "PAYLOAD_TOO_LARGE",
+            "Value does not fit in the cache",
+        ),
+        StoreError::LimitExceeded { namespace: None } => build_status_error(
+            StatusCode::INSUFFICIENT_STORAGE,
+            "LIMIT_EXCEEDED",
+            "The cache holds the maximum number of keys",
+        ),
+        StoreError::LimitExceeded { namespace: Some(ns) } => build_status_error(
+            StatusCode::INSUFFICIENT_STORAGE,
+            "LIMIT_EXCEEDED",
+            &format!("Namespace \"{}\" holds the maximum number of keys", ns),
+        ),
+        StoreError::OverQuota { prefix, max_bytes, .. } => build_status_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "PAYLOAD_TOO_LARGE",
+            &format!("Value exceeds the {} byte quota of prefix \"{}\"", max_bytes, prefix),
+        ),
+        StoreError::OutOfMemory => build_status_error(
+            StatusCode::INSUFFICIENT_STORAGE,
+            "INSUFFICIENT_STORAGE",
+            "The cache could not allocate memory for the value",
+        ),
+    }
+}
+
+/// `DELETE /lru?key=`: removes a key. Its bytes are freed once no other key shares them.
+pub async fn delete(
+    Extension(tools): Extension<Tools>,
+    Query(req): Query<dtos::DownloadRequest>,
+) -> StandardApiResult<dtos::DeleteResponse> {
+    if tools.write_store(&req.key).await?.for_key_mut(&req.key).remove(&req.key) {
+        Ok(dtos::DeleteResponse { key: req.key }.into())
+    } else {
+        Err(build_status_error(StatusCode::NOT_FOUND, "NOT_FOUND", "Data not found"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::http::auth;
+    use crate::http::middleware::REQUEST_ID_HEADER;
+    use crate::http::router::axum_router;
+    use crate::http::store::{BlobMeta, OnLimit, StoreError};
+    use crate::http::Tools;
+    use crate::settings::{NamespaceConfig, ServerConfig};
+    use axum::body::{to_bytes, Body, Bytes};
+    use axum::http::{header, Request, StatusCode};
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
     use std::hash::{DefaultHasher, Hasher};
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    async fn insert(tools: &Tools, key: &str, data: &[u8]) {
+        let hash = Sha256::digest(data).into();
+        tools.store.shard(key).write().await.for_key_mut(key).insert(key.to_string(), hash, data.to_vec().into(), BlobMeta::default()).unwrap();
+    }
+
+    async fn signed_tools() -> Tools {
+        let config = ServerConfig {
+            api_token: Some("api-token".to_string()),
+            signing_key: Some("signing-key".to_string()),
+            ..ServerConfig::default()
+        };
+        let tools = Tools::for_test(config);
+        insert(&tools, "apple", b"red").await;
+        tools
+    }
+
+    fn multipart_request(uri: &str, field: &str, data: &[u8]) -> Request<Body> {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--XBOUNDARYX\r\n");
+        body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"; filename=\"f.bin\"\r\n", field).as_bytes());
+        body.extend_from_slice(b"Content-Type: application/octet-stream\r\n\r\n");
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n--XBOUNDARYX--\r\n");
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XBOUNDARYX")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn multipart_body(fields: &[(&str, &[u8])]) -> Request<Body> {
+        let mut body = Vec::new();
+        for (name, data) in fields {
+            body.extend_from_slice(b"--XBOUNDARYX\r\n");
+            body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--XBOUNDARYX--\r\n");
+        Request::post("/api/lru?key=k")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XBOUNDARYX")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn upload_key(tools: &Tools, uri: &str, data: &[u8]) -> String {
+        let res = axum_router(tools.clone()).oneshot(multipart_request(uri, "file", data)).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        body["data"]["key"].as_str().unwrap().to_string()
+    }
+
+    async fn status_of(tools: &Tools, req: Request<Body>) -> StatusCode {
+        axum_router(tools.clone()).oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_upload_field_validation() {
+        let tools = Tools::for_test(ServerConfig::default());
+        let code_of = |fields: &[(&str, &[u8])]| {
+            let req = multipart_body(fields);
+            let router = axum_router(tools.clone());
+            async move {
+                let res = router.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+                (status, body["code"].as_str().unwrap().to_string())
+            }
+        };
+        let bad = |code: &str| (StatusCode::BAD_REQUEST, code.to_string());
+        assert_eq!(code_of(&[("data", b"x")]).await, bad("UNEXPECTED_FIELD"));
+        assert_eq!(code_of(&[("file", b"")]).await, bad("EMPTY_FIELD"));
+        assert_eq!(code_of(&[("file", b"x"), ("file", b"y")]).await, bad("EXTRA_FIELD"));
+        assert_eq!(code_of(&[("file", b"x")]).await, (StatusCode::OK, "00000".to_string()));
+        assert_eq!(stats(&tools).await["entries"], 1);
+
+        // bodies cut short inside the value, and inside the headers of a trailing part
+        let truncated: [&[u8]; 2] = [
+            b"--XBOUNDARYX\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\nxyz",
+            b"--XBOUNDARYX\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\nx\r\n--XBOUNDARYX\r\nContent-Disp",
+        ];
+        for body in truncated {
+            let req = Request::post("/api/lru?key=cut")
+                .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XBOUNDARYX")
+                .body(Body::from(body))
+                .unwrap();
+            let res = axum_router(tools.clone()).oneshot(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body["code"], "MALFORMED_MULTIPART");
+        }
+        assert_eq!(stats(&tools).await["entries"], 1);
+
+        let tools = Tools::for_test(ServerConfig { upload_field_name: "blob".to_string(), ..ServerConfig::default() });
+        assert_eq!(status_of(&tools, multipart_body(&[("blob", b"x")])).await, StatusCode::OK);
+        assert_eq!(status_of(&tools, multipart_body(&[("file", b"x")])).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_presign_and_download() {
+        let tools = signed_tools().await;
+        let req = Request::post("/api/lru/presign")
+            .header(header::AUTHORIZATION, "Bearer api-token")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"key":"apple","expires_in_secs":60}"#))
+            .unwrap();
+        let res = axum_router(tools.clone()).oneshot(req).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let url = body["data"]["url"].as_str().unwrap().to_string();
+        assert!(url.starts_with("/api/lru/apple?sig="));
+
+        let res = axum_router(tools.clone())
+            .oneshot(Request::get(&url).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(&to_bytes(res.into_body(), usize::MAX).await.unwrap()[..], b"red");
+    }
+
+    #[tokio::test]
+    async fn test_presign_requires_auth() {
+        let tools = signed_tools().await;
+        let req = Request::post("/api/lru/presign")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"key":"apple","expires_in_secs":60}"#))
+            .unwrap();
+        assert_eq!(status_of(&tools, req).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_signed_download_rejections() {
+        let tools = signed_tools().await;
+        let exp = auth::unix_now() + 60;
+        let sig = auth::sign("signing-key", "apple", exp);
+
+        // signature issued for another key
+        let other = auth::sign("signing-key", "banana", exp);
+        let uri = format!("/api/lru/apple?sig={}&exp={}", other, exp);
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::UNAUTHORIZED);
+
+        // tampered expiry
+        let uri = format!("/api/lru/apple?sig={}&exp={}", sig, exp + 1000);
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::UNAUTHORIZED);
+
+        // expired beyond the skew window
+        let old = auth::unix_now() - 120;
+        let uri = format!("/api/lru/apple?sig={}&exp={}", auth::sign("signing-key", "apple", old), old);
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::UNAUTHORIZED);
+
+        // no signature, but the bearer token works
+        let req = Request::get("/api/lru/apple")
+            .header(header::AUTHORIZATION, "Bearer api-token")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(status_of(&tools, req).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_upload_is_stored_in_chunks() {
+        let tools = Tools::for_test(ServerConfig { chunk_size_bytes: 16, ..ServerConfig::default() });
+        let data: Vec<u8> = (0..100u8).collect();
+        let key = upload_key(&tools, "/api/lru", &data).await;
+        {
+            let (stored, _) = tools.store.shard(&key).write().await.for_key_mut(&key).get(&key).unwrap();
+            assert_eq!(stored.chunks().len(), 7);
+            assert_eq!(stored.to_vec(), data);
+        }
+
+        let uri = format!("/api/lru?key={}", key);
+        let res = axum_router(tools.clone()).oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "100");
+        assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec(), data);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sliding_ttl_outlives_fixed() {
+        let tools = Tools::for_test(ServerConfig { max_ttl_secs: 60, ..ServerConfig::default() });
+        let fixed = upload_key(&tools, "/api/lru?ttl_secs=10&ttl_mode=fixed", b"fixed").await;
+        let sliding = upload_key(&tools, "/api/lru?ttl_secs=10&ttl_mode=sliding", b"sliding").await;
+
+        for _ in 0..5 {
+            tokio::time::advance(Duration::from_secs(6)).await;
+            let uri = format!("/api/lru?key={}", sliding);
+            assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::OK);
+        }
+
+        let uri = format!("/api/lru?key={}", fixed);
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::NOT_FOUND);
+        // exists-style reads do not slide the expiry
+        assert!(tools.store.shards()[0].read().await.default_store().index().contains(sliding.as_str()));
+        assert!(tools.store.shards()[0].read().await.default_store().ttl(sliding.as_str()).unwrap() <= Duration::from_secs(60));
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        let uri = format!("/api/lru?key={}", sliding);
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sliding_ttl_capped_by_max_ttl() {
+        let tools = Tools::for_test(ServerConfig { max_ttl_secs: 15, ..ServerConfig::default() });
+        let key = upload_key(&tools, "/api/lru?ttl_secs=10&ttl_mode=sliding", b"data").await;
+        for _ in 0..3 {
+            let uri = format!("/api/lru?key={}", key);
+            assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::OK);
+        }
+        assert_eq!(tools.store.shards()[0].read().await.default_store().ttl(key.as_str()), Some(Duration::from_secs(15)));
+    }
+
+    async fn stats(tools: &Tools) -> Value {
+        let res = axum_router(tools.clone()).oneshot(Request::get("/api/lru/stats").body(Body::empty()).unwrap()).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<Value>(&body).unwrap()["data"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_identical_uploads_are_deduplicated() {
+        let tools = Tools::for_test(ServerConfig::default());
+        let data = vec![42u8; 1000];
+        for tenant in ["a", "b", "c"] {
+            let uri = format!("/api/lru?key={}", tenant);
+            assert_eq!(upload_key(&tools, &uri, &data).await, tenant);
+        }
+        let body = stats(&tools).await;
+        assert_eq!(body["entries"], 3);
+        assert_eq!(body["distinctValues"], 1);
+        assert_eq!(body["logicalBytes"], 3000);
+        assert_eq!(body["physicalBytes"], 1000);
+
+        // deleting a referrer keeps the shared bytes for the others
+        let delete = |key: &str| Request::delete(format!("/api/lru?key={}", key)).body(Body::empty()).unwrap();
+        assert_eq!(status_of(&tools, delete("a")).await, StatusCode::OK);
+        assert_eq!(status_of(&tools, delete("a")).await, StatusCode::NOT_FOUND);
+        let res = axum_router(tools.clone()).oneshot(Request::get("/api/lru?key=b").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec(), data);
+
+        assert_eq!(status_of(&tools, delete("b")).await, StatusCode::OK);
+        assert_eq!(status_of(&tools, delete("c")).await, StatusCode::OK);
+        let body = stats(&tools).await;
+        assert_eq!(body["entries"], 0);
+        assert_eq!(body["physicalBytes"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_head_matches_get_headers() {
+        let tools = Tools::for_test(ServerConfig::default());
+        insert(&tools, "apple", b"red").await;
+        insert(&tools, "pear", b"green").await;
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(REQUEST_ID_HEADER, "fixed")
+                .body(Body::empty())
+                .unwrap()
+        };
+        for uri in ["/api/lru?key=apple", "/api/lru/apple"] {
+            let head = axum_router(tools.clone()).oneshot(request("HEAD", uri)).await.unwrap();
+            assert_eq!(head.status(), StatusCode::OK);
+            // HEAD does not promote: "pear" stays the most recently used key
+            assert_eq!(stats(&tools).await["firstKey"], "pear");
+            let get = axum_router(tools.clone()).oneshot(request("GET", uri)).await.unwrap();
+            assert_eq!(head.headers(), get.headers());
+            assert_eq!(head.headers()[header::CONTENT_LENGTH], "3");
+            assert!(to_bytes(head.into_body(), usize::MAX).await.unwrap().is_empty());
+            assert_eq!(&to_bytes(get.into_body(), usize::MAX).await.unwrap()[..], b"red");
+            insert(&tools, "pear", b"green").await;
+        }
+        let missing = axum_router(tools.clone()).oneshot(request("HEAD", "/api/lru?key=plum")).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_requests_shed_while_lock_is_held() {
+        let config = ServerConfig { lock_wait_ms: Some(50), read_lock_wait_ms: Some(100), ..ServerConfig::default() };
+        let tools = Tools::for_test(config);
+        insert(&tools, "apple", b"red").await;
+        let (locked_tx, locked_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let store = tools.store.clone();
+        let holder = tokio::spawn(async move {
+            let _guard = store.shards()[0].write().await;
+            locked_tx.send(()).unwrap();
+            let _ = release_rx.await;
+        });
+        locked_rx.await.unwrap();
+
+        for (uri, wait) in [("/api/lru?key=apple", 50), ("/api/lru/stats", 100)] {
+            let started = Instant::now();
+            let res = axum_router(tools.clone()).oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap();
+            let waited = started.elapsed();
+            assert!(waited >= Duration::from_millis(wait) && waited < Duration::from_secs(2), "{} waited {:?}", uri, waited);
+            assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+            let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body["code"], "LOCK_BUSY");
+        }
+
+        release_tx.send(()).unwrap();
+        holder.await.unwrap();
+        assert_eq!(status_of(&tools, Request::get("/api/lru?key=apple").body(Body::empty()).unwrap()).await, StatusCode::OK);
+        assert_eq!(tools.stats.snapshot().lock_shed_total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_head_only_takes_the_read_lock() {
+        let config = ServerConfig { lock_wait_ms: Some(50), ..ServerConfig::default() };
+        let tools = Tools::for_test(config);
+        insert(&tools, "apple", b"red").await;
+        let _reader = tools.store.shards()[0].read().await;
+
+        let head = Request::head("/api/lru?key=apple").body(Body::empty()).unwrap();
+        assert_eq!(status_of(&tools, head).await, StatusCode::OK);
+        assert_eq!(status_of(&tools, Request::get("/api/lru?key=apple").body(Body::empty()).unwrap()).await, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_sharded_stores_stay_within_cache_size() {
+        let tools = Tools::for_test(ServerConfig { cache_size: 8, cache_shards: 4, ..ServerConfig::default() });
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let tools = tools.clone();
+                tokio::spawn(async move {
+                    for i in 0..50 {
+                        let key = format!("k{}", (t * 50 + i) % 60);
+                        insert(&tools, &key, key.as_bytes()).await;
+                        let get = Request::get(format!("/api/lru?key={}", key)).body(Body::empty()).unwrap();
+                        // another writer may have evicted it already
+                        assert!(matches!(status_of(&tools, get).await, StatusCode::OK | StatusCode::NOT_FOUND));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let mut entries = 0;
+        for shard in tools.store.shards() {
+            let stores = shard.read().await;
+            assert_eq!(stores.default_store().usage().capacity, Some(2));
+            assert!(stores.default_store().len() <= 2);
+            entries += stores.default_store().len();
+        }
+        let res = axum_router(tools.clone()).oneshot(Request::get("/api/lru/stats").body(Body::empty()).unwrap()).await.unwrap();
+        let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["data"]["entries"], entries);
+        assert!(entries <= 8);
+    }
+
+    fn range_request(key: &str, range: &str, if_range: Option<&str>) -> Request<Body> {
+        let mut req = Request::get(format!("/api/lru?key={}", key)).header(header::RANGE, range);
+        if let Some(if_range) = if_range {
+            req = req.header(header::IF_RANGE, if_range);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_range_download() {
+        let tools = Tools::for_test(ServerConfig { chunk_size_bytes: 4, ..ServerConfig::default() });
+        let data: Vec<u8> = (0..20u8).collect();
+        let key = upload_key(&tools, "/api/lru", &data).await;
+
+        let res = axum_router(tools.clone()).oneshot(range_request(&key, "bytes=3-10", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 3-10/20");
+        assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec(), data[3..11]);
+
+        let res = axum_router(tools.clone()).oneshot(range_request(&key, "bytes=20-", None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */20");
+    }
+
+    #[tokio::test]
+    async fn test_if_range() {
+        let tools = Tools::for_test(ServerConfig::default());
+        let data: Vec<u8> = (0..20u8).collect();
+        let key = upload_key(&tools, "/api/lru", &data).await;
+        let res = axum_router(tools.clone()).oneshot(Request::get(format!("/api/lru?key={}", key)).body(Body::empty()).unwrap()).await.unwrap();
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+        let last_modified = res.headers()[header::LAST_MODIFIED].to_str().unwrap().to_string();
+
+        // validators still match: the range is honored
+        for validator in [etag.as_str(), last_modified.as_str()] {
+            let res = axum_router(tools.clone()).oneshot(range_request(&key, "bytes=5-", Some(validator))).await.unwrap();
+            assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+            assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec(), data[5..]);
+        }
+
+        // changed entity, weak tag, old date or garbage: the full body instead of a splice
+        let weak = format!("W/{}", etag);
+        for validator in ["\"0123\"", weak.as_str(), "Thu, 01 Jan 1970 00:00:00 GMT", "not a validator"] {
+            let res = axum_router(tools.clone()).oneshot(range_request(&key, "bytes=5-", Some(validator))).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(header::CONTENT_RANGE).is_none());
+            assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec(), data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_limit_reject_before_body() {
+        let config = ServerConfig { max_keys_per_namespace: Some(1), on_limit: OnLimit::Reject, ..ServerConfig::default() };
+        let tools = Tools::for_test(config);
+        assert_eq!(upload_key(&tools, "/api/lru?key=t1/a", b"one").await, "t1/a");
+
+        // a body that fails when read: the rejection must come before anyone reads it
+        let body = Body::from_stream(futures_util::stream::iter([Err::<Bytes, _>(std::io::Error::other("body was read"))]));
+        let req = Request::post("/api/lru?key=t1/b")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XBOUNDARYX")
+            .header(header::CONTENT_LENGTH, "100")
+            .body(body)
+            .unwrap();
+        let res = axum_router(tools.clone()).oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "LIMIT_EXCEEDED");
+
+        // other namespaces and overwrites are unaffected
+        assert_eq!(upload_key(&tools, "/api/lru?key=t2/a", b"two").await, "t2/a");
+        assert_eq!(upload_key(&tools, "/api/lru?key=t1/a", b"three").await, "t1/a");
+        let body = stats(&tools).await;
+        assert_eq!(body["namespaces"]["t1"]["keys"], 1);
+        assert_eq!(body["namespaces"]["t1"]["rejected"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_limit_evict_at_boundary() {
+        let tools = Tools::for_test(ServerConfig { max_keys: Some(2), ..ServerConfig::default() });
+        for key in ["a", "b", "c"] {
+            upload_key(&tools, &format!("/api/lru?key={}", key), key.as_bytes()).await;
+        }
+        let body = stats(&tools).await;
+        assert_eq!(body["entries"], 2);
+        assert_eq!(body["namespaces"][""]["keys"], 2);
+        let uri = "/api/lru?key=a";
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_namespaces_evict_by_their_own_mode() {
+        let mut config = ServerConfig::default();
+        let blob = NamespaceConfig { cache_mode: "capacity".to_string(), cache_bytes: Some(2 * (1000 + 64 + 10)), ..NamespaceConfig::default() };
+        let meta = NamespaceConfig { cache_size: 2, default_ttl_secs: Some(30), ..NamespaceConfig::default() };
+        config.namespaces.insert("blob".to_string(), blob);
+        config.namespaces.insert("meta".to_string(), meta);
+        let tools = Tools::for_test(config);
+
+        // three small meta entries: the item limit of 2 evicts the first
+        for key in ["meta/1", "meta/2", "meta/3"] {
+            upload_key(&tools, &format!("/api/lru?key={}", key), key.as_bytes()).await;
+        }
+        // two 1000-byte blobs fill the byte budget; a third pushes the oldest out
+        for (i, key) in ["blob/1", "blob/2", "blob/3"].into_iter().enumerate() {
+            upload_key(&tools, &format!("/api/lru?key={}", key), &[i as u8; 1000]).await;
+        }
+        // the default cache keeps its own item limit of 5
+        upload_key(&tools, "/api/lru?key=plain", b"x").await;
+
+        for (key, status) in [("meta/1", 404), ("meta/3", 200), ("blob/1", 404), ("blob/2", 200), ("plain", 200)] {
+            let uri = format!("/api/lru?key={}", key);
+            assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, status, "{}", key);
+        }
+
+        let body = stats(&tools).await;
+        assert_eq!(body["entries"], 5);
+        assert_eq!(body["namespaces"]["meta"]["cacheMode"], "item");
+        assert_eq!(body["namespaces"]["meta"]["capacity"], 2);
+        assert_eq!(body["namespaces"]["meta"]["used"], 2);
+        assert_eq!(body["namespaces"]["blob"]["cacheMode"], "capacity");
+        assert_eq!(body["namespaces"]["blob"]["used"], 2 * (1000 + 64 + 6));
+        assert!(body["namespaces"][""].get("cacheMode").is_none());
+
+        // meta uploads without ttl_secs expire after the namespace default
+        tokio::time::advance(Duration::from_secs(31)).await;
+        let uri = "/api/lru?key=meta/3";
+        assert_eq!(status_of(&tools, Request::get(uri).body(Body::empty()).unwrap()).await, StatusCode::NOT_FOUND);
+        assert_eq!(status_of(&tools, Request::get("/api/lru?key=blob/2").body(Body::empty()).unwrap()).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_out_of_memory_is_insufficient_storage() {
+        let (status, body) = super::store_error(StoreError::OutOfMemory);
+        assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+        assert_eq!(body.code, "INSUFFICIENT_STORAGE");
+    }
 
     #[test]
     fn test_hasher() {
@@ -1,3 +1,5 @@
+use crate::http::stats::StatsSnapshot;
+use crate::http::store::{StoreStats, TtlMode};
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Serialize)]
@@ -11,4 +13,88 @@ pub struct UploadResponse {
 #[serde(rename_all = "camelCase")]
 pub struct DownloadRequest {
     pub key: String,
-}
\ No newline at end of file
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct UploadQuery {
+    /// Stores the value under this key instead of one derived from its content.
+    pub key: Option<String>,
+    pub ttl_secs: Option<u64>,
+    #[serde(default)]
+    pub ttl_mode: TtlMode,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SignedDownloadQuery {
+    pub sig: Option<String>,
+    pub exp: Option<u64>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignRequest {
+    pub key: String,
+    #[serde(alias = "expires_in_secs")]
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignResponse {
+    pub url: String,
+    pub expires_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InfoResponse {
+    pub version: String,
+    pub git_hash: String,
+    pub build_timestamp: u64,
+    pub features: Vec<String>,
+    pub cache_mode: String,
+    pub cache_capacity: usize,
+    pub cache_len: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub server: StatsSnapshot,
+    #[serde(flatten)]
+    pub store: StoreStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteResponse {
+    pub key: String,
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct ShutdownRequest {
+    pub drain_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShutdownResponse {
+    pub drain_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ResizeRequest {
+    /// A namespace with its own cache; the default cache when unset.
+    pub namespace: Option<String>,
+    /// Keys in item mode, bytes in capacity mode.
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResizeResponse {
+    pub namespace: Option<String>,
+    pub cache_mode: String,
+    pub capacity: usize,
+    pub evicted: usize,
+}
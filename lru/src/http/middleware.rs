@@ -0,0 +1,85 @@
+use crate::http::common::StandardApiJsonBody;
+use crate::http::stats::ServerStats;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tower_http::catch_panic::ResponseForPanic;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns the id of the request currently being handled, if any.
+pub fn current_request_id() -> Option<String> { REQUEST_ID.try_with(|id| id.clone()).ok() }
+
+/// Seconds a client is told to wait before retrying a `503`.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// Adds `Retry-After` to `503` responses, which the server only sends when shedding load.
+pub async fn retry_after(req: Request, next: Next) -> Response {
+    let mut res = next.run(req).await;
+    if res.status() == StatusCode::SERVICE_UNAVAILABLE {
+        res.headers_mut().entry(header::RETRY_AFTER).or_insert(RETRY_AFTER_SECS.into());
+    }
+    res
+}
+
+/// Tags every request with an id, reusing the client's `x-request-id` when present.
+/// The id is echoed in the response and visible to the panic responder.
+pub async fn request_id(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| format!("{:016x}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)));
+
+    // parent of the cache's own spans when the `tracing` feature is on
+    let span = tracing::info_span!("request", request_id = %id, method = %req.method(), path = req.uri().path());
+    let mut res = REQUEST_ID.scope(id.clone(), next.run(req).instrument(span)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    res
+}
+
+/// Turns a handler panic into a structured 500. The panic message is logged, never returned.
+#[derive(Debug, Clone)]
+pub struct PanicResponder {
+    pub stats: Arc<ServerStats>,
+}
+
+impl ResponseForPanic for PanicResponder {
+    type ResponseBody = Body;
+
+    fn response_for_panic(&mut self, err: Box<dyn Any + Send + 'static>) -> Response<Self::ResponseBody> {
+        let payload = if let Some(s) = err.downcast_ref::<String>() {
+            s.as_str()
+        } else if let Some(s) = err.downcast_ref::<&str>() {
+            s
+        } else {
+            "unknown panic payload"
+        };
+        let request_id = current_request_id().unwrap_or_default();
+        tracing::error!(request_id = %request_id, panic = %payload, "handler panicked");
+        self.stats.inc_panics();
+
+        let body = StandardApiJsonBody {
+            code: "INTERNAL_PANIC".to_string(),
+            message: "Internal server error".to_string(),
+            data: (),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+    }
+}
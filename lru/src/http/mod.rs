@@ -1,43 +1,94 @@
-use crate::http::router::axum_router;
-use crate::lru::lru_cache::LRUCache;
-use config::Config;
-use std::num::NonZeroUsize;
+use crate::http::access_log::AccessLog;
+use crate::http::common::LockBusy;
+use crate::http::stats::ServerStats;
+use crate::http::store::{ShardedStores, Stores};
+use crate::settings::ServerConfig;
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+pub use server::{Server, ShutdownHandle};
 
 mod router;
+pub(crate) mod access_log;
 mod data;
+mod admin;
+mod auth;
 mod common;
 mod dtos;
+mod middleware;
+mod range;
+mod reload;
+mod server;
+pub(crate) mod stats;
+pub(crate) mod store;
 
 #[derive(Debug, Clone)]
 struct Tools {
-    lru_cache: Arc<RwLock<LRUCache<String, Vec<u8>>>>,
+    store: Arc<ShardedStores>,
+    config: Arc<ServerConfig>,
+    stats: Arc<ServerStats>,
+    shutdown: ShutdownHandle,
+    access_log: Option<AccessLog>,
 }
 
-pub async fn axum_serve(config: Config) {
-    let port = config.get::<u16>("server_port").unwrap();
-    let cache_mode = config.get::<String>("cache_mode").unwrap();
-    let cache_size = config.get::<usize>("cache_size").unwrap();
+// The store locks, taken within `lock_wait_ms` for writes and `read_lock_wait_ms` for reads;
+// every lock given up on is counted as shed.
+impl Tools {
+    async fn write_store(&self, key: &str) -> Result<RwLockWriteGuard<'_, Stores>, LockBusy> {
+        self.store.write(key, self.lock_wait()).await.inspect_err(|_| self.stats.inc_lock_shed())
+    }
 
-    let lru_cache = match cache_mode.as_str() {
-        "item" | "default" => {
-            LRUCache::new(NonZeroUsize::new(cache_size).unwrap())
-        }
-        "capacity" => {
-            LRUCache::storage(NonZeroUsize::new(cache_size).unwrap())
-        }
-        "unlimited" => {
-            LRUCache::unbounded()
-        }
-        _ => {
-            LRUCache::new(NonZeroUsize::new(cache_size).unwrap())
-        }
-    };
-    let lru_cache: Arc<RwLock<LRUCache<String, Vec<u8>>>> = Arc::new(RwLock::new(lru_cache));
+    async fn read_store(&self, key: &str) -> Result<RwLockReadGuard<'_, Stores>, LockBusy> {
+        self.store.read(key, self.read_lock_wait()).await.inspect_err(|_| self.stats.inc_lock_shed())
+    }
+
+    async fn write_stores(&self) -> Result<Vec<RwLockWriteGuard<'_, Stores>>, LockBusy> {
+        self.store.write_all(self.lock_wait()).await.inspect_err(|_| self.stats.inc_lock_shed())
+    }
+
+    async fn read_stores(&self) -> Result<Vec<RwLockReadGuard<'_, Stores>>, LockBusy> {
+        self.store.read_all(self.read_lock_wait()).await.inspect_err(|_| self.stats.inc_lock_shed())
+    }
+
+    fn lock_wait(&self) -> Option<Duration> { self.config.lock_wait_ms.map(Duration::from_millis) }
 
-    let axum_app = axum_router(Tools { lru_cache: lru_cache.clone() });
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await.unwrap();
-    axum::serve(listener, axum_app).await.unwrap();
-}
\ No newline at end of file
+    fn read_lock_wait(&self) -> Option<Duration> { self.config.read_lock_wait_ms.map(Duration::from_millis) }
+}
+
+/// Serves until Ctrl-C or SIGTERM. Fails at startup if the stores cannot be built from
+/// `config`, a zero `cache_size` for instance, or the listener cannot be bound.
+pub async fn axum_serve(config: ServerConfig) -> io::Result<()> { serve_until_signal(config, None).await }
+
+/// Like `axum_serve`, watching `path`, the file `config` was loaded from, for changes.
+pub async fn axum_serve_watching(config: ServerConfig, path: PathBuf) -> io::Result<()> { serve_until_signal(config, Some(path)).await }
+
+async fn serve_until_signal(config: ServerConfig, path: Option<PathBuf>) -> io::Result<()> {
+    let drain = Duration::from_secs(config.shutdown_drain_secs);
+    let mut server = Server::bind(config).await?;
+    if let Some(path) = path {
+        server.watch_config(path);
+    }
+    let shutdown = server.shutdown_handle();
+    tokio::spawn(async move {
+        server::termination_signal().await;
+        shutdown.shutdown(drain);
+    });
+    server.serve().await
+}
+
+#[cfg(test)]
+impl Tools {
+    fn for_test(config: ServerConfig) -> Self {
+        let store = server::build_sharded_stores(&config).unwrap();
+        Tools {
+            store: Arc::new(store),
+            config: Arc::new(config),
+            stats: Arc::default(),
+            shutdown: ShutdownHandle::default(),
+            access_log: None,
+        }
+    }
+}
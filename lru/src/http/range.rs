@@ -0,0 +1,98 @@
+use std::ops::Range;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Outcome of matching a `Range` header against a value of known length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: serve the whole value with 200.
+    Full,
+    /// Serve these bytes with 206.
+    Partial(Range<usize>),
+    /// The range lies outside the value: answer 416.
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=start-end`, `bytes=start-` or `bytes=-suffix` range.
+/// Malformed headers, other units and multiple ranges fall back to the full body.
+pub fn parse_range(header: &str, len: usize) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(start), Ok(end)) if start <= end => start..(end + 1).min(len),
+        (Ok(start), Err(_)) if end.is_empty() => start..len,
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            len.saturating_sub(suffix)..len
+        }
+        _ => return ByteRange::Full,
+    };
+    if range.start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(range)
+    }
+}
+
+/// Formats unix seconds as an HTTP-date.
+pub fn http_date(unix_secs: u64) -> String { httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(unix_secs)) }
+
+/// Decides whether the `If-Range` validator still describes the stored entry.
+/// Entity tags must match strongly (weak tags never do); HTTP-dates must equal `Last-Modified`.
+/// Anything else is malformed and does not match, so the full body is served.
+pub fn if_range_matches(value: &str, etag: &str, last_modified: u64) -> bool {
+    let value = value.trim();
+    if value.starts_with("W/") {
+        return false;
+    }
+    if value.starts_with('"') {
+        return value.len() >= 2 && value.ends_with('"') && value == etag;
+    }
+    match httpdate::parse_http_date(value) {
+        Ok(date) => date.duration_since(UNIX_EPOCH).is_ok_and(|d| d.as_secs() == last_modified),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{http_date, if_range_matches, parse_range, ByteRange};
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), ByteRange::Partial(0..10));
+        assert_eq!(parse_range("bytes=90-200", 100), ByteRange::Partial(90..100));
+        assert_eq!(parse_range("bytes=50-", 100), ByteRange::Partial(50..100));
+        assert_eq!(parse_range("bytes=-10", 100), ByteRange::Partial(90..100));
+        assert_eq!(parse_range("bytes=-200", 100), ByteRange::Partial(0..100));
+        assert_eq!(parse_range("bytes=100-", 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 100), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=9-0", 100), ByteRange::Full);
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), ByteRange::Full);
+        assert_eq!(parse_range("items=0-1", 100), ByteRange::Full);
+        assert_eq!(parse_range("bytes=abc", 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_if_range_matches() {
+        let etag = "\"abc\"";
+        assert!(if_range_matches("\"abc\"", etag, 0));
+        assert!(!if_range_matches("\"abd\"", etag, 0));
+        assert!(!if_range_matches("W/\"abc\"", etag, 0));
+        assert!(!if_range_matches("\"abc", etag, 0));
+
+        let modified = 1_700_000_000;
+        assert!(if_range_matches(&http_date(modified), etag, modified));
+        assert!(!if_range_matches(&http_date(modified - 1), etag, modified));
+        assert!(!if_range_matches("yesterday", etag, modified));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::http::store::{resize_shards, ShardedStores, Stores};
+use crate::settings::{load_config, NamespaceConfig, ServerConfig};
+use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// What `apply_config` did with a new config.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Reload {
+    /// One `key: old -> new` line per change applied to the running stores.
+    pub applied: Vec<String>,
+    /// Changed keys that only take effect after a restart.
+    pub restart: Vec<String>,
+}
+
+/// Refuses configs the stores could not be built from.
+pub(crate) fn validate(config: &ServerConfig) -> Result<(), String> {
+    config.check_cache_modes()?;
+    config.check_ttl_mode()?;
+    if config.cache_mode != "unlimited" && config.cache_mode != "ttl" && config.cache_size == 0 {
+        return Err("cache_size must be positive".to_string());
+    }
+    for (name, ns) in &config.namespaces {
+        if ns.cache_mode != "capacity" && ns.cache_mode != "unlimited" && ns.cache_size == 0 {
+            return Err(format!("namespaces.{}.cache_size must be positive", name));
+        }
+    }
+    config.check_chunk_size()?;
+    config.check_cache_shards()
+}
+
+// The bound `BlobStore::resize` changes, and the one only a rebuild can, as the store was built
+// by `build_namespace_store`.
+fn namespace_bounds(ns: &NamespaceConfig) -> (Option<usize>, Option<usize>) {
+    match ns.cache_mode.as_str() {
+        "capacity" => (Some(ns.cache_bytes.unwrap_or(ns.cache_size)), None),
+        "unlimited" => (ns.cache_bytes, None),
+        _ => (Some(ns.cache_size), ns.cache_bytes),
+    }
+}
+
+/// Applies the settings of `new` that can change under a running server to the shards of the
+/// stores: the bound of every cache, evicting what no longer fits, and the default TTL of
+/// namespaces. A cache whose mode changed is left alone and reported in `restart`, as is a new
+/// `cache_shards`.
+pub(crate) fn apply_config<T: DerefMut<Target = Stores>>(shards: &mut [T], old: &ServerConfig, new: &ServerConfig) -> Reload {
+    let mut reload = Reload::default();
+    let mut hot = BTreeSet::from(["namespaces"]);
+
+    if old.cache_mode == new.cache_mode {
+        hot.insert("cache_size");
+        if let (Some(size), true) = (NonZeroUsize::new(new.cache_size), old.cache_size != new.cache_size) {
+            if let Some(evicted) = resize_shards(shards, None, size) {
+                reload.applied.push(format!("cache_size: {} -> {} ({} evicted)", old.cache_size, new.cache_size, evicted));
+            }
+        }
+    }
+
+    let names: BTreeSet<&String> = old.namespaces.keys().chain(new.namespaces.keys()).collect();
+    for name in names {
+        let (Some(was), Some(ns)) = (old.namespaces.get(name), new.namespaces.get(name)) else {
+            reload.restart.push(format!("namespaces.{}", name));
+            continue;
+        };
+        let (bound, fixed) = namespace_bounds(ns);
+        let (old_bound, old_fixed) = namespace_bounds(was);
+        if ns.cache_mode != was.cache_mode || ns.hasher != was.hasher || fixed != old_fixed || bound.is_some() != old_bound.is_some() {
+            reload.restart.push(format!("namespaces.{}", name));
+            continue;
+        }
+        if let (Some(size), Some(old_size)) = (bound.and_then(NonZeroUsize::new), old_bound) {
+            if size.get() != old_size {
+                if let Some(evicted) = resize_shards(shards, Some(name), size) {
+                    reload.applied.push(format!("namespaces.{}: size {} -> {} ({} evicted)", name, old_size, size, evicted));
+                }
+            }
+        }
+        if ns.default_ttl_secs != was.default_ttl_secs {
+            for stores in shards.iter_mut() {
+                if let Some(store) = stores.namespace_mut(Some(name)) {
+                    store.set_default_ttl(ns.default_ttl_secs.map(Duration::from_secs));
+                }
+            }
+            reload.applied.push(format!("namespaces.{}.default_ttl_secs: {:?} -> {:?}", name, was.default_ttl_secs, ns.default_ttl_secs));
+        }
+    }
+
+    // secrets are compared redacted, so a rotated token is reported without its value
+    let (old_json, new_json) = (old.redacted(), new.redacted());
+    if let (Some(old_keys), Some(new_keys)) = (old_json.as_object(), new_json.as_object()) {
+        for (key, value) in new_keys {
+            if !hot.contains(key.as_str()) && old_keys.get(key) != Some(value) {
+                reload.restart.push(key.clone());
+            }
+        }
+    }
+    reload
+}
+
+// mtime and length tell a rewrite apart; `None` while the file is missing
+fn fingerprint(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Polls `path` every `interval` and applies its changes with `apply_config`, once the file
+/// stayed the same for a whole interval so that a burst of writes is read once. Files that do
+/// not parse or validate are logged and ignored; the running config stays in place.
+pub(crate) async fn watch_config(path: PathBuf, interval: Duration, store: Arc<ShardedStores>, mut current: ServerConfig) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut seen = fingerprint(&path);
+    let mut pending = false;
+    loop {
+        ticker.tick().await;
+        let now = fingerprint(&path);
+        if now != seen {
+            seen = now;
+            pending = true;
+            continue;
+        }
+        if !std::mem::take(&mut pending) {
+            continue;
+        }
+        let config = match load_config(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!("ignoring changed config: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = validate(&config) {
+            tracing::warn!("ignoring changed config {}: {}", path.display(), e);
+            continue;
+        }
+        let mut shards = store.write_all(None).await.expect("a lock without a deadline is not given up");
+        let reload = apply_config(&mut shards, &current, &config);
+        drop(shards);
+        for change in &reload.applied {
+            tracing::info!("config reloaded: {}", change);
+        }
+        if !reload.restart.is_empty() {
+            tracing::warn!(keys = ?reload.restart, "config changes that need a restart were not applied");
+        }
+        current = config;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_config, validate, watch_config};
+    use crate::http::server::{build_sharded_stores, build_stores};
+    use crate::settings::{NamespaceConfig, ServerConfig};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn config(cache_size: usize, meta_ttl: Option<u64>) -> ServerConfig {
+        let meta = NamespaceConfig { cache_size: 10, default_ttl_secs: meta_ttl, ..Default::default() };
+        ServerConfig {
+            cache_mode: "item".to_string(),
+            cache_size,
+            namespaces: [("meta".to_string(), meta)].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let old = config(5, None);
+        let mut stores = build_stores(&old).unwrap();
+        let new = ServerConfig { server_port: 1, ..config(3, Some(60)) };
+
+        let reload = apply_config(&mut [&mut stores], &old, &new);
+        assert_eq!(reload.applied, vec!["cache_size: 5 -> 3 (0 evicted)", "namespaces.meta.default_ttl_secs: None -> Some(60)"]);
+        assert_eq!(reload.restart, vec!["server_port"]);
+        assert_eq!(stores.default_store().usage().capacity, Some(3));
+        assert_eq!(stores.namespace_mut(Some("meta")).unwrap().default_ttl(), Some(Duration::from_secs(60)));
+
+        // a new mode needs a new store
+        let capacity = ServerConfig { cache_mode: "capacity".to_string(), ..new.clone() };
+        let reload = apply_config(&mut [&mut stores], &new, &capacity);
+        assert!(reload.applied.is_empty());
+        assert_eq!(reload.restart, vec!["cache_mode"]);
+        assert_eq!(validate(&config(0, None)), Err("cache_size must be positive".to_string()));
+        let unchunked = ServerConfig { chunk_size_bytes: 0, ..new.clone() };
+        assert_eq!(validate(&unchunked), Err("chunk_size_bytes must be positive".to_string()));
+        let ttl = ServerConfig { cache_mode: "ttl".to_string(), ..config(0, None) };
+        assert_eq!(validate(&ttl), Err("ttl mode needs a positive ttl_seconds".to_string()));
+        assert_eq!(validate(&ServerConfig { ttl_seconds: Some(60), ..ttl }), Ok(()));
+        assert_eq!(validate(&ServerConfig { cache_mode: "lfu".to_string(), ..new.clone() }), Ok(()));
+        let arc = ServerConfig { cache_mode: "arc".to_string(), ..new.clone() };
+        assert_eq!(
+            validate(&arc),
+            Err("cache_mode: unknown mode \"arc\", expected one of default, item, fifo, lfu, capacity, unlimited, ttl".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher_resizes_on_rewrite() {
+        let path = std::env::temp_dir().join(format!("see-watch-{}.toml", std::process::id()));
+        std::fs::write(&path, "cache_mode = \"item\"\ncache_size = 5\n").unwrap();
+        let initial = crate::load_config(&path).unwrap();
+        let store = Arc::new(build_sharded_stores(&initial).unwrap());
+        let watcher = tokio::spawn(watch_config(path.clone(), Duration::from_millis(20), store.clone(), initial));
+        let capacity = || async { store.shards()[0].read().await.default_store().usage().capacity };
+
+        // invalid files are skipped
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(&path, "cache_mode = \"item\"\ncache_size = 0\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(capacity().await, Some(5));
+
+        std::fs::write(&path, "cache_mode = \"item\"\ncache_size = 2\n").unwrap();
+        let mut waited = Duration::ZERO;
+        while capacity().await != Some(2) && waited < Duration::from_secs(5) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            waited += Duration::from_millis(20);
+        }
+        watcher.abort();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(capacity().await, Some(2));
+    }
+}
@@ -1,8 +1,14 @@
-use crate::http::data::{download, upload};
+use crate::http::access_log;
+use crate::http::admin;
+use crate::http::auth::require_auth;
+use crate::http::data::{delete, download, download_by_key, presign, upload};
+use crate::http::middleware::{request_id, retry_after, PanicResponder};
 use crate::http::Tools;
 use axum::extract::DefaultBodyLimit;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::routing::{get, post};
 use axum::{Extension, Router};
+use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::cors::{Any, CorsLayer};
 
 pub fn axum_router(tools: Tools) -> Router {
@@ -10,13 +16,40 @@ pub fn axum_router(tools: Tools) -> Router {
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
+    let catch_panic = CatchPanicLayer::custom(PanicResponder { stats: tools.stats.clone() });
+    let access_log = tools.access_log.clone();
+
+    let admin_router = Router::new()
+        .route("/info", get(admin::info))
+        .route("/config", get(admin::config))
+        .route("/resize", post(admin::resize))
+        .route("/shutdown", post(admin::shutdown));
 
     let api_router = Router::new()
         .route("/lru", get(download))
-        .route("/lru", post(upload))
+        .route("/lru", post(upload).delete(delete))
+        .route("/lru/stats", get(admin::stats))
+        .route("/lru/metrics", get(admin::metrics))
+        .route("/lru/presign", post(presign))
+        .nest("/lru/admin", admin_router);
+    #[cfg(any(test, feature = "test-routes"))]
+    let api_router = api_router.route("/lru/__panic", get(admin::panic));
+
+    // `/lru/{key}` authorizes itself so that pre-signed URLs work without the API token
+    let api_router = api_router
+        .route_layer(from_fn(require_auth))
+        .route("/lru/{key}", get(download_by_key))
         .layer(Extension(tools))
+        .layer(from_fn(retry_after))
         .layer(DefaultBodyLimit::disable())
-        .layer(cors);
+        .layer(catch_panic)
+        .layer(from_fn(request_id));
+    // outside `request_id` so that the logged response carries the id
+    let api_router = match access_log {
+        Some(log) => api_router.layer(from_fn_with_state(log, access_log::access_log)),
+        None => api_router,
+    };
+    let api_router = api_router.layer(cors);
 
     Router::new().nest("/api", api_router)
 }
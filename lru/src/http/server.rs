@@ -0,0 +1,726 @@
+use crate::http::access_log::{AccessLog, LogFormat};
+use crate::http::reload::watch_config;
+use crate::http::router::axum_router;
+use crate::http::store::{BlobIndex, BlobStore, KeyLimits, ShardedStores, Stores, TokioClock};
+use crate::http::Tools;
+use crate::lru::clock::Clock;
+use crate::lru::error::CacheError;
+use crate::lru::lfu_cache::LFUCache;
+use crate::lru::lru_cache::LRUCache;
+use crate::lru::sharded_cache::shard_share;
+use crate::lru::ttl_cache::TTLCache;
+use crate::settings::{NamespaceConfig, ServerConfig};
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tower::ServiceExt;
+
+/// Asks a running `Server` to stop. Cloning is cheap; every clone drives the same server.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    // `Some(drain)` once shutdown was requested
+    tx: Arc<watch::Sender<Option<Duration>>>,
+}
+
+impl ShutdownHandle {
+    fn new() -> Self { ShutdownHandle { tx: Arc::new(watch::channel(None).0) } }
+
+    /// Stops accepting connections and gives in-flight requests up to `drain` to finish.
+    /// Only the first request counts.
+    pub fn shutdown(&self, drain: Duration) {
+        self.tx.send_if_modified(|state| state.is_none() && state.replace(drain).is_none());
+    }
+
+    pub fn is_shutting_down(&self) -> bool { self.tx.borrow().is_some() }
+
+    /// Resolves with the drain deadline once shutdown was requested.
+    async fn requested(&self) -> Duration {
+        let mut rx = self.tx.subscribe();
+        // the sender lives in `self`, so waiting cannot fail
+        let drain = rx.wait_for(Option::is_some).await.ok().and_then(|drain| *drain);
+        drain.unwrap_or_default()
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self { ShutdownHandle::new() }
+}
+
+type ShutdownHook = Box<dyn FnOnce() + Send>;
+
+/// The HTTP server, bound but not yet serving. Lets embedders and tests pick the port,
+/// register shutdown hooks and stop the server without signals.
+pub struct Server {
+    listener: TcpListener,
+    tools: Tools,
+    hooks: Vec<ShutdownHook>,
+    config_path: Option<PathBuf>,
+}
+
+impl Server {
+    /// Builds the stores described by `config` and serves on the socket passed by systemd socket
+    /// activation, or binds `0.0.0.0:server_port` when there is none.
+    pub async fn bind(config: ServerConfig) -> io::Result<Server> {
+        #[cfg(unix)]
+        if let Some(fd) = activation::listen_fd() {
+            // SAFETY: systemd hands the process ownership of the first passed descriptor
+            let listener = unsafe { activation::adopt(fd)? };
+            tracing::info!("serving on socket-activated listener {}", listener.local_addr()?);
+            return Server::with_listener(config, listener).await;
+        }
+        if config.require_socket_activation {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "require_socket_activation is set but no socket was passed"));
+        }
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.server_port));
+        Server::bind_to(config, addr).await
+    }
+
+    /// Like `bind`, but on an explicit address (port 0 picks a free one).
+    pub async fn bind_to(config: ServerConfig, addr: SocketAddr) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr).await?;
+        Server::with_listener(config, listener).await
+    }
+
+    async fn with_listener(config: ServerConfig, listener: TcpListener) -> io::Result<Server> {
+        let access_log = if config.access_log {
+            let format = LogFormat::parse(&config.access_log_format)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            Some(AccessLog::spawn(format, config.access_log_path.as_deref()).await?)
+        } else {
+            None
+        };
+        let store = build_sharded_stores(&config)?;
+        let tools = Tools {
+            store: Arc::new(store),
+            config: Arc::new(config),
+            stats: Arc::default(),
+            shutdown: ShutdownHandle::new(),
+            access_log,
+        };
+        Ok(Server { listener, tools, hooks: Vec::new(), config_path: None })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> { self.listener.local_addr() }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle { self.tools.shutdown.clone() }
+
+    /// Registers work to run after the server stopped serving, e.g. flushing a snapshot.
+    /// Hooks run in registration order.
+    pub fn on_shutdown(&mut self, hook: impl FnOnce() + Send + 'static) { self.hooks.push(Box::new(hook)); }
+
+    /// Names the file the config was loaded from, which is then re-read every
+    /// `config_watch_interval_secs` while serving.
+    pub fn watch_config(&mut self, path: impl Into<PathBuf>) { self.config_path = Some(path.into()); }
+
+    /// Serves until shutdown is requested, then waits for in-flight requests up to the drain
+    /// deadline, runs the shutdown hooks and resolves.
+    pub async fn serve(self) -> io::Result<()> {
+        let shutdown = self.tools.shutdown.clone();
+        let store = self.tools.store.clone();
+        let sweeper = self.tools.config.max_idle_secs.map(|secs| tokio::spawn(sweep_idle(store.clone(), Duration::from_secs(secs))));
+        let watcher = match (self.config_path, self.tools.config.config_watch_interval_secs) {
+            (Some(path), Some(secs)) => {
+                let config = ServerConfig::clone(&self.tools.config);
+                Some(tokio::spawn(watch_config(path, Duration::from_secs(secs.max(1)), store, config)))
+            }
+            _ => None,
+        };
+        let connections = Connections::from_config(&self.tools.config);
+        let app = axum_router(self.tools);
+        let serve = connections.serve(self.listener, app, shutdown.clone());
+        let deadline = async {
+            let drain = shutdown.requested().await;
+            tokio::time::sleep(drain).await;
+        };
+        tokio::select! {
+            res = serve => res?,
+            _ = deadline => tracing::warn!("drain deadline passed, dropping in-flight requests"),
+        }
+        for task in [sweeper, watcher].into_iter().flatten() {
+            task.abort();
+        }
+        for hook in self.hooks {
+            hook();
+        }
+        Ok(())
+    }
+}
+
+/// How accepted connections are served: the protocols spoken and the socket and keep-alive
+/// tuning from the config. With the defaults this is what `axum::serve` does.
+struct Connections {
+    http2: bool,
+    http1_keepalive_timeout: Option<Duration>,
+    http2_max_concurrent_streams: Option<u32>,
+    tcp_nodelay: bool,
+}
+
+impl Connections {
+    fn from_config(config: &ServerConfig) -> Self {
+        Connections {
+            http2: config.http2_enabled,
+            http1_keepalive_timeout: config.http1_keepalive_timeout_secs.map(Duration::from_secs),
+            http2_max_concurrent_streams: config.http2_max_concurrent_streams,
+            tcp_nodelay: config.tcp_nodelay,
+        }
+    }
+
+    fn builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        if let Some(timeout) = self.http1_keepalive_timeout {
+            builder.http1().timer(TokioTimer::new()).header_read_timeout(timeout);
+        }
+        builder.http2().max_concurrent_streams(self.http2_max_concurrent_streams);
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+
+    /// Accepts connections until shutdown is requested, then waits for the open ones to finish
+    /// their in-flight requests.
+    async fn serve(self, listener: TcpListener, app: Router, shutdown: ShutdownHandle) -> io::Result<()> {
+        // every connection task holds a receiver; `closed()` resolves once all of them are gone
+        let (close_tx, close_rx) = watch::channel(());
+        loop {
+            let (stream, remote_addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // e.g. out of file descriptors: back off instead of spinning
+                        tracing::warn!("failed to accept a connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        continue;
+                    }
+                },
+                _ = shutdown.requested() => break,
+            };
+            if self.tcp_nodelay {
+                let _ = stream.set_nodelay(true);
+            }
+            let service = app.clone().map_request(move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(remote_addr));
+                req.map(Body::new)
+            });
+            let builder = self.builder();
+            let (shutdown, close_rx) = (shutdown.clone(), close_rx.clone());
+            tokio::spawn(async move {
+                // no upgrades: nothing uses them, and `http1_only` only applies without them
+                let conn = builder.serve_connection(TokioIo::new(stream), TowerToHyperService::new(service));
+                tokio::pin!(conn);
+                tokio::select! {
+                    res = conn.as_mut() => {
+                        if let Err(e) = res {
+                            tracing::trace!("failed to serve connection: {:#}", e);
+                        }
+                    }
+                    _ = shutdown.requested() => {
+                        conn.as_mut().graceful_shutdown();
+                        let _ = conn.await;
+                    }
+                }
+                drop(close_rx);
+            });
+        }
+        drop(close_rx);
+        drop(listener);
+        close_tx.closed().await;
+        Ok(())
+    }
+}
+
+/// Removes keys idle for longer than `max_idle`, checking every quarter of it (between a
+/// second and a minute). Shards are swept one at a time.
+async fn sweep_idle(store: Arc<ShardedStores>, max_idle: Duration) {
+    let period = (max_idle / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+    let mut ticker = tokio::time::interval(period);
+    loop {
+        ticker.tick().await;
+        let mut removed = 0;
+        for shard in store.shards() {
+            let mut stores = shard.write().await;
+            removed += tracing::debug_span!("sweep_idle").in_scope(|| stores.pop_idle(max_idle, TokioClock.now()));
+        }
+        if removed > 0 {
+            tracing::debug!(removed, "removed idle keys");
+        }
+    }
+}
+
+/// Creates `cache_shards` shards of the stores of `config`, each with its share of every bound.
+pub(crate) fn build_sharded_stores(config: &ServerConfig) -> io::Result<ShardedStores> {
+    config.check_chunk_size().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    config.check_cache_shards().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let shards = (0..config.cache_shards).map(|i| build_stores(&shard_config(config, i))).collect::<io::Result<_>>()?;
+    Ok(ShardedStores::new(shards))
+}
+
+// `config` with the bounds cut to the share of shard `i`
+fn shard_config(config: &ServerConfig, i: usize) -> ServerConfig {
+    let share = |total| shard_share(total, config.cache_shards, i);
+    let mut shard = config.clone();
+    shard.cache_size = share(config.cache_size);
+    for ns in shard.namespaces.values_mut() {
+        ns.cache_size = share(ns.cache_size);
+        ns.cache_bytes = ns.cache_bytes.map(share);
+    }
+    shard
+}
+
+/// Creates the default store and one store per configured namespace, or tells which
+/// `cache_size` they cannot be built with.
+pub(crate) fn build_stores(config: &ServerConfig) -> io::Result<Stores> {
+    config.check_cache_modes().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    config.check_ttl_mode().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let invalid = |key: String, e: CacheError| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", key, e));
+    let mut stores = Stores::new(build_store(config).map_err(|e| invalid("cache_size".to_string(), e))?);
+    for (name, ns) in &config.namespaces {
+        let store = build_namespace_store(ns, config).map_err(|e| invalid(format!("namespaces.{}.cache_size", name), e))?;
+        stores = stores.with_namespace(name.clone(), store);
+    }
+    Ok(stores)
+}
+
+/// Creates the store for `config.cache_mode`, whose index is the boxed cache of the mode's
+/// policy. In capacity mode the index is unbounded and the store enforces the byte budget
+/// itself. In ttl mode it is an unbounded `TTLCache` of `ttl_seconds`, so that keys leave by
+/// age alone; an upload's own TTL only makes its key leave sooner. Fifo mode bounds the number
+/// of items like item mode, but reads do not promote; lfu mode bounds it with an `LFUCache`,
+/// which evicts the least frequently used key.
+fn build_store(config: &ServerConfig) -> Result<BlobStore, CacheError> {
+    let cache_size = config.cache_size;
+    let (index, byte_budget): (Box<dyn BlobIndex>, _) = match config.cache_mode.as_str() {
+        "item" | "default" | "fifo" => (Box::new(LRUCache::try_new(cache_size)?), None),
+        "lfu" => (Box::new(LFUCache::new(NonZeroUsize::new(cache_size).ok_or(CacheError::ZeroCapacity)?)), None),
+        "capacity" if cache_size == 0 => return Err(CacheError::ZeroCapacity),
+        "capacity" => (Box::new(LRUCache::unbounded()), Some(cache_size)),
+        "unlimited" => (Box::new(LRUCache::unbounded()), None),
+        "ttl" => {
+            let ttl = config.ttl_seconds.expect("build_stores checked ttl_seconds");
+            (Box::new(TTLCache::new(Duration::from_secs(ttl))), None)
+        }
+        other => unreachable!("build_stores refused cache_mode {:?}", other),
+    };
+    let store = BlobStore::new(index, byte_budget);
+    let limits = KeyLimits {
+        max_keys: config.max_keys,
+        max_keys_per_namespace: config.max_keys_per_namespace,
+        on_limit: config.on_limit,
+    };
+    let mut store = store
+        .with_size_buckets(config.size_buckets_bytes.clone())
+        .with_read_promotion(config.cache_mode != "fifo")
+        .with_key_limits(limits)
+        .with_prefix_quotas(config.prefix_quotas.clone());
+    store.set_clock(&TokioClock);
+    Ok(store)
+}
+
+/// systemd socket activation (`sd_listen_fds(3)`): inherited sockets start at fd 3 and are
+/// announced through `LISTEN_FDS`, for the process named by `LISTEN_PID`.
+#[cfg(unix)]
+mod activation {
+    use std::io;
+    use std::os::fd::{FromRawFd, RawFd};
+    use tokio::net::TcpListener;
+
+    const LISTEN_FDS_START: RawFd = 3;
+
+    /// The first inherited socket, when this process was socket activated.
+    pub fn listen_fd() -> Option<RawFd> {
+        let pid = std::env::var("LISTEN_PID").ok();
+        let fds = std::env::var("LISTEN_FDS").ok();
+        passed_fd(pid.as_deref(), fds.as_deref(), std::process::id())
+    }
+
+    // the variables are inherited by children too, so they only count when addressed to us
+    fn passed_fd(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Option<RawFd> {
+        let listen_pid: u32 = listen_pid?.trim().parse().ok()?;
+        let listen_fds: u32 = listen_fds?.trim().parse().ok()?;
+        (listen_pid == pid && listen_fds >= 1).then_some(LISTEN_FDS_START)
+    }
+
+    /// Turns an inherited listening TCP socket into a tokio listener. Sockets that are not
+    /// TCP, such as unix sockets, are rejected.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open descriptor owned by nobody else; it is closed with the listener.
+    pub unsafe fn adopt(fd: RawFd) -> io::Result<TcpListener> {
+        let listener = std::net::TcpListener::from_raw_fd(fd);
+        if listener.local_addr().is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("inherited fd {} is not a TCP listening socket", fd)));
+        }
+        // tokio requires non-blocking sockets, and systemd passes them blocking
+        listener.set_nonblocking(true)?;
+        TcpListener::from_std(listener)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{adopt, passed_fd, LISTEN_FDS_START};
+        use crate::http::server::Server;
+        use crate::settings::ServerConfig;
+        use std::os::fd::IntoRawFd;
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        #[test]
+        fn test_passed_fd() {
+            assert_eq!(passed_fd(Some("42"), Some("1"), 42), Some(LISTEN_FDS_START));
+            assert_eq!(passed_fd(Some("42"), Some("2"), 42), Some(LISTEN_FDS_START));
+            assert_eq!(passed_fd(Some("41"), Some("1"), 42), None);
+            assert_eq!(passed_fd(Some("42"), Some("0"), 42), None);
+            assert_eq!(passed_fd(None, Some("1"), 42), None);
+            assert_eq!(passed_fd(Some("42"), Some("x"), 42), None);
+        }
+
+        #[tokio::test]
+        async fn test_serves_on_inherited_socket() {
+            // a blocking listener bound elsewhere stands in for the socket systemd would pass
+            let inherited = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = inherited.local_addr().unwrap();
+            let listener = unsafe { adopt(inherited.into_raw_fd()).unwrap() };
+
+            let server = Server::with_listener(ServerConfig::default(), listener).await.unwrap();
+            let shutdown = server.shutdown_handle();
+            let handle = tokio::spawn(server.serve());
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"GET /api/lru/admin/info HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+            shutdown.shutdown(Duration::from_secs(1));
+            tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_rejects_non_tcp_socket() {
+            let (socket, _peer) = std::os::unix::net::UnixStream::pair().unwrap();
+            assert!(unsafe { adopt(socket.into_raw_fd()) }.is_err());
+        }
+    }
+}
+
+// like `build_store`, without key limits: the namespace's own mode bounds it
+fn build_namespace_store(ns: &NamespaceConfig, config: &ServerConfig) -> Result<BlobStore, CacheError> {
+    let (index, byte_budget): (Box<dyn BlobIndex>, _) = match ns.cache_mode.as_str() {
+        "capacity" => (Box::new(LRUCache::unbounded()), Some(ns.cache_bytes.unwrap_or(ns.cache_size))),
+        "unlimited" => (Box::new(LRUCache::unbounded()), ns.cache_bytes),
+        "item" | "default" | "fifo" => (Box::new(LRUCache::try_new(ns.cache_size)?), ns.cache_bytes),
+        "lfu" => (Box::new(LFUCache::new(NonZeroUsize::new(ns.cache_size).ok_or(CacheError::ZeroCapacity)?)), ns.cache_bytes),
+        other => unreachable!("build_stores refused cache_mode {:?}", other),
+    };
+    let store = BlobStore::new(index, byte_budget);
+    let mut store = store
+        .with_size_buckets(config.size_buckets_bytes.clone())
+        .with_default_ttl(ns.default_ttl_secs.map(Duration::from_secs))
+        .with_read_promotion(ns.cache_mode != "fifo")
+        .with_prefix_quotas(config.prefix_quotas.clone());
+    store.set_clock(&TokioClock);
+    Ok(store)
+}
+
+/// Resolves on Ctrl-C, and on SIGTERM on unix.
+pub async fn termination_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_sharded_stores, build_stores, sweep_idle, Server};
+    use crate::http::store::{sharded_stats, BlobMeta};
+    use axum::body::Body;
+    use axum::http::Version;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use crate::settings::{NamespaceConfig, ServerConfig};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn raw_request(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    async fn start(config: ServerConfig) -> (SocketAddr, Arc<AtomicBool>, tokio::task::JoinHandle<std::io::Result<()>>) {
+        let mut server = Server::bind_to(config, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let flushed = Arc::new(AtomicBool::new(false));
+        let hook_flag = flushed.clone();
+        server.on_shutdown(move || hook_flag.store(true, Ordering::SeqCst));
+        (addr, flushed, tokio::spawn(server.serve()))
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_endpoint_stops_server() {
+        let config = ServerConfig { api_token: Some("t".to_string()), ..ServerConfig::default() };
+        let (addr, flushed, handle) = start(config).await;
+
+        let body = r#"{"drain_secs":5}"#;
+        let unauthorized = format!(
+            "POST /api/lru/admin/shutdown HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        assert!(raw_request(addr, &unauthorized).await.starts_with("HTTP/1.1 401"));
+        assert!(!handle.is_finished());
+
+        let authorized = unauthorized.replace("Host: x\r\n", "Host: x\r\nAuthorization: Bearer t\r\n");
+        assert!(raw_request(addr, &authorized).await.starts_with("HTTP/1.1 202"));
+
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+        assert!(flushed.load(Ordering::SeqCst));
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_handle_without_body() {
+        let (addr, flushed, handle) = start(ServerConfig::default()).await;
+        let request = "POST /api/lru/admin/shutdown HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+        assert!(raw_request(addr, request).await.starts_with("HTTP/1.1 202"));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+        assert!(flushed.load(Ordering::SeqCst));
+    }
+
+    // sends an HTTP/2 request with prior knowledge, as h2c clients do
+    async fn h2_get(addr: SocketAddr, path: &str) -> Result<axum::http::Response<hyper::body::Incoming>, hyper::Error> {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+        tokio::spawn(conn);
+        let req = axum::http::Request::get(format!("http://{}{}", addr, path)).body(Body::empty()).unwrap();
+        sender.send_request(req).await
+    }
+
+    #[tokio::test]
+    async fn test_h2c_prior_knowledge() {
+        let config = ServerConfig { http2_enabled: true, http2_max_concurrent_streams: Some(8), tcp_nodelay: true, ..ServerConfig::default() };
+        let (addr, _, handle) = start(config).await;
+        let res = h2_get(addr, "/api/lru/admin/info").await.unwrap();
+        assert_eq!(res.version(), Version::HTTP_2);
+        assert_eq!(res.status(), 200);
+        // HTTP/1.1 keeps working next to it
+        let response = raw_request(addr, "GET /api/lru/admin/info HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        handle.abort();
+
+        let (addr, _, handle) = start(ServerConfig::default()).await;
+        assert!(h2_get(addr, "/api/lru/admin/info").await.is_err());
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_http1_keepalive_timeout_closes_idle_connections() {
+        let (addr, _, handle) = start(ServerConfig { http1_keepalive_timeout_secs: Some(1), ..ServerConfig::default() }).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /api/lru/admin/info HTTP/1.1\r\nHost: x\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 200"));
+        // the connection stays open for the next request, then goes once it sat idle too long
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            while stream.read(&mut buf).await.unwrap() > 0 {}
+        });
+        closed.await.unwrap();
+        handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_require_socket_activation_without_socket() {
+        // the test runner is never socket activated
+        let config = ServerConfig { require_socket_activation: true, ..ServerConfig::default() };
+        let err = Server::bind(config).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_zero_cache_size_fails_at_startup() {
+        for cache_mode in ["item", "capacity"] {
+            let config = ServerConfig { cache_mode: cache_mode.to_string(), cache_size: 0, ..ServerConfig::default() };
+            let err = Server::bind_to(config, "127.0.0.1:0".parse().unwrap()).await.err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), "cache_size: capacity must be positive");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zero_chunk_size_fails_at_startup() {
+        let config = ServerConfig { chunk_size_bytes: 0, ..ServerConfig::default() };
+        let err = Server::bind_to(config, "127.0.0.1:0".parse().unwrap()).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "chunk_size_bytes must be positive");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_cache_mode_fails_at_startup() {
+        let config = ServerConfig { cache_mode: "arc".to_string(), ..ServerConfig::default() };
+        let err = Server::bind_to(config, "127.0.0.1:0".parse().unwrap()).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(err.to_string().starts_with("cache_mode: unknown mode \"arc\""), "{}", err);
+
+        // a namespace has no ttl mode
+        let ns = NamespaceConfig { cache_mode: "ttl".to_string(), ..NamespaceConfig::default() };
+        let config = ServerConfig { namespaces: [("meta".to_string(), ns)].into(), ..ServerConfig::default() };
+        let err = build_stores(&config).err().unwrap();
+        assert!(err.to_string().starts_with("namespaces.meta.cache_mode: unknown mode \"ttl\""), "{}", err);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sweep_removes_idle_keys() {
+        let config = ServerConfig { max_idle_secs: Some(60), cache_shards: 2, ..ServerConfig::default() };
+        let store = Arc::new(build_sharded_stores(&config).unwrap());
+        let busy = || async { store.shard("busy").write().await.for_key_mut("busy").get("busy").is_some() };
+        for key in ["idle", "busy"] {
+            let mut stores = store.shard(key).write().await;
+            stores.for_key_mut(key).insert(key.to_string(), [0; 32], vec![1u8].into(), BlobMeta::default()).unwrap();
+        }
+        let sweeper = tokio::spawn(sweep_idle(store.clone(), Duration::from_secs(60)));
+
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert!(busy().await);
+        tokio::time::sleep(Duration::from_secs(40)).await;
+
+        let shards = store.read_all(None).await.unwrap();
+        let stats = sharded_stats(&shards);
+        drop(shards);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.expired, 1);
+        assert!(busy().await);
+        sweeper.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_mode_expires_by_age() {
+        let config = ServerConfig { cache_mode: "ttl".to_string(), ttl_seconds: Some(60), ..ServerConfig::default() };
+        let mut stores = build_stores(&config).unwrap();
+        let store = stores.for_key_mut("a");
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            store.insert(key.to_string(), [0; 32], vec![1u8].into(), BlobMeta::default()).unwrap();
+        }
+        assert_eq!(store.len(), 6);
+
+        // an upload's own TTL can make its key leave sooner, not later
+        let meta = |secs| BlobMeta { ttl: Some(Duration::from_secs(secs)), ..BlobMeta::default() };
+        store.insert("short".to_string(), [1; 32], vec![2u8].into(), meta(10)).unwrap();
+        store.insert("long".to_string(), [1; 32], vec![2u8].into(), meta(600)).unwrap();
+        assert_eq!(store.ttl("long"), Some(Duration::from_secs(60)));
+
+        tokio::time::sleep(Duration::from_secs(40)).await;
+        assert!(store.get("a").is_some());
+        assert!(store.get("short").is_none());
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(store.get("a").is_none());
+        assert!(store.get("long").is_none());
+        // the keys the index let go released their values
+        assert_eq!((store.len(), store.distinct_values(), store.stats().expired), (0, 0, 8));
+
+        let untimed = ServerConfig { ttl_seconds: None, ..config };
+        let err = build_stores(&untimed).unwrap_err();
+        assert_eq!(err.to_string(), "ttl mode needs a positive ttl_seconds");
+    }
+
+    #[test]
+    fn test_lfu_mode_keeps_a_hot_key_through_a_scan() {
+        let hot_survives = |config: &ServerConfig, namespace: &str| {
+            let mut stores = build_stores(config).unwrap();
+            let key = |name: String| format!("{}{}", namespace, name);
+            let store = stores.for_key_mut(&key("hot".to_string()));
+            store.insert(key("hot".to_string()), [0; 32], vec![0u8].into(), BlobMeta::default()).unwrap();
+            store.get(&key("hot".to_string()));
+            for i in 1..50u8 {
+                store.insert(key(i.to_string()), [i; 32], vec![i].into(), BlobMeta::default()).unwrap();
+            }
+            assert_eq!(store.len(), 3);
+            // the evicted keys released their values
+            assert_eq!(store.distinct_values(), 3);
+            store.peek(&key("hot".to_string())).is_some()
+        };
+        let lfu = ServerConfig { cache_mode: "lfu".to_string(), cache_size: 3, ..ServerConfig::default() };
+        let item = ServerConfig { cache_mode: "item".to_string(), ..lfu.clone() };
+        assert!(hot_survives(&lfu, ""));
+        assert!(!hot_survives(&item, ""));
+
+        // an item-count LFU namespace next to the LRU default store
+        let ns = NamespaceConfig { cache_mode: "lfu".to_string(), cache_size: 3, ..NamespaceConfig::default() };
+        let config = ServerConfig { namespaces: [("meta".to_string(), ns)].into(), ..ServerConfig::default() };
+        assert!(hot_survives(&config, "meta/"));
+        let stats = build_stores(&config).unwrap().stats();
+        assert_eq!(stats.namespaces["meta"].cache.as_ref().unwrap().capacity, Some(3));
+    }
+
+    #[test]
+    fn test_fifo_mode_ignores_reads() {
+        let fifo = ServerConfig { cache_mode: "fifo".to_string(), cache_size: 2, ..ServerConfig::default() };
+        let item = ServerConfig { cache_mode: "item".to_string(), ..fifo.clone() };
+        let survivors = |config: &ServerConfig| {
+            let mut stores = build_stores(config).unwrap();
+            let store = stores.for_key_mut("a");
+            store.insert("a".to_string(), [0; 32], vec![1u8].into(), BlobMeta::default()).unwrap();
+            store.insert("b".to_string(), [1; 32], vec![2u8].into(), BlobMeta::default()).unwrap();
+            store.get("a");
+            store.insert("c".to_string(), [2; 32], vec![3u8].into(), BlobMeta::default()).unwrap();
+            (store.peek("a").is_some(), store.peek("b").is_some())
+        };
+        assert_eq!(survivors(&fifo), (false, true));
+        assert_eq!(survivors(&item), (true, false));
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline_bounds_shutdown() {
+        let (addr, flushed, handle) = start(ServerConfig::default()).await;
+
+        // an upload whose body never finishes keeps a request in flight
+        let mut stalled = TcpStream::connect(addr).await.unwrap();
+        let head = "POST /api/lru HTTP/1.1\r\nHost: x\r\nContent-Type: multipart/form-data; boundary=B\r\nContent-Length: 1000\r\n\r\n--B\r\n";
+        stalled.write_all(head.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let request = "POST /api/lru/admin/shutdown HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: 16\r\n\r\n{\"drain_secs\":1}";
+        assert!(raw_request(addr, request).await.starts_with("HTTP/1.1 202"));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!handle.is_finished());
+
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+        assert!(flushed.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::http::store::StoreStats;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Server-wide counters shared by every handler.
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    panics_total: AtomicU64,
+    lock_shed_total: AtomicU64,
+}
+
+impl ServerStats {
+    pub fn inc_panics(&self) { self.panics_total.fetch_add(1, Ordering::Relaxed); }
+
+    pub fn inc_lock_shed(&self) { self.lock_shed_total.fetch_add(1, Ordering::Relaxed); }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            panics_total: self.panics_total.load(Ordering::Relaxed),
+            lock_shed_total: self.lock_shed_total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSnapshot {
+    pub panics_total: u64,
+    /// Requests answered `LOCK_BUSY` because the store lock was not granted in time.
+    pub lock_shed_total: u64,
+}
+
+/// Default upper bounds of the value-size histogram: 1K, 16K, 256K, 4M and 64M.
+pub const DEFAULT_SIZE_BUCKETS: [usize; 5] = [1 << 10, 1 << 14, 1 << 18, 1 << 22, 1 << 26];
+
+/// Number of stored values per size range. Bucket `i` counts sizes up to and including
+/// `bounds[i]` that did not fit a smaller bucket; the last bucket counts everything larger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeHistogram {
+    bounds: Vec<usize>,
+    counts: Vec<u64>,
+    sum: u64,
+}
+
+impl SizeHistogram {
+    /// Bounds are sorted and deduplicated, so the config may list them in any order.
+    pub fn new(mut bounds: Vec<usize>) -> Self {
+        bounds.sort_unstable();
+        bounds.dedup();
+        let counts = vec![0; bounds.len() + 1];
+        SizeHistogram { bounds, counts, sum: 0 }
+    }
+
+    fn bucket(&self, size: usize) -> usize { self.bounds.partition_point(|&b| b < size) }
+
+    pub fn record(&mut self, size: usize) {
+        let i = self.bucket(size);
+        self.counts[i] += 1;
+        self.sum += size as u64;
+    }
+
+    /// Forgets a value previously passed to `record`.
+    pub fn unrecord(&mut self, size: usize) {
+        let i = self.bucket(size);
+        self.counts[i] -= 1;
+        self.sum -= size as u64;
+    }
+
+    /// Adds the counts of `other`, which must have the same bounds.
+    pub fn merge(&mut self, other: &SizeHistogram) {
+        debug_assert_eq!(self.bounds, other.bounds);
+        self.counts.iter_mut().zip(&other.counts).for_each(|(count, other)| *count += other);
+        self.sum += other.sum;
+    }
+
+    pub fn bounds(&self) -> &[usize] { &self.bounds }
+
+    /// Per-bucket (not cumulative) counts; one more than `bounds`.
+    pub fn counts(&self) -> &[u64] { &self.counts }
+
+    pub fn count(&self) -> u64 { self.counts.iter().sum() }
+
+    pub fn sum(&self) -> u64 { self.sum }
+}
+
+impl Default for SizeHistogram {
+    fn default() -> Self { SizeHistogram::new(DEFAULT_SIZE_BUCKETS.to_vec()) }
+}
+
+impl Serialize for SizeHistogram {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Bucket {
+            le: String,
+            count: u64,
+        }
+        #[derive(Serialize)]
+        struct Repr {
+            buckets: Vec<Bucket>,
+            count: u64,
+            sum: u64,
+        }
+        let buckets = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| Bucket { le: le_label(self.bounds.get(i)), count })
+            .collect();
+        Repr { buckets, count: self.count(), sum: self.sum }.serialize(serializer)
+    }
+}
+
+fn le_label(bound: Option<&usize>) -> String { bound.map_or("+Inf".to_string(), usize::to_string) }
+
+/// Renders the counters in the Prometheus text exposition format.
+pub fn render_prometheus(server: &StatsSnapshot, store: &StoreStats) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+    };
+    metric("see_panics_total", "counter", "Handler panics caught by the server.", server.panics_total);
+    metric("see_lock_shed_total", "counter", "Requests shed because the store lock was busy.", server.lock_shed_total);
+    metric("see_entries", "gauge", "Keys currently stored.", store.entries as u64);
+    metric("see_logical_bytes", "gauge", "Stored bytes counting shared values once per key.", store.logical_bytes as u64);
+    metric("see_physical_bytes", "gauge", "Stored bytes counting shared values once.", store.physical_bytes as u64);
+    metric("see_expired_total", "counter", "Keys removed for an elapsed TTL or idle time.", store.expired);
+
+    let sizes = &store.value_sizes;
+    let name = "see_value_size_bytes";
+    let _ = writeln!(out, "# HELP {} Sizes of stored values.\n# TYPE {} histogram", name, name);
+    let mut cumulative = 0;
+    for (i, count) in sizes.counts().iter().enumerate() {
+        cumulative += count;
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le_label(sizes.bounds().get(i)), cumulative);
+    }
+    let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, sizes.sum(), name, sizes.count());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_prometheus, SizeHistogram, StatsSnapshot};
+    use crate::http::store::StoreStats;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut sizes = SizeHistogram::new(vec![1024, 16]);
+        assert_eq!(sizes.bounds(), &[16, 1024]);
+        for size in [0, 16, 17, 1024, 1025, 1 << 20] {
+            sizes.record(size);
+        }
+        assert_eq!(sizes.counts(), &[2, 2, 2]);
+        assert_eq!(sizes.count(), 6);
+
+        sizes.unrecord(1 << 20);
+        assert_eq!(sizes.counts(), &[2, 2, 1]);
+        assert_eq!(sizes.sum(), 16 + 17 + 1024 + 1025);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let mut sizes = SizeHistogram::new(vec![16, 1024]);
+        sizes.record(10);
+        sizes.record(100);
+        sizes.record(5000);
+        let store = StoreStats { entries: 3, distinct_values: 3, logical_bytes: 5110, physical_bytes: 5110, value_sizes: sizes, expired: 0, first_key: None, last_key: None, namespaces: Default::default(), prefixes: Vec::new() };
+        let text = render_prometheus(&StatsSnapshot { panics_total: 2, lock_shed_total: 0 }, &store);
+        assert!(text.contains("# TYPE see_value_size_bytes histogram\n"));
+        assert!(text.contains("see_value_size_bytes_bucket{le=\"16\"} 1\n"));
+        assert!(text.contains("see_value_size_bytes_bucket{le=\"1024\"} 2\n"));
+        assert!(text.contains("see_value_size_bytes_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("see_value_size_bytes_sum 5110\nsee_value_size_bytes_count 3\n"));
+        assert!(text.contains("see_panics_total 2\n"));
+    }
+}
@@ -0,0 +1,1328 @@
+use crate::http::common::LockBusy;
+use crate::http::stats::SizeHistogram;
+use crate::lru::cache::{Cache, DefaultHasher, DynCache};
+use crate::lru::chunked_bytes::ChunkedBytes;
+use crate::lru::clock::{Clock, DEFAULT_CLOCK};
+use crate::lru::error::CacheError;
+use crate::lru::item_size::ItemSize;
+use crate::lru::lfu_cache::LFUCache;
+use crate::lru::lru_cache::LRUCache;
+use crate::lru::ttl_cache::TTLCache;
+use crate::lru::sharded_cache::{shard_of, shard_share};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// SHA-256 of a stored value, used to address its bytes.
+pub type ContentHash = [u8; 32];
+
+/// Bytes charged against the byte budget for every key on top of the key itself.
+pub const ENTRY_OVERHEAD: usize = 64;
+
+/// How an entry's TTL behaves when it is downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtlMode {
+    /// Expires `ttl` after upload no matter how often it is read.
+    #[default]
+    Fixed,
+    /// Every download pushes the expiry out by the original TTL, capped by `max_ttl_secs`.
+    Sliding,
+}
+
+/// What happens to a write that would push a key count past its limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnLimit {
+    /// Evict the least recently used key of the full scope.
+    #[default]
+    Evict,
+    /// Refuse the write with `StoreError::LimitExceeded`.
+    Reject,
+}
+
+/// Ceilings on the number of keys, globally and per namespace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyLimits {
+    pub max_keys: Option<usize>,
+    pub max_keys_per_namespace: Option<usize>,
+    pub on_limit: OnLimit,
+}
+
+/// A ceiling on the keys sharing a prefix, so that one key family cannot take over a store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrefixQuota {
+    pub prefix: String,
+    pub max_entries: Option<usize>,
+    /// Bound on the value bytes stored under the prefix.
+    pub max_bytes: Option<usize>,
+}
+
+/// A `PrefixQuota` and what its keys currently hold.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefixUsage {
+    pub prefix: String,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
+
+impl PrefixUsage {
+    fn exceeded_by(&self, entries: usize, bytes: usize) -> bool {
+        self.max_entries.is_some_and(|max| entries > max) || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+/// The namespace of `key`: everything before the first `/`, or `""` for keys without one.
+pub fn namespace_of(key: &str) -> &str { key.split_once('/').map_or("", |(ns, _)| ns) }
+
+/// Keys in stats are cut to this many characters.
+pub const MAX_REPORTED_KEY_CHARS: usize = 64;
+
+fn reported_key(key: &str) -> String {
+    match key.char_indices().nth(MAX_REPORTED_KEY_CHARS) {
+        Some((end, _)) => format!("{}…", &key[..end]),
+        None => key.to_string(),
+    }
+}
+
+/// Per-entry metadata chosen at upload time.
+#[derive(Debug, Clone, Default)]
+pub struct BlobMeta {
+    pub ttl: Option<Duration>,
+    pub ttl_mode: TtlMode,
+    /// Upload time in unix seconds, served as `Last-Modified`.
+    pub last_modified: u64,
+}
+
+/// An index entry of the HTTP layer: the value itself lives in the content table.
+#[derive(Debug, Clone)]
+pub struct CachedBlob {
+    pub hash: ContentHash,
+    pub len: usize,
+    pub meta: BlobMeta,
+    /// When the key was last uploaded or promoted by a download, see `BlobStore::pop_idle`.
+    pub used_at: Instant,
+    /// When `meta.ttl` runs out, moved by `BlobStore::extend_ttl`.
+    pub expires_at: Option<Instant>,
+}
+
+impl CachedBlob {
+    /// Strong entity tag derived from the content hash.
+    pub fn etag(&self) -> String { format!("\"{}\"", hex::encode(self.hash)) }
+
+    fn is_expired(&self, now: Instant) -> bool { self.expires_at.is_some_and(|deadline| deadline <= now) }
+}
+
+impl ItemSize for CachedBlob {
+    fn size_of(&self) -> usize { ENTRY_OVERHEAD }
+}
+
+/// The key index of a `BlobStore`, whose policy the server picks from `cache_mode`: a
+/// `DynCache` looked up by `&str`, plus what the store needs on top. The TTLs of uploads are
+/// kept by the store in `CachedBlob::expires_at`, so that every policy honors them; a
+/// `TTLCache` also expires every key its own `ttl` after it was put, whichever comes first.
+pub trait BlobIndex: DynCache<String, CachedBlob, str> + Send + Sync {
+    /// See `Cache::peek`.
+    fn peek(&self, key: &str) -> Option<&CachedBlob>;
+
+    /// See `Cache::peek_mut`.
+    fn peek_mut(&mut self, key: &str) -> Option<&mut CachedBlob>;
+
+    /// See `Cache::get_mut`: counts a use of `key`, the way the policy does.
+    fn get_mut(&mut self, key: &str) -> Option<&mut CachedBlob>;
+
+    /// See `Cache::push`.
+    fn push(&mut self, key: String, blob: CachedBlob) -> Option<(String, CachedBlob)>;
+
+    /// See `Cache::pop_entry`.
+    fn pop_entry(&mut self, key: &str) -> Option<(String, CachedBlob)>;
+
+    /// See `Cache::peek_last`.
+    fn peek_last(&self) -> Option<(&String, &CachedBlob)>;
+
+    /// See `Cache::first_key`.
+    fn first_key(&self) -> Option<&String>;
+
+    /// See `Cache::last_key`.
+    fn last_key(&self) -> Option<&String>;
+
+    /// The entries from the next to be evicted on.
+    fn iter_from_last(&self) -> Box<dyn Iterator<Item = (&String, &CachedBlob)> + '_>;
+
+    /// Whether the entries are kept in use order, so that the idle ones are all at the back.
+    fn in_use_order(&self) -> bool { false }
+
+    /// See `Cache::limit`.
+    fn limit(&self) -> Option<NonZeroUsize>;
+
+    /// Bounds the number of keys to `cap`, and returns the entries evicted to fit.
+    fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(String, CachedBlob)>;
+
+    /// Reserves room for `additional` more keys, failing instead of aborting.
+    fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError>;
+
+    /// Makes the index keep the entries it lets go by age for `pop_expired` rather than drop
+    /// them, so that the store can release their contents. Called by `BlobStore::new`.
+    fn keep_expired(&mut self) {}
+
+    /// Removes and returns the entries the policy let go by age. Only a `TTLCache` has any.
+    fn pop_expired(&mut self) -> Vec<(String, CachedBlob)> { Vec::new() }
+
+    /// How long the policy keeps `key` before it expires by age, if it does.
+    fn time_left(&self, _key: &str) -> Option<Duration> { None }
+
+    /// Replaces the clock the index reads, if it reads one.
+    fn set_clock(&mut self, _clock: &'static dyn Clock) {}
+}
+
+impl fmt::Debug for dyn BlobIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlobIndex").field("len", &self.len()).field("limit", &self.limit()).finish()
+    }
+}
+
+impl BlobIndex for LRUCache<String, CachedBlob> {
+    fn peek(&self, key: &str) -> Option<&CachedBlob> { Cache::peek(self, key) }
+
+    fn peek_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::peek_mut(self, key) }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::get_mut(self, key) }
+
+    fn push(&mut self, key: String, blob: CachedBlob) -> Option<(String, CachedBlob)> { Cache::push(self, key, blob) }
+
+    fn pop_entry(&mut self, key: &str) -> Option<(String, CachedBlob)> { Cache::pop_entry(self, key) }
+
+    fn peek_last(&self) -> Option<(&String, &CachedBlob)> { Cache::peek_last(self) }
+
+    fn first_key(&self) -> Option<&String> { Cache::first_key(self) }
+
+    fn last_key(&self) -> Option<&String> { Cache::last_key(self) }
+
+    fn iter_from_last(&self) -> Box<dyn Iterator<Item = (&String, &CachedBlob)> + '_> { Box::new(self.iter().rev()) }
+
+    fn in_use_order(&self) -> bool { true }
+
+    fn limit(&self) -> Option<NonZeroUsize> { Cache::limit(self) }
+
+    fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(String, CachedBlob)> { LRUCache::resize_with_evicted(self, cap) }
+
+    fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> { LRUCache::try_reserve(self, additional) }
+
+    fn set_clock(&mut self, clock: &'static dyn Clock) { LRUCache::set_clock(self, clock) }
+}
+
+impl BlobIndex for LFUCache<String, CachedBlob> {
+    fn peek(&self, key: &str) -> Option<&CachedBlob> { Cache::peek(self, key) }
+
+    fn peek_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::peek_mut(self, key) }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::get_mut(self, key) }
+
+    fn push(&mut self, key: String, blob: CachedBlob) -> Option<(String, CachedBlob)> { Cache::push(self, key, blob) }
+
+    fn pop_entry(&mut self, key: &str) -> Option<(String, CachedBlob)> { Cache::pop_entry(self, key) }
+
+    fn peek_last(&self) -> Option<(&String, &CachedBlob)> { Cache::peek_last(self) }
+
+    fn first_key(&self) -> Option<&String> { Cache::first_key(self) }
+
+    fn last_key(&self) -> Option<&String> { Cache::last_key(self) }
+
+    fn iter_from_last(&self) -> Box<dyn Iterator<Item = (&String, &CachedBlob)> + '_> { Box::new(LFUCache::iter_from_last(self)) }
+
+    fn limit(&self) -> Option<NonZeroUsize> { Cache::limit(self) }
+
+    fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(String, CachedBlob)> { LFUCache::resize_with_evicted(self, cap) }
+
+    fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> { LFUCache::try_reserve(self, additional) }
+}
+
+impl BlobIndex for TTLCache<String, CachedBlob> {
+    fn peek(&self, key: &str) -> Option<&CachedBlob> { Cache::peek(self, key) }
+
+    fn peek_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::peek_mut(self, key) }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut CachedBlob> { Cache::get_mut(self, key) }
+
+    fn push(&mut self, key: String, blob: CachedBlob) -> Option<(String, CachedBlob)> { Cache::push(self, key, blob) }
+
+    fn pop_entry(&mut self, key: &str) -> Option<(String, CachedBlob)> { Cache::pop_entry(self, key) }
+
+    fn peek_last(&self) -> Option<(&String, &CachedBlob)> { Cache::peek_last(self) }
+
+    fn first_key(&self) -> Option<&String> { Cache::first_key(self) }
+
+    fn last_key(&self) -> Option<&String> { Cache::last_key(self) }
+
+    fn iter_from_last(&self) -> Box<dyn Iterator<Item = (&String, &CachedBlob)> + '_> { Box::new(self.iter().rev()) }
+
+    fn limit(&self) -> Option<NonZeroUsize> { Cache::limit(self) }
+
+    fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(String, CachedBlob)> { TTLCache::resize_with_evicted(self, cap) }
+
+    fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> { TTLCache::try_reserve(self, additional) }
+
+    fn keep_expired(&mut self) { self.keep_purged() }
+
+    fn pop_expired(&mut self) -> Vec<(String, CachedBlob)> {
+        self.purge_expired();
+        self.take_purged()
+    }
+
+    fn time_left(&self, key: &str) -> Option<Duration> { self.time_to_live(key) }
+
+    fn set_clock(&mut self, clock: &'static dyn Clock) { TTLCache::set_clock(self, clock) }
+}
+
+/// Bytes shared by every key whose value hashes to the same `ContentHash`.
+#[derive(Debug)]
+struct Content {
+    data: ChunkedBytes,
+    refs: usize,
+}
+
+/// Sizes reported by the stats endpoint.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreStats {
+    pub entries: usize,
+    pub distinct_values: usize,
+    pub logical_bytes: usize,
+    pub physical_bytes: usize,
+    pub value_sizes: SizeHistogram,
+    /// Keys removed because their TTL passed or they sat idle past `max_idle_secs`.
+    pub expired: u64,
+    /// Most recently used key of the default store, shortened to `MAX_REPORTED_KEY_CHARS`.
+    /// Unset with several shards, see `sharded_stats`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_key: Option<String>,
+    /// Least recently used key of the default store, the next to be evicted. Unset with
+    /// several shards.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_key: Option<String>,
+    pub namespaces: BTreeMap<String, NamespaceStats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub prefixes: Vec<PrefixUsage>,
+}
+
+impl StoreStats {
+    // adds the counts of another shard and drops the reported keys, which no order spans
+    fn add_shard(&mut self, other: StoreStats) {
+        self.first_key = None;
+        self.last_key = None;
+        self.entries += other.entries;
+        self.distinct_values += other.distinct_values;
+        self.logical_bytes += other.logical_bytes;
+        self.physical_bytes += other.physical_bytes;
+        self.value_sizes.merge(&other.value_sizes);
+        self.expired += other.expired;
+        for (namespace, own) in other.namespaces {
+            let stats = self.namespaces.entry(namespace).or_default();
+            stats.keys += own.keys;
+            stats.rejected += own.rejected;
+            stats.cache = match (stats.cache.take(), own.cache) {
+                (Some(usage), Some(own)) => Some(CacheUsage {
+                    capacity: usage.capacity.zip(own.capacity).map(|(a, b)| a + b),
+                    used: usage.used + own.used,
+                    ..usage
+                }),
+                (usage, own) => usage.or(own),
+            };
+            if let Some(usage) = stats.cache.as_mut() {
+                usage.first_key = None;
+                usage.last_key = None;
+            }
+        }
+    }
+}
+
+/// Key counts of one namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceStats {
+    pub keys: usize,
+    /// Writes refused because a key limit was reached.
+    pub rejected: u64,
+    /// Set for namespaces stored in their own `[namespaces.<name>]` cache.
+    #[serde(flatten)]
+    pub cache: Option<CacheUsage>,
+}
+
+/// How full a store is, in the units of its mode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheUsage {
+    /// `item`, `capacity` or `unlimited`.
+    pub cache_mode: &'static str,
+    /// Keys in item mode, bytes in capacity mode; unset when unbounded.
+    pub capacity: Option<usize>,
+    pub used: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_key: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError {
+    /// The value alone does not fit in the byte budget.
+    TooLarge { size: usize, budget: usize },
+    /// A key limit is reached and `on_limit` is `reject`. `namespace` is `None` for the global one.
+    LimitExceeded { namespace: Option<String> },
+    /// The value alone is larger than the `max_bytes` of its prefix quota.
+    OverQuota { prefix: String, size: usize, max_bytes: usize },
+    /// The index or the content table could not grow. Nothing was changed.
+    OutOfMemory,
+}
+
+/// Content-addressed storage behind the HTTP API.
+///
+/// `index` maps keys to content hashes in the eviction order of its policy, see `BlobIndex`;
+/// `contents` holds each distinct value once with a reference count. Whenever an index entry
+/// goes away (replaced, popped, evicted or expired) its reference is released, and the bytes
+/// are freed when the last one goes. With a byte budget, the physical bytes are charged once
+/// plus `ENTRY_OVERHEAD` and the key length per entry, and whole keys are evicted from the end
+/// of the index until the total fits.
+/// A key under a `PrefixQuota` first evicts the next keys to be evicted of its own prefix
+/// until the quota holds, so other prefixes only lose keys to the store-wide bounds.
+#[derive(Debug)]
+pub struct BlobStore {
+    index: Box<dyn BlobIndex>,
+    // clock stamps `used_at` and decides when `expires_at` is reached
+    clock: &'static dyn Clock,
+    contents: HashMap<ContentHash, Content>,
+    byte_budget: Option<usize>,
+    logical_bytes: usize,
+    physical_bytes: usize,
+    index_bytes: usize,
+    // sizes of the values as stored under each key
+    value_sizes: SizeHistogram,
+    limits: KeyLimits,
+    namespaces: HashMap<String, NamespaceStats>,
+    default_ttl: Option<Duration>,
+    expired: u64,
+    prefixes: Vec<PrefixUsage>,
+    // whether `get` moves the key to the front, off in fifo mode
+    promote_on_read: bool,
+}
+
+impl BlobStore {
+    /// Wraps an index that bounds the number of keys (or nothing), with an optional byte budget.
+    pub fn new(mut index: Box<dyn BlobIndex>, byte_budget: Option<usize>) -> Self {
+        index.keep_expired();
+        BlobStore {
+            index,
+            clock: DEFAULT_CLOCK,
+            contents: HashMap::new(),
+            byte_budget,
+            logical_bytes: 0,
+            physical_bytes: 0,
+            index_bytes: 0,
+            value_sizes: SizeHistogram::default(),
+            limits: KeyLimits::default(),
+            namespaces: HashMap::new(),
+            default_ttl: None,
+            expired: 0,
+            prefixes: Vec::new(),
+            promote_on_read: true,
+        }
+    }
+
+    /// Sets the TTL of entries inserted without one.
+    pub fn with_default_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Changes the TTL given to later inserts without one; existing entries keep theirs.
+    pub fn set_default_ttl(&mut self, ttl: Option<Duration>) { self.default_ttl = ttl; }
+
+    #[cfg(test)]
+    pub fn default_ttl(&self) -> Option<Duration> { self.default_ttl }
+
+    /// Makes `get` leave the order alone when `promote` is false, so that keys are evicted in
+    /// the order they were uploaded.
+    pub fn with_read_promotion(mut self, promote: bool) -> Self {
+        self.promote_on_read = promote;
+        self
+    }
+
+    /// Sets the key-count limits enforced by `insert`.
+    pub fn with_key_limits(mut self, limits: KeyLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the prefix quotas enforced by `insert`. A key falls under the first quota whose
+    /// prefix it starts with. Call before inserting anything.
+    pub fn with_prefix_quotas(mut self, quotas: Vec<PrefixQuota>) -> Self {
+        self.prefixes = quotas
+            .into_iter()
+            .map(|q| PrefixUsage { prefix: q.prefix, entries: 0, bytes: 0, max_entries: q.max_entries, max_bytes: q.max_bytes })
+            .collect();
+        self
+    }
+
+    /// Replaces the upper bounds of the value-size histogram. Call before inserting anything.
+    pub fn with_size_buckets(mut self, bounds: Vec<usize>) -> Self {
+        self.value_sizes = SizeHistogram::new(bounds);
+        self
+    }
+
+    /// The key index, for read-only inspection (capacity, order).
+    pub fn index(&self) -> &dyn BlobIndex { &*self.index }
+
+    /// The time `key` has left to live, `None` if it is missing, expired or never expires.
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        let now = self.clock.now();
+        let blob = self.index.peek(key).filter(|blob| !blob.is_expired(now))?;
+        let own = blob.expires_at.and_then(|deadline| deadline.checked_duration_since(now)).filter(|d| !d.is_zero());
+        match (own, self.index.time_left(key)) {
+            (Some(own), Some(left)) => Some(own.min(left)),
+            (own, left) => own.or(left),
+        }
+    }
+
+    /// Pushes the expiry of `key` `by` further into the future without promoting it. Returns
+    /// false if the key is missing, expired or never expires. An index that expires keys by
+    /// age, see `BlobIndex::time_left`, still lets the key go when its own time is up.
+    pub fn extend_ttl(&mut self, key: &str, by: Duration) -> bool {
+        let now = self.clock.now();
+        match self.index.peek_mut(key).and_then(|blob| blob.expires_at.as_mut()) {
+            Some(deadline) if *deadline > now => {
+                *deadline += by;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Replaces the clock of the store and of its index.
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) {
+        self.clock = clock;
+        self.index.set_clock(clock);
+    }
+
+    pub fn byte_budget(&self) -> Option<usize> { self.byte_budget }
+
+    pub fn len(&self) -> usize { self.index.len() }
+
+    /// Number of distinct values held.
+    pub fn distinct_values(&self) -> usize { self.contents.len() }
+
+    /// Bytes charged against the byte budget.
+    pub fn charged_bytes(&self) -> usize { self.physical_bytes + self.index_bytes }
+
+    /// Usage of each prefix quota, in configuration order.
+    pub fn prefix_usage(&self) -> &[PrefixUsage] { &self.prefixes }
+
+    fn quota_of(&self, key: &str) -> Option<usize> { self.prefixes.iter().position(|p| key.starts_with(&p.prefix)) }
+
+    /// The mode bounding the store: an item limit on the index wins over a byte budget.
+    pub fn usage(&self) -> CacheUsage {
+        let (cache_mode, capacity, used) = match (self.index.limit(), self.byte_budget) {
+            (Some(cap), _) => ("item", Some(cap.get()), self.len()),
+            (_, Some(budget)) => ("capacity", Some(budget), self.charged_bytes()),
+            (_, None) => ("unlimited", None, self.len()),
+        };
+        CacheUsage {
+            cache_mode,
+            capacity,
+            used,
+            first_key: self.index.first_key().map(|k| reported_key(k)),
+            last_key: self.index.last_key().map(|k| reported_key(k)),
+        }
+    }
+
+    /// Changes the bound of the store in the units of its mode: keys in item mode, bytes in
+    /// capacity mode. Returns the number of keys evicted to fit, or `None` when unbounded.
+    pub fn resize(&mut self, size: NonZeroUsize) -> Option<usize> {
+        self.purge_expired();
+        let mut evicted = 0;
+        match self.usage().cache_mode {
+            "item" => {
+                for (old_key, old) in self.index.resize_with_evicted(size) {
+                    self.release(&old_key, &old);
+                    evicted += 1;
+                }
+            }
+            "capacity" => {
+                self.byte_budget = Some(size.get());
+                while self.charged_bytes() > size.get() && self.evict_last() {
+                    evicted += 1;
+                }
+            }
+            _ => return None,
+        }
+        Some(evicted)
+    }
+
+    pub fn stats(&self) -> StoreStats {
+        StoreStats {
+            entries: self.len(),
+            distinct_values: self.distinct_values(),
+            logical_bytes: self.logical_bytes,
+            physical_bytes: self.physical_bytes,
+            value_sizes: self.value_sizes.clone(),
+            expired: self.expired,
+            first_key: self.index.first_key().map(|k| reported_key(k)),
+            last_key: self.index.last_key().map(|k| reported_key(k)),
+            namespaces: self.namespaces.iter().map(|(ns, stats)| (ns.clone(), stats.clone())).collect(),
+            prefixes: self.prefix_usage().to_vec(),
+        }
+    }
+
+    /// Stores `data` under `key`, reusing the bytes of an identical value if one is held.
+    /// Returns the number of keys evicted to make room.
+    pub fn insert(&mut self, key: String, hash: ContentHash, data: ChunkedBytes, mut meta: BlobMeta) -> Result<usize, StoreError> {
+        let len = data.len();
+        meta.ttl = meta.ttl.or(self.default_ttl);
+        self.purge_expired();
+        // an expired entry under `key` is gone for the index lookups below
+        self.purge_if_expired(&key);
+        if let Some(budget) = self.byte_budget {
+            let size = len + key.len() + ENTRY_OVERHEAD;
+            if size > budget {
+                return Err(StoreError::TooLarge { size, budget });
+            }
+        }
+        // grow the tables up front so that running out of memory is reported before anything changes
+        if self.index.try_reserve(1).is_err() || self.contents.try_reserve(1).is_err() {
+            return Err(StoreError::OutOfMemory);
+        }
+        let mut evicted = self.make_room_for(&key)?;
+        evicted += self.make_quota_room_for(&key, len)?;
+
+        let content = self.contents.entry(hash).or_insert_with(|| Content { data, refs: 0 });
+        if content.refs == 0 {
+            self.physical_bytes += len;
+        }
+        content.refs += 1;
+        self.logical_bytes += len;
+        self.index_bytes += key.len() + ENTRY_OVERHEAD;
+        self.value_sizes.record(len);
+        self.namespaces.entry(namespace_of(&key).to_string()).or_default().keys += 1;
+        if let Some(i) = self.quota_of(&key) {
+            self.prefixes[i].entries += 1;
+            self.prefixes[i].bytes += len;
+        }
+
+        let now = self.clock.now();
+        let blob = CachedBlob { hash, len, expires_at: meta.ttl.map(|ttl| now + ttl), meta, used_at: now };
+        if let Some((old_key, old)) = self.index.push(key.clone(), blob) {
+            if old_key != key {
+                evicted += 1;
+            }
+            self.release(&old_key, &old);
+        }
+
+        if let Some(budget) = self.byte_budget {
+            while self.charged_bytes() > budget && self.index.len() > 1 && self.evict_last() {
+                evicted += 1;
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Checks the key limits for a write of `key` without storing anything, so that a doomed
+    /// upload can be refused before its body is read. Only `reject` limits can fail; a failure
+    /// counts as a rejection in the namespace stats.
+    pub fn admits(&mut self, key: &str) -> Result<(), StoreError> {
+        self.purge_if_expired(key);
+        if self.limits.on_limit == OnLimit::Evict || self.index.contains(key) {
+            return Ok(());
+        }
+        match self.full_scope(key) {
+            Some(scope) => Err(self.reject(key, scope)),
+            None => Ok(()),
+        }
+    }
+
+    fn reject(&mut self, key: &str, scope: Option<String>) -> StoreError {
+        self.namespaces.entry(namespace_of(key).to_string()).or_default().rejected += 1;
+        StoreError::LimitExceeded { namespace: scope }
+    }
+
+    // the limit a new `key` would exceed: `Some(None)` for the global one, `Some(Some(ns))`
+    // for its namespace
+    fn full_scope(&self, key: &str) -> Option<Option<String>> {
+        if self.limits.max_keys.is_some_and(|max| self.index.len() >= max) {
+            return Some(None);
+        }
+        let namespace = namespace_of(key);
+        let keys = self.namespaces.get(namespace).map_or(0, |ns| ns.keys);
+        if self.limits.max_keys_per_namespace.is_some_and(|max| keys >= max) {
+            return Some(Some(namespace.to_string()));
+        }
+        None
+    }
+
+    // Enforces the key limits before `key` is added, evicting or rejecting per `on_limit`.
+    // Returns the number of keys evicted.
+    fn make_room_for(&mut self, key: &str) -> Result<usize, StoreError> {
+        if self.index.contains(key) {
+            return Ok(0);
+        }
+        let mut evicted = 0;
+        while let Some(scope) = self.full_scope(key) {
+            if self.limits.on_limit == OnLimit::Reject {
+                return Err(self.reject(key, scope));
+            }
+            let victim = match &scope {
+                None => self.index.last_key().cloned(),
+                Some(ns) => self.index.iter_from_last().find(|(k, _)| namespace_of(k) == ns).map(|(k, _)| k.clone()),
+            };
+            match victim {
+                Some(victim) => {
+                    self.remove(&victim);
+                    evicted += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(evicted)
+    }
+
+    // Evicts the least recently used keys of the quota `key` falls under until a value of `len`
+    // bytes fits, not counting the entry `key` replaces. Returns the number of keys evicted.
+    fn make_quota_room_for(&mut self, key: &str, len: usize) -> Result<usize, StoreError> {
+        let Some(i) = self.quota_of(key) else {
+            return Ok(0);
+        };
+        if let Some(max_bytes) = self.prefixes[i].max_bytes.filter(|&max| len > max) {
+            return Err(StoreError::OverQuota { prefix: self.prefixes[i].prefix.clone(), size: len, max_bytes });
+        }
+        let replaced = self.index.peek(key).map_or(0, |blob| blob.len);
+        let mut evicted = 0;
+        loop {
+            let usage = &self.prefixes[i];
+            let entries = usage.entries + 1 - usize::from(self.index.contains(key));
+            if !usage.exceeded_by(entries, usage.bytes + len - replaced) {
+                break;
+            }
+            let victim = self.index.iter_from_last().find(|(k, _)| k.as_str() != key && self.quota_of(k) == Some(i)).map(|(k, _)| k.clone());
+            match victim {
+                Some(victim) => {
+                    self.remove(&victim);
+                    evicted += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Returns the value and its index entry for `key`, promoting it unless read promotion is off.
+    pub fn get(&mut self, key: &str) -> Option<(ChunkedBytes, CachedBlob)> {
+        self.purge_if_expired(key);
+        let blob = if self.promote_on_read {
+            let now = self.clock.now();
+            let blob = self.index.get_mut(key)?;
+            blob.used_at = now;
+            blob.clone()
+        } else {
+            self.index.peek(key)?.clone()
+        };
+        let content = self.contents.get(&blob.hash)?;
+        Some((content.data.clone(), blob))
+    }
+
+    /// Like `get`, but leaves the recency order alone and only needs shared access. An expired
+    /// key is missing, and stays in the store until a write removes it.
+    pub fn peek(&self, key: &str) -> Option<(ChunkedBytes, CachedBlob)> {
+        let blob = self.index.peek(key).filter(|blob| !blob.is_expired(self.clock.now()))?.clone();
+        let content = self.contents.get(&blob.hash)?;
+        Some((content.data.clone(), blob))
+    }
+
+    /// Removes `key`, freeing its bytes if no other key shares them.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.purge_expired();
+        match self.index.pop_entry(key) {
+            Some((old_key, old)) => {
+                self.release(&old_key, &old);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn evict_last(&mut self) -> bool {
+        match self.index.pop_last() {
+            Some((old_key, old)) => {
+                self.release(&old_key, &old);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the keys idle for longer than `max_idle` at `now`, counting them as expired.
+    /// When the index keeps its keys in use order, the walk stops at the first recent one.
+    pub fn pop_idle(&mut self, max_idle: Duration, now: Instant) -> usize {
+        self.purge_expired();
+        let idle = |blob: &CachedBlob| now.saturating_duration_since(blob.used_at) > max_idle;
+        let mut removed = 0;
+        if self.index.in_use_order() {
+            while self.index.peek_last().is_some_and(|(_, blob)| idle(blob)) {
+                self.evict_last();
+                removed += 1;
+            }
+        } else {
+            let victims: Vec<String> = self.index.iter_from_last().filter(|(_, blob)| idle(blob)).map(|(k, _)| k.clone()).collect();
+            for victim in victims {
+                self.remove(&victim);
+                removed += 1;
+            }
+        }
+        self.expired += removed as u64;
+        removed
+    }
+
+    // Releases the entries the index let go by age, see `BlobIndex::pop_expired`, then `key`
+    // if its own TTL ran out.
+    fn purge_if_expired(&mut self, key: &str) {
+        self.purge_expired();
+        if !self.index.peek(key).is_some_and(|blob| blob.is_expired(self.clock.now())) {
+            return;
+        }
+        if let Some((old_key, old)) = self.index.pop_entry(key) {
+            self.release(&old_key, &old);
+            self.expired += 1;
+        }
+    }
+
+    fn purge_expired(&mut self) {
+        for (old_key, old) in self.index.pop_expired() {
+            self.release(&old_key, &old);
+            self.expired += 1;
+        }
+    }
+
+    fn release(&mut self, key: &str, blob: &CachedBlob) {
+        self.logical_bytes -= blob.len;
+        self.index_bytes -= key.len() + ENTRY_OVERHEAD;
+        self.value_sizes.unrecord(blob.len);
+        if let Some(ns) = self.namespaces.get_mut(namespace_of(key)) {
+            ns.keys -= 1;
+            if ns.keys == 0 && ns.rejected == 0 {
+                self.namespaces.remove(namespace_of(key));
+            }
+        }
+        if let Some(i) = self.quota_of(key) {
+            self.prefixes[i].entries -= 1;
+            self.prefixes[i].bytes -= blob.len;
+        }
+        if let Some(content) = self.contents.get_mut(&blob.hash) {
+            content.refs -= 1;
+            if content.refs == 0 {
+                self.physical_bytes -= content.data.len();
+                self.contents.remove(&blob.hash);
+            }
+        }
+    }
+}
+
+/// The default store plus one store per `[namespaces.<name>]` table. Keys are routed by their
+/// namespace; keys of namespaces without a table live in the default store.
+#[derive(Debug)]
+pub struct Stores {
+    default: BlobStore,
+    namespaces: HashMap<String, BlobStore>,
+}
+
+impl Stores {
+    pub fn new(default: BlobStore) -> Self { Stores { default, namespaces: HashMap::new() } }
+
+    /// Gives `namespace` its own store.
+    pub fn with_namespace(mut self, namespace: String, store: BlobStore) -> Self {
+        self.namespaces.insert(namespace, store);
+        self
+    }
+
+    pub fn default_store(&self) -> &BlobStore { &self.default }
+
+    /// The store holding `key`.
+    pub fn for_key(&self, key: &str) -> &BlobStore { self.namespaces.get(namespace_of(key)).unwrap_or(&self.default) }
+
+    /// The store holding `key`.
+    pub fn for_key_mut(&mut self, key: &str) -> &mut BlobStore {
+        match self.namespaces.get_mut(namespace_of(key)) {
+            Some(store) => store,
+            None => &mut self.default,
+        }
+    }
+
+    /// The store of a configured namespace, or the default store for `None`.
+    pub fn namespace_mut(&mut self, namespace: Option<&str>) -> Option<&mut BlobStore> {
+        match namespace {
+            Some(namespace) => self.namespaces.get_mut(namespace),
+            None => Some(&mut self.default),
+        }
+    }
+
+    /// Runs `BlobStore::pop_idle` on every store.
+    pub fn pop_idle(&mut self, max_idle: Duration, now: Instant) -> usize {
+        self.namespaces.values_mut().chain([&mut self.default]).map(|store| store.pop_idle(max_idle, now)).sum()
+    }
+
+    /// Totals over every store. Configured namespaces are always listed, with their usage.
+    pub fn stats(&self) -> StoreStats {
+        let mut stats = self.default.stats();
+        for (namespace, store) in &self.namespaces {
+            let own = store.stats();
+            stats.entries += own.entries;
+            stats.distinct_values += own.distinct_values;
+            stats.logical_bytes += own.logical_bytes;
+            stats.physical_bytes += own.physical_bytes;
+            stats.value_sizes.merge(&own.value_sizes);
+            stats.expired += own.expired;
+            stats.namespaces.extend(own.namespaces);
+            stats.prefixes.extend(own.prefixes);
+            stats.namespaces.entry(namespace.clone()).or_default().cache = Some(store.usage());
+        }
+        stats
+    }
+}
+
+/// `Stores` split into shards by the hash of the key, each behind its own lock, so that requests
+/// for keys of different shards do not wait for each other. Every shard holds all the configured
+/// stores with its share of their bounds, divided like the capacity of a `ShardedLRUCache`.
+///
+/// The locks are taken through `write`, `read`, `write_all` and `read_all`, which give up with
+/// `LockBusy` once `wait` is over, so that a stalled lock sheds load instead of queueing
+/// requests until they all time out. `None` waits as long as it takes.
+#[derive(Debug)]
+pub struct ShardedStores {
+    shards: Box<[RwLock<Stores>]>,
+    hasher: DefaultHasher,
+}
+
+impl ShardedStores {
+    /// Routes keys across `shards`, of which there must be at least one.
+    pub fn new(shards: Vec<Stores>) -> Self {
+        assert!(!shards.is_empty(), "a store needs a shard");
+        ShardedStores { shards: shards.into_iter().map(RwLock::new).collect(), hasher: DefaultHasher::default() }
+    }
+
+    /// The shard holding `key`.
+    pub fn shard(&self, key: &str) -> &RwLock<Stores> { &self.shards[shard_of(&self.hasher, key, self.shards.len())] }
+
+    pub fn shards(&self) -> &[RwLock<Stores>] { &self.shards }
+
+    /// Takes the write lock of the shard holding `key`.
+    pub async fn write(&self, key: &str, wait: Option<Duration>) -> Result<RwLockWriteGuard<'_, Stores>, LockBusy> {
+        within(wait, self.shard(key).write()).await
+    }
+
+    /// Takes the read lock of the shard holding `key`.
+    pub async fn read(&self, key: &str, wait: Option<Duration>) -> Result<RwLockReadGuard<'_, Stores>, LockBusy> {
+        within(wait, self.shard(key).read()).await
+    }
+
+    /// Takes the write lock of every shard in turn, all within `wait`.
+    pub async fn write_all(&self, wait: Option<Duration>) -> Result<Vec<RwLockWriteGuard<'_, Stores>>, LockBusy> {
+        let lock_all = async {
+            let mut guards = Vec::with_capacity(self.shards.len());
+            for shard in self.shards.iter() {
+                guards.push(shard.write().await);
+            }
+            guards
+        };
+        within(wait, lock_all).await
+    }
+
+    /// Like `write_all`, taking the read locks.
+    pub async fn read_all(&self, wait: Option<Duration>) -> Result<Vec<RwLockReadGuard<'_, Stores>>, LockBusy> {
+        let lock_all = async {
+            let mut guards = Vec::with_capacity(self.shards.len());
+            for shard in self.shards.iter() {
+                guards.push(shard.read().await);
+            }
+            guards
+        };
+        within(wait, lock_all).await
+    }
+}
+
+async fn within<G>(wait: Option<Duration>, lock: impl Future<Output = G>) -> Result<G, LockBusy> {
+    match wait {
+        Some(wait) => tokio::time::timeout(wait, lock).await.map_err(|_| LockBusy),
+        None => Ok(lock.await),
+    }
+}
+
+/// Totals over `shards`, of which there must be at least one. The first and last keys are only
+/// reported with a single shard: each shard orders its own keys, and no order spans them.
+pub fn sharded_stats<T: Deref<Target = Stores>>(shards: &[T]) -> StoreStats {
+    let mut stats = shards[0].stats();
+    for shard in &shards[1..] {
+        stats.add_shard(shard.stats());
+    }
+    stats
+}
+
+/// Resizes the store of `namespace` (see `Stores::namespace_mut`) in every shard to its share of
+/// `size`, at least one. Returns the number of keys evicted, or `None` when there is no such
+/// store or it is unbounded.
+pub fn resize_shards<T: DerefMut<Target = Stores>>(shards: &mut [T], namespace: Option<&str>, size: NonZeroUsize) -> Option<usize> {
+    let count = shards.len();
+    let mut evicted = 0;
+    for (i, stores) in shards.iter_mut().enumerate() {
+        let share = NonZeroUsize::new(shard_share(size.get(), count, i)).unwrap_or(NonZeroUsize::MIN);
+        evicted += stores.namespace_mut(namespace)?.resize(share)?;
+    }
+    Some(evicted)
+}
+
+/// Clock backed by tokio's time, so tests can pause and advance it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant { tokio::time::Instant::now().into_std() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sharded_stats, BlobMeta, BlobStore, ContentHash, KeyLimits, OnLimit, PrefixQuota, ShardedStores, StoreError, Stores, ENTRY_OVERHEAD, MAX_REPORTED_KEY_CHARS};
+    use crate::lru::chunked_bytes::ChunkedBytes;
+    use crate::lru::clock::ManualClock;
+    use crate::lru::lru_cache::LRUCache;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    fn refs(store: &BlobStore, hash: &ContentHash) -> usize { store.contents.get(hash).map_or(0, |c| c.refs) }
+
+    fn hash(b: u8) -> ContentHash { [b; 32] }
+
+    fn data(len: usize) -> ChunkedBytes { vec![7u8; len].into() }
+
+    fn item_store(cap: usize) -> BlobStore { BlobStore::new(Box::new(LRUCache::new(NonZeroUsize::new(cap).unwrap())), None) }
+
+    #[test]
+    fn test_identical_values_stored_once() {
+        let mut store = item_store(10);
+        for key in ["a", "b", "c"] {
+            store.insert(key.to_string(), hash(1), data(100), BlobMeta::default()).unwrap();
+        }
+        assert_eq!(store.len(), 3);
+        assert_eq!(refs(&store, &hash(1)), 3);
+        assert_eq!(store.distinct_values(), 1);
+        assert_eq!(store.logical_bytes, 300);
+        assert_eq!(store.physical_bytes, 100);
+        assert_eq!(store.get("b").unwrap().0.len(), 100);
+    }
+
+    #[test]
+    fn test_refcount_lifecycle() {
+        let mut store = item_store(10);
+        store.insert("a".to_string(), hash(1), data(100), BlobMeta::default()).unwrap();
+        store.insert("b".to_string(), hash(1), data(100), BlobMeta::default()).unwrap();
+
+        assert!(store.remove("a"));
+        assert!(!store.remove("a"));
+        assert_eq!(refs(&store, &hash(1)), 1);
+        assert_eq!(store.physical_bytes, 100);
+
+        // overwriting the last referrer with other content frees the old bytes
+        store.insert("b".to_string(), hash(2), data(50), BlobMeta::default()).unwrap();
+        assert_eq!(refs(&store, &hash(1)), 0);
+        assert_eq!(refs(&store, &hash(2)), 1);
+        assert_eq!(store.physical_bytes, 50);
+        assert_eq!(store.logical_bytes, 50);
+
+        assert!(store.remove("b"));
+        assert_eq!(store.distinct_values(), 0);
+        assert_eq!(store.physical_bytes, 0);
+        assert_eq!(store.charged_bytes(), 0);
+    }
+
+    #[test]
+    fn test_eviction_of_last_referrer_frees_bytes() {
+        let mut store = item_store(2);
+        store.insert("a".to_string(), hash(1), data(100), BlobMeta::default()).unwrap();
+        store.insert("b".to_string(), hash(1), data(100), BlobMeta::default()).unwrap();
+
+        // evicts "a": the bytes stay because "b" still refers to them
+        assert_eq!(store.insert("c".to_string(), hash(2), data(10), BlobMeta::default()), Ok(1));
+        assert_eq!(refs(&store, &hash(1)), 1);
+        assert_eq!(store.physical_bytes, 110);
+
+        // evicts "b", the last referrer
+        assert_eq!(store.insert("d".to_string(), hash(3), data(10), BlobMeta::default()), Ok(1));
+        assert_eq!(refs(&store, &hash(1)), 0);
+        assert_eq!(store.physical_bytes, 20);
+        assert!(store.get("b").is_none());
+    }
+
+    #[test]
+    fn test_byte_budget_charges_shared_bytes_once() {
+        let budget = 300 + 3 * (1 + ENTRY_OVERHEAD);
+        let mut store = BlobStore::new(Box::new(LRUCache::unbounded()), Some(budget));
+        for key in ["a", "b", "c"] {
+            store.insert(key.to_string(), hash(1), data(300), BlobMeta::default()).unwrap();
+        }
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.charged_bytes(), budget);
+
+        // new distinct bytes push the oldest keys out; the shared value survives while referenced
+        assert_eq!(store.insert("d".to_string(), hash(2), data(30), BlobMeta::default()), Ok(2));
+        assert!(store.get("a").is_none());
+        assert!(store.get("b").is_none());
+        assert_eq!(refs(&store, &hash(1)), 1);
+        assert!(store.charged_bytes() <= budget);
+
+        assert_eq!(
+            store.insert("e".to_string(), hash(3), data(budget), BlobMeta::default()),
+            Err(StoreError::TooLarge { size: budget + 1 + ENTRY_OVERHEAD, budget })
+        );
+    }
+
+    #[test]
+    fn test_value_size_histogram() {
+        let mut store = item_store(2).with_size_buckets(vec![16, 1024]);
+        store.insert("a".to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        store.insert("b".to_string(), hash(2), data(100), BlobMeta::default()).unwrap();
+        assert_eq!(store.stats().value_sizes.counts(), &[1, 1, 0]);
+
+        // overwriting moves the key to the bucket of its new size
+        store.insert("a".to_string(), hash(3), data(5000), BlobMeta::default()).unwrap();
+        assert_eq!(store.stats().value_sizes.counts(), &[0, 1, 1]);
+
+        // eviction and removal take values out
+        store.insert("c".to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        assert_eq!(store.stats().value_sizes.counts(), &[1, 0, 1]);
+        store.remove("a");
+        assert_eq!(store.stats().value_sizes.counts(), &[1, 0, 0]);
+        assert_eq!(store.stats().value_sizes.sum(), 10);
+    }
+
+    fn limited_store(max_keys: Option<usize>, per_namespace: Option<usize>, on_limit: OnLimit) -> BlobStore {
+        let limits = KeyLimits { max_keys, max_keys_per_namespace: per_namespace, on_limit };
+        BlobStore::new(Box::new(LRUCache::unbounded()), None).with_key_limits(limits)
+    }
+
+    fn put(store: &mut BlobStore, key: &str) -> Result<usize, StoreError> {
+        store.insert(key.to_string(), hash(1), data(1), BlobMeta::default())
+    }
+
+    #[test]
+    fn test_key_limits_evict() {
+        let mut store = limited_store(Some(5), Some(2), OnLimit::Evict);
+        for key in ["a/1", "b/1", "a/2", "b/2"] {
+            assert_eq!(put(&mut store, key), Ok(0));
+        }
+        // "a" is full: its least recently used key goes, even though "b/1" is older
+        store.get("a/1");
+        assert_eq!(put(&mut store, "a/3"), Ok(1));
+        assert!(store.index().contains("a/1"));
+        assert!(!store.index().contains("a/2"));
+        assert_eq!(store.stats().namespaces["a"].keys, 2);
+
+        // the global limit evicts the overall least recently used key
+        assert_eq!(put(&mut store, "c/1"), Ok(0));
+        assert_eq!(put(&mut store, "c/2"), Ok(1));
+        assert!(!store.index().contains("b/1"));
+        assert_eq!(store.len(), 5);
+    }
+
+    #[test]
+    fn test_key_limits_reject() {
+        let mut store = limited_store(Some(3), Some(2), OnLimit::Reject);
+        assert_eq!(put(&mut store, "a/1"), Ok(0));
+        assert_eq!(put(&mut store, "a/2"), Ok(0));
+        assert_eq!(store.admits("a/3"), Err(StoreError::LimitExceeded { namespace: Some("a".to_string()) }));
+        assert_eq!(put(&mut store, "a/3"), Err(StoreError::LimitExceeded { namespace: Some("a".to_string()) }));
+        // overwriting a key at the ceiling is fine
+        assert_eq!(store.admits("a/1"), Ok(()));
+        assert_eq!(put(&mut store, "a/1"), Ok(0));
+
+        assert_eq!(put(&mut store, "b"), Ok(0));
+        assert_eq!(put(&mut store, "c"), Err(StoreError::LimitExceeded { namespace: None }));
+        assert_eq!(store.len(), 3);
+
+        let stats = store.stats();
+        assert_eq!(stats.namespaces["a"].keys, 2);
+        assert_eq!(stats.namespaces["a"].rejected, 2);
+        assert_eq!(stats.namespaces[""].keys, 1);
+        assert_eq!(stats.namespaces[""].rejected, 1);
+
+        // below the ceiling again after a removal
+        store.remove("a/2");
+        assert_eq!(put(&mut store, "a/3"), Ok(0));
+    }
+
+    #[test]
+    fn test_resize_in_mode_units() {
+        let mut store = item_store(4);
+        for key in ["a", "b", "c", "d"] {
+            store.insert(key.to_string(), hash(key.as_bytes()[0]), data(10), BlobMeta::default()).unwrap();
+        }
+        assert_eq!(store.resize(NonZeroUsize::new(2).unwrap()), Some(2));
+        assert_eq!(store.usage().capacity, Some(2));
+        assert!(!store.index().contains("b"));
+        assert_eq!(store.physical_bytes, 20);
+
+        let entry = 100 + 1 + ENTRY_OVERHEAD;
+        let mut store = BlobStore::new(Box::new(LRUCache::unbounded()), Some(3 * entry));
+        for key in ["a", "b", "c"] {
+            store.insert(key.to_string(), hash(key.as_bytes()[0]), data(100), BlobMeta::default()).unwrap();
+        }
+        assert_eq!(store.resize(NonZeroUsize::new(2 * entry).unwrap()), Some(1));
+        assert_eq!(store.usage().used, 2 * entry);
+
+        let mut store = BlobStore::new(Box::new(LRUCache::unbounded()), None);
+        assert_eq!(store.resize(NonZeroUsize::new(1).unwrap()), None);
+    }
+
+    #[test]
+    fn test_stores_route_by_namespace() {
+        let mut stores = Stores::new(item_store(10)).with_namespace("meta".to_string(), item_store(1));
+        for key in ["meta/1", "meta/2", "blob/1", "plain"] {
+            stores.for_key_mut(key).insert(key.to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        }
+        assert!(!stores.for_key_mut("meta/1").index().contains("meta/1"));
+        assert_eq!(stores.default_store().len(), 2);
+
+        let stats = stores.stats();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.value_sizes.count(), 3);
+        let meta = &stats.namespaces["meta"];
+        assert_eq!(meta.keys, 1);
+        assert_eq!(meta.cache.as_ref().map(|c| (c.cache_mode, c.capacity, c.used)), Some(("item", Some(1), 1)));
+        assert!(stats.namespaces["blob"].cache.is_none());
+        assert!(stores.namespace_mut(Some("blob")).is_none());
+    }
+
+    #[test]
+    fn test_stats_report_first_and_last_key() {
+        let mut store = item_store(10);
+        assert_eq!(store.stats().first_key, None);
+        let long = "k".repeat(MAX_REPORTED_KEY_CHARS + 10);
+        for key in ["cold", "warm", long.as_str()] {
+            store.insert(key.to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        }
+        let stats = store.stats();
+        assert_eq!(stats.first_key, Some(format!("{}…", &long[..MAX_REPORTED_KEY_CHARS])));
+        assert_eq!(stats.last_key.as_deref(), Some("cold"));
+        assert_eq!(store.usage().last_key.as_deref(), Some("cold"));
+    }
+
+    #[tokio::test]
+    async fn test_shard_locks_give_up_after_their_wait() {
+        let store = ShardedStores::new(vec![Stores::new(item_store(1)), Stores::new(item_store(1))]);
+        let wait = Some(Duration::from_millis(10));
+        let held = store.write("a", None).await.unwrap();
+        assert!(store.read("a", wait).await.is_err());
+        assert!(store.write_all(wait).await.is_err());
+        // the shard of another key is free
+        let other = (0..).map(|i| i.to_string()).find(|k| !std::ptr::eq(store.shard(k), store.shard("a"))).unwrap();
+        assert!(store.write(&other, wait).await.is_ok());
+        drop(held);
+        assert_eq!(store.read_all(wait).await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_sharded_stats_report_keys_of_a_single_shard_only() {
+        let shard = |keys: &[&str]| {
+            let mut stores = Stores::new(item_store(10)).with_namespace("meta".to_string(), item_store(10));
+            for key in keys {
+                stores.for_key_mut(key).insert(key.to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+            }
+            stores
+        };
+        let (a, b) = (shard(&["a", "meta/a"]), shard(&["b", "meta/b"]));
+
+        let single = sharded_stats(&[&a]);
+        assert_eq!((single.first_key.as_deref(), single.last_key.as_deref()), (Some("a"), Some("a")));
+        assert_eq!(single.namespaces["meta"].cache.as_ref().unwrap().first_key.as_deref(), Some("meta/a"));
+
+        let both = sharded_stats(&[&a, &b]);
+        assert_eq!((both.entries, both.first_key, both.last_key), (4, None, None));
+        let meta = both.namespaces["meta"].cache.as_ref().unwrap();
+        assert_eq!((meta.used, meta.capacity, &meta.first_key, &meta.last_key), (2, Some(20), &None, &None));
+    }
+
+    fn quota(prefix: &str, max_entries: Option<usize>, max_bytes: Option<usize>) -> PrefixQuota {
+        PrefixQuota { prefix: prefix.to_string(), max_entries, max_bytes }
+    }
+
+    #[test]
+    fn test_prefix_quota_evicts_within_prefix() {
+        let mut store = item_store(10).with_prefix_quotas(vec![quota("thumbnails/", Some(2), None), quota("big/", None, Some(250))]);
+        for key in ["docs/a", "docs/b"] {
+            store.insert(key.to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        }
+        for i in 0..5 {
+            store.insert(format!("thumbnails/{}", i), hash(2), data(10), BlobMeta::default()).unwrap();
+        }
+        // the global capacity has room, so only thumbnails made way for thumbnails
+        assert!(store.index().contains("docs/a") && store.index().contains("docs/b"));
+        assert!(store.index().contains("thumbnails/3") && store.index().contains("thumbnails/4"));
+        assert_eq!(store.len(), 4);
+        let usage = &store.prefix_usage()[0];
+        assert_eq!((usage.entries, usage.bytes), (2, 20));
+
+        store.insert("big/1".to_string(), hash(3), data(100), BlobMeta::default()).unwrap();
+        store.insert("big/2".to_string(), hash(4), data(100), BlobMeta::default()).unwrap();
+        // replacing a key does not count its old value against the quota
+        assert_eq!(store.insert("big/2".to_string(), hash(5), data(150), BlobMeta::default()), Ok(0));
+        assert_eq!(store.insert("big/3".to_string(), hash(6), data(100), BlobMeta::default()), Ok(1));
+        assert!(!store.index().contains("big/1"));
+        assert_eq!(store.prefix_usage()[1].bytes, 250);
+        assert!(matches!(
+            store.insert("big/4".to_string(), hash(7), data(300), BlobMeta::default()),
+            Err(StoreError::OverQuota { max_bytes: 250, .. })
+        ));
+
+        store.remove("big/2");
+        assert_eq!((store.prefix_usage()[1].entries, store.prefix_usage()[1].bytes), (1, 100));
+        assert_eq!(store.stats().prefixes.len(), 2);
+    }
+
+    #[test]
+    fn test_prefix_quota_falls_back_to_global_eviction() {
+        let mut store = item_store(3).with_prefix_quotas(vec![quota("thumbnails/", Some(5), None)]);
+        for key in ["docs/a", "thumbnails/1", "thumbnails/2", "thumbnails/3"] {
+            store.insert(key.to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+        }
+        assert!(!store.index().contains("docs/a"));
+        assert_eq!(store.prefix_usage()[0].entries, 3);
+    }
+
+    #[test]
+    fn test_ttls_are_kept_by_the_store() {
+        let clock = ManualClock::leaked();
+        let mut store = item_store(10);
+        store.set_clock(clock);
+        let meta = BlobMeta { ttl: Some(Duration::from_secs(10)), ..BlobMeta::default() };
+        store.insert("a".to_string(), hash(1), data(10), meta).unwrap();
+        store.insert("b".to_string(), hash(1), data(10), BlobMeta::default()).unwrap();
+
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(store.ttl("a"), Some(Duration::from_secs(6)));
+        assert_eq!(store.ttl("b"), None);
+        assert!(store.extend_ttl("a", Duration::from_secs(4)));
+        assert!(!store.extend_ttl("b", Duration::from_secs(4)));
+
+        clock.advance(Duration::from_secs(9));
+        assert!(store.peek("a").is_some());
+        clock.advance(Duration::from_secs(1));
+        // a missing key for reads, until a write takes it out
+        assert!(store.peek("a").is_none());
+        assert_eq!(store.len(), 2);
+        assert!(store.get("a").is_none());
+        assert_eq!((store.len(), store.stats().expired, refs(&store, &hash(1))), (1, 1, 1));
+        assert!(!store.extend_ttl("a", Duration::from_secs(4)));
+    }
+}
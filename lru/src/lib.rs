@@ -1,11 +1,18 @@
-use std::path::PathBuf;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 pub mod lru;
+#[cfg(feature = "std")]
 pub mod http;
+#[cfg(feature = "std")]
+pub mod build_info;
+#[cfg(feature = "std")]
+pub mod settings;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
 
-pub fn load_from_file(path: PathBuf) -> config::Config {
-    config::Config::builder()
-        .add_source(config::File::with_name(path.to_str().unwrap()))
-        .build()
-        .unwrap()
-}
\ No newline at end of file
+#[cfg(feature = "std")]
+pub use settings::{load_config, ConfigError};
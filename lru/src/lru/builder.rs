@@ -0,0 +1,226 @@
+use alloc::boxed::Box;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use core::hash::{BuildHasher, Hash};
+use core::num::NonZeroUsize;
+use core::time::Duration;
+
+use crate::lru::cache::DefaultHasher;
+use crate::lru::clock::Clock;
+use crate::lru::error::BuildError;
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+use crate::lru::observer::CacheObserver;
+
+/// Configures a `LRUCache` in one expression. The bound picks the mode: `max_items` for
+/// `new`, `max_bytes` for `storage`, neither for `unbounded`. The other settings are those of
+/// the cache's setters.
+pub struct LRUCacheBuilder<K, V, S = DefaultHasher> {
+    max_items: Option<usize>,
+    max_bytes: Option<usize>,
+    hasher: S,
+    ttl: Option<Duration>,
+    clock: Option<&'static dyn Clock>,
+    #[cfg(target_has_atomic = "ptr")]
+    observer: Option<Arc<dyn CacheObserver<K, V>>>,
+    auto_shrink: Option<usize>,
+    watermarks: Option<(usize, usize)>,
+    on_evict: Option<Box<dyn FnMut(K, V) + Send>>,
+}
+
+impl<K, V> LRUCacheBuilder<K, V> {
+    pub fn new() -> Self {
+        LRUCacheBuilder {
+            max_items: None,
+            max_bytes: None,
+            hasher: DefaultHasher::default(),
+            ttl: None,
+            clock: None,
+            #[cfg(target_has_atomic = "ptr")]
+            observer: None,
+            auto_shrink: None,
+            watermarks: None,
+            on_evict: None,
+        }
+    }
+}
+
+impl<K, V> Default for LRUCacheBuilder<K, V> {
+    fn default() -> Self { LRUCacheBuilder::new() }
+}
+
+impl<K, V, S> LRUCacheBuilder<K, V, S> {
+    /// Holds at most `max` entries.
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.max_items = Some(max);
+        self
+    }
+
+    /// Holds values whose `ItemSize` add up to at most `max` bytes.
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Hashes keys with `hasher`.
+    pub fn hasher<S2>(self, hasher: S2) -> LRUCacheBuilder<K, V, S2> {
+        LRUCacheBuilder {
+            max_items: self.max_items,
+            max_bytes: self.max_bytes,
+            hasher,
+            ttl: self.ttl,
+            clock: self.clock,
+            #[cfg(target_has_atomic = "ptr")]
+            observer: self.observer,
+            auto_shrink: self.auto_shrink,
+            watermarks: self.watermarks,
+            on_evict: self.on_evict,
+        }
+    }
+
+    /// See `LRUCache::set_default_ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// See `LRUCache::set_clock`.
+    pub fn clock(mut self, clock: &'static dyn Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// See `LRUCache::set_observer`.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn observer(mut self, observer: Arc<dyn CacheObserver<K, V>>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// See `LRUCache::set_auto_shrink`.
+    pub fn auto_shrink(mut self, ratio: usize) -> Self {
+        self.auto_shrink = Some(ratio);
+        self
+    }
+
+    /// See `LRUCache::set_watermarks`.
+    pub fn watermarks(mut self, low: usize, high: usize) -> Self {
+        self.watermarks = Some((low, high));
+        self
+    }
+
+    /// See `LRUCache::set_on_evict`.
+    pub fn on_evict(mut self, f: impl FnMut(K, V) + Send + 'static) -> Self {
+        self.on_evict = Some(Box::new(f));
+        self
+    }
+
+    /// Creates the cache, or tells which settings do not go together.
+    pub fn build(self) -> Result<LRUCache<K, V, S>, BuildError>
+    where
+        K: Hash + Eq,
+        V: ItemSize,
+        S: BuildHasher,
+    {
+        let positive = |max: usize| NonZeroUsize::new(max).ok_or(BuildError::ZeroCapacity);
+        let mut cache = match (self.max_items, self.max_bytes) {
+            (Some(_), Some(_)) => return Err(BuildError::ConflictingLimits),
+            (Some(max), None) => LRUCache::with_hasher(CacheMode::ItemLimit, positive(max)?, self.hasher),
+            (None, Some(max)) => LRUCache::storage_with_hasher(positive(max)?, self.hasher),
+            (None, None) => LRUCache::unbounded_with_hasher(CacheMode::UnLimit, self.hasher),
+        };
+        if self.ttl == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroTtl);
+        }
+        if self.watermarks.is_some_and(|(low, high)| low >= high) {
+            return Err(BuildError::InvalidWatermarks);
+        }
+        cache.set_default_ttl(self.ttl);
+        if let Some(clock) = self.clock {
+            cache.set_clock(clock);
+        }
+        #[cfg(target_has_atomic = "ptr")]
+        if let Some(observer) = self.observer {
+            cache.set_observer(observer);
+        }
+        cache.set_auto_shrink(self.auto_shrink);
+        cache.set_watermarks(self.watermarks);
+        cache.set_boxed_on_evict(self.on_evict);
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasherDefault, DefaultHasher};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::LRUCacheBuilder;
+    use crate::lru::cache::Cache;
+    use crate::lru::clock::ManualClock;
+    use crate::lru::error::BuildError;
+    use crate::lru::lru_cache::{CacheMode, LRUCache};
+    use crate::lru::observer::CacheCounters;
+
+    #[test]
+    fn test_bound_picks_the_mode() {
+        let mut items: LRUCache<&str, &str> = LRUCache::builder().max_items(2).build().unwrap();
+        assert!(matches!(items.cache_mode(), CacheMode::ItemLimit));
+        items.put_many([("a", "1"), ("b", "2"), ("c", "3")]);
+        assert_eq!(items.len(), 2);
+
+        let mut bytes: LRUCache<&str, &str> = LRUCacheBuilder::new().max_bytes(4).build().unwrap();
+        assert!(matches!(bytes.cache_mode(), CacheMode::StoreLimit));
+        bytes.put_many([("a", "11"), ("b", "22"), ("c", "33")]);
+        assert_eq!((bytes.len(), bytes.used_bytes()), (2, 4));
+
+        let mut unbounded: LRUCache<u32, u32> = LRUCacheBuilder::default().build().unwrap();
+        assert!(matches!(unbounded.cache_mode(), CacheMode::UnLimit));
+        unbounded.put_many((0..100).map(|i| (i, i)));
+        assert_eq!(unbounded.len(), 100);
+    }
+
+    #[test]
+    fn test_settings() {
+        let clock = ManualClock::leaked();
+        let counters = Arc::new(CacheCounters::default());
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::builder()
+            .max_items(2)
+            .hasher(BuildHasherDefault::<DefaultHasher>::default())
+            .ttl(Duration::from_secs(10))
+            .clock(clock)
+            .observer(counters.clone())
+            .auto_shrink(4)
+            .on_evict(move |k, v| sink.lock().unwrap().push((k, v)))
+            .build()
+            .unwrap();
+
+        cache.put("a", 1);
+        cache.put_with_ttl("b", 2, Duration::from_secs(60));
+        assert_eq!(cache.ttl(&"a"), Some(Duration::from_secs(10)));
+        cache.put("c", 3);
+        assert_eq!(*evicted.lock().unwrap(), [("a", 1)]);
+        assert_eq!(counters.inserts(), 3);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.get(&"c"), None);
+        assert_eq!(cache.get(&"b"), Some(&2));
+        // entries from `get_or_insert` get the default TTL too
+        cache.get_or_insert("d", || 4);
+        assert_eq!(cache.ttl(&"d"), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let build = |builder: LRUCacheBuilder<u32, u32>| builder.build().map(|_| ());
+        assert_eq!(build(LRUCacheBuilder::new().max_items(0)), Err(BuildError::ZeroCapacity));
+        assert_eq!(build(LRUCacheBuilder::new().max_bytes(0)), Err(BuildError::ZeroCapacity));
+        assert_eq!(build(LRUCacheBuilder::new().max_items(1).max_bytes(1)), Err(BuildError::ConflictingLimits));
+        assert_eq!(build(LRUCacheBuilder::new().ttl(Duration::ZERO)), Err(BuildError::ZeroTtl));
+        assert_eq!(build(LRUCacheBuilder::new().max_items(8).watermarks(4, 4)), Err(BuildError::InvalidWatermarks));
+        assert_eq!(BuildError::ConflictingLimits.to_string(), "max_items and max_bytes cannot both be set");
+    }
+}
@@ -1,10 +1,53 @@
+use crate::lru::clock_cache::ClockCache;
+use crate::lru::fifo_cache::FIFOCache;
 use crate::lru::item_size::ItemSize;
-use std::borrow::Borrow;
-use std::hash::{Hash, Hasher};
-use std::num::NonZeroUsize;
+use crate::lru::lfu_cache::LFUCache;
+use crate::lru::lru_cache::LRUCache;
+use crate::lru::slru_cache::SLRUCache;
+use crate::lru::ttl_cache::TTLCache;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::convert::Infallible;
+use core::hash::{Hash, Hasher};
+use core::num::NonZeroUsize;
+#[cfg(feature = "std")]
+use std::ffi::{OsStr, OsString};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "std")]
 pub type DefaultHasher = std::collections::hash_map::RandomState;
 
+/// Without std there is no randomness to seed a hasher from, so the caches hash with a fixed
+/// FNV-1a. Keys an attacker picks can then collide on purpose: give such caches a keyed hasher
+/// through `with_hasher`.
+#[cfg(not(feature = "std"))]
+pub type DefaultHasher = core::hash::BuildHasherDefault<FnvHasher>;
+
+/// 64-bit FNV-1a, the `DefaultHasher` of builds without std.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self { FnvHasher(0xcbf2_9ce4_8422_2325) }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 { self.0 }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
 /// Struct used to hold a reference to a key.
 #[derive(Clone)]
 pub struct KeyRef<K> {
@@ -33,9 +76,32 @@ impl<T: ?Sized> Borrow<T> for KeyRef<Box<T>> {
     fn borrow(&self) -> &T { unsafe { &*self.k } }
 }
 
+#[cfg(target_has_atomic = "ptr")]
+impl<T: ?Sized> Borrow<T> for KeyRef<Arc<T>> {
+    fn borrow(&self) -> &T { unsafe { &*self.k } }
+}
+
+impl<T: ?Sized> Borrow<T> for KeyRef<Rc<T>> {
+    fn borrow(&self) -> &T { unsafe { &*self.k } }
+}
+
+impl<T: ?Sized + ToOwned> Borrow<T> for KeyRef<Cow<'_, T>> {
+    fn borrow(&self) -> &T { unsafe { &*self.k } }
+}
+
 impl<T> Borrow<[T]> for KeyRef<Vec<T>> {
     fn borrow(&self) -> &[T] { unsafe { &*self.k } }
 }
+
+#[cfg(feature = "std")]
+impl Borrow<Path> for KeyRef<PathBuf> {
+    fn borrow(&self) -> &Path { unsafe { &*self.k } }
+}
+
+#[cfg(feature = "std")]
+impl Borrow<OsStr> for KeyRef<OsString> {
+    fn borrow(&self) -> &OsStr { unsafe { &*self.k } }
+}
 pub trait Cache<K, V, S = DefaultHasher>
 where
     K: Hash + Eq,
@@ -44,9 +110,16 @@ where
     /// Returns the number of key-value pairs that are currently in the the cache.
     fn len(&self) -> usize;
 
-    /// Returns the maximum number of key-value pairs the cache can hold.
+    /// Returns the maximum number of key-value pairs the cache can hold, `NonZeroUsize::MAX`
+    /// for an unbounded cache; `limit` tells the two apart.
     fn cap(&self) -> NonZeroUsize;
 
+    /// Returns the capacity the cache evicts at, or `None` if it never evicts to make room.
+    fn limit(&self) -> Option<NonZeroUsize> { Some(self.cap()) }
+
+    /// Returns whether the cache is unbounded, see `limit`.
+    fn is_unbounded(&self) -> bool { self.limit().is_none() }
+
     /// Returns a bool indicating whether the cache is empty or not.
     fn is_empty(&self) -> bool;
 
@@ -59,6 +132,16 @@ where
     /// the old entry's key-value pair. Otherwise, returns `None`.
     fn push(&mut self, k: K, v: V) -> Option<(K, V)>;
 
+    /// Puts a key-value pair only if the key is missing, evicting like `put`. Otherwise the pair
+    /// is handed back and the cache is left as it was, the existing entry not even promoted.
+    fn put_if_absent(&mut self, k: K, v: V) -> Result<(), (K, V)> {
+        if self.contains(&k) {
+            return Err((k, v));
+        }
+        self.put(k, v);
+        Ok(())
+    }
+
     /// Returns a reference to the value of the key in the cache or `None` if it is not
     /// present in the cache.
     fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
@@ -66,6 +149,12 @@ where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    /// Like `get`, but also returns the key as it is stored in the cache.
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
     /// Returns a mutable reference to the value of the key in the cache or `None` if it
     /// is not present in the cache.
     fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
@@ -79,7 +168,10 @@ where
     /// the list and a reference is returned.
     fn get_or_insert<F>(&'_ mut self, k: K, f: F) -> &'_ V
     where
-        F: FnOnce() -> V;
+        F: FnOnce() -> V,
+    {
+        self.get_or_insert_mut(k, f)
+    }
 
     /// Returns a mutable reference to the value of the key in the cache if it is
     /// present in the cache.
@@ -87,16 +179,77 @@ where
     /// the list and a mutable reference is returned.
     fn get_or_insert_mut<F>(&'_ mut self, k: K, f: F) -> &'_ mut V
     where
-        F: FnOnce() -> V;
+        F: FnOnce() -> V,
+    {
+        self.get_or_insert_with_key_mut(k, |_| f())
+    }
+
+    /// Like `get_or_insert`, but `f` receives the key that will be stored. `f` only runs when
+    /// the key is missing.
+    fn get_or_insert_with_key<F>(&'_ mut self, k: K, f: F) -> &'_ V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        self.get_or_insert_with_key_mut(k, f)
+    }
+
+    /// Like `get_or_insert_mut`, but `f` receives the key that will be stored. `f` only runs
+    /// when the key is missing.
+    fn get_or_insert_with_key_mut<F>(&'_ mut self, k: K, f: F) -> &'_ mut V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        // an `Infallible` error cannot be built, so this always matches
+        let Ok(v) = self.try_get_or_insert_with_key_mut(k, |k| Ok::<V, Infallible>(f(k)));
+        v
+    }
+
+    /// Like `get_or_insert`, but `f` may fail. Its error is returned and leaves the cache
+    /// untouched: no entry is evicted unless `f` succeeded.
+    fn try_get_or_insert<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.try_get_or_insert_mut(k, f).map(|v| &*v)
+    }
+
+    /// Like `get_or_insert_mut`, but `f` may fail. Its error is returned and leaves the cache
+    /// untouched: no entry is evicted unless `f` succeeded.
+    fn try_get_or_insert_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.try_get_or_insert_with_key_mut(k, |_| f())
+    }
+
+    /// The `get_or_insert` every other one is built on: `f` receives the key and may fail. The
+    /// key is looked up once, so an entry expiring meanwhile cannot be found and then missed.
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>;
 
     /// Returns a reference to the value corresponding to the key in the cache or `None` if it is
     /// not present in the cache. Unlike `get`, `peek` does not update the Cache list so the key's
     /// position will be unchanged.
-    fn peek<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
     where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    /// Like `peek`, but also returns the key as it is stored in the cache.
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Looks up every key of `keys` like `peek`, in order, with shared access only. The recency
+    /// order is left alone.
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>;
+
     /// Returns a mutable reference to the value corresponding to the key in the cache or `None`
     /// if it is not present in the cache. Unlike `get_mut`, `peek_mut` does not update the Cache
     /// list so the key's position will be unchanged.
@@ -108,7 +261,21 @@ where
     /// Returns the value corresponding to the least recently used item or `None` if the
     /// cache is empty. Like `peek`, `peek_last` does not update the Cache list so the item's
     /// position will be unchanged.
-    fn peek_last(&'_ mut self) -> Option<(&'_ K, &'_ V)>;
+    fn peek_last(&self) -> Option<(&K, &V)>;
+
+    /// Like `peek_last`, with mutable access to the value. The entry stays where it is, still
+    /// the next one `pop_last` returns.
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)>;
+
+    /// Returns the most recently used item or `None` if the cache is empty, without updating
+    /// the Cache list.
+    fn peek_first(&self) -> Option<(&K, &V)>;
+
+    /// Returns the most recently used key without promoting it, or `None` if the cache is empty.
+    fn first_key(&self) -> Option<&K>;
+
+    /// Returns the least recently used key, the next to be evicted, without promoting it.
+    fn last_key(&self) -> Option<&K>;
 
     /// Returns a bool indicating whether the given key is in the cache. Does not update the
     /// Cache.
@@ -135,12 +302,64 @@ where
     /// used item or `None` if the cache is empty.
     fn pop_last(&mut self) -> Option<(K, V)>;
 
+    /// Removes up to `n` of the least recently used items like repeated `pop_last` calls, and
+    /// returns them least recently used first.
+    fn pop_last_n(&mut self, n: usize) -> Vec<(K, V)> {
+        let mut popped = Vec::with_capacity(n.min(self.len()));
+        while popped.len() < n {
+            match self.pop_last() {
+                Some(entry) => popped.push(entry),
+                None => break,
+            }
+        }
+        popped
+    }
+
+    /// Removes the least recently used items for as long as `f` holds for them, like
+    /// `pop_last` after each `peek_last` returning true, and returns them least recently used
+    /// first. Stops at the first item `f` rejects, which stays, or once the cache is empty.
+    fn pop_last_while<F>(&mut self, mut f: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut popped = Vec::new();
+        while self.peek_last().is_some_and(|(k, v)| f(k, v)) {
+            popped.extend(self.pop_last());
+        }
+        popped
+    }
+
+    /// Removes and returns the key and value of the most recently used item or `None` if the
+    /// cache is empty.
+    fn pop_first(&mut self) -> Option<(K, V)>;
+
     /// Marks the key as the last eliminated one.
     fn promote<Q>(&mut self, k: &Q)
     where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    /// Marks the key as the last eliminated one if it is present, and returns whether it was.
+    fn touch<Q>(&mut self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let found = self.contains(k);
+        self.promote(k);
+        found
+    }
+
+    /// Touches every key in order and returns how many were present.
+    fn touch_many<'q, Q, I>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = &'q Q>,
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'q,
+    {
+        keys.into_iter().filter(|k| self.touch(*k)).count()
+    }
+
     /// Marks the key as the first eliminated one.
     fn demote<Q>(&mut self, k: &Q)
     where
@@ -154,3 +373,140 @@ where
     /// Clears the contents of the cache.
     fn clear(&mut self);
 }
+
+/// The part of `Cache` usable through `dyn`, to pick a cache policy at runtime behind a
+/// `Box<dyn DynCache<K, V>>`. Keys are looked up as `&Q`, `&K` unless another borrowed form is
+/// chosen (`DynCache<String, V, str>`), the generic methods of `Cache` not being object safe.
+/// Every cache of this crate is a `DynCache`, whatever its hasher.
+pub trait DynCache<K, V, Q: ?Sized = K> {
+    /// See `Cache::get`.
+    fn get(&mut self, k: &Q) -> Option<&V>;
+
+    /// See `Cache::put`.
+    fn put(&mut self, k: K, v: V) -> Option<V>;
+
+    /// See `Cache::pop`.
+    fn pop(&mut self, k: &Q) -> Option<V>;
+
+    fn contains(&self, k: &Q) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool { self.len() == 0 }
+
+    fn cap(&self) -> NonZeroUsize;
+
+    /// See `Cache::pop_last`.
+    fn pop_last(&mut self) -> Option<(K, V)>;
+
+    fn clear(&mut self);
+}
+
+// one impl per cache type: over every `C: Cache<K, V, S>`, `S` would be left unconstrained
+macro_rules! impl_dyn_cache {
+    ($($cache:ident),*) => {$(
+        impl<K, V, S, Q> DynCache<K, V, Q> for $cache<K, V, S>
+        where
+            K: Hash + Eq,
+            V: ItemSize,
+            Q: Hash + Eq + ?Sized,
+            KeyRef<K>: Borrow<Q>,
+            $cache<K, V, S>: Cache<K, V, S>,
+        {
+            fn get(&mut self, k: &Q) -> Option<&V> { Cache::get(self, k) }
+
+            fn put(&mut self, k: K, v: V) -> Option<V> { Cache::put(self, k, v) }
+
+            fn pop(&mut self, k: &Q) -> Option<V> { Cache::pop(self, k) }
+
+            fn contains(&self, k: &Q) -> bool { Cache::contains(self, k) }
+
+            fn len(&self) -> usize { Cache::len(self) }
+
+            fn is_empty(&self) -> bool { Cache::is_empty(self) }
+
+            fn cap(&self) -> NonZeroUsize { Cache::cap(self) }
+
+            fn pop_last(&mut self) -> Option<(K, V)> { Cache::pop_last(self) }
+
+            fn clear(&mut self) { Cache::clear(self) }
+        }
+    )*};
+}
+
+impl_dyn_cache!(LRUCache, FIFOCache, LFUCache, SLRUCache, TTLCache, ClockCache);
+
+#[cfg(test)]
+mod tests {
+    use std::hash::BuildHasherDefault;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, RwLock};
+
+    use super::DynCache;
+    use crate::lru::fifo_cache::FIFOCache;
+    use crate::lru::lfu_cache::LFUCache;
+    use crate::lru::lru_cache::{CacheMode, LRUCache};
+
+    type Backend = Box<dyn DynCache<String, Vec<u8>> + Send + Sync>;
+
+    // Puts three keys in a cache of two after reading the first one, and returns the key left
+    // out.
+    fn evicted(cache: &RwLock<Backend>) -> String {
+        let mut cache = cache.write().unwrap();
+        cache.put("a".to_string(), b"1".to_vec());
+        cache.put("b".to_string(), b"2".to_vec());
+        assert_eq!(cache.get(&"a".to_string()), Some(&b"1".to_vec()));
+        cache.put("c".to_string(), b"3".to_vec());
+        assert_eq!(cache.len(), 2);
+        ["a", "b", "c"].into_iter().find(|k| !cache.contains(&k.to_string())).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_policies_behind_one_handle() {
+        let cap = NonZeroUsize::new(2).unwrap();
+        let shared: Arc<RwLock<Backend>> = Arc::new(RwLock::new(Box::new(LRUCache::new(cap))));
+        assert_eq!(evicted(&shared), "b");
+
+        *shared.write().unwrap() = Box::new(FIFOCache::new(cap));
+        assert_eq!(evicted(&shared), "a");
+
+        *shared.write().unwrap() = Box::new(LFUCache::new(cap));
+        assert_eq!(evicted(&shared), "b");
+
+        // the hasher does not show in the boxed type
+        let hasher = BuildHasherDefault::<std::hash::DefaultHasher>::default();
+        *shared.write().unwrap() = Box::new(LRUCache::with_hasher(CacheMode::ItemLimit, cap, hasher));
+        assert_eq!(evicted(&shared), "b");
+
+        // keys looked up by a borrowed form
+        let mut by_str: Box<dyn DynCache<String, Vec<u8>, str>> = Box::new(LFUCache::new(cap));
+        by_str.put("a".to_string(), b"1".to_vec());
+        assert_eq!(by_str.get("a"), Some(&b"1".to_vec()));
+        assert!(!by_str.contains("b"));
+        assert_eq!(by_str.pop("a"), Some(b"1".to_vec()));
+
+        let mut cache = shared.write().unwrap();
+        assert_eq!(cache.cap(), cap);
+        assert_eq!(cache.pop(&"a".to_string()), Some(b"1".to_vec()));
+        assert_eq!(cache.pop_last(), Some(("c".to_string(), b"3".to_vec())));
+        cache.put("d".to_string(), Vec::new());
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_fnv_hasher() {
+        use std::hash::Hasher;
+
+        use super::FnvHasher;
+
+        let hash = |bytes: &[u8]| {
+            let mut h = FnvHasher::default();
+            h.write(bytes);
+            h.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::lru::item_size::ItemSize;
+use bytes::{Bytes, BytesMut};
+use std::ops::{Bound, RangeBounds};
+
+/// Default segment size used by `ChunkedBytes`: 1 MiB.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// A byte string stored as a list of fixed-size segments (a rope).
+/// Every segment except the last one holds exactly `chunk_size` bytes, so large values never
+/// need one contiguous allocation and byte offsets map onto segments by division.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedBytes {
+    chunks: Vec<Bytes>,
+    len: usize,
+    chunk_size: usize,
+}
+
+impl ChunkedBytes {
+    /// Creates an empty value with the given segment size.
+    pub fn new(chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+        ChunkedBytes {
+            chunks: Vec::new(),
+            len: 0,
+            chunk_size,
+        }
+    }
+
+    /// Splits `data` into segments of `chunk_size` bytes. Segments share `data`'s allocation.
+    pub fn from_bytes(data: Bytes, chunk_size: usize) -> Self {
+        let mut value = ChunkedBytes::new(chunk_size);
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = (offset + chunk_size).min(data.len());
+            value.chunks.push(data.slice(offset..end));
+            offset = end;
+        }
+        value.len = data.len();
+        value
+    }
+
+    /// Total length in bytes.
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Size of every segment but the last.
+    pub fn chunk_size(&self) -> usize { self.chunk_size }
+
+    /// An iterator over the segments in order.
+    pub fn chunks(&self) -> Chunks<'_> { Chunks { inner: self.chunks.iter() } }
+
+    /// Returns the segments covering `range`, trimmed to its bounds. No bytes are copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or decreasing.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Vec<Bytes> {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len,
+        };
+        assert!(start <= end && end <= self.len, "range {}..{} out of bounds for length {}", start, end, self.len);
+
+        let mut out = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            let idx = pos / self.chunk_size;
+            let chunk_start = idx * self.chunk_size;
+            let chunk = &self.chunks[idx];
+            let from = pos - chunk_start;
+            let to = (end - chunk_start).min(chunk.len());
+            out.push(chunk.slice(from..to));
+            pos = chunk_start + to;
+        }
+        out
+    }
+
+    /// Copies the value into one contiguous buffer.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.len);
+        self.chunks.iter().for_each(|c| out.extend_from_slice(c));
+        out
+    }
+}
+
+impl Default for ChunkedBytes {
+    fn default() -> Self { ChunkedBytes::new(DEFAULT_CHUNK_SIZE) }
+}
+
+impl From<Vec<u8>> for ChunkedBytes {
+    fn from(value: Vec<u8>) -> Self { ChunkedBytes::from_bytes(Bytes::from(value), DEFAULT_CHUNK_SIZE) }
+}
+
+impl ItemSize for ChunkedBytes {
+    fn size_of(&self) -> usize { self.len }
+}
+
+/// An iterator over the segments of a `ChunkedBytes`.
+#[derive(Clone)]
+pub struct Chunks<'a> {
+    inner: std::slice::Iter<'a, Bytes>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a Bytes;
+
+    fn next(&mut self) -> Option<Self::Item> { self.inner.next() }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
+}
+
+impl DoubleEndedIterator for Chunks<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.inner.next_back() }
+}
+
+impl ExactSizeIterator for Chunks<'_> {}
+
+/// Assembles a `ChunkedBytes` from pieces of arbitrary size, e.g. a streamed request body.
+/// At most one partially filled segment is allocated at a time.
+#[derive(Debug)]
+pub struct ChunkedBytesBuilder {
+    value: ChunkedBytes,
+    current: BytesMut,
+}
+
+impl ChunkedBytesBuilder {
+    pub fn new(chunk_size: usize) -> Self {
+        ChunkedBytesBuilder {
+            value: ChunkedBytes::new(chunk_size),
+            current: BytesMut::new(),
+        }
+    }
+
+    /// Appends `data`, sealing segments as they fill up.
+    pub fn extend_from_slice(&mut self, mut data: &[u8]) {
+        let chunk_size = self.value.chunk_size;
+        while !data.is_empty() {
+            if self.current.capacity() == 0 {
+                self.current.reserve(chunk_size);
+            }
+            let n = (chunk_size - self.current.len()).min(data.len());
+            self.current.extend_from_slice(&data[..n]);
+            self.value.len += n;
+            data = &data[n..];
+            if self.current.len() == chunk_size {
+                self.value.chunks.push(self.current.split().freeze());
+            }
+        }
+    }
+
+    /// Number of bytes appended so far.
+    pub fn len(&self) -> usize { self.value.len }
+
+    pub fn is_empty(&self) -> bool { self.value.len == 0 }
+
+    /// Bytes currently allocated by the builder: sealed segments plus the open one.
+    pub fn allocated(&self) -> usize {
+        self.value.chunks.iter().map(Bytes::len).sum::<usize>() + self.current.capacity()
+    }
+
+    pub fn finish(mut self) -> ChunkedBytes {
+        if !self.current.is_empty() {
+            self.value.chunks.push(self.current.split().freeze());
+        }
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedBytes, ChunkedBytesBuilder};
+    use crate::lru::item_size::ItemSize;
+    use bytes::Bytes;
+
+    fn sample(len: usize) -> Vec<u8> { (0..len).map(|i| (i % 251) as u8).collect() }
+
+    fn concat(parts: Vec<Bytes>) -> Vec<u8> { parts.iter().flat_map(|b| b.iter().copied()).collect() }
+
+    #[test]
+    fn test_from_bytes_segments() {
+        let data = sample(10);
+        let value = ChunkedBytes::from_bytes(Bytes::from(data.clone()), 4);
+        assert_eq!(value.len(), 10);
+        assert_eq!(value.size_of(), 10);
+        assert_eq!(value.chunks().map(Bytes::len).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert_eq!(value.to_vec(), data);
+        assert!(ChunkedBytes::new(4).is_empty());
+    }
+
+    #[test]
+    fn test_slice_across_chunk_boundaries() {
+        let data = sample(10);
+        let value = ChunkedBytes::from_bytes(Bytes::from(data.clone()), 4);
+
+        // inside one chunk
+        assert_eq!(concat(value.slice(1..3)), data[1..3]);
+        // exactly one chunk
+        assert_eq!(value.slice(4..8).len(), 1);
+        assert_eq!(concat(value.slice(4..8)), data[4..8]);
+        // spanning three chunks
+        let parts = value.slice(3..9);
+        assert_eq!(parts.iter().map(Bytes::len).collect::<Vec<_>>(), vec![1, 4, 1]);
+        assert_eq!(concat(parts), data[3..9]);
+        // inclusive, open and empty ranges
+        assert_eq!(concat(value.slice(7..=9)), data[7..]);
+        assert_eq!(concat(value.slice(..)), data);
+        assert!(value.slice(5..5).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_slice_out_of_bounds() {
+        let value = ChunkedBytes::from_bytes(Bytes::from(sample(10)), 4);
+        value.slice(5..11);
+    }
+
+    #[test]
+    fn test_builder_matches_input() {
+        let data = sample(1000);
+        let mut builder = ChunkedBytesBuilder::new(64);
+        for piece in data.chunks(7) {
+            builder.extend_from_slice(piece);
+        }
+        assert_eq!(builder.len(), 1000);
+        let value = builder.finish();
+        assert_eq!(value.to_vec(), data);
+        assert!(value.chunks().rev().skip(1).all(|c| c.len() == 64));
+        assert_eq!(value, ChunkedBytes::from_bytes(Bytes::from(data), 64));
+    }
+
+    #[test]
+    fn test_builder_allocation_stays_bounded() {
+        let chunk_size = 256;
+        let mut builder = ChunkedBytesBuilder::new(chunk_size);
+        for piece in sample(10_000).chunks(100) {
+            builder.extend_from_slice(piece);
+            // never more than one open segment on top of the data stored so far
+            assert!(builder.allocated() <= builder.len() + chunk_size);
+        }
+        let value = builder.finish();
+        assert_eq!(value.len(), 10_000);
+        assert_eq!(value.chunks().len(), 10_000_usize.div_ceil(chunk_size));
+    }
+}
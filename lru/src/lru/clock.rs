@@ -0,0 +1,108 @@
+#[cfg(target_has_atomic = "64")]
+use alloc::boxed::Box;
+use core::fmt;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+pub use std::time::Instant;
+
+/// Source of time for expiry decisions, injectable so tests don't have to sleep.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.debug_struct("Clock").field("now", &self.now()).finish() }
+}
+
+/// A point in time, in builds without std: how long after its clock's origin, boot say, it is.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    /// The instant `since` after the origin, for `Clock`s reading a hardware timer.
+    pub const fn from_origin(since: Duration) -> Self { Instant(since) }
+
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> { self.0.checked_sub(earlier.0) }
+
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration { self.0.saturating_sub(earlier.0) }
+}
+
+#[cfg(not(feature = "std"))]
+impl core::ops::Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, by: Duration) -> Instant { Instant(self.0 + by) }
+}
+
+/// The real monotonic clock.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant { Instant::now() }
+}
+
+/// The clock of caches built without std, which have no system clock to read. It never leaves
+/// the origin, so TTLs only run out once `set_clock` installs a clock that moves.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoppedClock;
+
+#[cfg(not(feature = "std"))]
+impl Clock for StoppedClock {
+    fn now(&self) -> Instant { Instant::default() }
+}
+
+// The clock a cache reads until it is given another.
+#[cfg(feature = "std")]
+pub(crate) static DEFAULT_CLOCK: &dyn Clock = &SystemClock;
+#[cfg(not(feature = "std"))]
+pub(crate) static DEFAULT_CLOCK: &dyn Clock = &StoppedClock;
+
+/// A clock that only moves when told to. Caches hold their clock as a `&'static dyn Clock`, so
+/// either keep it in a `static` or get one from `ManualClock::leaked`. Needs 64-bit atomics.
+#[cfg(target_has_atomic = "64")]
+pub struct ManualClock {
+    origin: Instant,
+    // elapsed counts the nanoseconds the clock was advanced by since `origin`.
+    elapsed: AtomicU64,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl ManualClock {
+    pub fn new() -> Self { ManualClock { origin: DEFAULT_CLOCK.now(), elapsed: AtomicU64::new(0) } }
+
+    /// A new clock that lives for the rest of the program, ready for `set_clock`. Meant for
+    /// tests: each call leaks one `ManualClock`.
+    pub fn leaked() -> &'static ManualClock { Box::leak(Box::new(ManualClock::new())) }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let nanos = u64::try_from(by.as_nanos()).unwrap_or(u64::MAX);
+        self.elapsed.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Default for ManualClock {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant { self.origin + Duration::from_nanos(self.elapsed.load(Ordering::Relaxed)) }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ManualClock").field("now", &self.now()).finish()
+    }
+}
@@ -0,0 +1,559 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+use core::num::NonZeroUsize;
+
+use hashbrown::HashTable;
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::item_size::ItemSize;
+
+// No slot: the hand of an empty ring, the end of the free list.
+const NIL: usize = usize::MAX;
+
+// A slot of the ring of a `ClockCache`.
+struct ClockSlot<K, V> {
+    // kv is `None` in a free slot.
+    kv: Option<(K, V)>,
+    // referenced is set by reads and cleared when the hand passes over the slot.
+    referenced: bool,
+    // prev and next are the neighbours in the ring; next is the next free slot in a free one.
+    prev: usize,
+    next: usize,
+}
+
+impl<K, V> ClockSlot<K, V> {
+    fn key(&self) -> &K { &self.pair().0 }
+
+    fn pair(&self) -> &(K, V) { self.kv.as_ref().expect("the slot holds an entry") }
+
+    fn value_mut(&mut self) -> &mut V { &mut self.kv.as_mut().expect("the slot holds an entry").1 }
+}
+
+/// A cache approximating LRU with second-chance eviction. Entries sit in a ring; a read only
+/// sets the entry's reference bit, and the hand moves around the ring clearing the bits it
+/// finds set, resting on the first entry whose bit is already clear, the next to be evicted.
+/// New entries start with a clear bit, right behind the hand.
+///
+/// The `Cache` methods order entries as the hand meets them: `peek_last` and `pop_last` see the
+/// next to be evicted, `peek_first` and `pop_first` the entry the hand reaches last.
+pub struct ClockCache<K, V, S = DefaultHasher> {
+    // map holds the slots of the entries, hashed and compared by the keys in them.
+    map: HashTable<usize>,
+    hasher: S,
+    // slots holds the ring, linked through `prev` and `next`, and the free slots, chained from
+    // `free`; slots never move, as the ring is only rebuilt by `clear`.
+    slots: Vec<ClockSlot<K, V>>,
+    free: usize,
+    // hand is on an unreferenced entry whenever the ring is not empty, see `settle`.
+    hand: usize,
+    cap: NonZeroUsize,
+    // swept counts the slots the hand moved over, to measure what evictions cost
+    swept: usize,
+}
+
+impl<K: Hash + Eq, V> ClockCache<K, V> {
+    /// Creates a cache holding at most `cap` entries.
+    pub fn new(cap: NonZeroUsize) -> Self { ClockCache::with_hasher(cap, DefaultHasher::default()) }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> ClockCache<K, V, S> {
+    /// Like `new`, hashing keys with `hasher`.
+    pub fn with_hasher(cap: NonZeroUsize, hasher: S) -> Self {
+        ClockCache {
+            map: HashTable::with_capacity(cap.get()),
+            hasher,
+            slots: Vec::with_capacity(cap.get()),
+            free: NIL,
+            hand: NIL,
+            cap,
+            swept: 0,
+        }
+    }
+
+    /// Whether `k` was read since the hand last passed it, `None` if it is not present.
+    pub fn is_referenced<Q>(&self, k: &Q) -> Option<bool>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(k).map(|i| self.slots[i].referenced)
+    }
+
+    /// Entries in the order the hand meets them, the next to be evicted first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut i = self.hand;
+        (0..self.map.len()).map(move |_| {
+            let (k, v) = self.slots[i].pair();
+            i = self.slots[i].next;
+            (k, v)
+        })
+    }
+
+    fn find<Q>(&self, k: &Q) -> Option<usize>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let holds = |&i: &usize| <KeyRef<K> as Borrow<Q>>::borrow(&KeyRef { k: self.slots[i].key() }) == k;
+        self.map.find(self.hasher.hash_one(k), holds).copied()
+    }
+
+    fn entry(&self, i: usize) -> (&K, &V) {
+        let (k, v) = self.slots[i].pair();
+        (k, v)
+    }
+
+    // Sets the bit of slot `i` on a read. The hand does not rest on a referenced entry, so it
+    // moves on if it was there.
+    fn reference(&mut self, i: usize) {
+        self.slots[i].referenced = true;
+        if i == self.hand {
+            self.settle();
+        }
+    }
+
+    // Moves the hand past the referenced entries, clearing their bits, to the first one that
+    // is not. Each step undoes one read, so a full turn only follows as many reads.
+    fn settle(&mut self) {
+        while self.hand != NIL && self.slots[self.hand].referenced {
+            self.slots[self.hand].referenced = false;
+            self.hand = self.slots[self.hand].next;
+            self.swept += 1;
+        }
+    }
+
+    // Takes slot `i` out of the ring and the map, and frees it.
+    fn take(&mut self, i: usize) -> (K, V) {
+        let hash = self.hasher.hash_one(self.slots[i].key());
+        if let Ok(found) = self.map.find_entry(hash, |&other| other == i) {
+            found.remove();
+        }
+        let ClockSlot { prev, next, .. } = self.slots[i];
+        if next == i {
+            self.hand = NIL;
+        } else {
+            self.slots[prev].next = next;
+            self.slots[next].prev = prev;
+            if self.hand == i {
+                self.hand = next;
+                self.settle();
+            }
+        }
+        let free = ClockSlot { kv: None, referenced: false, prev: NIL, next: self.free };
+        self.free = i;
+        mem::replace(&mut self.slots[i], free).kv.expect("the slot holds an entry")
+    }
+
+    // Puts a new, unreferenced entry right behind the hand, evicting when the cache is full.
+    fn insert(&mut self, k: K, v: V) -> (usize, Option<(K, V)>) {
+        let evicted = if self.map.len() >= self.cap.get() { self.evict() } else { None };
+        let hash = self.hasher.hash_one(&k);
+        let slot = ClockSlot { kv: Some((k, v)), referenced: false, prev: NIL, next: NIL };
+        let i = match self.free {
+            NIL => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            }
+            i => {
+                self.free = mem::replace(&mut self.slots[i], slot).next;
+                i
+            }
+        };
+        match self.hand {
+            NIL => {
+                self.slots[i].prev = i;
+                self.slots[i].next = i;
+                self.hand = i;
+            }
+            hand => {
+                let prev = self.slots[hand].prev;
+                self.slots[i].prev = prev;
+                self.slots[i].next = hand;
+                self.slots[prev].next = i;
+                self.slots[hand].prev = i;
+            }
+        }
+        let ClockCache { map, hasher, slots, .. } = self;
+        map.insert_unique(hash, i, |&i| hasher.hash_one(slots[i].key()));
+        (i, evicted)
+    }
+
+    // Takes the entry under the hand, which moves on to the next unreferenced one.
+    fn evict(&mut self) -> Option<(K, V)> {
+        if self.hand == NIL {
+            return None;
+        }
+        self.swept += 1;
+        Some(self.take(self.hand))
+    }
+}
+
+impl<K, V, S> Cache<K, V, S> for ClockCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn len(&self) -> usize { self.map.len() }
+
+    fn cap(&self) -> NonZeroUsize { self.cap }
+
+    fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Puts `k` in a free slot, or replaces its value and sets its bit if it was already present.
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        match self.find(&k) {
+            Some(i) => {
+                self.reference(i);
+                Some(mem::replace(self.slots[i].value_mut(), v))
+            }
+            None => {
+                self.insert(k, v);
+                None
+            }
+        }
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        match self.find(&k) {
+            Some(i) => {
+                self.reference(i);
+                Some((k, mem::replace(self.slots[i].value_mut(), v)))
+            }
+            None => self.insert(k, v).1,
+        }
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_mut(k).map(|v| &*v)
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(k)?;
+        self.reference(i);
+        Some(self.entry(i))
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(k)?;
+        self.reference(i);
+        Some(self.slots[i].value_mut())
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        let i = match self.find(&k) {
+            Some(i) => {
+                self.reference(i);
+                i
+            }
+            None => {
+                let v = f(&k)?;
+                self.insert(k, v).0
+            }
+        };
+        Ok(self.slots[i].value_mut())
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_key_value(k).map(|(_, v)| v)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(k).map(|i| self.entry(i))
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter().map(|k| self.peek(k)).collect()
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(k)?;
+        Some(self.slots[i].value_mut())
+    }
+
+    /// The next entry to be evicted.
+    fn peek_last(&self) -> Option<(&K, &V)> { (self.hand != NIL).then(|| self.entry(self.hand)) }
+
+    /// Like `peek_last`, leaving the bits alone too.
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> {
+        let slot = self.slots.get_mut(self.hand)?;
+        slot.kv.as_mut().map(|(k, v)| (&*k, v))
+    }
+
+    /// The entry right behind the hand, the last one it reaches.
+    fn peek_first(&self) -> Option<(&K, &V)> { (self.hand != NIL).then(|| self.entry(self.slots[self.hand].prev)) }
+
+    fn first_key(&self) -> Option<&K> { self.peek_first().map(|(k, _)| k) }
+
+    fn last_key(&self) -> Option<&K> { self.peek_last().map(|(k, _)| k) }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(k).is_some()
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop_entry(k).map(|(_, v)| v)
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let i = self.find(k)?;
+        Some(self.take(i))
+    }
+
+    /// Evicts the entry under the hand, which then runs on, clearing the bits it passes, to the
+    /// first unreferenced entry.
+    fn pop_last(&mut self) -> Option<(K, V)> { self.evict() }
+
+    /// Removes the entry right behind the hand, leaving the hand and the bits alone.
+    fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.hand == NIL {
+            return None;
+        }
+        Some(self.take(self.slots[self.hand].prev))
+    }
+
+    /// Sets the reference bit of `k`, like `get`.
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(i) = self.find(k) {
+            self.reference(i);
+        }
+    }
+
+    /// Clears the reference bit of `k`, so that the hand takes it the next time it gets there.
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(i) = self.find(k) {
+            self.slots[i].referenced = false;
+        }
+    }
+
+    /// Changes the capacity, running the hand to evict what no longer fits. The entries left
+    /// keep their order, starting at the hand.
+    fn resize(&mut self, cap: NonZeroUsize) {
+        while self.map.len() > cap.get() {
+            self.evict();
+        }
+        self.cap = cap;
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.slots.clear();
+        self.free = NIL;
+        self.hand = NIL;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::{ClockCache, NIL};
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    fn cache(cap: usize) -> ClockCache<&'static str, u32> { ClockCache::new(NonZeroUsize::new(cap).unwrap()) }
+
+    fn fill(cache: &mut ClockCache<&'static str, u32>, keys: &[&'static str]) {
+        for (i, &k) in keys.iter().enumerate() {
+            cache.put(k, i as u32);
+        }
+    }
+
+    // Checks that the ring links back, holds exactly the entries of the map and that the hand
+    // rests on an unreferenced entry.
+    fn assert_consistent<K: std::hash::Hash + Eq, V>(cache: &ClockCache<K, V>) {
+        if cache.map.is_empty() {
+            assert_eq!(cache.hand, NIL);
+            return;
+        }
+        assert!(!cache.slots[cache.hand].referenced);
+        let mut i = cache.hand;
+        for _ in 0..cache.map.len() {
+            assert_eq!(cache.find(cache.slots[i].key()), Some(i));
+            assert_eq!(cache.slots[cache.slots[i].next].prev, i);
+            i = cache.slots[i].next;
+        }
+        assert_eq!(i, cache.hand);
+        assert!(cache.map.len() <= cache.cap.get());
+    }
+
+    #[test]
+    fn test_unreferenced_slots_are_replaced() {
+        let mut cache = cache(3);
+        fill(&mut cache, &["a", "b", "c"]);
+        // none referenced: the hand takes the slot it is on
+        assert_eq!(cache.last_key(), Some(&"a"));
+        assert_eq!(cache.push("d", 3), Some(("a", 0)));
+
+        // b gets a second chance, c is taken
+        cache.get(&"b");
+        assert_eq!(cache.last_key(), Some(&"c"));
+        assert_eq!(cache.push("e", 4), Some(("c", 2)));
+        assert_eq!(cache.is_referenced(&"b"), Some(false));
+        // peeks do not set the bit
+        cache.peek(&"d");
+        assert_eq!(cache.is_referenced(&"d"), Some(false));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["d", "b", "e"]);
+    }
+
+    #[test]
+    fn test_hand_wraps_around() {
+        let mut cache = cache(3);
+        fill(&mut cache, &["a", "b", "c"]);
+        for k in ["a", "b", "c"] {
+            cache.promote(&k);
+        }
+        // a full turn clears every bit, the next one takes a
+        assert_eq!(cache.pop_last(), Some(("a", 0)));
+        assert_eq!(cache.is_referenced(&"c"), Some(false));
+        // d goes right behind the hand, which is on b
+        cache.put("d", 3);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["b", "c", "d"]);
+        // reading the entry under the hand moves the hand on
+        cache.get(&"b");
+        assert_eq!((cache.last_key(), cache.is_referenced(&"b")), (Some(&"c"), Some(false)));
+        // b is read again, and gets a second chance after the hand wraps around past d
+        cache.get(&"b");
+        assert_eq!(cache.push("e", 4), Some(("c", 2)));
+        assert_eq!(cache.push("f", 5), Some(("d", 3)));
+        assert_eq!(cache.is_referenced(&"b"), Some(false));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["e", "b", "f"]);
+        assert_eq!(cache.first_key(), Some(&"f"));
+        assert_eq!(cache.pop_first(), Some(("f", 5)));
+    }
+
+    #[test]
+    fn test_resize_keeps_the_hand_order() {
+        let mut cache = cache(4);
+        fill(&mut cache, &["a", "b", "c", "d"]);
+        cache.get(&"a");
+        cache.resize(NonZeroUsize::new(2).unwrap());
+        // a got a second chance, b and c did not
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["d", "a"]);
+        cache.resize(NonZeroUsize::new(3).unwrap());
+        cache.put("e", 4);
+        assert_eq!(*cache.get_or_insert("e", || 0), 4);
+        assert_eq!(cache.push("f", 5), Some(("d", 3)));
+        assert_consistent(&cache);
+        cache.clear();
+        assert!(cache.is_empty());
+        fill(&mut cache, &["x", "y", "z"]);
+        assert_eq!(cache.len(), 3);
+        assert_consistent(&cache);
+    }
+
+    #[test]
+    fn test_random_ops_keep_the_ring() {
+        let mut cache = ClockCache::new(NonZeroUsize::new(16).unwrap());
+        let mut state = 0x2545_f491_u64;
+        for _ in 0..5_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = (state >> 8) % 32;
+            match state % 7 {
+                0 | 1 => {
+                    cache.put(key, key);
+                }
+                2 | 3 => {
+                    cache.get(&key);
+                }
+                4 => cache.demote(&key),
+                5 => {
+                    cache.pop_first();
+                }
+                _ => {
+                    cache.pop(&key);
+                }
+            }
+            assert_consistent(&cache);
+        }
+        // freed slots are reused, and an eviction frees one before the new entry takes it
+        assert!(cache.slots.len() <= 16);
+    }
+
+    #[test]
+    fn test_operation_counts() {
+        let cap = NonZeroUsize::new(64).unwrap();
+        let mut clock = ClockCache::new(cap);
+        let mut lru = LRUCache::new(cap);
+        let mut hits = (0, 0);
+        let mut state = 0x9e37_79b9_u64;
+        for _ in 0..20_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // a hot set of 32 keys read half the time, cold keys put the other half
+            let key = if state.is_multiple_of(2) { (state >> 8) % 32 } else { 1_000 + (state >> 8) % 10_000 };
+            if clock.get(&key).is_some() {
+                hits.0 += 1;
+            } else {
+                clock.put(key, key);
+            }
+            if lru.get(&key).is_some() {
+                hits.1 += 1;
+            } else {
+                lru.put(key, key);
+            }
+        }
+        // the hand only passes over the bits reads set, a few slots per eviction on average
+        let evictions = 20_000 - hits.0 - clock.len();
+        assert!(clock.swept <= 3 * evictions, "{} slots swept for {} evictions", clock.swept, evictions);
+        // and the approximation keeps the hot set about as well as LRU does
+        assert!(hits.0 * 10 >= hits.1 * 9, "clock {} hits, lru {}", hits.0, hits.1);
+    }
+}
@@ -0,0 +1,40 @@
+use alloc::vec::Vec;
+
+use serde::Serialize;
+
+/// What `LRUCache::dump` includes besides keys and sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpOptions {
+    pub include_values: bool,
+    /// Values with a larger `ItemSize` are elided even when `include_values` is set.
+    pub max_value_bytes: usize,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self { DumpOptions { include_values: false, max_value_bytes: 256 } }
+}
+
+/// A point-in-time view of a cache for debugging, serializable when `K` and `V` are.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheDump<'a, K, V> {
+    pub capacity: usize,
+    pub len: usize,
+    /// Most recently used first.
+    pub entries: Vec<DumpEntry<'a, K, V>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DumpEntry<'a, K, V> {
+    /// 0 for the most recently used entry.
+    pub rank: usize,
+    pub key: &'a K,
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<&'a V>,
+    /// Milliseconds until the TTL passes; unset for entries without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u128>,
+    /// Milliseconds since the entry was last used; unset without the `entry-timestamps` feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_ms: Option<u128>,
+}
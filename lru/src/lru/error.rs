@@ -0,0 +1,93 @@
+use alloc::collections::TryReserveError;
+use core::fmt;
+
+/// Why a fallible cache operation such as `try_put` gave up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheError {
+    /// The map could not grow or a new entry could not be allocated. The cache is unchanged.
+    AllocError,
+    /// The value alone is larger than the budget of a capacity-mode cache.
+    TooLarge { size: usize, capacity: usize },
+    /// A constructor such as `try_new` was given a capacity of 0.
+    ZeroCapacity,
+}
+
+impl From<TryReserveError> for CacheError {
+    fn from(_: TryReserveError) -> Self { CacheError::AllocError }
+}
+
+impl From<hashbrown::TryReserveError> for CacheError {
+    fn from(_: hashbrown::TryReserveError) -> Self { CacheError::AllocError }
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::AllocError => write!(f, "memory allocation failed"),
+            CacheError::TooLarge { size, capacity } => write!(f, "value of {} bytes exceeds the {} byte capacity", size, capacity),
+            CacheError::ZeroCapacity => write!(f, "capacity must be positive"),
+        }
+    }
+}
+
+impl core::error::Error for CacheError {}
+
+/// What `LRUCache::check_consistency` found wrong. Positions count from the most recently
+/// used entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsistencyError {
+    /// A sigil links outwards, or the list does not end at the tail sigil.
+    BrokenSigil,
+    /// The node at `position`, or the tail sigil past the end, does not link back to the
+    /// node before it.
+    BrokenLink { position: usize },
+    /// The node at `position` is not the one the map holds for its key.
+    NotInMap { position: usize },
+    /// The list and the map hold different numbers of entries. A list running past the map,
+    /// maybe in a cycle, is reported at one more than the map.
+    LengthMismatch { list: usize, map: usize },
+    /// The weights charged to the entries do not add up to what the limiter counts as used.
+    WeightMismatch { entries: usize, limiter: usize },
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsistencyError::BrokenSigil => write!(f, "a sigil node is broken"),
+            ConsistencyError::BrokenLink { position } => write!(f, "the node at {} does not link back", position),
+            ConsistencyError::NotInMap { position } => write!(f, "the node at {} is not in the map", position),
+            ConsistencyError::LengthMismatch { list, map } => write!(f, "the list holds {} entries, the map {}", list, map),
+            ConsistencyError::WeightMismatch { entries, limiter } => {
+                write!(f, "the entries weigh {}, the limiter counts {}", entries, limiter)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ConsistencyError {}
+
+/// Why `LRUCacheBuilder::build` refused its settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// `max_items` or `max_bytes` was 0.
+    ZeroCapacity,
+    /// Both `max_items` and `max_bytes` were set; a cache is bounded by one of them.
+    ConflictingLimits,
+    /// `ttl` was zero, which would expire every entry as it is stored.
+    ZeroTtl,
+    /// The low watermark was not below the high one.
+    InvalidWatermarks,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::ZeroCapacity => write!(f, "capacity must be positive"),
+            BuildError::ConflictingLimits => write!(f, "max_items and max_bytes cannot both be set"),
+            BuildError::ZeroTtl => write!(f, "ttl must be positive"),
+            BuildError::InvalidWatermarks => write!(f, "the low watermark must be below the high one"),
+        }
+    }
+}
+
+impl core::error::Error for BuildError {}
@@ -0,0 +1,257 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::num::NonZeroUsize;
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+
+/// A cache evicting the entry put first. Reads never reorder anything, and putting a key that
+/// is already present replaces its value in place, so the order is the insertion order unless
+/// `promote` or `demote` override it.
+pub struct FIFOCache<K, V, S = DefaultHasher> {
+    // entries run from the one put last to the oldest, which is evicted first
+    inner: LRUCache<K, V, S>,
+}
+
+impl<K: Hash + Eq, V: ItemSize> FIFOCache<K, V> {
+    /// Creates a cache holding at most `cap` entries.
+    pub fn new(cap: NonZeroUsize) -> Self { FIFOCache::with_hasher(cap, DefaultHasher::default()) }
+}
+
+impl<K, V, S> FIFOCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// Like `new`, hashing keys with `hasher`.
+    pub fn with_hasher(cap: NonZeroUsize, hasher: S) -> Self {
+        FIFOCache { inner: LRUCache::with_hasher(CacheMode::ItemLimit, cap, hasher) }
+    }
+
+    /// Entries from the one put last to the oldest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> { self.inner.iter() }
+}
+
+impl<K, V, S> Cache<K, V, S> for FIFOCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn len(&self) -> usize { self.inner.len() }
+
+    fn cap(&self) -> NonZeroUsize { self.inner.cap() }
+
+    fn is_empty(&self) -> bool { self.inner.is_empty() }
+
+    /// Puts `k` at the front, or replaces its value where it is if it was already present.
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        match self.inner.contains(&k) {
+            true => self.inner.replace(&k, v),
+            false => self.inner.put(k, v),
+        }
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        match self.inner.contains(&k) {
+            true => self.inner.replace(&k, v).map(|old| (k, old)),
+            false => self.inner.push(k, v),
+        }
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek(k)
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_key_value(k)
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_mut(k)
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        self.inner.lookup_or_insert(k, f, false)
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek(k)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_key_value(k)
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        self.inner.peek_many(keys)
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_mut(k)
+    }
+
+    /// The oldest entry, the next to be evicted.
+    fn peek_last(&self) -> Option<(&K, &V)> { self.inner.peek_last() }
+
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> { self.inner.peek_last_mut() }
+
+    /// The entry put last.
+    fn peek_first(&self) -> Option<(&K, &V)> { self.inner.peek_first() }
+
+    fn first_key(&self) -> Option<&K> { self.inner.first_key() }
+
+    fn last_key(&self) -> Option<&K> { self.inner.last_key() }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.contains(k)
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.pop(k)
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.pop_entry(k)
+    }
+
+    /// Removes the oldest entry.
+    fn pop_last(&mut self) -> Option<(K, V)> { self.inner.pop_last() }
+
+    fn pop_first(&mut self) -> Option<(K, V)> { self.inner.pop_first() }
+
+    /// Moves `k` to the front, as if it had just been put.
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.promote(k);
+    }
+
+    /// Moves `k` to the back, making it the next to be evicted.
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.demote(k);
+    }
+
+    /// Changes the capacity, evicting the oldest entries that no longer fit.
+    fn resize(&mut self, cap: NonZeroUsize) { self.inner.resize(cap); }
+
+    fn clear(&mut self) { self.inner.clear(); }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::FIFOCache;
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    // Runs the same puts and reads against `cache` and returns the keys it evicted, in order.
+    fn evictions<C: Cache<&'static str, u32>>(cache: &mut C) -> Vec<&'static str> {
+        let mut evicted = Vec::new();
+        for (i, k) in ["a", "b", "c"].into_iter().enumerate() {
+            cache.put(k, i as u32);
+        }
+        cache.get(&"a");
+        *cache.get_mut(&"b").unwrap() += 10;
+        cache.get_or_insert("a", || 0);
+        for k in ["d", "e"] {
+            evicted.extend(cache.push(k, 0).map(|(k, _)| k));
+        }
+        cache.put("c", 20);
+        evicted.extend(cache.push("f", 0).map(|(k, _)| k));
+        evicted
+    }
+
+    #[test]
+    fn test_reads_do_not_reorder() {
+        let cap = NonZeroUsize::new(3).unwrap();
+        let mut fifo = FIFOCache::new(cap);
+        let mut lru = LRUCache::new(cap);
+        assert_eq!(evictions(&mut fifo), ["a", "b", "c"]);
+        assert_eq!(evictions(&mut lru), ["c", "b", "d"]);
+
+        assert_eq!(fifo.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["f", "e", "d"]);
+        assert_eq!(lru.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["f", "c", "e"]);
+    }
+
+    #[test]
+    fn test_put_replaces_in_place() {
+        let mut cache = FIFOCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(cache.put("a", 3), Some(1));
+        assert_eq!(cache.push("a", 4), Some(("a", 3)));
+        assert_eq!(cache.peek_last(), Some((&"a", &4)));
+        assert_eq!(cache.push("c", 5), Some(("a", 4)));
+    }
+
+    #[test]
+    fn test_promote_and_demote_override_the_order() {
+        let mut cache = FIFOCache::new(NonZeroUsize::new(3).unwrap());
+        for (i, k) in ["a", "b", "c"].into_iter().enumerate() {
+            cache.put(k, i as u32);
+        }
+        cache.promote(&"a");
+        cache.demote(&"c");
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(cache.push("d", 3), Some(("c", 2)));
+
+        cache.resize(NonZeroUsize::new(1).unwrap());
+        assert_eq!(cache.pop_first(), Some(("d", 3)));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,21 @@
+//! Values that can tell whether someone outside the cache still holds them.
+
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+
+/// Implemented by values that may be shared with readers outside the cache, such as a buffer
+/// being streamed to a client. See `LRUCache::skip_in_use_victims`.
+pub trait InUse {
+    /// Whether a reference other than the cache's own is alive.
+    fn in_use(&self) -> bool;
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T: ?Sized> InUse for Arc<T> {
+    fn in_use(&self) -> bool { Arc::strong_count(self) > 1 }
+}
+
+impl<T: ?Sized> InUse for Rc<T> {
+    fn in_use(&self) -> bool { Rc::strong_count(self) > 1 }
+}
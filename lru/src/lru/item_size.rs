@@ -1,3 +1,8 @@
+use alloc::string::String;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
 pub trait ItemSize {
     fn size_of(&self) -> usize;
 }
@@ -21,6 +26,9 @@ impl ItemSize for bool { fn size_of(&self) -> usize { 1 } }
 impl ItemSize for String { fn size_of(&self) -> usize { self.len() } }
 impl ItemSize for &str { fn size_of(&self) -> usize { self.len() } }
 impl ItemSize for [&u8] { fn size_of(&self) -> usize { self.iter().len() } }
+impl ItemSize for [u8] { fn size_of(&self) -> usize { self.len() } }
+#[cfg(target_has_atomic = "ptr")]
+impl<T: ItemSize + ?Sized> ItemSize for Arc<T> { fn size_of(&self) -> usize { (**self).size_of() } }
 impl<T> ItemSize for Vec<T>
 where
     T: ItemSize,
@@ -0,0 +1,660 @@
+use alloc::vec::Vec;
+use alloc::vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+use core::num::NonZeroUsize;
+
+use hashbrown::{HashMap, HashTable};
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::error::CacheError;
+use crate::lru::item_size::ItemSize;
+
+// The slots of the two sigils, first in `LFUCache::entries`.
+const HEAD: u32 = 0;
+const TAIL: u32 = 1;
+// The end of the free list.
+const NIL: u32 = u32::MAX;
+
+// An entry of a `LFUCache`, linked into the one list holding every entry.
+struct LFUEntry<K, V> {
+    // kv is `None` in the sigils and in free slots.
+    kv: Option<(K, V)>,
+    // freq counts the puts and reads of the key since it came in, 0 for the sigils.
+    freq: usize,
+    prev: u32,
+    // next is the next free slot in a free one.
+    next: u32,
+}
+
+impl<K, V> LFUEntry<K, V> {
+    fn new(key: K, val: V) -> Self { LFUEntry { kv: Some((key, val)), freq: 1, prev: NIL, next: NIL } }
+
+    // A slot holding no entry: a sigil, or a free slot whose `next` is the next free one.
+    fn empty(next: u32) -> Self { LFUEntry { kv: None, freq: 0, prev: NIL, next } }
+
+    fn key(&self) -> &K { &self.pair().0 }
+
+    fn pair(&self) -> &(K, V) { self.kv.as_ref().expect("the slot holds an entry") }
+
+    fn value_mut(&mut self) -> &mut V { &mut self.kv.as_mut().expect("the slot holds an entry").1 }
+}
+
+/// A cache evicting the least frequently used entry, the least recently used of them on a tie.
+/// Every `put` and `get` of a key counts as a use; `peek` does not. Puts, reads and evictions
+/// are O(1).
+///
+/// In the `Cache` methods, "most recently used" reads as "most frequently used": `peek_first`
+/// and `pop_first` see the entry used most often, `peek_last` and `pop_last` the next to be
+/// evicted.
+pub struct LFUCache<K, V, S = DefaultHasher> {
+    // map holds the slots of the entries, hashed and compared by the keys in them.
+    map: HashTable<u32>,
+    hasher: S,
+    // entries holds the two sigils, then the entries, addressed by `u32` slots like the ones
+    // of an `LRUCache`. Slots freed by removals are chained from `free` and reused first.
+    entries: Vec<LFUEntry<K, V>>,
+    free: u32,
+    // groups maps every frequency some entry has to the most recently used of those entries.
+    // The list runs from the highest frequency to the lowest and, within one frequency, from
+    // the most recently used entry to the least, so each group is a run starting at its entry
+    // here and the victim is always right before the tail.
+    groups: HashMap<usize, u32, DefaultHasher>,
+    cap: NonZeroUsize,
+}
+
+impl<K: Hash + Eq, V> LFUCache<K, V> {
+    /// Creates a cache holding at most `cap` entries.
+    pub fn new(cap: NonZeroUsize) -> Self { LFUCache::with_hasher(cap, DefaultHasher::default()) }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> LFUCache<K, V, S> {
+    /// Like `new`, hashing keys with `hasher`.
+    pub fn with_hasher(cap: NonZeroUsize, hasher: S) -> Self {
+        let mut entries = vec![LFUEntry::empty(TAIL), LFUEntry::empty(NIL)];
+        entries[TAIL as usize].prev = HEAD;
+        LFUCache {
+            map: HashTable::new(),
+            hasher,
+            entries,
+            free: NIL,
+            groups: HashMap::default(),
+            cap,
+        }
+    }
+
+    /// How many times `k` was used since it came in, `None` if it is not present.
+    pub fn frequency<Q>(&self, k: &Q) -> Option<usize>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.node(k).map(|node| self.slot(node).freq)
+    }
+
+    /// Entries from the most frequently used to the next to be evicted.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut node = self.slot(HEAD).next;
+        core::iter::from_fn(move || {
+            let entry = self.entry(node)?;
+            node = self.slot(node).next;
+            Some(entry)
+        })
+    }
+
+    /// Entries from the next to be evicted to the most frequently used, `iter` backwards.
+    pub fn iter_from_last(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut node = self.slot(TAIL).prev;
+        core::iter::from_fn(move || {
+            let entry = self.entry(node)?;
+            node = self.slot(node).prev;
+            Some(entry)
+        })
+    }
+
+    /// Reserves room for at least `additional` more keys, returning `CacheError::AllocError`
+    /// instead of aborting if that cannot be allocated.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> {
+        let LFUCache { map, hasher, entries, groups, .. } = self;
+        map.try_reserve(additional, |&node| hasher.hash_one(entries[node as usize].key()))?;
+        entries.try_reserve(additional)?;
+        // a new key may start the group of frequency 1
+        groups.try_reserve(1)?;
+        Ok(())
+    }
+
+    /// Like `resize`, but returns the entries that no longer fit, least frequently used first.
+    pub fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(K, V)> {
+        let mut evicted = Vec::new();
+        while self.map.len() > cap.get() {
+            evicted.extend(self.pop_victim());
+        }
+        self.cap = cap;
+        evicted
+    }
+
+    fn slot(&self, node: u32) -> &LFUEntry<K, V> { &self.entries[node as usize] }
+
+    fn slot_mut(&mut self, node: u32) -> &mut LFUEntry<K, V> { &mut self.entries[node as usize] }
+
+    fn node<Q>(&self, k: &Q) -> Option<u32>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let holds = |&node: &u32| <KeyRef<K> as Borrow<Q>>::borrow(&KeyRef { k: self.slot(node).key() }) == k;
+        self.map.find(self.hasher.hash_one(k), holds).copied()
+    }
+
+    fn detach(&mut self, node: u32) {
+        let LFUEntry { prev, next, .. } = *self.slot(node);
+        self.slot_mut(prev).next = next;
+        self.slot_mut(next).prev = prev;
+    }
+
+    // Links `node` right before `at`.
+    fn attach_before(&mut self, node: u32, at: u32) {
+        let prev = self.slot(at).prev;
+        let entry = self.slot_mut(node);
+        entry.next = at;
+        entry.prev = prev;
+        self.slot_mut(prev).next = node;
+        self.slot_mut(at).prev = node;
+    }
+
+    // Takes `node` out of its group, handing the group to the next entry when there is one.
+    fn leave_group(&mut self, node: u32) {
+        let LFUEntry { freq, next, .. } = *self.slot(node);
+        if self.groups.get(&freq) != Some(&node) {
+            return;
+        }
+        if next != TAIL && self.slot(next).freq == freq {
+            self.groups.insert(freq, next);
+        } else {
+            self.groups.remove(&freq);
+        }
+    }
+
+    // Counts one more use of `node`, moving it to the front of the next group.
+    fn bump(&mut self, node: u32) {
+        let freq = self.slot(node).freq;
+        let Some(up) = freq.checked_add(1) else { return };
+        // `node` is in the group of `freq`, so one of the two is there
+        let at = self.groups.get(&up).or_else(|| self.groups.get(&freq)).copied().unwrap_or(node);
+        self.leave_group(node);
+        if at != node {
+            self.detach(node);
+            self.attach_before(node, at);
+        }
+        self.slot_mut(node).freq = up;
+        self.groups.insert(up, node);
+    }
+
+    // Adds a new entry used once, evicting the victim first when the cache is full.
+    fn insert(&mut self, k: K, v: V) -> (u32, Option<(K, V)>) {
+        let evicted = if self.map.len() >= self.cap.get() { self.pop_victim() } else { None };
+        let hash = self.hasher.hash_one(&k);
+        let node = match self.free {
+            NIL => {
+                let node = u32::try_from(self.entries.len()).ok().filter(|&node| node != NIL);
+                let node = node.unwrap_or_else(|| panic!("an LFUCache holds fewer than {NIL} entries"));
+                self.entries.push(LFUEntry::new(k, v));
+                node
+            }
+            node => {
+                self.free = mem::replace(self.slot_mut(node), LFUEntry::new(k, v)).next;
+                node
+            }
+        };
+        let at = self.groups.get(&1).copied().unwrap_or(TAIL);
+        self.attach_before(node, at);
+        self.groups.insert(1, node);
+        let LFUCache { map, hasher, entries, .. } = self;
+        map.insert_unique(hash, node, |&node| hasher.hash_one(entries[node as usize].key()));
+        (node, evicted)
+    }
+
+    fn remove(&mut self, node: u32) -> (K, V) {
+        self.leave_group(node);
+        self.detach(node);
+        let hash = self.hasher.hash_one(self.slot(node).key());
+        if let Ok(found) = self.map.find_entry(hash, |&other| other == node) {
+            found.remove();
+        }
+        let empty = LFUEntry::empty(self.free);
+        let entry = mem::replace(self.slot_mut(node), empty);
+        self.free = node;
+        entry.kv.expect("the slot holds an entry")
+    }
+
+    // Removes the entry right before the tail, the least frequently used one.
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        let node = self.slot(TAIL).prev;
+        self.entry(node)?;
+        Some(self.remove(node))
+    }
+
+    // The key and value in `node`, `None` for a sigil.
+    fn entry(&self, node: u32) -> Option<(&K, &V)> { self.slot(node).kv.as_ref().map(|(k, v)| (k, v)) }
+}
+
+impl<K, V, S> Cache<K, V, S> for LFUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn len(&self) -> usize { self.map.len() }
+
+    fn cap(&self) -> NonZeroUsize { self.cap }
+
+    fn is_empty(&self) -> bool { self.map.is_empty() }
+
+    /// Puts `k`, counting a use if it was already present.
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        match self.node(&k) {
+            Some(node) => {
+                self.bump(node);
+                Some(mem::replace(self.slot_mut(node).value_mut(), v))
+            }
+            None => {
+                self.insert(k, v);
+                None
+            }
+        }
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        match self.node(&k) {
+            Some(node) => {
+                self.bump(node);
+                let old = mem::replace(self.slot_mut(node).value_mut(), v);
+                Some((k, old))
+            }
+            None => self.insert(k, v).1,
+        }
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(k).map(|(_, v)| v)
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.node(k)?;
+        self.bump(node);
+        self.entry(node)
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.node(k)?;
+        self.bump(node);
+        Some(self.slot_mut(node).value_mut())
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        let node = match self.node(&k) {
+            Some(node) => {
+                self.bump(node);
+                node
+            }
+            None => {
+                let v = f(&k)?;
+                self.insert(k, v).0
+            }
+        };
+        Ok(self.slot_mut(node).value_mut())
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_key_value(k).map(|(_, v)| v)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entry(self.node(k)?)
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter().map(|k| self.peek(k)).collect()
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.node(k)?;
+        Some(self.slot_mut(node).value_mut())
+    }
+
+    /// The next entry to be evicted.
+    fn peek_last(&self) -> Option<(&K, &V)> { self.entry(self.slot(TAIL).prev) }
+
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> {
+        let node = self.slot(TAIL).prev;
+        self.slot_mut(node).kv.as_mut().map(|(k, v)| (&*k, v))
+    }
+
+    /// The most frequently used entry.
+    fn peek_first(&self) -> Option<(&K, &V)> { self.entry(self.slot(HEAD).next) }
+
+    fn first_key(&self) -> Option<&K> { self.peek_first().map(|(k, _)| k) }
+
+    fn last_key(&self) -> Option<&K> { self.peek_last().map(|(k, _)| k) }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.node(k).is_some()
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop_entry(k).map(|(_, v)| v)
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.node(k)?;
+        Some(self.remove(node))
+    }
+
+    /// Evicts the least frequently used entry, the least recently used of them on a tie.
+    fn pop_last(&mut self) -> Option<(K, V)> { self.pop_victim() }
+
+    /// Removes the most frequently used entry.
+    fn pop_first(&mut self) -> Option<(K, V)> {
+        let node = self.slot(HEAD).next;
+        self.entry(node)?;
+        Some(self.remove(node))
+    }
+
+    /// Counts a use of `k`, like `get`.
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(node) = self.node(k) {
+            self.bump(node);
+        }
+    }
+
+    /// Makes `k` the next to be evicted, lowering its count to the lowest one in the cache.
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.node(k) else { return };
+        let last = self.slot(TAIL).prev;
+        if node == last {
+            return;
+        }
+        self.leave_group(node);
+        self.detach(node);
+        self.attach_before(node, TAIL);
+        // the group of `last` keeps its first entry, `node` joins it at the back
+        self.slot_mut(node).freq = self.slot(last).freq;
+    }
+
+    /// Changes the capacity, evicting the least frequently used entries that no longer fit.
+    fn resize(&mut self, cap: NonZeroUsize) {
+        while self.map.len() > cap.get() {
+            self.pop_victim();
+        }
+        self.cap = cap;
+    }
+
+    fn clear(&mut self) { while self.pop_victim().is_some() {} }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{LFUCache, HEAD, TAIL};
+    use crate::lru::cache::Cache;
+    use crate::lru::item_size::ItemSize;
+    use crate::lru::lru_cache::LRUCache;
+
+    fn cache(cap: usize) -> LFUCache<&'static str, u32> { LFUCache::new(NonZeroUsize::new(cap).unwrap()) }
+
+    // Checks that the list runs by falling frequency, that every group starts at its entry in
+    // `groups` and that the map holds exactly the listed entries.
+    fn assert_consistent<K: std::hash::Hash + Eq, V>(cache: &LFUCache<K, V>) {
+        let mut node = cache.slot(HEAD).next;
+        let mut prev_freq = usize::MAX;
+        let mut len = 0;
+        while node != TAIL {
+            let freq = cache.slot(node).freq;
+            assert!(freq >= 1 && freq <= prev_freq);
+            if freq != prev_freq {
+                assert_eq!(cache.groups.get(&freq), Some(&node));
+            }
+            assert_eq!(cache.node(cache.slot(node).key()), Some(node));
+            assert_eq!(cache.slot(cache.slot(node).next).prev, node);
+            prev_freq = freq;
+            len += 1;
+            node = cache.slot(node).next;
+        }
+        assert_eq!(len, cache.map.len());
+        assert!(len <= cache.cap.get());
+    }
+
+    #[test]
+    fn test_hot_key_survives_a_scan() {
+        let mut lfu = LFUCache::new(NonZeroUsize::new(3).unwrap());
+        let mut lru = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        lfu.put("hot".to_string(), 0);
+        lru.put("hot".to_string(), 0);
+        lfu.get("hot");
+        lru.get("hot");
+
+        for i in 1..100 {
+            lfu.put(i.to_string(), i);
+            lru.put(i.to_string(), i);
+        }
+        assert_eq!(lfu.peek("hot"), Some(&0));
+        assert_eq!(lfu.frequency("hot"), Some(2));
+        assert!(!lru.contains("hot"));
+        assert_eq!(lfu.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["hot", "99", "98"]);
+        assert_consistent(&lfu);
+    }
+
+    #[test]
+    fn test_ties_broken_by_recency() {
+        let mut cache = cache(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.get(&"a");
+        assert_eq!(cache.push("d", 4), Some(("b", 2)));
+        cache.get(&"c");
+        cache.get(&"d");
+        cache.get(&"d");
+        assert_consistent(&cache);
+        // d used 3 times, then c and a twice with c the more recent
+        assert_eq!(cache.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), [("d", 4), ("c", 3), ("a", 1)]);
+        assert_eq!(cache.put("a", 10), Some(1));
+        assert_eq!(cache.pop_last(), Some(("c", 3)));
+        // a caught up with d and was used last
+        assert_eq!(cache.pop_first(), Some(("a", 10)));
+        assert_eq!(cache.push("d", 5), Some(("d", 4)));
+        assert_eq!(cache.frequency(&"d"), Some(4));
+        assert_consistent(&cache);
+    }
+
+    #[test]
+    fn test_promote_demote_and_resize() {
+        let mut cache = cache(4);
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.put(key, i as u32);
+        }
+        cache.promote(&"a");
+        cache.promote(&"a");
+        cache.promote(&"b");
+        assert_eq!(cache.first_key(), Some(&"a"));
+
+        cache.demote(&"a");
+        assert_eq!(cache.last_key(), Some(&"a"));
+        assert_eq!(cache.frequency(&"a"), Some(1));
+        assert_consistent(&cache);
+        // peeks do not count as uses
+        cache.peek(&"a");
+        cache.peek_mut(&"a");
+        assert_eq!(cache.last_key(), Some(&"a"));
+
+        assert_eq!(cache.iter_from_last().map(|(k, _)| *k).collect::<Vec<_>>(), ["a", "c", "d", "b"]);
+        cache.try_reserve(10).unwrap();
+        assert_eq!(cache.resize_with_evicted(NonZeroUsize::new(3).unwrap()), [("a", 0)]);
+        cache.resize(NonZeroUsize::new(2).unwrap());
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["b", "d"]);
+        assert_consistent(&cache);
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(cache.groups.is_empty());
+    }
+
+    #[test]
+    fn test_random_ops_keep_the_groups() {
+        let mut cache = LFUCache::new(NonZeroUsize::new(16).unwrap());
+        let mut state = 0x2545_f491_u64;
+        for _ in 0..5_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = (state >> 8) % 32;
+            match state % 6 {
+                0 | 1 => {
+                    cache.put(key, key);
+                }
+                2 | 3 => {
+                    cache.get(&key);
+                }
+                4 => cache.demote(&key),
+                _ => {
+                    cache.pop(&key);
+                }
+            }
+            assert_consistent(&cache);
+        }
+        // freed slots are reused, and an eviction frees one before the new entry takes it
+        assert!(cache.entries.len() <= 16 + 2);
+    }
+
+    #[test]
+    fn test_usable_as_a_cache() {
+        fn fill<C: Cache<String, u32>>(cache: &mut C) -> Option<u32> {
+            cache.get_or_insert("a".to_string(), || 1);
+            cache.put_if_absent("b".to_string(), 2).unwrap();
+            cache.get_or_insert_mut("a".to_string(), || 0).clone_from(&5);
+            cache.pop("b")
+        }
+        let mut cache = LFUCache::new(NonZeroUsize::new(2).unwrap());
+        assert_eq!(fill(&mut cache), Some(2));
+        assert_eq!(cache.peek("a"), Some(&5));
+        assert_eq!(cache.frequency("a"), Some(2));
+    }
+
+    #[test]
+    fn test_no_memory_leaks() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for _ in 0..n {
+            let mut cache = LFUCache::new(NonZeroUsize::new(1).unwrap());
+            for i in 0..n {
+                cache.put(i, DropCounter {});
+            }
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+    }
+
+    #[test]
+    fn test_no_memory_leaks_with_pops_and_drop() {
+        static KEY_DROPS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct KeyCounter(usize);
+
+        impl Drop for KeyCounter {
+            fn drop(&mut self) { KEY_DROPS.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { VALUE_DROPS.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let mut cache = LFUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..20 {
+            cache.put(KeyCounter(i), DropCounter);
+            cache.get(&KeyCounter(i / 2));
+        }
+        // the first ten were evicted
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 10);
+        drop(cache.pop_last());
+        drop(cache.pop_first());
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 12);
+        // a value put over an existing key is dropped, the new key too
+        let keys_before = KEY_DROPS.load(Ordering::SeqCst);
+        let key = cache.first_key().map(|k| k.0).unwrap();
+        cache.put(KeyCounter(key), DropCounter);
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 13);
+        assert_eq!(KEY_DROPS.load(Ordering::SeqCst), keys_before + 1);
+
+        drop(cache);
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 21);
+        assert_eq!(KEY_DROPS.load(Ordering::SeqCst), keys_before + 9);
+    }
+}
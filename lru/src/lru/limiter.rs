@@ -0,0 +1,277 @@
+use core::num::NonZeroUsize;
+
+use crate::lru::item_size::ItemSize;
+
+/// Weighs the entries of a bounded `LRUCache` against its capacity, so that one eviction loop
+/// serves every kind of bound: the cache charges what comes in, gives back what leaves, and
+/// evicts while `over_budget` says the next entry does not fit.
+pub(crate) trait Limiter {
+    /// What an entry holding `v` takes from the capacity.
+    fn charge<V: ItemSize>(&self, v: &V) -> usize;
+
+    /// Whether taking `extra` more would go past the capacity.
+    fn over_budget(&self, extra: usize) -> bool;
+
+    /// Counts `amount`, from `charge` or an explicit weight, as taken.
+    fn add(&mut self, amount: usize);
+
+    /// Gives back `amount` taken by an entry that left.
+    fn remove(&mut self, amount: usize);
+
+    /// What the entries take together.
+    fn used(&self) -> usize;
+
+    /// The capacity, `None` if nothing is ever over budget.
+    fn cap(&self) -> Option<NonZeroUsize>;
+
+    fn set_cap(&mut self, cap: NonZeroUsize);
+
+    /// Forgets everything taken, for a cache emptied at once.
+    fn reset(&mut self);
+}
+
+/// Bounds the number of entries, for `CacheMode::ItemLimit`.
+#[derive(Debug, Clone)]
+pub(crate) struct ItemCount {
+    cap: NonZeroUsize,
+    len: usize,
+}
+
+impl ItemCount {
+    pub(crate) fn new(cap: NonZeroUsize) -> Self { ItemCount { cap, len: 0 } }
+}
+
+impl Limiter for ItemCount {
+    fn charge<V: ItemSize>(&self, _v: &V) -> usize { 1 }
+
+    fn over_budget(&self, extra: usize) -> bool { self.len + extra > self.cap.get() }
+
+    fn add(&mut self, amount: usize) { self.len += amount; }
+
+    fn remove(&mut self, amount: usize) { self.len = self.len.saturating_sub(amount); }
+
+    fn used(&self) -> usize { self.len }
+
+    fn cap(&self) -> Option<NonZeroUsize> { Some(self.cap) }
+
+    fn set_cap(&mut self, cap: NonZeroUsize) { self.cap = cap; }
+
+    fn reset(&mut self) { self.len = 0; }
+}
+
+/// Bounds the sum of the values' `ItemSize`, for `CacheMode::StoreLimit`.
+#[derive(Debug, Clone)]
+pub(crate) struct ByteSize {
+    cap: NonZeroUsize,
+    used: usize,
+}
+
+impl ByteSize {
+    pub(crate) fn new(cap: NonZeroUsize) -> Self { ByteSize { cap, used: 0 } }
+}
+
+impl Limiter for ByteSize {
+    fn charge<V: ItemSize>(&self, v: &V) -> usize { v.size_of() }
+
+    fn over_budget(&self, extra: usize) -> bool { self.used + extra > self.cap.get() }
+
+    fn add(&mut self, amount: usize) { self.used += amount; }
+
+    fn remove(&mut self, amount: usize) { self.used = self.used.saturating_sub(amount); }
+
+    fn used(&self) -> usize { self.used }
+
+    fn cap(&self) -> Option<NonZeroUsize> { Some(self.cap) }
+
+    fn set_cap(&mut self, cap: NonZeroUsize) { self.cap = cap; }
+
+    fn reset(&mut self) { self.used = 0; }
+}
+
+/// Counts the entries without bounding them, for `CacheMode::UnLimit`. Watermarks still
+/// weigh the count.
+#[derive(Debug, Clone)]
+pub(crate) struct Unbounded {
+    len: usize,
+}
+
+impl Unbounded {
+    pub(crate) fn new(len: usize) -> Self { Unbounded { len } }
+}
+
+impl Limiter for Unbounded {
+    fn charge<V: ItemSize>(&self, _v: &V) -> usize { 1 }
+
+    fn over_budget(&self, _extra: usize) -> bool { false }
+
+    fn add(&mut self, amount: usize) { self.len += amount; }
+
+    fn remove(&mut self, amount: usize) { self.len = self.len.saturating_sub(amount); }
+
+    fn used(&self) -> usize { self.len }
+
+    fn cap(&self) -> Option<NonZeroUsize> { None }
+
+    // Bounding an unbounded count is up to `Limit::set_cap`, which turns it into an `ItemCount`.
+    fn set_cap(&mut self, _cap: NonZeroUsize) {}
+
+    fn reset(&mut self) { self.len = 0; }
+}
+
+/// The limiter a cache holds, picked by its mode.
+#[derive(Debug, Clone)]
+pub(crate) enum Limit {
+    Items(ItemCount),
+    Bytes(ByteSize),
+    Unbounded(Unbounded),
+}
+
+impl Limiter for Limit {
+    fn charge<V: ItemSize>(&self, v: &V) -> usize {
+        match self {
+            Limit::Items(items) => items.charge(v),
+            Limit::Bytes(bytes) => bytes.charge(v),
+            Limit::Unbounded(unbounded) => unbounded.charge(v),
+        }
+    }
+
+    fn over_budget(&self, extra: usize) -> bool {
+        match self {
+            Limit::Items(items) => items.over_budget(extra),
+            Limit::Bytes(bytes) => bytes.over_budget(extra),
+            Limit::Unbounded(unbounded) => unbounded.over_budget(extra),
+        }
+    }
+
+    fn add(&mut self, amount: usize) {
+        match self {
+            Limit::Items(items) => items.add(amount),
+            Limit::Bytes(bytes) => bytes.add(amount),
+            Limit::Unbounded(unbounded) => unbounded.add(amount),
+        }
+    }
+
+    fn remove(&mut self, amount: usize) {
+        match self {
+            Limit::Items(items) => items.remove(amount),
+            Limit::Bytes(bytes) => bytes.remove(amount),
+            Limit::Unbounded(unbounded) => unbounded.remove(amount),
+        }
+    }
+
+    fn used(&self) -> usize {
+        match self {
+            Limit::Items(items) => items.used(),
+            Limit::Bytes(bytes) => bytes.used(),
+            Limit::Unbounded(unbounded) => unbounded.used(),
+        }
+    }
+
+    fn cap(&self) -> Option<NonZeroUsize> {
+        match self {
+            Limit::Items(items) => items.cap(),
+            Limit::Bytes(bytes) => bytes.cap(),
+            Limit::Unbounded(unbounded) => unbounded.cap(),
+        }
+    }
+
+    /// Bounding an unbounded limit starts counting its entries against `cap`.
+    fn set_cap(&mut self, cap: NonZeroUsize) {
+        match self {
+            Limit::Items(items) => items.set_cap(cap),
+            Limit::Bytes(bytes) => bytes.set_cap(cap),
+            Limit::Unbounded(unbounded) => *self = Limit::Items(ItemCount { cap, len: unbounded.len }),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Limit::Items(items) => items.reset(),
+            Limit::Bytes(bytes) => bytes.reset(),
+            Limit::Unbounded(unbounded) => unbounded.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+
+    use super::{ByteSize, ItemCount, Limit, Limiter, Unbounded};
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    const VALUES: [&str; 5] = ["a", "bb", "ccc", "d", "e"];
+
+    // What putting `VALUES` in turn, each under its own key, leaves in `cache`: the evicted
+    // keys, the kept ones least recently used first, `len`, `used_bytes` and `current_size`.
+    fn fill(mut cache: LRUCache<&'static str, &'static str>) -> (Vec<&'static str>, Vec<&'static str>, usize, usize, usize) {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        cache.set_on_evict(move |k, _| sink.lock().unwrap().push(k));
+        cache.recompute_size();
+        for v in VALUES {
+            cache.put(v, v);
+        }
+        cache.assert_invariants();
+        let kept = cache.keys().rev().copied().collect();
+        let evicted = evicted.lock().unwrap().clone();
+        (evicted, kept, cache.len(), cache.used_bytes(), cache.current_size())
+    }
+
+    #[test]
+    fn test_item_count_evicts_by_entries() {
+        let cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        // each key and value weighs as much as the other, so the size is twice the values
+        assert_eq!(fill(cache), (vec!["a"], vec!["bb", "ccc", "d", "e"], 4, 0, 14));
+    }
+
+    #[test]
+    fn test_byte_size_evicts_by_value_size() {
+        let cache = LRUCache::storage(NonZeroUsize::new(4).unwrap());
+        // "ccc" pushes out both before it, and "e" then pushes out "ccc"
+        assert_eq!(fill(cache), (vec!["a", "bb", "ccc"], vec!["d", "e"], 2, 2, 4));
+    }
+
+    #[test]
+    fn test_unbounded_evicts_nothing() {
+        let cache = LRUCache::unbounded();
+        assert_eq!(fill(cache), (vec![], VALUES.to_vec(), 5, 0, 16));
+    }
+
+    #[test]
+    fn test_resize_and_reset() {
+        let mut bytes = ByteSize::new(NonZeroUsize::new(10).unwrap());
+        bytes.add(8);
+        assert!(!bytes.over_budget(2));
+        bytes.set_cap(NonZeroUsize::new(5).unwrap());
+        assert!(bytes.over_budget(0));
+        bytes.remove(20);
+        assert_eq!(bytes.used(), 0);
+
+        let mut items = ItemCount::new(NonZeroUsize::new(1).unwrap());
+        items.add(1);
+        assert!(items.over_budget(1));
+        items.reset();
+        assert!(!items.over_budget(1));
+    }
+
+    #[test]
+    fn test_unbounded_counts_until_bounded() {
+        let mut cache = LRUCache::unbounded();
+        cache.put_many(VALUES.map(|v| (v, v)));
+        // the entries were counted all along, so bounding the cache evicts down to the cap
+        assert_eq!(cache.resize_with_evicted(NonZeroUsize::new(2).unwrap()), [("a", "a"), ("bb", "bb"), ("ccc", "ccc")]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.limit(), NonZeroUsize::new(2));
+
+        let mut limit = Limit::Unbounded(Unbounded::new(3));
+        assert_eq!(limit.cap(), None);
+        assert!(!limit.over_budget(usize::MAX));
+        limit.set_cap(NonZeroUsize::new(2).unwrap());
+        assert_eq!(limit.cap(), NonZeroUsize::new(2));
+        assert!(limit.over_budget(0));
+        assert_eq!(limit.used(), 3);
+    }
+}
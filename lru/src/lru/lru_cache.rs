@@ -1,55 +1,316 @@
-use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::hash::{BuildHasher, Hash};
-use std::iter::FusedIterator;
-use std::marker::PhantomData;
-use std::num::NonZeroUsize;
-use std::ptr::{null_mut, NonNull};
-use std::{fmt, mem};
-
+use alloc::boxed::Box;
+use alloc::collections::TryReserveError;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::convert::Infallible;
+use core::hash::{BuildHasher, Hash};
+use core::iter::{FusedIterator, Rev};
+use core::marker::PhantomData;
+use core::num::NonZeroUsize;
+use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::time::Duration;
+use core::{fmt, mem};
+
+use hashbrown::HashTable;
+
+use crate::lru::builder::LRUCacheBuilder;
 use crate::lru::cache::{self, Cache, KeyRef};
+use crate::lru::clock::{Clock, Instant, DEFAULT_CLOCK};
+use crate::lru::dump::{CacheDump, DumpEntry, DumpOptions};
+use crate::lru::error::{CacheError, ConsistencyError};
+use crate::lru::in_use::InUse;
 use crate::lru::item_size::ItemSize;
+use crate::lru::limiter::{ByteSize, ItemCount, Limit, Limiter, Unbounded};
+use crate::lru::observer::{CacheObserver, CacheStats, EvictionCause};
+use crate::lru::trace::op_span;
+
+type Replace<K, V> = (Option<(K, V)>, u32);
+type OnEvict<K, V> = Box<dyn FnMut(K, V) + Send>;
+
+// Holds the `on_evict` callback, which is only ever reached through `&mut`: no `&self` method
+// looks inside, so sharing the cache between threads shares nothing of the callback.
+struct OnEvictSlot<K, V>(OnEvict<K, V>);
+
+// SAFETY: the callback is only reached through `&mut OnEvictSlot`, never through `&`.
+unsafe impl<K, V> Sync for OnEvictSlot<K, V> {}
+
+// Makes room in the slab for one more slot, failing if the allocator gave up.
+type SlotAlloc<K, V> = fn(&mut Vec<LRUEntry<K, V>>) -> Result<(), TryReserveError>;
+
+/// Maps at or below this capacity are never shrunk by `set_auto_shrink`.
+pub const AUTO_SHRINK_MIN_CAPACITY: usize = 1024;
+// Tells whether a value is in use, and how many in-use entries an eviction may pass over.
+type InUsePolicy<V> = (fn(&V) -> bool, usize);
+
+// The link of the sigils pointing outside the list.
+const NIL: u32 = u32::MAX;
+// The sigil slots, the first two of every slab: the most recently used entry follows `HEAD`,
+// the least recently used one precedes `TAIL`.
+const HEAD: u32 = 0;
+const TAIL: u32 = 1;
+
+/// The timestamps of an entry, see `LRUCache::peek_meta`. Both are read from the cache's clock.
+#[cfg(feature = "entry-timestamps")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// When the key was put while missing. Overwrites keep it.
+    pub created_at: Instant,
+    /// When the entry last moved to the front: put, read or promoted.
+    pub last_accessed: Instant,
+    /// How long ago `created_at` was.
+    pub age: Duration,
+    /// How long ago `last_accessed` was.
+    pub idle: Duration,
+}
+
+#[cfg(feature = "entry-timestamps")]
+impl EntryMeta {
+    fn of<K, V>(entry: &LRUEntry<K, V>, now: Instant) -> Self {
+        let Stamps { created_at, accessed_at } = entry.stamps;
+        EntryMeta {
+            created_at,
+            last_accessed: accessed_at,
+            age: now.saturating_duration_since(created_at),
+            idle: now.saturating_duration_since(accessed_at),
+        }
+    }
+}
+
+// When an entry was created and when it last moved to the front. They are only kept with the
+// `entry-timestamps` feature: without it this is empty and the clock is never read for them.
+#[derive(Clone, Copy)]
+struct Stamps {
+    // created_at is when the key was put while missing; overwrites keep it.
+    #[cfg(feature = "entry-timestamps")]
+    created_at: Instant,
+    // accessed_at is when the entry last moved to the front, see `pop_older_than`.
+    #[cfg(feature = "entry-timestamps")]
+    accessed_at: Instant,
+}
 
-type Replace<K, V> = (Option<(K, V)>, NonNull<LRUEntry<K, V>>);
+impl Stamps {
+    // Both times set to now.
+    #[cfg(feature = "entry-timestamps")]
+    fn new(clock: &dyn Clock) -> Self {
+        let now = clock.now();
+        Stamps { created_at: now, accessed_at: now }
+    }
+
+    #[cfg(not(feature = "entry-timestamps"))]
+    fn new(_clock: &dyn Clock) -> Self { Stamps {} }
+
+    // Records an access now.
+    #[cfg(feature = "entry-timestamps")]
+    fn touch(&mut self, clock: &dyn Clock) { self.accessed_at = clock.now(); }
+
+    #[cfg(not(feature = "entry-timestamps"))]
+    fn touch(&mut self, _clock: &dyn Clock) {}
+}
 
 /// LRUEntry used to hold a key value pair. Also contains
-/// references to previous and next entries so we can
+/// the slots of the previous and next entries so we can
 /// maintain the entries in a linked list ordered by their use.
 struct LRUEntry<K, V> {
-    key: mem::MaybeUninit<K>,
-    value: mem::MaybeUninit<V>,
-    prev: *mut LRUEntry<K, V>,
-    next: *mut LRUEntry<K, V>,
+    // kv is `None` in the sigils and in free slots.
+    kv: Option<(K, V)>,
+    // expires_at is the deadline set by `put_with_ttl`, `None` never expires.
+    expires_at: Option<Instant>,
+    stamps: Stamps,
+    // weight is what the entry was charged against the budget in capacity mode, its `ItemSize`
+    // unless set by `put_weighted`.
+    weight: usize,
+    // prev and next are slots in the slab; in a free slot, next is the next free one.
+    prev: u32,
+    next: u32,
 }
 
 impl<K, V> LRUEntry<K, V> {
-    fn new(key: K, val: V) -> Self {
+    fn new(key: K, val: V, stamps: Stamps) -> Self {
         LRUEntry {
-            key: mem::MaybeUninit::new(key),
-            value: mem::MaybeUninit::new(val),
-            prev: null_mut(),
-            next: null_mut(),
+            kv: Some((key, val)),
+            expires_at: None,
+            stamps,
+            weight: 0,
+            prev: NIL,
+            next: NIL,
         }
     }
 
-    fn new_sigil() -> Self {
+    // A slot holding no entry: a sigil, or a free slot whose `next` is the next free one.
+    fn empty(stamps: Stamps, next: u32) -> Self {
         LRUEntry {
-            key: mem::MaybeUninit::uninit(),
-            value: mem::MaybeUninit::uninit(),
-            prev: null_mut(),
-            next: null_mut(),
+            kv: None,
+            expires_at: None,
+            stamps,
+            weight: 0,
+            prev: NIL,
+            next,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool { self.expires_at.is_some_and(|deadline| deadline <= now) }
+
+    // The key and the value are only read from slots holding an entry, never from a sigil or
+    // a free slot.
+    fn key(&self) -> &K { &self.pair().0 }
+
+    fn value(&self) -> &V { &self.pair().1 }
+
+    fn value_mut(&mut self) -> &mut V { &mut self.pair_mut().1 }
+
+    fn key_value_mut(&mut self) -> (&K, &mut V) {
+        let (key, value) = self.pair_mut();
+        (key, value)
+    }
+
+    fn pair(&self) -> &(K, V) { self.kv.as_ref().expect("the slot holds an entry") }
+
+    fn pair_mut(&mut self) -> &mut (K, V) { self.kv.as_mut().expect("the slot holds an entry") }
+
+    // Moves the key and the value out of an entry taken from the slab.
+    fn into_pair(self) -> (K, V) { self.kv.expect("the slot holds an entry") }
+}
+
+fn alloc_slot<K, V>(entries: &mut Vec<LRUEntry<K, V>>) -> Result<(), TryReserveError> {
+    entries.reserve(1);
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    // makes `try_alloc_slot` fail, standing in for an exhausted allocator
+    static FAIL_SLOT_ALLOC: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+fn try_alloc_slot<K, V>(entries: &mut Vec<LRUEntry<K, V>>) -> Result<(), TryReserveError> {
+    #[cfg(test)]
+    if FAIL_SLOT_ALLOC.with(|fail| fail.get()) {
+        // a reservation no allocator can satisfy
+        return Vec::<u64>::new().try_reserve(usize::MAX);
+    }
+    entries.try_reserve(1)
+}
+
+// The slots of the entries of a cache, addressed by `u32` indices, with the two sigils first.
+// Slots freed by removals are chained through their `next` link and reused first. Entries
+// move when the vector grows, which is why the map holds indices and never references.
+struct Slab<K, V> {
+    entries: Vec<LRUEntry<K, V>>,
+    // the first free slot, `NIL` if there is none
+    free: u32,
+}
+
+impl<K, V> Slab<K, V> {
+    fn new() -> Self {
+        let stamps = Stamps::new(DEFAULT_CLOCK);
+        let mut slab = Slab { entries: vec![LRUEntry::empty(stamps, TAIL), LRUEntry::empty(stamps, NIL)], free: NIL };
+        slab[TAIL].prev = HEAD;
+        slab
+    }
+
+    // Slots handed out so far, in use or free.
+    fn len(&self) -> usize { self.entries.len() }
+
+    fn insert(&mut self, entry: LRUEntry<K, V>) -> u32 {
+        match self.try_insert(entry, alloc_slot) {
+            Some(idx) => idx,
+            None => unreachable!("alloc_slot aborts instead of failing"),
+        }
+    }
+
+    // Stores `entry` in a free slot, or in a new one past the others, making room with `alloc`
+    // when the vector is full. Gives `None` back, and drops `entry`, if that fails.
+    fn try_insert(&mut self, entry: LRUEntry<K, V>, alloc: SlotAlloc<K, V>) -> Option<u32> {
+        if self.free != NIL {
+            let idx = self.free;
+            self.free = mem::replace(&mut self[idx], entry).next;
+            return Some(idx);
+        }
+        let idx = u32::try_from(self.entries.len()).ok().filter(|&idx| idx != NIL);
+        let idx = idx.unwrap_or_else(|| panic!("an LRUCache holds fewer than {NIL} entries"));
+        alloc(&mut self.entries).ok()?;
+        self.entries.push(entry);
+        Some(idx)
+    }
+
+    // Takes the entry out of slot `idx`, which becomes free. The slot must hold one.
+    fn remove(&mut self, idx: u32) -> LRUEntry<K, V> {
+        let empty = LRUEntry::empty(self[idx].stamps, self.free);
+        let entry = mem::replace(&mut self[idx], empty);
+        self.free = idx;
+        entry
+    }
+
+    // Moves the entries down over the free slots, keeping their order, and gives the memory of
+    // the slots left over back. Returns the new slot of each old one, `NIL` for the free ones,
+    // or `None` if there was no free slot and nothing moved.
+    fn compact(&mut self) -> Option<Vec<u32>> {
+        if self.free == NIL {
+            self.entries.shrink_to_fit();
+            return None;
         }
+        let mut remap = vec![NIL; self.entries.len()];
+        let mut live = 0;
+        for (idx, entry) in self.entries.iter().enumerate() {
+            // the sigils hold no entry but stay in place
+            if idx <= TAIL as usize || entry.kv.is_some() {
+                remap[idx] = live;
+                live += 1;
+            }
+        }
+        let mut idx = 0;
+        self.entries.retain(|_| {
+            idx += 1;
+            remap[idx - 1] != NIL
+        });
+        for entry in &mut self.entries {
+            for link in [&mut entry.prev, &mut entry.next] {
+                if *link != NIL {
+                    *link = remap[*link as usize];
+                }
+            }
+        }
+        self.entries.shrink_to_fit();
+        self.free = NIL;
+        Some(remap)
     }
 }
 
+impl<K, V> Index<u32> for Slab<K, V> {
+    type Output = LRUEntry<K, V>;
+
+    fn index(&self, idx: u32) -> &LRUEntry<K, V> { &self.entries[idx as usize] }
+}
+
+impl<K, V> IndexMut<u32> for Slab<K, V> {
+    fn index_mut(&mut self, idx: u32) -> &mut LRUEntry<K, V> { &mut self.entries[idx as usize] }
+}
+
+// Whether slot `node` holds `k`, comparing what the key borrows as, as the map would.
+fn holds<K, V, Q>(slab: &Slab<K, V>, node: u32, k: &Q) -> bool
+where
+    KeyRef<K>: Borrow<Q>,
+    Q: Eq + ?Sized,
+{
+    let key = KeyRef { k: slab[node].key() };
+    <KeyRef<K> as Borrow<Q>>::borrow(&key) == k
+}
+
+// Hashes the key in a slot, for the map to move its indices around when it grows or shrinks.
+fn rehash<'a, K: Hash, V, S: BuildHasher>(slab: &'a Slab<K, V>, hasher: &'a S) -> impl Fn(&u32) -> u64 + 'a {
+    move |&node| hasher.hash_one(slab[node].key())
+}
+
 /// An iterator over the entries of a `LRUCache`.
 pub struct Iter<'a, K: 'a, V: 'a> {
     len: usize,
 
-    ptr: *const LRUEntry<K, V>,
-    end: *const LRUEntry<K, V>,
-
-    phantom_data: PhantomData<&'a K>,
+    slab: &'a Slab<K, V>,
+    ptr: u32,
+    end: u32,
 }
 
 impl<'a, K: 'a, V: 'a> Iterator for Iter<'a, K, V> {
@@ -60,13 +321,12 @@ impl<'a, K: 'a, V: 'a> Iterator for Iter<'a, K, V> {
             return None;
         }
 
-        let key = unsafe { &(*(*self.ptr).key.as_ptr()) };
-        let val = unsafe { &(*(*self.ptr).value.as_ptr()) };
+        let entry = &self.slab[self.ptr];
 
         self.len -= 1;
-        self.ptr = unsafe { (*self.ptr).next };
+        self.ptr = entry.next;
 
-        Some((key, val))
+        Some((entry.key(), entry.value()))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) { (self.len, Some(self.len)) }
@@ -85,13 +345,12 @@ impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
             return None;
         }
 
-        let key = unsafe { &(*(*self.end).key.as_ptr()) };
-        let val = unsafe { &(*(*self.end).value.as_ptr()) };
+        let entry = &self.slab[self.end];
 
         self.len -= 1;
-        self.end = unsafe { (*self.end).prev };
+        self.end = entry.prev;
 
-        Some((key, val))
+        Some((entry.key(), entry.value()))
     }
 }
 
@@ -102,24 +361,82 @@ impl<K, V> Clone for Iter<'_, K, V> {
     fn clone(&self) -> Self {
         Self {
             len: self.len,
+            slab: self.slab,
             ptr: self.ptr,
             end: self.end,
-            phantom_data: PhantomData,
         }
     }
 }
 
-unsafe impl<K: Send, V: Send> Send for Iter<'_, K, V> {}
-unsafe impl<K: Sync, V: Sync> Sync for Iter<'_, K, V> {}
+/// An iterator over a run of the entries of a `LRUCache`, from `iter_from` and
+/// `iter_from_back`. It stops where the run ends instead of counting its entries up front, so
+/// it is not an `ExactSizeIterator`.
+pub struct IterFrom<'a, K: 'a, V: 'a> {
+    slab: &'a Slab<K, V>,
+    // the first and the last entry not yielded yet, meaningless once `done`
+    ptr: u32,
+    end: u32,
+    done: bool,
+}
+
+impl<'a, K: 'a, V: 'a> IterFrom<'a, K, V> {
+    // The entries from `ptr` to `end`, both included, none if `ptr` is a sigil.
+    fn new(slab: &'a Slab<K, V>, ptr: u32, end: u32) -> Self {
+        IterFrom { slab, ptr, end, done: ptr == TAIL || end == HEAD }
+    }
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for IterFrom<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = &self.slab[self.ptr];
+        self.done = self.ptr == self.end;
+        self.ptr = entry.next;
+        Some((entry.key(), entry.value()))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterFrom<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = &self.slab[self.end];
+        self.done = self.ptr == self.end;
+        self.end = entry.prev;
+        Some((entry.key(), entry.value()))
+    }
+}
+
+impl<K, V> FusedIterator for IterFrom<'_, K, V> {}
+
+impl<K, V> Clone for IterFrom<'_, K, V> {
+    fn clone(&self) -> Self { IterFrom { slab: self.slab, ptr: self.ptr, end: self.end, done: self.done } }
+}
 
 /// An iterator over mutable entries of a `LRUCache`.
 pub struct IterMut<'a, K: 'a, V: 'a> {
     len: usize,
 
-    ptr: *mut LRUEntry<K, V>,
-    end: *mut LRUEntry<K, V>,
+    // the slots of a `&mut LRUCache`, borrowed for `'a`; every entry is yielded once
+    entries: *mut LRUEntry<K, V>,
+    slots: usize,
+    ptr: u32,
+    end: u32,
 
-    phantom_data: PhantomData<&'a K>,
+    phantom_data: PhantomData<&'a mut LRUEntry<K, V>>,
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    fn slot(&mut self, idx: u32) -> &'a mut LRUEntry<K, V> {
+        assert!((idx as usize) < self.slots, "slot {idx} is out of the slab");
+        // the list goes through each slot at most once, so no two references alias
+        unsafe { &mut *self.entries.add(idx as usize) }
+    }
 }
 
 impl<'a, K: 'a, V: 'a> Iterator for IterMut<'a, K, V> {
@@ -130,13 +447,12 @@ impl<'a, K: 'a, V: 'a> Iterator for IterMut<'a, K, V> {
             return None;
         }
 
-        let key = unsafe { &(*(*self.ptr).key.as_ptr()) };
-        let val = unsafe { &mut (*(*self.ptr).value.as_mut_ptr()) };
+        let entry = self.slot(self.ptr);
 
         self.len -= 1;
-        self.ptr = unsafe { (*self.ptr).next };
+        self.ptr = entry.next;
 
-        Some((key, val))
+        Some(entry.key_value_mut())
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) { (self.len, Some(self.len)) }
@@ -155,13 +471,12 @@ impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
             return None;
         }
 
-        let key = unsafe { &(*(*self.end).key.as_ptr()) };
-        let val = unsafe { &mut (*(*self.end).value.as_mut_ptr()) };
+        let entry = self.slot(self.end);
 
         self.len -= 1;
-        self.end = unsafe { (*self.end).prev };
+        self.end = entry.prev;
 
-        Some((key, val))
+        Some(entry.key_value_mut())
     }
 }
 
@@ -171,19 +486,95 @@ impl<K, V> FusedIterator for IterMut<'_, K, V> {}
 unsafe impl<K: Send, V: Send> Send for IterMut<'_, K, V> {}
 unsafe impl<K: Sync, V: Sync> Sync for IterMut<'_, K, V> {}
 
+/// An iterator over the keys of a `LRUCache`, most recently used first.
+pub struct Keys<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> { self.inner.next().map(|(k, _)| k) }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
+
+    fn count(self) -> usize { self.inner.count() }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.inner.next_back().map(|(k, _)| k) }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+impl<K, V> Clone for Keys<'_, K, V> {
+    fn clone(&self) -> Self { Keys { inner: self.inner.clone() } }
+}
+
+/// An iterator over the values of a `LRUCache`, most recently used first.
+pub struct Values<'a, K: 'a, V: 'a> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> { self.inner.next().map(|(_, v)| v) }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
+
+    fn count(self) -> usize { self.inner.count() }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.inner.next_back().map(|(_, v)| v) }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+impl<K, V> Clone for Values<'_, K, V> {
+    fn clone(&self) -> Self { Values { inner: self.inner.clone() } }
+}
+
+/// An iterator over mutable values of a `LRUCache`, most recently used first.
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> { self.inner.next().map(|(_, v)| v) }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
+
+    fn count(self) -> usize { self.inner.count() }
+}
+
+impl<K, V> DoubleEndedIterator for ValuesMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> { self.inner.next_back().map(|(_, v)| v) }
+}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
+
 /// An iterator that moves out of a `LRUCache`.
-pub struct IntoIter<K, V>
+pub struct IntoIter<K, V, S = cache::DefaultHasher>
 where
     K: Hash + Eq,
     V: ItemSize,
+    S: BuildHasher,
 {
-    cache: LRUCache<K, V>,
+    cache: LRUCache<K, V, S>,
 }
 
-impl<K, V> Iterator for IntoIter<K, V>
+impl<K, V, S> Iterator for IntoIter<K, V, S>
 where
     K: Hash + Eq,
     V: ItemSize,
+    S: BuildHasher,
 {
     type Item = (K, V);
 
@@ -197,1265 +588,4992 @@ where
     fn count(self) -> usize { self.cache.len() }
 }
 
-impl<K, V> ExactSizeIterator for IntoIter<K, V> where K: Hash + Eq, V: ItemSize {}
-impl<K, V> FusedIterator for IntoIter<K, V> where K: Hash + Eq, V: ItemSize {}
-
-#[derive(Debug, Clone)]
-pub enum CacheMode {
-    ItemLimit,
-    StoreLimit,
-    UnLimit,
+/// Takes entries from the most recently used end.
+impl<K, V, S> DoubleEndedIterator for IntoIter<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn next_back(&mut self) -> Option<(K, V)> { self.cache.pop_first() }
 }
 
-/// A LRU cache.
-/// This is a single level thread unsafe LRU implementation.
-#[derive(Clone)]
-pub struct LRUCache<K, V, S = cache::DefaultHasher> {
-    // map is used to speed up LRU access.
-    map: HashMap<KeyRef<K>, NonNull<LRUEntry<K, V>>, S>,
-    // cache_mode is used to set the mode of cache: limit by number of items, limit by capacity, unlimited
-    cache_mode: CacheMode,
-    // cap is used to specific LRU cache capacity.
-    cap: NonZeroUsize,
-    // used_cap is items/capacity used
-    used_cap: usize,
+impl<K, V, S> ExactSizeIterator for IntoIter<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
+impl<K, V, S> FusedIterator for IntoIter<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
 
-    // head and tail are sigil nodes to facilitate inserting entries
-    head: *mut LRUEntry<K, V>,
-    tail: *mut LRUEntry<K, V>,
+/// An iterator that moves the keys out of a `LRUCache`, least recently used first. The values
+/// are dropped as their keys are taken.
+pub struct IntoKeys<K, V, S = cache::DefaultHasher>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    cache: LRUCache<K, V, S>,
 }
 
-impl<K, V, S> LRUCache<K, V, S>
+impl<K, V, S> Iterator for IntoKeys<K, V, S>
 where
     K: Hash + Eq,
     V: ItemSize,
     S: BuildHasher,
 {
-    /// Creates a new LRU Cache with the given capacity.
-    fn construct(cache_mode: CacheMode, cap: NonZeroUsize, map: HashMap<KeyRef<K>, NonNull<LRUEntry<K, V>>, S>) -> Self {
-        let cache = LRUCache {
-            map,
-            cache_mode,
-            cap,
-            used_cap: 0,
-            head: Box::into_raw(Box::new(LRUEntry::new_sigil())),
-            tail: Box::into_raw(Box::new(LRUEntry::new_sigil())),
-        };
+    type Item = K;
 
-        unsafe {
-            (*cache.head).next = cache.tail;
-            (*cache.tail).prev = cache.head;
-        }
+    fn next(&mut self) -> Option<K> { self.cache.pop_last().map(|(k, _)| k) }
 
-        cache
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cache.len();
+        (len, Some(len))
     }
 
-    /// Detach specific `node`.
-    fn detach(&mut self, node: *mut LRUEntry<K, V>) {
-        unsafe {
-            (*(*node).prev).next = (*node).next;
-            (*(*node).next).prev = (*node).prev;
-        }
-    }
+    fn count(self) -> usize { self.cache.len() }
+}
 
-    /// Attaches `node` after the sigil `self.head` node.
-    fn attach(&mut self, node: *mut LRUEntry<K, V>) {
-        unsafe {
-            (*node).next = (*self.head).next;
-            (*node).prev = self.head;
-            (*self.head).next = node;
-            (*(*node).next).prev = node;
-        }
-    }
+impl<K, V, S> ExactSizeIterator for IntoKeys<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
+impl<K, V, S> FusedIterator for IntoKeys<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
 
-    fn detach_last(&mut self) -> Option<Box<LRUEntry<K, V>>> {
-        let prev = unsafe { (*self.tail).prev };
+/// An iterator that moves the values out of a `LRUCache`, least recently used first. The keys
+/// are dropped as their values are taken.
+pub struct IntoValues<K, V, S = cache::DefaultHasher>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    cache: LRUCache<K, V, S>,
+}
 
-        if prev != self.head {
-            let old_key = KeyRef {
-                k: unsafe { &(*(*prev).key.as_ptr()) },
-            };
-            let old_node = self.map.remove(&old_key).unwrap();
+impl<K, V, S> Iterator for IntoValues<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    type Item = V;
 
-            let node_ptr: *mut LRUEntry<K, V> = old_node.as_ptr();
-            self.detach(node_ptr);
+    fn next(&mut self) -> Option<V> { self.cache.pop_last().map(|(_, v)| v) }
 
-            Some(unsafe { Box::from_raw(node_ptr) })
-        } else {
-            None
-        }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cache.len();
+        (len, Some(len))
     }
 
-    fn attach_last(&mut self, node: *mut LRUEntry<K, V>) {
-        unsafe {
-            (*node).next = self.tail;
-            (*node).prev = (*self.tail).prev;
-            (*self.tail).prev = node;
-            (*(*node).prev).next = node;
-        }
-    }
-
-    // Used internally to swap out a node if the cache is full or to create a new node if space
-    // is available. Shared between `put`, `push`, `get_or_insert`, and `get_or_insert_mut`.
-    // fn replace_or_create_node(&mut self, k: K, v: V) -> Replace<K, V> {
-    //     if self.len() == self.cap().get() {
-    //         // if the cache is full, remove the last entry so we can use it for the new key.
-    //         let old_key = KeyRef {
-    //             k: unsafe { &(*(*(*self.tail).prev).key.as_ptr()) },
-    //         };
-    //
-    //         let old_node = self.map.remove(&old_key).unwrap();
-    //
-    //         let node_ptr: *mut LRUEntry<K, V> = old_node.as_ptr();
-    //
-    //         // read out the node's old key and value and then replace it
-    //         let replaced = unsafe {
-    //             (
-    //                 mem::replace(&mut (*node_ptr).key, mem::MaybeUninit::new(k)).assume_init(),
-    //                 mem::replace(&mut (*node_ptr).value, mem::MaybeUninit::new(v)).assume_init(),
-    //             )
-    //         };
-    //
-    //         self.detach(node_ptr);
-    //
-    //         (Some(replaced), old_node)
-    //     } else {
-    //         (None, unsafe {
-    //             NonNull::new_unchecked(Box::into_raw(Box::new(LRUEntry::new(k, v))))
-    //         })
-    //     }
-    // }
+    fn count(self) -> usize { self.cache.len() }
+}
 
-    fn replace_or_create_node(&mut self, k: K, v: V) -> Replace<K, V> {
-        match &self.cache_mode {
-            CacheMode::ItemLimit => {
-                if self.len() == self.cap().get() {
-                    // if the cache is full, remove the last entry so we can use it for the new key.
-                    let old_key = KeyRef {
-                        k: unsafe { &(*(*(*self.tail).prev).key.as_ptr()) },
-                    };
-
-                    let old_node = self.map.remove(&old_key).unwrap();
-
-                    let node_ptr: *mut LRUEntry<K, V> = old_node.as_ptr();
-
-                    // read out the node's old key and value and then replace it
-                    let replaced = unsafe {
-                        (
-                            mem::replace(&mut (*node_ptr).key, mem::MaybeUninit::new(k)).assume_init(),
-                            mem::replace(&mut (*node_ptr).value, mem::MaybeUninit::new(v)).assume_init(),
-                        )
-                    };
-
-                    self.detach(node_ptr);
-
-                    (Some(replaced), old_node)
-                } else {
-                    (None, unsafe {
-                        NonNull::new_unchecked(Box::into_raw(Box::new(LRUEntry::new(k, v))))
-                    })
-                }
-            }
-            CacheMode::StoreLimit => {
-                // if insert V's size > cap, system will be error
-                let size = v.size_of();
-                let mut replaced_item = None;
-                while self.used_cap + size > self.cap().get() {
-                    let replaced = self.pop_last().unwrap();
-
-                    let v = &replaced.1;
-                    let pop_size = v.size_of();
-                    self.used_cap -= pop_size;
-
-                    replaced_item = Some(replaced);
-                }
-                self.used_cap += size;
-                (replaced_item, unsafe {
-                    NonNull::new_unchecked(Box::into_raw(Box::new(LRUEntry::new(k, v))))
-                })
-                /* `(std::option::Option<(K, V)>, std::ptr::NonNull<lru::lru_cache::LRUEntry<K, V>>)` value */
-                /* `(std::option::Option<(K, V)>, std::ptr::NonNull<lru::lru_cache::LRUEntry<K, V>>)` value */
-                /* `(std::option::Option<(K, V)>, std::ptr::NonNull<lru::lru_cache::LRUEntry<K, V>>)` value */
-            }
-            CacheMode::UnLimit => {
-                (None, unsafe {
-                    NonNull::new_unchecked(Box::into_raw(Box::new(LRUEntry::new(k, v))))
-                })
-            }
+impl<K, V, S> ExactSizeIterator for IntoValues<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
+impl<K, V, S> FusedIterator for IntoValues<K, V, S> where K: Hash + Eq, V: ItemSize, S: BuildHasher {}
+
+/// A draining iterator over the entries of a `LRUCache`, most recently used first. The cache
+/// is emptied when the iterator is created; entries it did not yield are dropped with it.
+pub struct Drain<'a, K, V> {
+    len: usize,
+    // the detached list, owned by the iterator from here on
+    slab: &'a mut Slab<K, V>,
+    ptr: u32,
+}
+
+impl<K, V> Iterator for Drain<'_, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        if self.len == 0 {
+            return None;
         }
+        let entry = self.slab.remove(self.ptr);
+        self.len -= 1;
+        self.ptr = entry.next;
+        Some(entry.into_pair())
     }
 
-    // Used internally by `put` and `push` to add a new entry to the lru.
-    // Takes ownership of and returns entries replaced due to the cache's capacity
-    // when `capture` is true.
-    fn capturing_put(&mut self, k: K, mut v: V, capture: bool) -> Option<(K, V)> {
-        let node_ref = self.map.get_mut(&KeyRef { k: &k });
-
-        match node_ref {
-            // if the key is already in the cache just update its
-            // value and move it to the front of the list
-            Some(node_ref) => {
-                let node_ptr: *mut LRUEntry<K, V> = (*node_ref).as_ptr();
+    fn size_hint(&self) -> (usize, Option<usize>) { (self.len, Some(self.len)) }
+}
 
-                unsafe {
-                    core::ptr::swap(&mut v, &mut (*(*node_ptr).value.as_mut_ptr()));
-                }
+impl<K, V> ExactSizeIterator for Drain<'_, K, V> {}
+impl<K, V> FusedIterator for Drain<'_, K, V> {}
 
-                self.detach(node_ptr);
-                self.attach(node_ptr);
+impl<K, V> Drop for Drain<'_, K, V> {
+    fn drop(&mut self) { self.for_each(drop); }
+}
 
-                Some((k, v))
-            }
-            None => {
-                let (replaced, node) = self.replace_or_create_node(k, v);
+/// A view into a single entry of the cache, returned by `LRUCache::entry`.
+pub enum Entry<'a, K, V, S = cache::DefaultHasher> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
 
-                let node_ptr: *mut LRUEntry<K, V> = node.as_ptr();
-                self.attach(node_ptr);
+/// An entry that is in the cache. It was promoted by `LRUCache::entry`.
+pub struct OccupiedEntry<'a, K, V, S = cache::DefaultHasher> {
+    cache: &'a mut LRUCache<K, V, S>,
+    // stays valid as long as `cache` is borrowed, since only this entry can change it
+    node: u32,
+}
 
-                let key_ref = KeyRef {
-                    k: unsafe { (*node_ptr).key.as_ptr() },
-                };
-                self.map.insert(key_ref, node);
+/// An entry missing from the cache, holding the key to insert it under.
+pub struct VacantEntry<'a, K, V, S = cache::DefaultHasher> {
+    cache: &'a mut LRUCache<K, V, S>,
+    key: K,
+}
 
-                replaced.filter(|_| capture)
-            }
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// The key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
         }
     }
 
-    /// Creates a new LRU Cache that holds at most `cap` items and
-    /// uses the provided hash builder to hash keys.
-    pub fn with_hasher(cache_mode: CacheMode, cap: NonZeroUsize, hasher: S) -> Self {
-        LRUCache::construct(cache_mode, cap, HashMap::with_capacity_and_hasher(cap.get(), hasher))
-    }
+    /// Inserts `default` if the entry is vacant, and returns the value either way.
+    pub fn or_insert(self, default: V) -> &'a mut V { self.or_insert_with(|| default) }
 
-    /// Creates a new LRU Cache that never automatically evicts items and
-    /// uses the provided hash builder to hash keys.
-    pub fn unbounded_with_hasher(cache_mode: CacheMode, hasher: S) -> Self {
-        LRUCache::construct(
-            cache_mode,
-            NonZeroUsize::new(usize::MAX).unwrap(),
-            HashMap::with_hasher(hasher),
-        )
-    }
+    /// Inserts the result of `f` if the entry is vacant, and returns the value either way.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a mut V { self.or_insert_with_key(|_| f()) }
 
-    /// An iterator visiting all entries in most-recently used order. The iterator element type is
-    /// `(&K, &V)`.
-    pub fn iter(&self) -> Iter<K, V> {
-        Iter {
-            len: self.len(),
-            ptr: unsafe { (*self.head).next },
-            end: unsafe { (*self.tail).prev },
-            phantom_data: PhantomData,
+    /// Like `or_insert_with`, but `f` receives the key.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = f(entry.key());
+                entry.insert(value)
+            }
         }
     }
 
-    /// An iterator visiting all entries in most-recently-used order, giving a mutable reference on
-    /// V.  The iterator element type is `(&K, &mut V)`.
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
-        IterMut {
-            len: self.len(),
-            ptr: unsafe { (*self.head).next },
-            end: unsafe { (*self.tail).prev },
-            phantom_data: PhantomData,
+    /// Calls `f` on the value if the entry is occupied.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
         }
+        self
     }
 }
 
-impl<K, V> LRUCache<K, V>
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize + Default,
+    S: BuildHasher,
+{
+    /// Inserts `V::default()` if the entry is vacant, and returns the value either way.
+    pub fn or_default(self) -> &'a mut V { self.or_insert_with(V::default) }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
 where
     K: Hash + Eq,
     V: ItemSize,
+    S: BuildHasher,
 {
-    /// Creates a new LRU Cache that holds at most `cap` items.
-    pub fn new(cap: NonZeroUsize) -> Self {
-        LRUCache::construct(CacheMode::ItemLimit, cap, HashMap::with_capacity(cap.get()))
-    }
+    pub fn key(&self) -> &K { self.cache.slab[self.node].key() }
+
+    pub fn get(&self) -> &V { self.cache.slab[self.node].value() }
+
+    /// A mutable reference to the value. Like `get_mut` on the cache, a value resized through
+    /// it is not recharged in capacity mode.
+    pub fn get_mut(&mut self) -> &mut V { self.cache.slab[self.node].value_mut() }
 
-    /// Creates a new LRU Cache that holds at most `cap` capacity(MB).
-    pub fn storage(cap: NonZeroUsize) -> Self {
-        LRUCache::construct(CacheMode::StoreLimit, cap, HashMap::with_capacity(cap.get()))
+    /// Like `get_mut`, with the lifetime of the cache borrow.
+    pub fn into_mut(self) -> &'a mut V {
+        let OccupiedEntry { cache, node } = self;
+        cache.slab[node].value_mut()
     }
 
-    /// Creates a new LRU Cache that never automatically evicts items.
-    pub fn unbounded() -> Self {
-        LRUCache::construct(CacheMode::UnLimit, NonZeroUsize::new(usize::MAX).unwrap(), HashMap::default())
+    /// Takes the entry out of the cache and returns its value.
+    pub fn remove(self) -> V { self.remove_entry().1 }
+
+    /// Takes the entry out of the cache and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        let OccupiedEntry { cache, node } = self;
+        cache.unindex_node(node);
+        let entry = cache.release(node).into_pair();
+        cache.maybe_shrink();
+        cache.debug_invariants();
+        entry
     }
 }
 
-impl<K, V, S> Cache<K, V, S> for LRUCache<K, V, S>
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
 where
     K: Hash + Eq,
     V: ItemSize,
     S: BuildHasher,
 {
-    fn len(&self) -> usize { self.map.len() }
+    pub fn key(&self) -> &K { &self.key }
 
-    fn cap(&self) -> NonZeroUsize { self.cap }
+    /// Gives the key back without inserting anything.
+    pub fn into_key(self) -> K { self.key }
 
-    fn is_empty(&self) -> bool { self.map.len() == 0 }
+    /// Inserts `value` as the most recently used entry, evicting like `get_or_insert` does. A
+    /// value too large for a capacity-mode cache is not stored, as with `get_or_insert`.
+    pub fn insert(self, value: V) -> &'a mut V { self.cache.insert_loaded(self.key, value) }
+}
 
-    fn put(&mut self, k: K, v: V) -> Option<V> { self.capturing_put(k, v, false).map(|(_, v)| v) }
+/// A value borrowed by `LRUCache::peek_mut_guard`. The entry stays where it is unless
+/// `promote` or `demote` was called, in which case it moves when the guard is dropped.
+pub struct PeekMut<'a, K, V, S = cache::DefaultHasher>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    cache: &'a mut LRUCache<K, V, S>,
+    // stays valid as long as `cache` is borrowed, like the node of an `OccupiedEntry`
+    node: u32,
+    // where the entry goes on drop: `Some(true)` to the front, `Some(false)` to the back
+    to_front: Option<bool>,
+}
 
-    fn push(&mut self, k: K, v: V) -> Option<(K, V)> { self.capturing_put(k, v, true) }
+impl<K, V, S> PeekMut<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K { self.cache.slab[self.node].key() }
 
-    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(node) = self.map.get_mut(k) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
+    /// Moves the entry to the front on drop, like `Cache::promote`. Overrides `demote`.
+    pub fn promote(&mut self) { self.to_front = Some(true); }
 
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+    /// Moves the entry to the back on drop, like `Cache::demote`. Overrides `promote`.
+    pub fn demote(&mut self) { self.to_front = Some(false); }
+}
 
-            Some(unsafe { &(*(*node_ptr).value.as_ptr()) })
-        } else {
-            None
-        }
-    }
+impl<K, V, S> Deref for PeekMut<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    type Target = V;
 
-    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(node) = self.map.get_mut(k) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
+    fn deref(&self) -> &V { self.cache.slab[self.node].value() }
+}
 
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+/// Like `OccupiedEntry::get_mut`, a value resized through the guard is not recharged in
+/// capacity mode.
+impl<K, V, S> DerefMut for PeekMut<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn deref_mut(&mut self) -> &mut V { self.cache.slab[self.node].value_mut() }
+}
 
-            Some(unsafe { &mut (*(*node_ptr).value.as_mut_ptr()) })
-        } else {
-            None
+impl<K, V, S> Drop for PeekMut<'_, K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn drop(&mut self) {
+        let Some(to_front) = self.to_front else { return };
+        self.cache.detach(self.node);
+        match to_front {
+            true => self.cache.attach(self.node),
+            false => self.cache.attach_last(self.node),
         }
+        self.cache.debug_invariants();
     }
+}
 
-    fn get_or_insert<F>(&'_ mut self, k: K, f: F) -> &'_ V
-    where
-        F: FnOnce() -> V,
-    {
-        if let Some(node) = self.map.get_mut(&KeyRef { k: &k }) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum CacheMode {
+    ItemLimit,
+    StoreLimit,
+    UnLimit,
+}
 
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+/// A LRU cache.
+/// This is a single level thread unsafe LRU implementation.
+pub struct LRUCache<K, V, S = cache::DefaultHasher> {
+    // map is used to speed up LRU access. It holds the slots of the entries, hashed and
+    // compared by the keys in them, see `find`.
+    map: HashTable<u32>,
+    hasher: S,
+    // slab holds the entries the map points at, see `Slab`.
+    slab: Slab<K, V>,
+    // cache_mode is used to set the mode of cache: limit by number of items, limit by capacity, unlimited
+    cache_mode: CacheMode,
+    // limit holds the capacity and weighs the entries against it, see `Limiter`.
+    limit: Limit,
+    // entry_size, once `recompute_size` set it, keeps size_used up to date, see `current_size`.
+    entry_size: Option<fn(&K, &V) -> usize>,
+    size_used: usize,
+    // clock decides when entries put with a TTL expire.
+    clock: &'static dyn Clock,
+    // observer is told about hits, misses, inserts and evictions. Observers are shared through
+    // `Arc`, so targets without atomic pointers have none.
+    #[cfg(target_has_atomic = "ptr")]
+    observer: Option<Arc<dyn CacheObserver<K, V>>>,
+    // stats counts what the observer is told about, see `stats`.
+    stats: CacheStats,
+    // in_use, when set, tells which values to pass over when picking an eviction victim, and
+    // how many of them at most.
+    in_use: Option<InUsePolicy<V>>,
+    // in_use_skips counts the entries passed over because they were in use.
+    in_use_skips: u64,
+    // shrink_ratio, when set, shrinks the map once it is less than 1/ratio full.
+    shrink_ratio: Option<usize>,
+    // watermarks, when set, are the (low, high) marks of `set_watermarks`.
+    watermarks: Option<(usize, usize)>,
+    // default_ttl is the TTL of entries put without one, see `set_default_ttl`.
+    default_ttl: Option<Duration>,
+    // on_evict takes the entries the cache drops on its own, see `set_on_evict`. It is only
+    // called through `&mut self`, which is what keeps the cache `Sync` all the same.
+    on_evict: Option<OnEvictSlot<K, V>>,
+    // rejected holds the last value a `get_or_insert` miss loaded but could not store, being
+    // larger than the whole capacity, so that the reference it returns has something to point
+    // at. The next such value, or `clear`, drops it.
+    rejected: Option<(K, V)>,
+}
 
-            unsafe { &(*(*node_ptr).value.as_ptr()) }
-        } else {
-            let v = f();
-            let (_, node) = self.replace_or_create_node(k, v);
+impl<K, V, S> LRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// Creates a new LRU Cache with the given capacity. `UnLimit` mode or no capacity makes it
+    /// unbounded, in `UnLimit` mode whatever `cache_mode` says.
+    fn construct(cache_mode: CacheMode, cap: Option<NonZeroUsize>, map: HashTable<u32>, hasher: S) -> Self {
+        let (cache_mode, limit) = match (cache_mode, cap) {
+            (CacheMode::StoreLimit, Some(cap)) => (CacheMode::StoreLimit, Limit::Bytes(ByteSize::new(cap))),
+            (CacheMode::ItemLimit, Some(cap)) => (CacheMode::ItemLimit, Limit::Items(ItemCount::new(cap))),
+            _ => (CacheMode::UnLimit, Limit::Unbounded(Unbounded::new(0))),
+        };
+        LRUCache {
+            map,
+            hasher,
+            slab: Slab::new(),
+            limit,
+            cache_mode,
+            entry_size: None,
+            size_used: 0,
+            clock: DEFAULT_CLOCK,
+            #[cfg(target_has_atomic = "ptr")]
+            observer: None,
+            stats: CacheStats::default(),
+            in_use: None,
+            in_use_skips: 0,
+            shrink_ratio: None,
+            watermarks: None,
+            default_ttl: None,
+            on_evict: None,
+            rejected: None,
+        }
+    }
 
-            let node_ptr: *mut LRUEntry<K, V> = node.as_ptr();
-            self.attach(node_ptr);
+    #[cfg(target_has_atomic = "ptr")]
+    fn observer(&self) -> Option<&dyn CacheObserver<K, V>> { self.observer.as_deref() }
 
-            let key_ref = KeyRef {
-                k: unsafe { (*node_ptr).key.as_ptr() },
-            };
-            self.map.insert(key_ref, node);
+    #[cfg(not(target_has_atomic = "ptr"))]
+    fn observer(&self) -> Option<&dyn CacheObserver<K, V>> { None }
 
-            unsafe { &(*(*node_ptr).value.as_ptr()) }
+    fn notify_hit(&mut self, node: u32) {
+        self.stats.hits += 1;
+        if let Some(observer) = self.observer() {
+            observer.on_hit(self.slab[node].key());
         }
     }
 
-    fn get_or_insert_mut<F>(&'_ mut self, k: K, f: F) -> &'_ mut V
-    where
-        F: FnOnce() -> V,
-    {
-        if let Some(node) = self.map.get_mut(&KeyRef { k: &k }) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
-
-            self.detach(node_ptr);
-            self.attach(node_ptr);
-
-            unsafe { &mut (*(*node_ptr).value.as_mut_ptr()) }
-        } else {
-            let v = f();
-            let (_, node) = self.replace_or_create_node(k, v);
+    fn notify_miss(&mut self) {
+        self.stats.misses += 1;
+        if let Some(observer) = self.observer() {
+            observer.on_miss();
+        }
+    }
 
-            let node_ptr: *mut LRUEntry<K, V> = node.as_ptr();
-            self.attach(node_ptr);
+    fn notify_insert(&mut self, node: u32) {
+        self.stats.inserts += 1;
+        if let Some(observer) = self.observer() {
+            let entry = &self.slab[node];
+            observer.on_insert(entry.key(), entry.value().size_of());
+        }
+    }
 
-            let key_ref = KeyRef {
-                k: unsafe { (*node_ptr).key.as_ptr() },
-            };
-            self.map.insert(key_ref, node);
+    fn notify_evict(&mut self, key: &K, value: &V, cause: EvictionCause) {
+        match cause {
+            EvictionCause::Capacity => self.stats.evictions += 1,
+            EvictionCause::Expired => self.stats.expirations += 1,
+        }
+        if let Some(observer) = self.observer() {
+            observer.on_evict(key, value, cause);
+        }
+    }
 
-            unsafe { &mut (*(*node_ptr).value.as_mut_ptr()) }
+    // Hands an entry the cache dropped on its own to `on_evict`. Only called where the list and
+    // the map agree, as the callback may panic.
+    fn evicted(&mut self, entry: Option<(K, V)>) {
+        if let (Some((key, value)), Some(on_evict)) = (entry, &mut self.on_evict) {
+            (on_evict.0)(key, value);
         }
     }
 
-    fn peek<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    // Removes `k` if it expired, for lookups that treat it as absent.
+    fn drop_if_expired<Q>(&mut self, k: &Q)
     where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.map
-            .get(k)
-            .map(|node| unsafe { &*node.as_ref().value.as_ptr() })
+        let expired = self.pop_if_expired(k);
+        self.evicted(expired);
     }
 
-    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    // The slot holding `k`.
+    fn find<Q>(&self, k: &Q) -> Option<u32>
     where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.map
-            .get_mut(k)
-            .map(|node| unsafe { &mut *(*(*node).as_ptr()).value.as_mut_ptr() })
+        let slab = &self.slab;
+        self.map.find(self.hasher.hash_one(k), |&node| holds(slab, node, k)).copied()
     }
 
-    fn peek_last(&'_ mut self) -> Option<(&'_ K, &'_ V)> {
-        if self.is_empty() {
-            return None;
-        }
-
-        let (key, val) = unsafe {
-            let node = (*self.tail).prev;
-
-            (&(*(*node).key.as_ptr()), &(*(*node).value.as_ptr()))
-        };
-
-        Some((key, val))
+    // Adds `node`, whose key is not in the map yet, to the map.
+    fn index(&mut self, node: u32) {
+        let hash = self.hasher.hash_one(self.slab[node].key());
+        self.map.insert_unique(hash, node, rehash(&self.slab, &self.hasher));
     }
 
-    fn contains<Q>(&self, k: &Q) -> bool
+    // Takes `k` out of the map, returning the slot holding it.
+    fn unindex<Q>(&mut self, k: &Q) -> Option<u32>
     where
         KeyRef<K>: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.map.contains_key(k)
+        let slab = &self.slab;
+        let found = self.map.find_entry(self.hasher.hash_one(k), |&node| holds(slab, node, k));
+        found.ok().map(|entry| entry.remove().0)
     }
 
-    fn pop<Q>(&mut self, k: &Q) -> Option<V>
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        match self.map.remove(k) {
-            Some(node) => {
-                let mut old_node = unsafe {
-                    let mut old_node = *Box::from_raw(node.as_ptr());
-                    std::ptr::drop_in_place(old_node.key.as_mut_ptr());
+    // Takes `node` out of the map.
+    fn unindex_node(&mut self, node: u32) {
+        let hash = self.hasher.hash_one(self.slab[node].key());
+        if let Ok(entry) = self.map.find_entry(hash, |&other| other == node) {
+            entry.remove();
+        }
+    }
+
+    /// Detach specific `node`.
+    fn detach(&mut self, node: u32) {
+        let LRUEntry { prev, next, .. } = self.slab[node];
+        self.slab[prev].next = next;
+        self.slab[next].prev = prev;
+    }
 
-                    old_node
-                };
+    /// Attaches `node` after the sigil `HEAD` node.
+    fn attach(&mut self, node: u32) {
+        let next = self.slab[HEAD].next;
+        let entry = &mut self.slab[node];
+        entry.stamps.touch(self.clock);
+        entry.next = next;
+        entry.prev = HEAD;
+        self.slab[HEAD].next = node;
+        self.slab[next].prev = node;
+    }
 
-                self.detach(&mut old_node);
+    fn detach_last(&mut self) -> Option<LRUEntry<K, V>> {
+        let prev = self.slab[TAIL].prev;
 
-                let LRUEntry { value, .. } = old_node;
-                Some(unsafe { value.assume_init() })
-            }
-            None => None,
+        if prev != HEAD {
+            self.unindex_node(prev);
+            Some(self.release(prev))
+        } else {
+            None
         }
     }
 
-    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        match self.map.remove(k) {
-            Some(node) => {
-                let mut old_node = unsafe { *Box::from_raw(node.as_ptr()) };
-                self.detach(&mut old_node);
+    // Takes `node`, already removed from the map, out of the list and the limiter, and frees
+    // its slot.
+    fn release(&mut self, node: u32) -> LRUEntry<K, V> {
+        self.detach(node);
+        self.uncharge(node);
+        self.slab.remove(node)
+    }
 
-                let LRUEntry { key, value, .. } = old_node;
-                Some(unsafe { (key.assume_init(), value.assume_init()) })
+    // The entry to evict: the least recently used one, unless it is in use and `in_use` allows
+    // walking toward hotter entries for one that is not. Must not be called on an empty list.
+    fn eviction_victim(&mut self) -> u32 {
+        let last = self.slab[TAIL].prev;
+        let Some((in_use, max_skips)) = self.in_use else {
+            return last;
+        };
+        let (mut node, mut skips) = (last, 0);
+        while in_use(self.slab[node].value()) {
+            node = self.slab[node].prev;
+            skips += 1;
+            if skips > max_skips || node == HEAD {
+                return last;
             }
-            None => None,
         }
+        self.in_use_skips += skips as u64;
+        node
     }
 
-    fn pop_last(&mut self) -> Option<(K, V)> {
-        let node = self.detach_last()?;
-        let node = *node;
-        let LRUEntry { key, value, .. } = node;
-
-        Some(unsafe { (key.assume_init(), value.assume_init()) })
+    // Checks the list rather than the map, which still holds an entry that `try_capturing_put`
+    // took out of the list while making room for its new value.
+    fn pop_victim(&mut self) -> Option<(K, V)> {
+        if self.slab[TAIL].prev == HEAD {
+            return None;
+        }
+        let victim = self.eviction_victim();
+        self.unindex_node(victim);
+        Some(self.release(victim).into_pair())
     }
 
-    fn promote<Q>(&mut self, k: &Q)
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(node) = self.map.get_mut(k) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
-            self.detach(node_ptr);
-            self.attach(node_ptr);
+    // Gives back to the limiter what an entry leaving the cache was charged, the weight stored
+    // in its node rather than the current size of a value that may have changed in place. Also
+    // takes the entry out of `current_size`; an empty cache starts both over at 0. Called once
+    // `node` is out of the map, before its key and value are dropped.
+    fn uncharge(&mut self, node: u32) {
+        let empty = self.map.is_empty();
+        match empty {
+            true => self.limit.reset(),
+            false => self.limit.remove(self.slab[node].weight),
         }
+        self.size_used = if empty { 0 } else { self.size_used.saturating_sub(self.entry_bytes(node)) };
     }
 
-    fn demote<Q>(&mut self, k: &Q)
-    where
-        KeyRef<K>: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        if let Some(node) = self.map.get_mut(k) {
-            let node_ptr: *mut LRUEntry<K, V> = (*node).as_ptr();
-            self.detach(node_ptr);
-            self.attach_last(node_ptr);
-        }
+    // The bytes `node` counts for in `current_size`, 0 while that is not tracked.
+    fn entry_bytes(&self, node: u32) -> usize {
+        let entry = &self.slab[node];
+        self.entry_size.map_or(0, |entry_size| entry_size(entry.key(), entry.value()))
     }
 
-    fn resize(&mut self, cap: NonZeroUsize) {
-        if cap == self.cap {
-            return;
+    // Called after every removal, so disabled or above the threshold it is one compare.
+    fn maybe_shrink(&mut self) {
+        if let Some(ratio) = self.shrink_ratio {
+            let capacity = self.map.capacity();
+            if self.map.len() < capacity / ratio && capacity > AUTO_SHRINK_MIN_CAPACITY {
+                self.shrink_to(self.map.len() * 2);
+            }
         }
+    }
 
-        while self.map.len() > cap.get() {
-            self.pop_last();
+    // Compacts the slab, pointing the map at the slots the entries moved to, and shrinks the
+    // map to room for `min` keys. No node index may be held across it.
+    fn shrink_to(&mut self, min: usize) {
+        if let Some(remap) = self.slab.compact() {
+            for node in self.map.iter_mut() {
+                *node = remap[*node as usize];
+            }
         }
-        self.map.shrink_to_fit();
+        self.map.shrink_to(min, rehash(&self.slab, &self.hasher));
+    }
 
-        self.cap = cap;
+    // With the `debug-invariants` feature, panics unless `check_consistency` passes. Called at
+    // the end of the public mutations, once the list, the map and the limiter agree again.
+    fn debug_invariants(&self) {
+        #[cfg(feature = "debug-invariants")]
+        self.assert_invariants();
     }
 
-    fn clear(&mut self) { while self.pop_last().is_some() {} }
-}
+    fn attach_last(&mut self, node: u32) {
+        let prev = self.slab[TAIL].prev;
+        let entry = &mut self.slab[node];
+        entry.next = TAIL;
+        entry.prev = prev;
+        self.slab[TAIL].prev = node;
+        self.slab[prev].next = node;
+    }
 
-impl<K, V, S> Drop for LRUCache<K, V, S> {
-    fn drop(&mut self) {
-        self.map.drain().for_each(|(_, node)| unsafe {
-            let mut node = *Box::from_raw(node.as_ptr());
-            std::ptr::drop_in_place((node).key.as_mut_ptr());
-            std::ptr::drop_in_place((node).value.as_mut_ptr());
+    fn replace_or_create_node(&mut self, k: K, v: V) -> Replace<K, V> {
+        let weight = self.weigh(&v, None);
+        match self.try_replace_or_create_node(k, v, weight, alloc_slot) {
+            Ok(replaced) => replaced,
+            Err(_) => unreachable!("alloc_slot aborts instead of failing"),
+        }
+    }
+
+    // Like `replace_or_create_node`, but leaves the cache untouched if `alloc` fails to grow
+    // the slab. The new entry is charged `weight`; callers turned away weights larger than the
+    // whole capacity.
+    fn try_replace_or_create_node(&mut self, k: K, v: V, weight: usize, alloc: SlotAlloc<K, V>) -> Result<Replace<K, V>, CacheError> {
+        // allocate before evicting so that a failure costs no entries
+        let entry = LRUEntry::new(k, v, Stamps::new(self.clock));
+        let node = self.slab.try_insert(entry, alloc).ok_or(CacheError::AllocError)?;
+        let mut replaced = None;
+        self.make_room(weight, |cache, entry| {
+            // only the last one can be handed to the caller
+            let earlier = replaced.replace(entry);
+            cache.evicted(earlier);
         });
+        let expires_at = self.default_deadline();
+        let entry = &mut self.slab[node];
+        entry.expires_at = expires_at;
+        entry.weight = weight;
+        self.limit.add(weight);
+        self.size_used += self.entry_bytes(node);
+        Ok((replaced, node))
+    }
 
-        let _head = unsafe { *Box::from_raw(self.head) };
-        let _tail = unsafe { *Box::from_raw(self.tail) };
+    // The eviction loop of every bound: evicts entries, least recently used first, until
+    // `extra` more fits the limiter, passing each to `sink`. Unbounded caches evict nothing
+    // unless watermarks are set. With watermarks, nothing is evicted until `extra` would take
+    // the cache past the high one, and then entries go until it stays at or under the low one.
+    fn make_room(&mut self, extra: usize, mut sink: impl FnMut(&mut Self, (K, V))) {
+        let used = self.limit.used() + extra;
+        let floor = match self.watermarks {
+            Some((low, high)) if used > high || self.limit.over_budget(extra) => Some(low),
+            _ => None,
+        };
+        if floor.is_none() && self.is_unbounded() {
+            return;
+        }
+        while self.limit.over_budget(extra) || floor.is_some_and(|low| self.limit.used() + extra > low) {
+            let Some((key, value)) = self.pop_victim() else { break };
+            self.notify_evict(&key, &value, EvictionCause::Capacity);
+            sink(self, (key, value));
+        }
     }
-}
 
-impl<'a, K: Hash + Eq, V: ItemSize, S: BuildHasher> IntoIterator for &'a LRUCache<K, V, S> {
-    type IntoIter = Iter<'a, K, V>;
-    type Item = (&'a K, &'a V);
+    // What `v` is charged: the weight given to `put_weighted` in capacity mode, or else what
+    // the limiter makes of it.
+    fn weigh(&self, v: &V, weight: Option<usize>) -> usize {
+        match (weight, &self.limit) {
+            (Some(weight), Limit::Bytes(_)) => weight,
+            _ => self.limit.charge(v),
+        }
+    }
 
-    fn into_iter(self) -> Self::IntoIter { self.iter() }
-}
+    // Whether an entry charged `weight` can be stored at all.
+    fn fits(&self, weight: usize) -> bool { self.limit.cap().is_none_or(|cap| weight <= cap.get()) }
 
-impl<'a, K: Hash + Eq, V: ItemSize, S: BuildHasher> IntoIterator for &'a mut LRUCache<K, V, S> {
-    type IntoIter = IterMut<'a, K, V>;
-    type Item = (&'a K, &'a mut V);
+    // `try_get_or_insert_with_key_mut`, which promotes a hit and counts it, and the miss, if
+    // `read`. The caches built on this one whose reads do not reorder pass false.
+    pub(crate) fn lookup_or_insert<F, E>(&mut self, k: K, f: F, read: bool) -> Result<&mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        self.drop_if_expired::<K>(&k);
+        if let Some(node) = self.find(&k) {
+            if read {
+                self.detach(node);
+                self.attach(node);
+                self.notify_hit(node);
+                self.debug_invariants();
+            }
+            return Ok(self.slab[node].value_mut());
+        }
+        if read {
+            self.notify_miss();
+        }
+        // the loader runs before `insert_loaded` so that a failure evicts nothing
+        let v = f(&k)?;
+        Ok(self.insert_loaded(k, v))
+    }
 
-    fn into_iter(self) -> IterMut<'a, K, V> { self.iter_mut() }
-}
+    // The miss path of `get_or_insert` and `VacantEntry::insert`: stores the loaded value as the
+    // most recently used entry, unless it is too large to fit, in which case it is set aside in
+    // `rejected` without evicting anything.
+    fn insert_loaded(&mut self, k: K, v: V) -> &mut V {
+        if !self.fits(self.weigh(&v, None)) {
+            return &mut self.rejected.insert((k, v)).1;
+        }
+        let (replaced, node) = self.replace_or_create_node(k, v);
 
-unsafe impl<K: Send, V: Send, S: Send> Send for LRUCache<K, V, S> {}
-unsafe impl<K: Sync, V: Sync, S: Sync> Sync for LRUCache<K, V, S> {}
+        self.attach(node);
+        self.notify_insert(node);
 
-impl<K: Hash + Eq, V: ItemSize> fmt::Debug for LRUCache<K, V> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("LRUCache")
-            .field("len", &self.len())
-            .field("cap", &self.cap())
-            .finish()
+        self.index(node);
+        self.evicted(replaced);
+        self.debug_invariants();
+
+        self.slab[node].value_mut()
     }
-}
 
-impl<K: Hash + Eq, V: ItemSize> IntoIterator for LRUCache<K, V> {
-    type IntoIter = IntoIter<K, V>;
-    type Item = (K, V);
+    // When an entry stored now without a TTL of its own expires, see `set_default_ttl`.
+    fn default_deadline(&self) -> Option<Instant> { self.default_ttl.map(|ttl| self.clock.now() + ttl) }
 
-    fn into_iter(self) -> IntoIter<K, V> { IntoIter { cache: self } }
-}
+    // Used internally by `put` and `push` to add a new entry to the lru.
+    // Takes ownership of and returns entries replaced due to the cache's capacity
+    // when `capture` is true.
+    // An entry too large for a capacity-mode cache is not stored, and handed back if `capture`.
+    fn capturing_put(&mut self, k: K, v: V, capture: bool, expires_at: Option<Instant>) -> Option<(K, V)> {
+        self.weighted_put(k, v, None, capture, expires_at)
+    }
 
-#[cfg(test)]
-mod tests {
-    use core::fmt::Debug;
-    use core::num::NonZeroUsize;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    // `capturing_put` charging `weight` instead of the value's `ItemSize` in capacity mode.
+    fn weighted_put(&mut self, k: K, v: V, weight: Option<usize>, capture: bool, expires_at: Option<Instant>) -> Option<(K, V)> {
+        if !self.fits(self.weigh(&v, weight)) {
+            return Some((k, v)).filter(|_| capture);
+        }
+        match self.try_capturing_put(k, v, capture, expires_at, weight, alloc_slot) {
+            Ok(replaced) => replaced,
+            Err(_) => unreachable!("alloc_slot aborts and oversized values were handled"),
+        }
+    }
 
-    use super::LRUCache;
-    use crate::lru::cache::Cache;
-    use crate::lru::item_size::ItemSize;
+    fn try_capturing_put(
+        &mut self,
+        k: K,
+        mut v: V,
+        capture: bool,
+        expires_at: Option<Instant>,
+        weight: Option<usize>,
+        alloc: SlotAlloc<K, V>,
+    ) -> Result<Option<(K, V)>, CacheError> {
+        let span = op_span!("lru.put");
+        let expires_at = expires_at.or_else(|| self.default_deadline());
+        span.value_size(v.size_of());
+        let size = self.weigh(&v, weight);
+        if !self.fits(size) {
+            // storing it would evict everything and still not fit
+            return Err(CacheError::TooLarge { size, capacity: self.cap().get() });
+        }
+        match self.find(&k) {
+            // if the key is already in the cache just update its
+            // value and move it to the front of the list
+            Some(node) => {
+                let old_bytes = self.entry_bytes(node);
+                let entry = &mut self.slab[node];
+                mem::swap(&mut v, entry.value_mut());
+                entry.expires_at = expires_at;
+                self.size_used = self.size_used.saturating_sub(old_bytes) + self.entry_bytes(node);
+
+                self.detach(node);
+                self.notify_insert(node);
+
+                // the new value may be larger; it is the most recent entry, so others go first.
+                // It stays out of the list meanwhile, for watermarks not to evict it as well,
+                // and `on_evict` waits for it to be back.
+                let mut evicted = Vec::new();
+                self.limit.remove(self.slab[node].weight);
+                self.make_room(size, |_, entry| evicted.push(entry));
+                self.limit.add(size);
+                self.slab[node].weight = size;
+                self.attach(node);
+                for entry in evicted {
+                    self.evicted(Some(entry));
+                }
 
-    extern crate alloc;
+                Ok(Some((k, v)))
+            }
+            None => {
+                let len = self.len();
+                let (replaced, node) = self.try_replace_or_create_node(k, v, size, alloc)?;
 
-    fn assert_opt_eq<V: PartialEq + Debug + ItemSize>(opt: Option<&V>, v: V) {
-        assert!(opt.is_some());
-        assert_eq!(opt.unwrap(), &v);
-    }
+                self.slab[node].expires_at = expires_at;
+                self.attach(node);
+                self.notify_insert(node);
 
-    fn assert_opt_eq_mut<V: PartialEq + Debug + ItemSize>(opt: Option<&mut V>, v: V) {
-        assert!(opt.is_some());
-        assert_eq!(opt.unwrap(), &v);
-    }
+                self.index(node);
+                span.evictions(len + 1 - self.len());
 
-    fn assert_opt_eq_tuple<K: PartialEq + Debug, V: PartialEq + Debug + ItemSize>(
-        opt: Option<(&K, &V)>,
-        kv: (K, V),
-    ) {
-        assert!(opt.is_some());
-        let res = opt.unwrap();
-        assert_eq!(res.0, &kv.0);
-        assert_eq!(res.1, &kv.1);
+                if capture {
+                    return Ok(replaced);
+                }
+                self.evicted(replaced);
+                Ok(None)
+            }
+        }
     }
 
-    fn assert_opt_eq_mut_tuple<K: PartialEq + Debug, V: PartialEq + Debug + ItemSize>(
-        opt: Option<(&K, &mut V)>,
-        kv: (K, V),
-    ) {
-        assert!(opt.is_some());
-        let res = opt.unwrap();
-        assert_eq!(res.0, &kv.0);
-        assert_eq!(res.1, &kv.1);
+    /// Creates a new LRU Cache that holds at most `cap` items and
+    /// uses the provided hash builder to hash keys.
+    pub fn with_hasher(cache_mode: CacheMode, cap: NonZeroUsize, hasher: S) -> Self {
+        LRUCache::construct(cache_mode, Some(cap), HashTable::with_capacity(cap.get()), hasher)
     }
 
-    #[test]
-    fn test_unbounded() {
-        let mut cache = LRUCache::unbounded();
-        for i in 0..13370 {
-            cache.put(i, ());
+    /// Like `with_hasher`, but takes the capacity as a plain `usize` and returns
+    /// `CacheError::ZeroCapacity` for 0 instead of needing a `NonZeroUsize`. In item mode the
+    /// map is sized for `cap` keys up front, like `new` does, and `CacheError::AllocError` is
+    /// returned if that cannot be allocated.
+    pub fn try_with_hasher(cache_mode: CacheMode, cap: usize, hasher: S) -> Result<Self, CacheError> {
+        let cap = NonZeroUsize::new(cap).ok_or(CacheError::ZeroCapacity)?;
+        let mut map = HashTable::new();
+        if matches!(cache_mode, CacheMode::ItemLimit) {
+            map.try_reserve(cap.get(), |_: &u32| unreachable!("the map is empty"))?;
         }
-        assert_eq!(cache.len(), 13370);
+        Ok(LRUCache::construct(cache_mode, Some(cap), map, hasher))
     }
 
-    #[test]
-    fn test_put_and_get() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
-        assert!(cache.is_empty());
+    // Like `storage`, with `hasher`. The map is not sized after `cap`, which counts bytes.
+    pub(crate) fn storage_with_hasher(cap: NonZeroUsize, hasher: S) -> Self {
+        LRUCache::construct(CacheMode::StoreLimit, Some(cap), HashTable::new(), hasher)
+    }
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
+    /// Like `storage`, with `hasher`, returning `CacheError::ZeroCapacity` for a budget of 0.
+    pub fn try_storage_with_hasher(cap: usize, hasher: S) -> Result<Self, CacheError> {
+        let cap = NonZeroUsize::new(cap).ok_or(CacheError::ZeroCapacity)?;
+        Ok(LRUCache::storage_with_hasher(cap, hasher))
+    }
 
-        assert_eq!(cache.cap().get(), 2);
-        assert_eq!(cache.len(), 2);
-        assert!(!cache.is_empty());
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+    /// Creates a new LRU Cache that never automatically evicts items and
+    /// uses the provided hash builder to hash keys. The cache is in `CacheMode::UnLimit`
+    /// whatever `cache_mode` says, until `resize` bounds it.
+    pub fn unbounded_with_hasher(cache_mode: CacheMode, hasher: S) -> Self {
+        LRUCache::construct(cache_mode, None, HashTable::new(), hasher)
     }
 
-    #[test]
+    /// Reserves room in the map and the slab for at least `additional` more keys, e.g. before
+    /// filling an unbounded cache.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional, rehash(&self.slab, &self.hasher));
+        self.slab.entries.reserve(additional);
+    }
+
+    /// Shrinks the map and the slab as much as possible for the current number of entries.
+    pub fn shrink_to_fit(&mut self) { self.shrink_to(0) }
+
+    /// The number of keys the map can hold without reallocating, see `reserve`.
+    pub fn map_capacity(&self) -> usize { self.map.capacity() }
+
+    /// Reserves room in the map and the slab for at least `additional` more keys, returning
+    /// `CacheError::AllocError` instead of aborting if that cannot be allocated.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> {
+        self.map.try_reserve(additional, rehash(&self.slab, &self.hasher))?;
+        self.slab.entries.try_reserve(additional)?;
+        Ok(())
+    }
+
+    /// Like `put`, but returns `CacheError::AllocError` instead of aborting when the map cannot
+    /// grow or the new entry cannot be allocated. The cache is unchanged on error.
+    pub fn try_put(&mut self, k: K, v: V) -> Result<Option<V>, CacheError> {
+        self.try_reserve(1)?;
+        Ok(self.try_capturing_put(k, v, false, None, None, try_alloc_slot)?.map(|(_, v)| v))
+    }
+
+    /// Returns the mode used to bound the cache.
+    pub fn cache_mode(&self) -> &CacheMode { &self.cache_mode }
+
+    /// Lifts the bound: puts evict nothing from now on, unless watermarks are set. Entries
+    /// are counted one each, in capacity mode too, and `resize` bounds the number of entries
+    /// again.
+    pub fn set_unbounded(&mut self) {
+        if self.is_unbounded() {
+            return;
+        }
+        let mut node = self.slab[HEAD].next;
+        while node != TAIL {
+            let entry = &mut self.slab[node];
+            entry.weight = 1;
+            node = entry.next;
+        }
+        self.limit = Limit::Unbounded(Unbounded::new(self.len()));
+        self.cache_mode = CacheMode::UnLimit;
+        self.debug_invariants();
+    }
+
+    /// Replaces the clock used to compute and check TTL deadlines. Clones of the cache share it.
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) { self.clock = clock; }
+
+    /// The clock TTL deadlines are read from.
+    pub fn clock(&self) -> &'static dyn Clock { self.clock }
+
+    /// Installs the instrumentation told about hits, misses, inserts and evictions, replacing
+    /// any previous one. Clones of the cache share it. Only on targets with atomic pointers.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn set_observer(&mut self, observer: Arc<dyn CacheObserver<K, V>>) { self.observer = Some(observer); }
+
+    /// Registers `f` to take every entry the cache drops on its own: capacity evictions that
+    /// `put` and `get_or_insert` do not return, entries removed by `resize` and `clear`, and
+    /// expired entries removed by lookups. Entries returned to the caller, by `push` or `pop`
+    /// for instance, are not passed to it. Clones of the cache start without one.
+    pub fn set_on_evict(&mut self, f: impl FnMut(K, V) + Send + 'static) { self.on_evict = Some(OnEvictSlot(Box::new(f))); }
+
+    // `set_on_evict` for a callback boxed already, which would otherwise need `K` and `V` to
+    // be `'static`.
+    pub(crate) fn set_boxed_on_evict(&mut self, f: Option<OnEvict<K, V>>) { self.on_evict = f.map(OnEvictSlot); }
+
+    /// Gives memory back after heavy churn: once a removal leaves the map less than `1/ratio`
+    /// full, it is shrunk to twice the number of entries. `Some(4)` is a reasonable ratio;
+    /// maps of up to `AUTO_SHRINK_MIN_CAPACITY` slots are left alone. `None` turns it off.
+    pub fn set_auto_shrink(&mut self, ratio: Option<usize>) { self.shrink_ratio = ratio.filter(|&r| r >= 2); }
+
+    /// Evicts in batches instead of one entry per insert: nothing is evicted until an insert
+    /// takes the cache past `high`, and then the least recently used entries go, each handed
+    /// to `on_evict`, until it is back to `low`. The marks count entries, or bytes in capacity
+    /// mode, and also bound an unbounded cache. The capacity still holds if `high` is above
+    /// it, an insert that would exceed it evicting down to `low` as well. `None` turns it off,
+    /// evicting one entry per insert again.
+    ///
+    /// # Panics
+    ///
+    /// If `low` is not below `high`.
+    pub fn set_watermarks(&mut self, marks: Option<(usize, usize)>) {
+        if let Some((low, high)) = marks {
+            assert!(low < high, "the low watermark {low} must be below the high one {high}");
+        }
+        self.watermarks = marks;
+    }
+
+    /// Gives the entries stored from now on without a TTL of their own, by `put`, `push` or
+    /// `get_or_insert` for instance, the TTL `ttl`. `None` turns it off.
+    pub fn set_default_ttl(&mut self, ttl: Option<Duration>) { self.default_ttl = ttl; }
+
+    /// Hits, misses, inserts, evictions and expirations counted since the cache was created or
+    /// `reset_stats` was last called. `peek` and the other non-promoting reads are not counted.
+    pub fn stats(&self) -> CacheStats { self.stats }
+
+    /// Sets every count of `stats` back to 0.
+    pub fn reset_stats(&mut self) { self.stats = CacheStats::default(); }
+
+    /// Sum of the values' `ItemSize`, or of their `put_weighted` cost, in capacity mode
+    /// (`LRUCache::storage`), 0 in the others.
+    pub fn used_bytes(&self) -> usize {
+        match &self.limit {
+            Limit::Bytes(bytes) => bytes.used(),
+            Limit::Items(_) | Limit::Unbounded(_) => 0,
+        }
+    }
+
+    /// Bytes held by the keys and values together, by their `ItemSize`, in any mode. Once
+    /// `recompute_size` was called this is kept up to date as entries come and go; until then
+    /// every call adds up all the entries.
+    pub fn current_size(&self) -> usize
+    where
+        K: ItemSize,
+    {
+        match self.entry_size {
+            Some(_) => self.size_used,
+            None => self.iter().map(|(k, v)| k.size_of() + v.size_of()).sum(),
+        }
+    }
+
+    /// What the entry of `k` counts for in `current_size`, or `None` if it is not present.
+    pub fn size_of_entry<Q>(&self, k: &Q) -> Option<usize>
+    where
+        K: ItemSize,
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_key_value(k).map(|(k, v)| k.size_of() + v.size_of())
+    }
+
+    /// Adds up `current_size` again and keeps it up to date from then on. Values changed in
+    /// place, through `get_mut`, `peek_mut` and the like, are only counted anew by this.
+    pub fn recompute_size(&mut self)
+    where
+        K: ItemSize,
+    {
+        self.entry_size = Some(|k: &K, v: &V| k.size_of() + v.size_of());
+        self.size_used = self.iter().map(|(k, v)| k.size_of() + v.size_of()).sum();
+    }
+
+    /// Number of entries passed over by evictions because they were in use, see
+    /// `skip_in_use_victims`.
+    pub fn in_use_skips(&self) -> u64 { self.in_use_skips }
+
+    /// Puts a key-value pair that expires `ttl` from now, even if it keeps being read. Expired
+    /// entries are treated as absent by `get`, `get_mut`, `peek`, `peek_mut`, `peek_many` and
+    /// `contains`; the ones taking `&mut self` also remove them. Until then an expired entry
+    /// still counts in `len`, is listed by `iter`, and is returned by `pop_last` and evictions
+    /// like any other. Returns the old value like `put`.
+    pub fn put_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> Option<V> {
+        let deadline = self.clock.now() + ttl;
+        self.capturing_put(k, v, false, Some(deadline)).map(|(_, v)| v)
+    }
+
+    /// Like `push`, but the entry expires `ttl` from now.
+    pub fn push_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> Option<(K, V)> {
+        let deadline = self.clock.now() + ttl;
+        self.capturing_put(k, v, true, Some(deadline))
+    }
+
+    /// Returns the time left before the entry expires, or `None` if it is missing, expired or
+    /// has no TTL.
+    pub fn ttl<Q>(&self, k: &Q) -> Option<Duration>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let deadline = self.slab[self.find(k)?].expires_at?;
+        deadline.checked_duration_since(self.clock.now()).filter(|d| !d.is_zero())
+    }
+
+    /// Pushes the deadline of a live entry with a TTL `by` further into the future without
+    /// promoting it. Returns false if the key is missing, expired or never expires.
+    pub fn extend_ttl<Q>(&mut self, k: &Q, by: Duration) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        match self.find(k) {
+            Some(node) => {
+                let node = &mut self.slab[node];
+                match node.expires_at {
+                    Some(deadline) if deadline > now => {
+                        node.expires_at = Some(deadline + by);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the entry for `k` if it carries a TTL that has passed. Lets owners
+    /// of values with side tables release them before an expired entry is dropped lazily.
+    pub fn pop_if_expired<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let expired = match self.find(k) {
+            Some(node) => self.slab[node].expires_at.is_some() && self.slab[node].is_expired(self.clock.now()),
+            None => false,
+        };
+        if !expired {
+            return None;
+        }
+        let (key, value) = self.pop_entry(k)?;
+        self.notify_evict(&key, &value, EvictionCause::Expired);
+        Some((key, value))
+    }
+
+    /// Walks the whole list, for debugging: checks that the sigils are intact, that every node
+    /// links back to the one before it and is the node the map holds for its key, that the
+    /// list and the map hold as many entries, and that the charged weights add up to what the
+    /// limiter counts. Returns the first problem found.
+    pub fn check_consistency(&self) -> Result<(), ConsistencyError> {
+        let (head, tail) = (&self.slab[HEAD], &self.slab[TAIL]);
+        if head.prev != NIL || tail.next != NIL {
+            return Err(ConsistencyError::BrokenSigil);
+        }
+        let (mut prev, mut node, mut len, mut weight) = (HEAD, head.next, 0, 0);
+        // distinct keys map to distinct nodes, so a list as long as the map and whose every
+        // node is in it holds all of the map
+        while node != TAIL {
+            // a link out of the slab, or to a sigil, means the list ran off its end
+            if node as usize >= self.slab.len() || node == HEAD {
+                return Err(ConsistencyError::BrokenSigil);
+            }
+            if len == self.map.len() {
+                // too long, or a cycle
+                return Err(ConsistencyError::LengthMismatch { list: len + 1, map: self.map.len() });
+            }
+            let entry = &self.slab[node];
+            if entry.prev != prev {
+                return Err(ConsistencyError::BrokenLink { position: len });
+            }
+            if entry.kv.is_none() || self.find(entry.key()) != Some(node) {
+                return Err(ConsistencyError::NotInMap { position: len });
+            }
+            weight += entry.weight;
+            (prev, node, len) = (node, entry.next, len + 1);
+        }
+        if tail.prev != prev {
+            return Err(ConsistencyError::BrokenLink { position: len });
+        }
+        if len != self.map.len() {
+            return Err(ConsistencyError::LengthMismatch { list: len, map: self.map.len() });
+        }
+        if weight != self.limit.used() {
+            return Err(ConsistencyError::WeightMismatch { entries: weight, limiter: self.limit.used() });
+        }
+        Ok(())
+    }
+
+    /// Panics with the problem `check_consistency` finds, if any. With the `debug-invariants`
+    /// feature it runs after every mutation.
+    pub fn assert_invariants(&self) {
+        if let Err(err) = self.check_consistency() {
+            panic!("inconsistent LRUCache: {err}");
+        }
+    }
+
+    /// Returns how old and how idle the entry for `k` is, or `None` if it is missing or
+    /// expired. Does not promote it.
+    #[cfg(feature = "entry-timestamps")]
+    pub fn peek_meta<Q>(&self, k: &Q) -> Option<EntryMeta>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock.now();
+        let node = &self.slab[self.find(k)?];
+        (!node.is_expired(now)).then(|| EntryMeta::of(node, now))
+    }
+
+    /// Like `iter`, pairing every key with its `peek_meta`, all measured at the same instant.
+    #[cfg(feature = "entry-timestamps")]
+    pub fn iter_meta(&self) -> impl Iterator<Item = (&K, EntryMeta)> {
+        let now = self.clock.now();
+        let mut node = self.slab[HEAD].next;
+        core::iter::from_fn(move || {
+            if node == TAIL {
+                return None;
+            }
+            let entry = &self.slab[node];
+            node = entry.next;
+            Some((entry.key(), EntryMeta::of(entry, now)))
+        })
+    }
+
+    /// Describes every entry, most recently used first, without promoting anything.
+    pub fn dump(&self, options: DumpOptions) -> CacheDump<'_, K, V> {
+        let now = self.clock.now();
+        let mut entries = Vec::with_capacity(self.len());
+        let mut node = self.slab[HEAD].next;
+        while node != TAIL {
+            let entry = &self.slab[node];
+            let (key, value) = (entry.key(), entry.value());
+            let size = value.size_of();
+            entries.push(DumpEntry {
+                rank: entries.len(),
+                key,
+                size,
+                value: Some(value).filter(|_| options.include_values && size <= options.max_value_bytes),
+                ttl_ms: entry.expires_at.map(|deadline| deadline.saturating_duration_since(now).as_millis()),
+                #[cfg(feature = "entry-timestamps")]
+                idle_ms: Some(now.saturating_duration_since(entry.stamps.accessed_at).as_millis()),
+                #[cfg(not(feature = "entry-timestamps"))]
+                idle_ms: None,
+            });
+            node = entry.next;
+        }
+        CacheDump { capacity: self.cap().get(), len: self.len(), entries }
+    }
+
+    /// Removes the entries not accessed within `max_idle` of `now`, least recently used first.
+    /// The walk stops at the first recent entry: recency order is access order, except for
+    /// entries moved back with `demote`, which can shield older ones behind them.
+    #[cfg(feature = "entry-timestamps")]
+    pub fn pop_older_than(&mut self, max_idle: Duration, now: Instant) -> Vec<(K, V)> {
+        let span = op_span!("lru.pop_older_than");
+        let mut removed = Vec::new();
+        loop {
+            let last = self.slab[TAIL].prev;
+            if last == HEAD || now.saturating_duration_since(self.slab[last].stamps.accessed_at) <= max_idle {
+                break;
+            }
+            match self.pop_last() {
+                Some((key, value)) => {
+                    self.notify_evict(&key, &value, EvictionCause::Expired);
+                    removed.push((key, value));
+                }
+                None => break,
+            }
+        }
+        span.evictions(removed.len());
+        removed
+    }
+
+    /// Keeps only the `n` most recently used entries and returns the others, least recently used
+    /// first. The capacity is unchanged.
+    pub fn truncate(&mut self, n: usize) -> Vec<(K, V)> {
+        let mut removed = Vec::with_capacity(self.len().saturating_sub(n));
+        while self.len() > n {
+            match self.pop_last() {
+                Some(entry) => removed.push(entry),
+                None => break,
+            }
+        }
+        removed
+    }
+
+    /// Moves every entry out, most recently used first, and leaves an empty cache with the same
+    /// capacity and hasher. Unlike `into_iter`, works through a `&mut` such as a lock guard.
+    pub fn take_all(&mut self) -> Vec<(K, V)> {
+        let mut all = self.truncate(0);
+        all.reverse();
+        all
+    }
+
+    /// Consumes the cache into one with the same keys, mode, capacity, hasher, clock and exact
+    /// recency order, with every value passed through `f`. TTLs and idle times are kept; the
+    /// observer is not, as it is typed on `V`. In capacity mode the new values are charged as
+    /// they are, even past the capacity.
+    pub fn map_values<V2, F>(self, mut f: F) -> LRUCache<K, V2, S>
+    where
+        V2: ItemSize,
+        S: Clone,
+        F: FnMut(&K, V) -> V2,
+    {
+        match self.try_map_values(|k, v| Ok::<_, Infallible>(f(k, v))) {
+            Ok(cache) => cache,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like `map_values`, but stops at the first error of `f`. The entries not mapped yet and
+    /// the ones already mapped are all dropped.
+    pub fn try_map_values<V2, E, F>(mut self, mut f: F) -> Result<LRUCache<K, V2, S>, E>
+    where
+        V2: ItemSize,
+        S: Clone,
+        F: FnMut(&K, V) -> Result<V2, E>,
+    {
+        let hasher = self.hasher.clone();
+        let mut mapped = LRUCache::construct(self.cache_mode.clone(), self.limit.cap(), HashTable::with_capacity(self.len()), hasher);
+        mapped.clock = self.clock;
+        mapped.in_use_skips = self.in_use_skips;
+        // Each entry is owned by exactly one of `self`, the locals below or `mapped` at any
+        // time, so a panic or an error in `f` drops everything once.
+        while let Some(entry) = self.detach_last() {
+            let LRUEntry { expires_at, stamps, .. } = entry;
+            let (key, value) = entry.into_pair();
+            let value = f(&key, value)?;
+            let weight = mapped.weigh(&value, None);
+            mapped.limit.add(weight);
+            let node = mapped.slab.insert(LRUEntry::new(key, value, stamps));
+            mapped.attach(node);
+            let entry = &mut mapped.slab[node];
+            entry.weight = weight;
+            entry.expires_at = expires_at;
+            entry.stamps = stamps;
+            mapped.index(node);
+        }
+        Ok(mapped)
+    }
+
+    /// An iterator visiting all entries in most-recently used order. The iterator element type is
+    /// `(&K, &V)`.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            len: self.len(),
+            slab: &self.slab,
+            ptr: self.slab[HEAD].next,
+            end: self.slab[TAIL].prev,
+        }
+    }
+
+    /// Like `iter`, starting right after `k`, toward the least recently used entry: a cursor to
+    /// resume a listing from the last key seen. `None` if `k` left the cache since, for the
+    /// caller to start over. Finding `k` takes one lookup, and each entry visited one step.
+    pub fn iter_from<Q>(&self, k: &Q) -> Option<IterFrom<'_, K, V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let ptr = self.slab[self.find(k)?].next;
+        Some(IterFrom::new(&self.slab, ptr, self.slab[TAIL].prev))
+    }
+
+    /// Like `iter_from`, toward the most recently used entry: the entries before `k`, the
+    /// closest first.
+    pub fn iter_from_back<Q>(&self, k: &Q) -> Option<Rev<IterFrom<'_, K, V>>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = self.find(k)?;
+        Some(IterFrom::new(&self.slab, self.slab[HEAD].next, self.slab[node].prev).rev())
+    }
+
+    /// An iterator visiting all entries in most-recently-used order, giving a mutable reference on
+    /// V.  The iterator element type is `(&K, &mut V)`.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            len: self.len(),
+            ptr: self.slab[HEAD].next,
+            end: self.slab[TAIL].prev,
+            slots: self.slab.len(),
+            entries: self.slab.entries.as_mut_ptr(),
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Stores the entry of `old` under `new`, keeping its value and its place in the recency
+    /// order. Returns false and changes nothing if `old` is missing or `new` is already taken.
+    pub fn rename<Q>(&mut self, old: &Q, new: K) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(old);
+        self.drop_if_expired::<K>(&new);
+        if self.find::<K>(&new).is_some() {
+            return false;
+        }
+        let Some(node) = self.unindex(old) else {
+            return false;
+        };
+
+        let old_bytes = self.entry_bytes(node);
+        // the map no longer holds the node, so its key can be replaced
+        let old_key = mem::replace(&mut self.slab[node].pair_mut().0, new);
+        self.size_used = self.size_used.saturating_sub(old_bytes) + self.entry_bytes(node);
+        self.index(node);
+        drop(old_key);
+        self.debug_invariants();
+        true
+    }
+
+    /// Swaps the value of `k` for `v` and returns the old one, leaving the entry where it is in
+    /// the recency order and keeping its TTL. Returns `None` and drops `v` if `k` is missing.
+    /// In capacity mode the byte count follows the new value; the budget is enforced again by
+    /// the next insert.
+    pub fn replace<Q>(&mut self, k: &Q, v: V) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(k);
+        let node = self.find(k)?;
+        let weight = self.weigh(&v, None);
+        self.limit.remove(self.slab[node].weight);
+        self.limit.add(weight);
+        self.slab[node].weight = weight;
+        let old_bytes = self.entry_bytes(node);
+        let old = mem::replace(self.slab[node].value_mut(), v);
+        self.size_used = self.size_used.saturating_sub(old_bytes) + self.entry_bytes(node);
+        Some(old)
+    }
+
+    /// Like `peek_mut`, but returns a guard that can still `promote` or `demote` the entry once
+    /// the value was looked at. Left alone, the guard does not move it.
+    pub fn peek_mut_guard<Q>(&mut self, k: &Q) -> Option<PeekMut<'_, K, V, S>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(k);
+        let node = self.find(k)?;
+        Some(PeekMut { cache: self, node, to_front: None })
+    }
+
+    /// The entry of `k`, for in-place manipulation. An occupied entry is promoted and counted
+    /// as a hit; a vacant one is counted as a miss. Expired entries are removed first.
+    pub fn entry(&mut self, k: K) -> Entry<'_, K, V, S> {
+        self.drop_if_expired::<K>(&k);
+        match self.find(&k) {
+            Some(node) => {
+                self.detach(node);
+                self.attach(node);
+                self.notify_hit(node);
+                Entry::Occupied(OccupiedEntry { cache: self, node })
+            }
+            None => {
+                self.notify_miss();
+                Entry::Vacant(VacantEntry { cache: self, key: k })
+            }
+        }
+    }
+
+    /// Moves the entry of `k`, if present, so that it becomes the `rank`-th most recently used
+    /// one: 0 makes it the most recently used like `promote`, `len() - 1` or more the least
+    /// recently used like `demote`. Only the order changes; the entry is not counted as used.
+    pub fn move_to_position<Q>(&mut self, k: &Q, rank: usize)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(node) = self.find(k) else { return };
+        self.detach(node);
+        // the entry it goes in front of, the tail sigil past the end
+        let mut next = self.slab[HEAD].next;
+        for _ in 0..rank {
+            if next == TAIL {
+                break;
+            }
+            next = self.slab[next].next;
+        }
+        let prev = self.slab[next].prev;
+        self.slab[node].next = next;
+        self.slab[node].prev = prev;
+        self.slab[prev].next = node;
+        self.slab[next].prev = node;
+        self.debug_invariants();
+    }
+
+    /// Like `resize`, but returns the entries that no longer fit, least recently used first,
+    /// instead of handing them to `on_evict`.
+    pub fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(K, V)> {
+        if Some(cap) == self.limit.cap() {
+            return Vec::new();
+        }
+
+        let span = op_span!("lru.resize");
+        let mut evicted = Vec::new();
+        // in capacity mode `cap` is a byte budget, see `used_bytes`; an unbounded cache goes
+        // to item mode, its entries being charged one each already
+        if self.is_unbounded() {
+            self.cache_mode = CacheMode::ItemLimit;
+        }
+        self.limit.set_cap(cap);
+        while self.limit.over_budget(0) {
+            let Some((key, value)) = self.pop_last() else { break };
+            self.notify_evict(&key, &value, EvictionCause::Capacity);
+            evicted.push((key, value));
+        }
+        span.evictions(evicted.len());
+        self.shrink_to_fit();
+        evicted
+    }
+
+    /// Keeps only the entries for which `f` returns true, visiting them most recently used first.
+    /// The others are dropped; the survivors keep their order and are not promoted.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let mut node = self.slab[HEAD].next;
+        while node != TAIL {
+            let next = self.slab[node].next;
+            let (key, value) = self.slab[node].key_value_mut();
+            let keep = f(key, value);
+            if !keep {
+                self.unindex_node(node);
+                drop(self.release(node).into_pair());
+            }
+            node = next;
+        }
+        self.maybe_shrink();
+        self.debug_invariants();
+    }
+
+    /// Keeps the `at` most recently used entries and returns a cache holding the others, in the
+    /// same order. It has the same mode, capacity, hasher and clock, and takes the observer and
+    /// settings along like `clone`.
+    pub fn split_off(&mut self, at: usize) -> LRUCache<K, V, S>
+    where
+        S: Clone,
+    {
+        let hasher = self.hasher.clone();
+        let mut rest = LRUCache::construct(self.cache_mode.clone(), self.limit.cap(), HashTable::new(), hasher);
+        rest.clock = self.clock;
+        #[cfg(target_has_atomic = "ptr")]
+        {
+            rest.observer = self.observer.clone();
+        }
+        rest.in_use = self.in_use;
+        rest.shrink_ratio = self.shrink_ratio;
+        rest.watermarks = self.watermarks;
+        rest.default_ttl = self.default_ttl;
+        rest.entry_size = self.entry_size;
+
+        let mut node = self.slab[HEAD].next;
+        for _ in 0..at {
+            if node == TAIL {
+                break;
+            }
+            node = self.slab[node].next;
+        }
+        rest.reserve(self.len().saturating_sub(at));
+        // the entries move to slots of `rest` as they are, each attached behind the previous one
+        while node != TAIL {
+            let next = self.slab[node].next;
+            self.unindex_node(node);
+            let moved = rest.slab.insert(self.release(node));
+            rest.limit.add(rest.slab[moved].weight);
+            rest.size_used += rest.entry_bytes(moved);
+            rest.attach_last(moved);
+            rest.index(moved);
+            node = next;
+        }
+        self.maybe_shrink();
+        self.debug_invariants();
+        rest.debug_invariants();
+        rest
+    }
+
+    /// Moves every entry of `other` into this cache, least recently used first, so that they end
+    /// up in front of the current entries in the same order and with their TTLs. A key already
+    /// here is overwritten like `put` does. Returns the entries evicted to make room, oldest
+    /// first, along with values too large to fit at all in capacity mode. `other` ends empty.
+    pub fn append<S2: BuildHasher>(&mut self, other: &mut LRUCache<K, V, S2>) -> Vec<(K, V)> {
+        let mut evicted = Vec::new();
+        while let Some(entry) = other.detach_last() {
+            let LRUEntry { expires_at, weight, .. } = entry;
+            let (k, v) = entry.into_pair();
+            // weights set by `put_weighted` carry over between capacity-mode caches
+            let weight = Some(weight).filter(|_| matches!(other.cache_mode, CacheMode::StoreLimit));
+            self.put_collecting(k, v, weight, expires_at, &mut evicted);
+        }
+        other.maybe_shrink();
+        evicted
+    }
+
+    /// Puts every entry in order, like a sequence of `put` calls, and returns the entries
+    /// evicted to make room, oldest first, along with values too large to fit at all in
+    /// capacity mode, which leave the entry already under their key alone like `put` does. The
+    /// values of keys already present are dropped, not returned.
+    pub fn put_many<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) -> Vec<(K, V)> {
+        let mut evicted = Vec::new();
+        for (k, v) in entries {
+            self.put_collecting(k, v, None, None, &mut evicted);
+        }
+        self.debug_invariants();
+        evicted
+    }
+
+    /// Puts `k` charged `cost` bytes against the budget in capacity mode, instead of the
+    /// value's `ItemSize`, until it leaves the cache. Returns the entries evicted to make room,
+    /// oldest first, or the entry itself if `cost` exceeds the whole budget, in which case an
+    /// entry already under `k` stays. Otherwise the value of a key already present is dropped,
+    /// not returned. Other modes ignore `cost`.
+    pub fn put_weighted(&mut self, k: K, v: V, cost: usize) -> Vec<(K, V)> {
+        let mut evicted = Vec::new();
+        self.put_collecting(k, v, Some(cost), None, &mut evicted);
+        self.debug_invariants();
+        evicted
+    }
+
+    // Puts like `put`, but pushes every entry evicted on the way to `evicted` rather than
+    // handing all but the last one to `on_evict`, and returns the value replaced. A value too
+    // large to fit at all goes to `evicted` too, leaving the entry already under `k` alone.
+    fn put_collecting(&mut self, k: K, v: V, weight: Option<usize>, expires_at: Option<Instant>, evicted: &mut Vec<(K, V)>) -> Option<V> {
+        if !self.fits(self.weigh(&v, weight)) {
+            evicted.push((k, v));
+            return None;
+        }
+        // the old entry leaves first, for its weight not to count against the new one
+        let replaced = self.pop_entry(&k).map(|(_, old)| old);
+        self.make_room(self.weigh(&v, weight), |_, entry| evicted.push(entry));
+        evicted.extend(self.weighted_put(k, v, weight, true, expires_at));
+        replaced
+    }
+
+    /// Like `get`, promoting `k`, but returns a clone of its value, so that a cache behind a
+    /// lock can be released before the value is used.
+    pub fn get_cloned<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.get(k).cloned()
+    }
+
+    /// Like `peek`, leaving the order alone, but returns a clone of the value.
+    pub fn peek_cloned<Q>(&self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.peek(k).cloned()
+    }
+
+    /// Looks up every key of `keys` like `get`, promoting each hit in the given order, so the
+    /// last key found ends up the most recently used. Expired entries are misses, and are left
+    /// for a later write to remove.
+    pub fn get_many<'a, 'q, Q, I>(&'a mut self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'q,
+        I: IntoIterator<Item = &'q Q>,
+    {
+        let now = self.clock.now();
+        // nodes are only moved around in the list until every key was looked up, so the
+        // slots still hold them for the references handed out at the end
+        let nodes: Vec<Option<u32>> = keys
+            .into_iter()
+            .map(|k| {
+                let node = self.find(k).filter(|&node| !self.slab[node].is_expired(now));
+                match node {
+                    Some(node) => {
+                        self.detach(node);
+                        self.attach(node);
+                        self.notify_hit(node);
+                    }
+                    None => self.notify_miss(),
+                }
+                node
+            })
+            .collect();
+        nodes.into_iter().map(|node| node.map(|node| self.slab[node].value())).collect()
+    }
+
+    /// Moves every entry out, most recently used first, leaving the cache empty but with its
+    /// settings and map allocation, so refilling it does not reallocate. Entries the iterator
+    /// did not yield are dropped with it; if it is leaked, they are leaked too.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        let (len, ptr) = (self.len(), self.slab[HEAD].next);
+        // the nodes now belong to the iterator; clearing keeps the map's buckets
+        self.map.clear();
+        self.slab[HEAD].next = TAIL;
+        self.slab[TAIL].prev = HEAD;
+        self.limit.reset();
+        self.size_used = 0;
+        Drain { len, slab: &mut self.slab, ptr }
+    }
+
+    /// Consumes the cache most recently used first, the reverse of `into_iter`.
+    pub fn into_iter_mru(self) -> Rev<IntoIter<K, V, S>> { self.into_iter().rev() }
+
+    /// Consumes the cache into its keys, least recently used first like `into_iter`.
+    pub fn into_keys(self) -> IntoKeys<K, V, S> { IntoKeys { cache: self } }
+
+    /// Consumes the cache into its values, least recently used first like `into_iter`.
+    pub fn into_values(self) -> IntoValues<K, V, S> { IntoValues { cache: self } }
+
+    /// An iterator visiting all keys in most-recently used order.
+    pub fn keys(&self) -> Keys<'_, K, V> { Keys { inner: self.iter() } }
+
+    /// An iterator visiting all values in most-recently used order.
+    pub fn values(&self) -> Values<'_, K, V> { Values { inner: self.iter() } }
+
+    /// An iterator visiting all values mutably in most-recently used order. Like `iter_mut`, it
+    /// does not promote the entries.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> { ValuesMut { inner: self.iter_mut() } }
+
+    /// The `n`-th most recently used entry, 0 being the most recent, without promoting it.
+    pub fn nth_recent(&self, n: usize) -> Option<(&K, &V)> { self.iter().nth(n) }
+
+    /// The `n`-th least recently used entry, 0 being the next victim, without promoting it.
+    pub fn nth_last(&self, n: usize) -> Option<(&K, &V)> { self.iter().nth_back(n) }
+}
+
+impl<K, V> LRUCache<K, V>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+{
+    /// Starts a `LRUCacheBuilder`, for caches configured beyond what the constructors offer.
+    pub fn builder() -> LRUCacheBuilder<K, V> { LRUCacheBuilder::new() }
+
+    /// Creates a new LRU Cache that holds at most `cap` items.
+    pub fn new(cap: NonZeroUsize) -> Self {
+        LRUCache::construct(CacheMode::ItemLimit, Some(cap), HashTable::with_capacity(cap.get()), Default::default())
+    }
+
+    /// Like `new`, but returns `CacheError::ZeroCapacity` for a capacity of 0, and
+    /// `CacheError::AllocError` if the map cannot be sized for `cap` keys, instead of panicking.
+    /// Meant for capacities read from configuration.
+    pub fn try_new(cap: usize) -> Result<Self, CacheError> {
+        LRUCache::try_with_hasher(CacheMode::ItemLimit, cap, cache::DefaultHasher::default())
+    }
+
+    /// Like `storage`, but returns `CacheError::ZeroCapacity` for a budget of 0.
+    pub fn try_storage(cap: usize) -> Result<Self, CacheError> {
+        LRUCache::try_storage_with_hasher(cap, cache::DefaultHasher::default())
+    }
+
+    /// Like `new`, with `on_evict` registered as by `set_on_evict`.
+    pub fn new_with_on_evict(cap: NonZeroUsize, on_evict: impl FnMut(K, V) + Send + 'static) -> Self {
+        let mut cache = LRUCache::new(cap);
+        cache.set_on_evict(on_evict);
+        cache
+    }
+
+    /// Creates a new LRU Cache whose values' `ItemSize` add up to at most `cap` bytes. A put
+    /// evicts least recently used entries until the new value fits; a value larger than `cap`
+    /// is not stored (`push` hands it back, `try_put` fails with `CacheError::TooLarge`, and
+    /// `get_or_insert` returns the loaded value without storing it).
+    /// Values resized in place through `get_mut` are not recharged.
+    pub fn storage(cap: NonZeroUsize) -> Self { LRUCache::construct(CacheMode::StoreLimit, Some(cap), HashTable::new(), Default::default()) }
+
+    /// Creates a new LRU Cache that never automatically evicts items, see `is_unbounded`.
+    pub fn unbounded() -> Self { LRUCache::construct(CacheMode::UnLimit, None, HashTable::new(), Default::default()) }
+
+    /// Creates a cache like `new` and puts every item of `iter` into it in order, so the last
+    /// items are the most recently used ones and those past `cap` evict the first.
+    pub fn from_iter_with_cap<I: IntoIterator<Item = (K, V)>>(cap: NonZeroUsize, iter: I) -> Self {
+        let mut cache = LRUCache::new(cap);
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl<K, V, S> Cache<K, V, S> for LRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn len(&self) -> usize { self.map.len() }
+
+    fn cap(&self) -> NonZeroUsize { self.limit.cap().unwrap_or(NonZeroUsize::MAX) }
+
+    fn limit(&self) -> Option<NonZeroUsize> { self.limit.cap() }
+
+    fn is_empty(&self) -> bool { self.map.len() == 0 }
+
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        let replaced = self.capturing_put(k, v, false, None);
+        self.debug_invariants();
+        replaced.map(|(_, v)| v)
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        let replaced = self.capturing_put(k, v, true, None);
+        self.debug_invariants();
+        replaced
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let span = op_span!("lru.get");
+        self.drop_if_expired(k);
+        if let Some(node) = self.find(k) {
+            self.detach(node);
+            self.attach(node);
+            self.notify_hit(node);
+            self.debug_invariants();
+
+            let value = self.slab[node].value();
+            span.value_size(value.size_of());
+            Some(value)
+        } else {
+            self.notify_miss();
+            None
+        }
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let span = op_span!("lru.get");
+        self.drop_if_expired(k);
+        if let Some(node) = self.find(k) {
+            self.detach(node);
+            self.attach(node);
+            self.notify_hit(node);
+            self.debug_invariants();
+
+            let entry = &self.slab[node];
+            let (key, value) = (entry.key(), entry.value());
+            span.value_size(value.size_of());
+            Some((key, value))
+        } else {
+            self.notify_miss();
+            None
+        }
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(k);
+        if let Some(node) = self.find(k) {
+            self.detach(node);
+            self.attach(node);
+            self.notify_hit(node);
+            self.debug_invariants();
+
+            Some(self.slab[node].value_mut())
+        } else {
+            self.notify_miss();
+            None
+        }
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        self.lookup_or_insert(k, f, true)
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_key_value(k).map(|(_, v)| v)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let node = &self.slab[self.find(k)?];
+        (!node.is_expired(self.clock.now())).then(|| (node.key(), node.value()))
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        let now = self.clock.now();
+        keys.into_iter()
+            .map(|k| {
+                let node = &self.slab[self.find(k)?];
+                (!node.is_expired(now)).then(|| node.value())
+            })
+            .collect()
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(k);
+        let node = self.find(k)?;
+        Some(self.slab[node].value_mut())
+    }
+
+    fn peek_last(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let node = &self.slab[self.slab[TAIL].prev];
+
+        Some((node.key(), node.value()))
+    }
+
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let node = self.slab[TAIL].prev;
+
+        Some(self.slab[node].key_value_mut())
+    }
+
+    fn peek_first(&self) -> Option<(&K, &V)> {
+        let node = self.slab[HEAD].next;
+        (node != TAIL).then(|| (self.slab[node].key(), self.slab[node].value()))
+    }
+
+    fn first_key(&self) -> Option<&K> {
+        let node = self.slab[HEAD].next;
+        (node != TAIL).then(|| self.slab[node].key())
+    }
+
+    fn last_key(&self) -> Option<&K> {
+        let node = self.slab[TAIL].prev;
+        (node != HEAD).then(|| self.slab[node].key())
+    }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(k).is_some_and(|node| !self.slab[node].is_expired(self.clock.now()))
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.unindex(k) {
+            Some(node) => {
+                let (_, value) = self.release(node).into_pair();
+                self.maybe_shrink();
+                self.debug_invariants();
+
+                Some(value)
+            }
+            None => None,
+        }
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.unindex(k) {
+            Some(node) => {
+                let entry = self.release(node).into_pair();
+                self.maybe_shrink();
+                self.debug_invariants();
+
+                Some(entry)
+            }
+            None => None,
+        }
+    }
+
+    fn pop_last(&mut self) -> Option<(K, V)> {
+        let span = op_span!("lru.pop_last");
+        let node = self.detach_last()?;
+        self.maybe_shrink();
+        self.debug_invariants();
+
+        span.evictions(1);
+        span.value_size(node.value().size_of());
+        Some(node.into_pair())
+    }
+
+    fn pop_first(&mut self) -> Option<(K, V)> {
+        let next = self.slab[HEAD].next;
+        if next == TAIL {
+            return None;
+        }
+
+        self.unindex_node(next);
+        let entry = self.release(next).into_pair();
+        self.maybe_shrink();
+        self.debug_invariants();
+
+        Some(entry)
+    }
+
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(node) = self.find(k) {
+            self.detach(node);
+            self.attach(node);
+            self.debug_invariants();
+        }
+    }
+
+    fn touch<Q>(&mut self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.drop_if_expired(k);
+        match self.find(k) {
+            Some(node) => {
+                self.detach(node);
+                self.attach(node);
+                self.debug_invariants();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(node) = self.find(k) {
+            self.detach(node);
+            self.attach_last(node);
+            self.debug_invariants();
+        }
+    }
+
+    fn resize(&mut self, cap: NonZeroUsize) {
+        for entry in self.resize_with_evicted(cap) {
+            self.evicted(Some(entry));
+        }
+        self.debug_invariants();
+    }
+
+    fn clear(&mut self) {
+        while let Some(entry) = self.pop_last() {
+            self.evicted(Some(entry));
+        }
+        // every slot is free now: back to the sigils alone
+        self.slab = Slab::new();
+        self.rejected = None;
+        self.debug_invariants();
+    }
+}
+
+impl<'a, K: Hash + Eq, V: ItemSize, S: BuildHasher> IntoIterator for &'a LRUCache<K, V, S> {
+    type IntoIter = Iter<'a, K, V>;
+    type Item = (&'a K, &'a V);
+
+    fn into_iter(self) -> Self::IntoIter { self.iter() }
+}
+
+impl<'a, K: Hash + Eq, V: ItemSize, S: BuildHasher> IntoIterator for &'a mut LRUCache<K, V, S> {
+    type IntoIter = IterMut<'a, K, V>;
+    type Item = (&'a K, &'a mut V);
+
+    fn into_iter(self) -> IterMut<'a, K, V> { self.iter_mut() }
+}
+
+impl<K, V, S> LRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize + InUse,
+    S: BuildHasher,
+{
+    /// Makes capacity evictions pass over values still held outside the cache, walking from the
+    /// least recently used entry toward hotter ones. When more than `max_skips` entries in a
+    /// row are in use, the least recently used one is evicted anyway.
+    pub fn skip_in_use_victims(&mut self, max_skips: usize) { self.in_use = Some((V::in_use, max_skips)); }
+}
+
+// Entries listed by `Debug`, most recently used first; the rest are elided.
+const DEBUG_ENTRIES: usize = 16;
+
+struct DebugEntries<'a, K, V>(Iter<'a, K, V>);
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for DebugEntries<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        list.entries(self.0.clone().take(DEBUG_ENTRIES));
+        if self.0.len() > DEBUG_ENTRIES {
+            list.finish_non_exhaustive()
+        } else {
+            list.finish()
+        }
+    }
+}
+
+/// Prints the capacity, the length and the first entries, most recently used first.
+impl<K, V, S> fmt::Debug for LRUCache<K, V, S>
+where
+    K: Hash + Eq + fmt::Debug,
+    V: ItemSize + fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LRUCache")
+            .field("cap", &self.cap())
+            .field("len", &self.len())
+            .field("entries", &DebugEntries(self.iter()))
+            .finish()
+    }
+}
+
+/// Two caches are equal when they hold equal entries in the same recency order, whatever their
+/// capacities and hashers.
+impl<K, V, S1, S2> PartialEq<LRUCache<K, V, S2>> for LRUCache<K, V, S1>
+where
+    K: Hash + Eq,
+    V: ItemSize + PartialEq,
+    S1: BuildHasher,
+    S2: BuildHasher,
+{
+    fn eq(&self, other: &LRUCache<K, V, S2>) -> bool { self.len() == other.len() && self.iter().eq(other.iter()) }
+}
+
+impl<K: Hash + Eq, V: ItemSize + Eq, S: BuildHasher> Eq for LRUCache<K, V, S> {}
+
+/// Copies every entry into fresh nodes, in the same recency order and with the same TTLs and
+/// idle times. The clone shares the clock and the observer, and starts with the same settings.
+impl<K, V, S> Clone for LRUCache<K, V, S>
+where
+    K: Clone + Hash + Eq,
+    V: Clone + ItemSize,
+    S: Clone + BuildHasher,
+{
+    fn clone(&self) -> Self {
+        let hasher = self.hasher.clone();
+        let mut cache = LRUCache::construct(self.cache_mode.clone(), self.limit.cap(), HashTable::with_capacity(self.len()), hasher);
+        cache.limit = self.limit.clone();
+        cache.entry_size = self.entry_size;
+        cache.size_used = self.size_used;
+        cache.clock = self.clock;
+        #[cfg(target_has_atomic = "ptr")]
+        {
+            cache.observer = self.observer.clone();
+        }
+        cache.stats = self.stats;
+        cache.in_use = self.in_use;
+        cache.in_use_skips = self.in_use_skips;
+        cache.shrink_ratio = self.shrink_ratio;
+        cache.watermarks = self.watermarks;
+        cache.default_ttl = self.default_ttl;
+        // least recently used first, each attached in front of the previous one; every node is
+        // in `cache.map` as soon as it is linked, so a panicking `clone` drops what was copied
+        let mut node = self.slab[TAIL].prev;
+        while node != HEAD {
+            let entry = &self.slab[node];
+            let copy = cache.slab.insert(LRUEntry::new(entry.key().clone(), entry.value().clone(), entry.stamps));
+            cache.attach(copy);
+            let copied = &mut cache.slab[copy];
+            copied.expires_at = entry.expires_at;
+            copied.stamps = entry.stamps;
+            copied.weight = entry.weight;
+            cache.index(copy);
+            node = entry.prev;
+        }
+        cache
+    }
+}
+
+impl<K: Hash + Eq, V: ItemSize, S: BuildHasher> IntoIterator for LRUCache<K, V, S> {
+    type IntoIter = IntoIter<K, V, S>;
+    type Item = (K, V);
+
+    fn into_iter(self) -> IntoIter<K, V, S> { IntoIter { cache: self } }
+}
+
+/// Puts every item in order, as a sequence of `put` calls would.
+impl<K: Hash + Eq, V: ItemSize, S: BuildHasher> Extend<(K, V)> for LRUCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.put(k, v);
+        }
+    }
+}
+
+/// Collects into an `unbounded` cache; see `from_iter_with_cap` for a bounded one.
+impl<K: Hash + Eq, V: ItemSize> FromIterator<(K, V)> for LRUCache<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut cache = LRUCache::unbounded();
+        cache.extend(iter);
+        cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Debug;
+    use core::num::NonZeroUsize;
+    use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher, Hash, Hasher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{CacheMode, Entry, LRUCache, AUTO_SHRINK_MIN_CAPACITY};
+    use crate::lru::cache::Cache;
+    #[cfg(feature = "entry-timestamps")]
+    use crate::lru::clock::Clock;
+    use crate::lru::clock::ManualClock;
+    use crate::lru::dump::DumpOptions;
+    use crate::lru::error::CacheError;
+    use crate::lru::item_size::ItemSize;
+    use crate::lru::limiter::Limiter;
+    use crate::lru::observer::{CacheCounters, CacheObserver, CacheStats, EvictionCause};
+
+    extern crate alloc;
+
+    fn assert_opt_eq<V: PartialEq + Debug + ItemSize>(opt: Option<&V>, v: V) {
+        assert!(opt.is_some());
+        assert_eq!(opt.unwrap(), &v);
+    }
+
+    fn assert_opt_eq_mut<V: PartialEq + Debug + ItemSize>(opt: Option<&mut V>, v: V) {
+        assert!(opt.is_some());
+        assert_eq!(opt.unwrap(), &v);
+    }
+
+    fn assert_opt_eq_tuple<K: PartialEq + Debug, V: PartialEq + Debug + ItemSize>(
+        opt: Option<(&K, &V)>,
+        kv: (K, V),
+    ) {
+        assert!(opt.is_some());
+        let res = opt.unwrap();
+        assert_eq!(res.0, &kv.0);
+        assert_eq!(res.1, &kv.1);
+    }
+
+    fn assert_opt_eq_mut_tuple<K: PartialEq + Debug, V: PartialEq + Debug + ItemSize>(
+        opt: Option<(&K, &mut V)>,
+        kv: (K, V),
+    ) {
+        assert!(opt.is_some());
+        let res = opt.unwrap();
+        assert_eq!(res.0, &kv.0);
+        assert_eq!(res.1, &kv.1);
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let mut cache = LRUCache::unbounded();
+        for i in 0..13370 {
+            cache.put(i, ());
+        }
+        assert_eq!(cache.len(), 13370);
+    }
+
+    #[test]
+    fn test_resize_unbounded_and_back() {
+        let mut cache = LRUCache::unbounded();
+        assert!(cache.is_unbounded());
+        assert_eq!((cache.limit(), cache.cap()), (None, NonZeroUsize::MAX));
+        for i in 0..5 {
+            cache.put(i, i);
+        }
+
+        assert_eq!(cache.resize_with_evicted(NonZeroUsize::new(3).unwrap()), [(0, 0), (1, 1)]);
+        assert!(!cache.is_unbounded());
+        assert!(matches!(cache.cache_mode(), CacheMode::ItemLimit));
+        assert_eq!(cache.limit(), NonZeroUsize::new(3));
+        assert_eq!(cache.push(5, 5), Some((2, 2)));
+
+        cache.set_unbounded();
+        assert!(cache.is_unbounded());
+        assert!(matches!(cache.cache_mode(), CacheMode::UnLimit));
+        for i in 6..10 {
+            assert_eq!(cache.push(i, i), None);
+        }
+        assert_eq!(cache.len(), 7);
+        assert_eq!(cache.check_consistency(), Ok(()));
+
+        // a byte budget lifted counts entries instead
+        let mut storage = LRUCache::storage(NonZeroUsize::new(8).unwrap());
+        storage.put("a", "aaaa");
+        storage.put("b", "bbbb");
+        storage.set_unbounded();
+        assert_eq!((storage.used_bytes(), storage.limit.used()), (0, 2));
+        storage.put("c", "cccccccccccc");
+        storage.resize(NonZeroUsize::new(2).unwrap());
+        assert_eq!(storage.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["c", "b"]);
+        assert_eq!(storage.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        assert!(cache.is_empty());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+
+        assert_eq!(cache.cap().get(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    #[test]
     fn test_put_and_get_or_insert() {
         let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
         assert!(cache.is_empty());
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+
+        assert_eq!(cache.cap().get(), 2);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+        assert_eq!(cache.get_or_insert("apple", || "orange"), &"red");
+        assert_eq!(cache.get_or_insert("banana", || "orange"), &"yellow");
+        assert_eq!(cache.get_or_insert("lemon", || "orange"), &"orange");
+        assert_eq!(cache.get_or_insert("lemon", || "red"), &"orange");
+    }
+
+    #[test]
+    fn test_put_and_get_or_insert_mut() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        assert!(cache.is_empty());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+
+        assert_eq!(cache.cap().get(), 2);
+        assert_eq!(cache.len(), 2);
+
+        let v = cache.get_or_insert_mut("apple", || "orange");
+        assert_eq!(v, &"red");
+        *v = "blue";
+
+        assert_eq!(cache.get_or_insert_mut("apple", || "orange"), &"blue");
+        assert_eq!(cache.get_or_insert_mut("banana", || "orange"), &"yellow");
+        assert_eq!(cache.get_or_insert_mut("lemon", || "orange"), &"orange");
+        assert_eq!(cache.get_or_insert_mut("lemon", || "red"), &"orange");
+    }
+
+    #[test]
+    fn test_get_or_insert_with_key() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("apple".to_string(), 5);
+
+        let mut calls = 0;
+        assert_eq!(cache.get_or_insert_with_key("apple".to_string(), |k| { calls += 1; k.len() }), &5);
+        assert_eq!(calls, 0);
+        assert_eq!(cache.get_or_insert_with_key("kiwi".to_string(), |k| { calls += 1; k.len() }), &4);
+        assert_eq!(calls, 1);
+
+        *cache.get_or_insert_with_key_mut("kiwi".to_string(), |_| { calls += 1; 0 }) += 10;
+        assert_eq!(calls, 1);
+        assert_opt_eq(cache.get("kiwi"), 14);
+        // "apple" is least recently used and makes room
+        assert_eq!(cache.get_or_insert_with_key_mut("fig".to_string(), |k| k.len()), &mut 3);
+        assert!(!cache.contains("apple"));
+    }
+
+    #[test]
+    fn test_put_if_absent() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.set_clock(clock);
+        assert_eq!(cache.put_if_absent("apple", "red"), Ok(()));
+        assert_eq!(cache.put_if_absent("banana", "yellow"), Ok(()));
+
+        assert_eq!(cache.put_if_absent("apple", "green"), Err(("apple", "green")));
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"banana", &"yellow"), (&"apple", &"red")]);
+
+        // a new key evicts as usual, and an expired one counts as missing
+        assert_eq!(cache.put_if_absent("pear", "green"), Ok(()));
+        assert!(!cache.contains(&"apple"));
+        cache.put_with_ttl("kiwi", "brown", Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.put_if_absent("kiwi", "green"), Ok(()));
+        assert_eq!(cache.get(&"kiwi"), Some(&"green"));
+    }
+
+    #[test]
+    fn test_try_get_or_insert() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.try_get_or_insert("lemon", || Err("offline")), Err("offline"));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.last_key(), Some(&"apple"));
+
+        assert_eq!(cache.try_get_or_insert("lemon", || Ok::<_, &str>("yellow")), Ok(&"yellow"));
+        assert!(!cache.contains(&"apple"));
+        assert_eq!(cache.try_get_or_insert("banana", || Err("unused")), Ok(&"yellow"));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec!["banana", "lemon"]);
+    }
+
+    #[test]
+    fn test_try_get_or_insert_mut() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("apple", vec![1]);
+        cache.put("banana", vec![2]);
+
+        // a failing loader neither inserts nor evicts
+        assert_eq!(cache.try_get_or_insert_mut("lemon", || Err("offline")), Err("offline"));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec!["banana", "apple"]);
+
+        // hits never call the loader
+        cache.try_get_or_insert_mut("apple", || Err("unused")).unwrap().push(10);
+        assert_opt_eq(cache.peek(&"apple"), vec![1, 10]);
+
+        // "banana" is least recently used now, and only goes once the loader succeeded
+        cache.try_get_or_insert_mut("lemon", || Ok::<_, &str>(vec![3])).unwrap().push(30);
+        assert!(!cache.contains(&"banana"));
+        assert_opt_eq(cache.get(&"lemon"), vec![3, 30]);
+    }
+
+    #[test]
+    fn test_put_and_get_mut() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.cap().get(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq_mut(cache.get_mut(&"apple"), "red");
+        assert_opt_eq_mut(cache.get_mut(&"banana"), "yellow");
+    }
+
+    #[test]
+    fn test_get_mut_and_update() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", 1);
+        cache.put("banana", 3);
+
+        {
+            let v = cache.get_mut(&"apple").unwrap();
+            *v = 4;
+        }
+
+        assert_eq!(cache.cap().get(), 2);
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq_mut(cache.get_mut(&"apple"), 4);
+        assert_opt_eq_mut(cache.get_mut(&"banana"), 3);
+    }
+
+    #[test]
+    fn test_put_update() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("apple", "green"), Some("red"));
+
+        assert_eq!(cache.len(), 1);
+        assert_opt_eq(cache.get(&"apple"), "green");
+    }
+
+    #[test]
+    fn test_put_removes_oldest() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(cache.put("apple", "red"), None);
+        assert_eq!(cache.put("banana", "yellow"), None);
+        assert_eq!(cache.put("pear", "green"), None);
+
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+        assert_opt_eq(cache.get(&"pear"), "green");
+
+        // Even though we inserted "apple" into the cache earlier it has since been removed from
+        // the cache so there is no current value for `put` to return.
+        assert_eq!(cache.put("apple", "green"), None);
+        assert_eq!(cache.put("tomato", "red"), None);
+
+        assert!(cache.get(&"pear").is_none());
+        assert_opt_eq(cache.get(&"apple"), "green");
+        assert_opt_eq(cache.get(&"tomato"), "red");
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_opt_eq(cache.peek(&"banana"), "yellow");
+        assert_opt_eq(cache.peek(&"apple"), "red");
+
+        cache.put("pear", "green");
+
+        assert!(cache.peek(&"apple").is_none());
+        assert_opt_eq(cache.peek(&"banana"), "yellow");
+        assert_opt_eq(cache.peek(&"pear"), "green");
+    }
+
+    #[test]
+    fn test_peek_mut() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
+        assert_opt_eq_mut(cache.peek_mut(&"apple"), "red");
+        assert!(cache.peek_mut(&"pear").is_none());
+
+        cache.put("pear", "green");
+
+        assert!(cache.peek_mut(&"apple").is_none());
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
+        assert_opt_eq_mut(cache.peek_mut(&"pear"), "green");
+
+        {
+            let v = cache.peek_mut(&"banana").unwrap();
+            *v = "green";
+        }
+
+        assert_opt_eq_mut(cache.peek_mut(&"banana"), "green");
+    }
+
+    #[test]
+    fn test_peek_lru() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        assert!(cache.peek_last().is_none());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+        assert_opt_eq_tuple(cache.peek_last(), ("apple", "red"));
+
+        cache.get(&"apple");
+        assert_opt_eq_tuple(cache.peek_last(), ("banana", "yellow"));
+
+        cache.clear();
+        assert!(cache.peek_last().is_none());
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+        cache.put("pear", "green");
+
+        assert!(!cache.contains(&"apple"));
+        assert!(cache.contains(&"banana"));
+        assert!(cache.contains(&"pear"));
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+
+        let popped = cache.pop(&"apple");
+        assert!(popped.is_some());
+        assert_eq!(popped.unwrap(), "red");
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    #[test]
+    fn test_pop_entry() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+
+        let popped = cache.pop_entry(&"apple");
+        assert!(popped.is_some());
+        assert_eq!(popped.unwrap(), ("apple", "red"));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&"apple").is_none());
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    #[test]
+    fn test_pop_lru() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(200).unwrap());
+
+        for i in 0..75 {
+            cache.put(i, "A");
+        }
+        for i in 0..75 {
+            cache.put(i + 100, "B");
+        }
+        for i in 0..75 {
+            cache.put(i + 200, "C");
+        }
+        assert_eq!(cache.len(), 200);
+
+        for i in 0..75 {
+            assert_opt_eq(cache.get(&(74 - i + 100)), "B");
+        }
+        assert_opt_eq(cache.get(&25), "A");
+
+        for i in 26..75 {
+            assert_eq!(cache.pop_last(), Some((i, "A")));
+        }
+        for i in 0..75 {
+            assert_eq!(cache.pop_last(), Some((i + 200, "C")));
+        }
+        for i in 0..75 {
+            assert_eq!(cache.pop_last(), Some((74 - i + 100, "B")));
+        }
+        assert_eq!(cache.pop_last(), Some((25, "A")));
+        for _ in 0..50 {
+            assert_eq!(cache.pop_last(), None);
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("apple", "red");
+        cache.put("banana", "yellow");
+
+        assert_eq!(cache.len(), 2);
+        assert_opt_eq(cache.get(&"apple"), "red");
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
+        for i in 1..=5 {
+            cache.put(i, i * 10);
+        }
+        cache.get(&1);
+        cache.get(&3);
+
+        assert!(cache.truncate(5).is_empty());
+        assert!(cache.truncate(9).is_empty());
+        assert_eq!(cache.truncate(3), vec![(2, 20), (4, 40)]);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![3, 1, 5]);
+        assert_eq!(cache.cap().get(), 5);
+
+        cache.get(&5);
+        assert_eq!(cache.truncate(1), vec![(1, 10), (3, 30)]);
+        assert_opt_eq(cache.get(&5), 50);
+
+        assert_eq!(cache.truncate(0), vec![(5, 50)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_resize_larger() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.resize(NonZeroUsize::new(4).unwrap());
+        cache.put(3, "c");
+        cache.put(4, "d");
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&2), Some(&"b"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.get(&4), Some(&"d"));
+    }
+
+    #[test]
+    fn test_resize_smaller() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.put(4, "d");
+
+        cache.resize(NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_none());
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.get(&4), Some(&"d"));
+    }
+
+    #[test]
+    fn test_send() {
+        use std::thread;
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.put(1, "a");
+
+        let handle = thread::spawn(move || {
+            assert_eq!(cache.get(&1), Some(&"a"));
+        });
+
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    fn test_iter_forwards() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        {
+            // iter const
+            let mut iter = cache.iter();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_tuple(iter.next(), ("c", 3));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_tuple(iter.next(), ("b", 2));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_tuple(iter.next(), ("a", 1));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next(), None);
+        }
+        {
+            // iter mut
+            let mut iter = cache.iter_mut();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_mut_tuple(iter.next(), ("c", 3));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_mut_tuple(iter.next(), ("b", 2));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_mut_tuple(iter.next(), ("a", 1));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_iter_backwards() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        {
+            // iter const
+            let mut iter = cache.iter();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_tuple(iter.next_back(), ("a", 1));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_tuple(iter.next_back(), ("b", 2));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_tuple(iter.next_back(), ("c", 3));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next_back(), None);
+        }
+
+        {
+            // iter mut
+            let mut iter = cache.iter_mut();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_mut_tuple(iter.next_back(), ("a", 1));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_mut_tuple(iter.next_back(), ("b", 2));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_mut_tuple(iter.next_back(), ("c", 3));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next_back(), None);
+        }
+    }
+
+    #[test]
+    fn test_keys_and_values_forwards() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        let mut keys = cache.keys();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.next(), Some(&"c"));
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys.next(), Some(&"b"));
+        assert_eq!(keys.next(), Some(&"a"));
+        assert_eq!(keys.len(), 0);
+        assert_eq!(keys.next(), None);
+
+        let mut values = cache.values();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.next(), Some(&3));
+        assert_eq!(values.next(), Some(&2));
+        assert_eq!(values.next(), Some(&1));
+        assert_eq!(values.next(), None);
+
+        let mut values = cache.values_mut();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.next(), Some(&mut 3));
+        assert_eq!(values.next(), Some(&mut 2));
+        assert_eq!(values.next(), Some(&mut 1));
+        assert_eq!(values.next(), None);
+    }
+
+    #[test]
+    fn test_keys_and_values_backwards() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        let mut keys = cache.keys();
+        assert_eq!(keys.next_back(), Some(&"a"));
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys.next_back(), Some(&"b"));
+        assert_eq!(keys.next_back(), Some(&"c"));
+        assert_eq!(keys.next_back(), None);
+
+        assert_eq!(cache.values().rev().collect::<Vec<_>>(), [&1, &2, &3]);
+
+        // values_mut changes values in place without touching the order
+        for value in cache.values_mut().rev() {
+            *value *= 10;
+        }
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"c", &30), (&"b", &20), (&"a", &10)]);
+    }
+
+    #[test]
+    fn test_iter_forwards_and_backwards() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        {
+            // iter const
+            let mut iter = cache.iter();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_tuple(iter.next(), ("c", 3));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_tuple(iter.next_back(), ("a", 1));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_tuple(iter.next(), ("b", 2));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next_back(), None);
+        }
+        {
+            // iter mut
+            let mut iter = cache.iter_mut();
+            assert_eq!(iter.len(), 3);
+            assert_opt_eq_mut_tuple(iter.next(), ("c", 3));
+
+            assert_eq!(iter.len(), 2);
+            assert_opt_eq_mut_tuple(iter.next_back(), ("a", 1));
+
+            assert_eq!(iter.len(), 1);
+            assert_opt_eq_mut_tuple(iter.next(), ("b", 2));
+
+            assert_eq!(iter.len(), 0);
+            assert_eq!(iter.next_back(), None);
+        }
+    }
+
+    #[test]
+    fn test_iter_clone() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+
+        let mut iter = cache.iter();
+        let mut iter_clone = iter.clone();
+
+        assert_eq!(iter.len(), 2);
+        assert_opt_eq_tuple(iter.next(), ("b", 2));
+        assert_eq!(iter_clone.len(), 2);
+        assert_opt_eq_tuple(iter_clone.next(), ("b", 2));
+
+        assert_eq!(iter.len(), 1);
+        assert_opt_eq_tuple(iter.next(), ("a", 1));
+        assert_eq!(iter_clone.len(), 1);
+        assert_opt_eq_tuple(iter_clone.next(), ("a", 1));
+
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter_clone.len(), 0);
+        assert_eq!(iter_clone.next(), None);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        let mut iter = cache.into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next(), Some(("a", 1)));
+
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next(), Some(("b", 2)));
+
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(("c", 3)));
+
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_that_pop_actually_detaches_node() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
+
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        cache.put("d", 4);
+        cache.put("e", 5);
+
+        assert_eq!(cache.pop(&"c"), Some(3));
+
+        cache.put("f", 6);
+
+        let mut iter = cache.iter();
+        assert_opt_eq_tuple(iter.next(), ("f", 6));
+        assert_opt_eq_tuple(iter.next(), ("e", 5));
+        assert_opt_eq_tuple(iter.next(), ("d", 4));
+        assert_opt_eq_tuple(iter.next(), ("b", 2));
+        assert_opt_eq_tuple(iter.next(), ("a", 1));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_get_with_borrow() {
+        use alloc::string::String;
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        let key = String::from("apple");
+        cache.put(key, "red");
+
+        assert_opt_eq(cache.get("apple"), "red");
+    }
+
+    #[test]
+    fn test_shared_and_cow_key_borrow() {
+        use std::borrow::Cow;
+        use std::rc::Rc;
+
+        fn hash_of<T: Hash + ?Sized>(v: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let shared: Arc<str> = Arc::from("apple");
+        assert_eq!(hash_of(&shared), hash_of("apple"));
+        let mut arcs = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        arcs.put(shared.clone(), 1);
+        assert_opt_eq(arcs.get("apple"), 1);
+        assert!(arcs.contains("apple"));
+        assert_eq!(arcs.pop("apple"), Some(1));
+        assert_eq!(Arc::strong_count(&shared), 1);
+
+        let mut rcs = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        rcs.put(Rc::<[u8]>::from(&b"key"[..]), 2);
+        assert_opt_eq(rcs.get(&b"key"[..]), 2);
+
+        let cows: [Cow<'static, str>; 2] = [Cow::Borrowed("a"), Cow::Owned("b".to_string())];
+        assert_eq!(hash_of(&cows[1]), hash_of("b"));
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        for (i, k) in cows.into_iter().enumerate() {
+            cache.put(k, i as u32);
+        }
+        assert_opt_eq(cache.get("a"), 0);
+        assert_opt_eq(cache.peek("b"), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_path_and_os_str_borrow() {
+        use std::ffi::{OsStr, OsString};
+        use std::path::{Path, PathBuf};
+
+        let mut files = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        files.put(PathBuf::from("/tmp/a"), 1);
+        files.put(PathBuf::from("/tmp/b"), 2);
+        assert_opt_eq(files.get(Path::new("/tmp/a")), 1);
+        assert!(files.contains(Path::new("/tmp/b")));
+        assert_eq!(files.pop(Path::new("/tmp/b")), Some(2));
+        assert!(!files.contains(Path::new("/tmp/b")));
+
+        let mut names = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        names.put(OsString::from("a"), 1);
+        assert_opt_eq(names.get(OsStr::new("a")), 1);
+        assert!(names.contains(OsStr::new("a")));
+        assert_eq!(names.pop(OsStr::new("a")), Some(1));
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn test_iter_from_pages() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(100).unwrap());
+        for i in 0..100 {
+            cache.put(i, i);
+        }
+
+        let mut seen = Vec::new();
+        let mut page: Vec<u32> = cache.iter().take(10).map(|(k, _)| *k).collect();
+        while let Some(&last) = page.last() {
+            seen.extend_from_slice(&page);
+            page = cache.iter_from(&last).unwrap().take(10).map(|(k, _)| *k).collect();
+        }
+        assert_eq!(seen, (0..100).rev().collect::<Vec<_>>());
+
+        let mut seen = Vec::new();
+        let mut page: Vec<u32> = cache.iter().rev().take(10).map(|(k, _)| *k).collect();
+        while let Some(&last) = page.last() {
+            seen.extend_from_slice(&page);
+            page = cache.iter_from_back(&last).unwrap().take(10).map(|(k, _)| *k).collect();
+        }
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+
+        let mut rest = cache.iter_from(&50).unwrap();
+        assert_eq!(rest.clone().count(), 50);
+        assert_eq!(rest.next_back(), Some((&0, &0)));
+        assert_eq!(rest.next(), Some((&49, &49)));
+        assert_eq!(rest.count(), 48);
+        assert_eq!(cache.iter_from_back(&50).unwrap().count(), 49);
+        // meeting in the middle yields every entry once
+        let mut both = cache.iter_from(&2).unwrap();
+        assert_eq!((both.next(), both.next_back(), both.next(), both.next_back()), (Some((&1, &1)), Some((&0, &0)), None, None));
+        // nothing past either end
+        assert_eq!(cache.iter_from(&0).unwrap().next(), None);
+        assert_eq!(cache.iter_from_back(&99).unwrap().next(), None);
+        // a cursor whose key left gives nothing to resume from
+        cache.pop(&50);
+        assert!(cache.iter_from(&50).is_none());
+        assert!(cache.iter_from_back(&50).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "entry-timestamps")]
+    fn test_entry_meta() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.set_clock(clock);
+        let start = clock.now();
+        cache.put("a", 1);
+        clock.advance(Duration::from_secs(2));
+        cache.put("b", 2);
+        clock.advance(Duration::from_secs(3));
+
+        let meta = cache.peek_meta(&"a").unwrap();
+        assert_eq!((meta.created_at, meta.last_accessed), (start, start));
+        assert_eq!((meta.age, meta.idle), (Duration::from_secs(5), Duration::from_secs(5)));
+
+        // reads and overwrites refresh the access, not the creation
+        cache.get(&"a");
+        clock.advance(Duration::from_secs(1));
+        cache.put("b", 3);
+        clock.advance(Duration::from_secs(1));
+        let meta = cache.peek_meta(&"a").unwrap();
+        assert_eq!((meta.age, meta.idle), (Duration::from_secs(7), Duration::from_secs(2)));
+        let meta = cache.peek_meta(&"b").unwrap();
+        assert_eq!((meta.age, meta.idle), (Duration::from_secs(5), Duration::from_secs(1)));
+
+        // peeking does not count as an access, and clones keep both
+        cache.peek(&"a");
+        let copy = cache.clone();
+        let ages = |cache: &LRUCache<&'static str, u32>| {
+            cache.iter_meta().map(|(k, m)| (*k, m.age.as_secs(), m.idle.as_secs())).collect::<Vec<_>>()
+        };
+        let expected = [("b", 5, 1), ("a", 7, 2)];
+        assert_eq!(ages(&cache), expected);
+        assert_eq!(ages(&copy), expected);
+
+        assert_eq!(cache.peek_meta(&"c"), None);
+        cache.put_with_ttl("c", 4, Duration::from_secs(1));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.peek_meta(&"c"), None);
+    }
+
+    #[test]
+    fn test_get_cloned_and_peek_cloned() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put("a", "apple".to_string());
+        cache.put("b", "banana".to_string());
+
+        assert_eq!(cache.peek_cloned(&"a"), Some("apple".to_string()));
+        assert_eq!(cache.last_key(), Some(&"a"));
+        assert_eq!(cache.get_cloned(&"a"), Some("apple".to_string()));
+        assert_eq!(cache.last_key(), Some(&"b"));
+        assert_eq!(cache.stats().hits, 1);
+
+        assert_eq!(cache.get_cloned(&"c"), None);
+        assert_eq!(cache.peek_cloned(&"c"), None);
+    }
+
+    #[test]
+    fn test_peek_mut_guard() {
+        let order = |cache: &LRUCache<&'static str, u32>| cache.keys().copied().collect::<Vec<_>>();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        // mutating without deciding leaves the order alone
+        {
+            let mut value = cache.peek_mut_guard(&"a").unwrap();
+            assert_eq!(value.key(), &"a");
+            *value += 10;
+        }
+        assert_eq!(order(&cache), ["c", "b", "a"]);
+        assert_eq!(cache.peek(&"a"), Some(&11));
+
+        // the decision is taken after reading the value
+        if let Some(mut value) = cache.peek_mut_guard(&"a") {
+            if *value > 10 {
+                value.promote();
+            }
+        }
+        assert_eq!(order(&cache), ["a", "c", "b"]);
+
+        let mut value = cache.peek_mut_guard(&"c").unwrap();
+        value.promote();
+        value.demote();
+        *value = 30;
+        drop(value);
+        assert_eq!(order(&cache), ["a", "b", "c"]);
+        assert_eq!(cache.push("d", 4), Some(("c", 30)));
+        assert!(cache.peek_mut_guard(&"c").is_none());
+        assert_eq!(cache.stats().hits, 0);
+        cache.assert_invariants();
+    }
+
+    #[test]
+    fn test_peek_last_mut() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        assert!(cache.peek_last_mut().is_none());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        let (key, value) = cache.peek_last_mut().unwrap();
+        assert_eq!(*key, "a");
+        *value += 10;
+        assert_eq!(cache.peek_last(), Some((&"a", &11)));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["c", "b", "a"]);
+        assert_eq!(cache.pop_last(), Some(("a", 11)));
+    }
+
+    #[test]
+    fn test_get_mut_with_borrow() {
+        use alloc::string::String;
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+
+        let key = String::from("apple");
+        cache.put(key, "red");
+
+        assert_opt_eq_mut(cache.get_mut("apple"), "red");
+    }
+
+    #[test]
+    fn test_no_memory_leaks() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for _ in 0..n {
+            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+            for i in 0..n {
+                cache.put(i, DropCounter {});
+            }
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+    }
+
+    #[test]
+    fn test_no_memory_leaks_with_into_keys_and_values() {
+        static KEY_DROPS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct KeyCounter(usize);
+
+        impl Drop for KeyCounter {
+            fn drop(&mut self) { KEY_DROPS.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { VALUE_DROPS.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let filled = || {
+            let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+            for i in 0..10 {
+                cache.put(KeyCounter(i), DropCounter);
+            }
+            cache
+        };
+
+        let mut keys = filled().into_keys();
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys.next().map(|k| k.0), Some(0));
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 1);
+        assert_eq!(keys.len(), 9);
+        // the entries left are dropped with the iterator
+        drop(keys);
+        assert_eq!((KEY_DROPS.load(Ordering::SeqCst), VALUE_DROPS.load(Ordering::SeqCst)), (10, 10));
+
+        let values = filled().into_values();
+        assert_eq!(values.count(), 10);
+        assert_eq!((KEY_DROPS.load(Ordering::SeqCst), VALUE_DROPS.load(Ordering::SeqCst)), (20, 20));
+        let values: Vec<_> = filled().into_values().collect();
+        assert_eq!(values.len(), 10);
+        assert_eq!(KEY_DROPS.load(Ordering::SeqCst), 30);
+        drop(values);
+        assert_eq!(VALUE_DROPS.load(Ordering::SeqCst), 30);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut cache = LRUCache::unbounded();
+        for i in 0..100 {
+            cache.put(i, i);
+        }
+        let capacity = cache.map_capacity();
+
+        let mut drain = cache.drain();
+        assert_eq!(drain.len(), 100);
+        assert_eq!(drain.next(), Some((99, 99)));
+        assert_eq!(drain.len(), 99);
+        drop(drain);
+        assert!(cache.is_empty());
+        assert_eq!(cache.map_capacity(), capacity);
+
+        cache.put(1, 1);
+        cache.put(2, 2);
+        assert_eq!(cache.drain().collect::<Vec<_>>(), [(2, 2), (1, 1)]);
+        assert_eq!(cache.map_capacity(), capacity);
+    }
 
-        assert_eq!(cache.cap().get(), 2);
-        assert_eq!(cache.len(), 2);
-        assert!(!cache.is_empty());
-        assert_eq!(cache.get_or_insert("apple", || "orange"), &"red");
-        assert_eq!(cache.get_or_insert("banana", || "orange"), &"yellow");
-        assert_eq!(cache.get_or_insert("lemon", || "orange"), &"orange");
-        assert_eq!(cache.get_or_insert("lemon", || "red"), &"orange");
+    #[test]
+    fn test_no_memory_leaks_with_drain() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..10 {
+            cache.put(i, DropCounter);
+        }
+        // dropped early: three taken, the other seven removed by the drop
+        let taken: Vec<_> = cache.drain().take(3).collect();
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 7);
+        drop(taken);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 10);
+        assert!(cache.is_empty());
+
+        for i in 0..10 {
+            cache.put(i, DropCounter);
+        }
+        cache.drain().for_each(drop);
+        drop(cache);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 20);
     }
 
     #[test]
-    fn test_put_and_get_or_insert_mut() {
+    fn test_get_key_value() {
         let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put(String::from("apple"), 1);
+        cache.put(String::from("banana"), 2);
+
+        let (key, value) = cache.peek_key_value("apple").unwrap();
+        assert_eq!((key, value), (&String::from("apple"), &1));
+        assert_eq!(cache.last_key().map(String::as_str), Some("apple"));
+
+        let (key, value): (&String, _) = cache.get_key_value("apple").unwrap();
+        assert_eq!((key.as_str(), *value), ("apple", 1));
+        assert_eq!(cache.first_key().map(String::as_str), Some("apple"));
+        assert_eq!(cache.get_key_value("pear"), None);
+        assert_eq!(cache.peek_key_value("pear"), None);
+    }
+
+    #[test]
+    fn test_pop_last_n() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(100).unwrap());
+        for (k, v) in [("a", "1"), ("b", "22"), ("c", "333"), ("d", "4444")] {
+            cache.put(k, v);
+        }
+        let mut copy = cache.clone();
+        assert_eq!(cache.pop_last_n(0), []);
+        assert_eq!(cache.pop_last_n(0).capacity(), 0);
+
+        let popped = cache.pop_last_n(3);
+        assert_eq!(popped, [copy.pop_last().unwrap(), copy.pop_last().unwrap(), copy.pop_last().unwrap()]);
+        assert_eq!(popped, [("a", "1"), ("b", "22"), ("c", "333")]);
+        assert_eq!(cache.used_bytes(), 4);
+
+        // stops once the cache is empty
+        assert_eq!(cache.pop_last_n(5), [("d", "4444")]);
+        assert_eq!(cache.used_bytes(), 0);
+        assert!(cache.pop_last_n(5).is_empty());
+    }
+
+    #[test]
+    fn test_pop_last_while() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..5 {
+            cache.put(i, i * 10);
+        }
+        assert!(cache.pop_last_while(|_, v| *v > 0).is_empty());
+        assert_eq!(cache.len(), 5);
+
+        let mut seen = Vec::new();
+        let popped = cache.pop_last_while(|k, _| {
+            seen.push(*k);
+            *k < 3
+        });
+        assert_eq!(popped, [(0, 0), (1, 10), (2, 20)]);
+        // the entry that stopped it stays
+        assert_eq!(seen, [0, 1, 2, 3]);
+        assert_eq!(cache.peek_last(), Some((&3, &30)));
+
+        assert_eq!(cache.pop_last_while(|_, _| true), [(3, 30), (4, 40)]);
         assert!(cache.is_empty());
+        assert!(cache.pop_last_while(|_, _| true).is_empty());
+    }
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
+    #[test]
+    fn test_pop_last_while_drops() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        assert_eq!(cache.cap().get(), 2);
-        assert_eq!(cache.len(), 2);
+        struct DropCounter(bool);
 
-        let v = cache.get_or_insert_mut("apple", || "orange");
-        assert_eq!(v, &"red");
-        *v = "blue";
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
 
-        assert_eq!(cache.get_or_insert_mut("apple", || "orange"), &"blue");
-        assert_eq!(cache.get_or_insert_mut("banana", || "orange"), &"yellow");
-        assert_eq!(cache.get_or_insert_mut("lemon", || "orange"), &"orange");
-        assert_eq!(cache.get_or_insert_mut("lemon", || "red"), &"orange");
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..10 {
+            cache.put(i, DropCounter(i < 6));
+        }
+        let popped = cache.pop_last_while(|_, v| v.0);
+        assert_eq!((popped.len(), DROP_COUNT.load(Ordering::SeqCst)), (6, 0));
+        drop(popped);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 6);
+        drop(cache);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 10);
     }
 
     #[test]
-    fn test_put_and_get_mut() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_pop_first_and_peek_first() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(cache.peek_first(), None);
+        assert_eq!(cache.pop_first(), None);
+
+        cache.put("apple", "red");
+        assert_eq!(cache.peek_first(), Some((&"apple", &"red")));
+        assert_eq!(cache.peek_last(), Some((&"apple", &"red")));
+        assert_eq!(cache.pop_first(), Some(("apple", "red")));
+        assert!(cache.is_empty());
+        assert_eq!(cache.pop_first(), None);
 
         cache.put("apple", "red");
         cache.put("banana", "yellow");
+        cache.put("pear", "green");
+        assert_eq!(cache.peek_first(), Some((&"pear", &"green")));
+        cache.promote(&"apple");
+        assert_eq!(cache.peek_first(), Some((&"apple", &"red")));
+        cache.demote(&"apple");
+        assert_eq!(cache.peek_first(), Some((&"pear", &"green")));
+
+        assert_eq!(cache.pop_first(), Some(("pear", "green")));
+        assert_eq!(cache.pop_first(), Some(("banana", "yellow")));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"pear"), None);
+        cache.put("kiwi", "green");
+        cache.put("plum", "purple");
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"plum", &"purple"), (&"kiwi", &"green"), (&"apple", &"red")]);
+    }
 
-        assert_eq!(cache.cap().get(), 2);
+    #[test]
+    fn test_from_iter_and_extend() {
+        let mut cache: LRUCache<_, _> = [("a", 1), ("b", 2), ("a", 3)].into_iter().collect();
         assert_eq!(cache.len(), 2);
-        assert_opt_eq_mut(cache.get_mut(&"apple"), "red");
-        assert_opt_eq_mut(cache.get_mut(&"banana"), "yellow");
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"a", &3), (&"b", &2)]);
+
+        cache.extend([("c", 4), ("b", 5)]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"b", &5), (&"c", &4), (&"a", &3)]);
     }
 
     #[test]
-    fn test_get_mut_and_update() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_from_iter_with_cap() {
+        let cache = LRUCache::from_iter_with_cap(NonZeroUsize::new(3).unwrap(), (0..10).map(|i| (i, i * 10)));
+        assert_eq!(cache.cap().get(), 3);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&9, &90), (&8, &80), (&7, &70)]);
+
+        let mut cache = LRUCache::from_iter_with_cap(NonZeroUsize::new(2).unwrap(), [("a", 1), ("b", 2), ("a", 3)]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"a", &3), (&"b", &2)]);
+        cache.extend([("c", 4)]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"c", &4), (&"a", &3)]);
+    }
 
+    #[test]
+    fn test_stats() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.set_clock(clock);
         cache.put("apple", 1);
-        cache.put("banana", 3);
+        cache.put("banana", 2);
+        cache.put("apple", 3);
+        assert_eq!(cache.get(&"apple"), Some(&3));
+        assert_eq!(cache.get(&"kiwi"), None);
+        cache.put("pear", 4); // evicts "banana"
+        *cache.get_mut(&"pear").unwrap() += 1;
+        cache.get_or_insert("lemon", || 6); // evicts "apple"
+        cache.get_or_insert("lemon", || 7);
+        assert_eq!(cache.peek(&"pear"), Some(&5));
+        cache.put_with_ttl("plum", 8, Duration::from_secs(1)); // evicts "pear"
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(&"plum"), None);
+        assert_eq!(
+            cache.stats(),
+            CacheStats { hits: 3, misses: 3, inserts: 6, evictions: 3, expirations: 1 }
+        );
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+        // entries taken by the caller are not evictions
+        assert_eq!(cache.pop(&"lemon"), Some(6));
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
 
-        {
-            let v = cache.get_mut(&"apple").unwrap();
-            *v = 4;
+    #[test]
+    fn test_debug() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(format!("{:?}", cache), "LRUCache { cap: 3, len: 0, entries: [] }");
+        cache.put("a", 1);
+        cache.put("b", 2);
+        assert_eq!(format!("{:?}", cache), r#"LRUCache { cap: 3, len: 2, entries: [("b", 2), ("a", 1)] }"#);
+
+        let mut cache = LRUCache::with_hasher(CacheMode::ItemLimit, NonZeroUsize::new(20).unwrap(), BuildHasherDefault::<DefaultHasher>::default());
+        for i in 0..20 {
+            cache.put(i, i);
         }
+        let debug = format!("{:?}", cache);
+        assert!(debug.starts_with("LRUCache { cap: 20, len: 20, entries: [(19, 19), (18, 18), "), "{}", debug);
+        assert!(debug.ends_with("(5, 5), (4, 4), ..] }"), "{}", debug);
+    }
 
-        assert_eq!(cache.cap().get(), 2);
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq_mut(cache.get_mut(&"apple"), 4);
-        assert_opt_eq_mut(cache.get_mut(&"banana"), 3);
+    #[test]
+    fn test_eq_follows_recency_order() {
+        let mut a = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        let mut b = LRUCache::with_hasher(CacheMode::ItemLimit, NonZeroUsize::new(10).unwrap(), BuildHasherDefault::<DefaultHasher>::default());
+        assert!(a == b);
+        for (k, v) in [("apple", 1), ("banana", 2)] {
+            a.put(k, v);
+            b.put(k, v);
+        }
+        assert!(a == b);
+
+        // same entries, different history
+        a.get(&"apple");
+        assert!(a != b);
+        b.promote(&"apple");
+        assert!(a == b);
+
+        b.put("apple", 3);
+        assert!(a != b);
+        let c = a.clone();
+        assert_eq!(a, c);
+        a.pop_last();
+        assert_ne!(a, c);
     }
 
     #[test]
-    fn test_put_update() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_replace_keeps_the_position() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("apple", "green"), Some("red"));
+        assert_eq!(cache.replace(&"a", 10), Some(1));
+        assert_eq!(cache.replace(&"missing", 4), None);
+        assert!(!cache.contains(&"missing"));
+        assert_eq!(cache.peek_last(), Some((&"a", &10)));
 
-        assert_eq!(cache.len(), 1);
-        assert_opt_eq(cache.get(&"apple"), "green");
+        // still the least recently used, so the next insert evicts it
+        cache.put("d", 4);
+        assert!(!cache.contains(&"a"));
+        assert_eq!(cache.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), [("d", 4), ("c", 3), ("b", 2)]);
+
+        let mut bytes = LRUCache::storage(NonZeroUsize::new(6).unwrap());
+        bytes.put("a", "11");
+        bytes.put("b", "22");
+        assert_eq!(bytes.replace(&"a", "1111"), Some("11"));
+        assert_eq!(bytes.used_bytes(), 6);
+        bytes.put("c", "3");
+        assert_eq!(bytes.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["c", "b"]);
+        bytes.assert_invariants();
     }
 
     #[test]
-    fn test_put_removes_oldest() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_rename() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put(String::from("upload-1"), 1);
+        cache.put(String::from("b"), 2);
+        cache.put(String::from("c"), 3);
+
+        assert!(cache.rename("upload-1", String::from("a")));
+        assert_eq!(cache.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(), [("c", 3), ("b", 2), ("a", 1)]);
+        assert!(!cache.contains("upload-1"));
+        assert_eq!(cache.peek("a"), Some(&1));
+
+        // the entry is found under its new key by every lookup
+        assert_eq!(cache.put(String::from("a"), 10), Some(1));
+        assert_eq!(cache.len(), 3);
+        cache.put(String::from("upload-1"), 4);
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains("b"));
+        assert_eq!(cache.pop("a"), Some(10));
+
+        // taken or missing keys leave the cache alone
+        assert!(!cache.rename("c", String::from("upload-1")));
+        assert!(!cache.rename("missing", String::from("d")));
+        assert!(!cache.rename("c", String::from("c")));
+        assert_eq!(cache.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(), [("upload-1", 4), ("c", 3)]);
+        cache.assert_invariants();
+    }
 
-        assert_eq!(cache.put("apple", "red"), None);
-        assert_eq!(cache.put("banana", "yellow"), None);
-        assert_eq!(cache.put("pear", "green"), None);
+    #[test]
+    fn test_into_iter_mru() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.put_many([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        cache.get(&"b");
+        let mut lru_first = cache.clone().into_iter().collect::<Vec<_>>();
+        assert_eq!(lru_first, [("a", 1), ("c", 3), ("d", 4), ("b", 2)]);
 
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
-        assert_opt_eq(cache.get(&"pear"), "green");
+        let mru_first = cache.clone().into_iter_mru().collect::<Vec<_>>();
+        lru_first.reverse();
+        assert_eq!(mru_first, lru_first);
 
-        // Even though we inserted "apple" into the cache earlier it has since been removed from
-        // the cache so there is no current value for `put` to return.
-        assert_eq!(cache.put("apple", "green"), None);
-        assert_eq!(cache.put("tomato", "red"), None);
+        // both ends at once
+        let mut iter = cache.into_iter();
+        assert_eq!((iter.next(), iter.next_back()), (Some(("a", 1)), Some(("b", 2))));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next_back(), Some(("d", 4)));
+        assert_eq!(iter.next(), Some(("c", 3)));
+        assert_eq!((iter.next(), iter.next_back()), (None, None));
+    }
 
-        assert!(cache.get(&"pear").is_none());
-        assert_opt_eq(cache.get(&"apple"), "green");
-        assert_opt_eq(cache.get(&"tomato"), "red");
+    #[test]
+    fn test_into_iter_with_hasher() {
+        #[derive(Clone, Copy)]
+        struct Seeded(u64);
+
+        impl BuildHasher for Seeded {
+            type Hasher = DefaultHasher;
+
+            fn build_hasher(&self) -> DefaultHasher {
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(self.0);
+                hasher
+            }
+        }
+
+        let mut cache = LRUCache::with_hasher(CacheMode::ItemLimit, NonZeroUsize::new(3).unwrap(), Seeded(42));
+        cache.put_many([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        let mut consumed = Vec::new();
+        for (k, v) in cache {
+            consumed.push((k, v));
+        }
+        assert_eq!(consumed, [("b", 2), ("c", 3), ("d", 4)]);
     }
 
     #[test]
-    fn test_peek() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_entry() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put(String::from("a"), 1);
+        cache.put(String::from("b"), 2);
+
+        *cache.entry(String::from("a")).or_insert(10) += 1;
+        assert_eq!(cache.peek("a"), Some(&2));
+        assert_eq!(cache.first_key().map(String::as_str), Some("a"));
+        assert_eq!(*cache.entry(String::from("c")).or_insert_with(|| 3), 3);
+        assert_eq!(*cache.entry(String::from("d")).or_insert_with_key(|k| k.len() as i32 * 4), 4);
+        assert!(!cache.contains("b"));
+        assert_eq!(*cache.entry(String::from("d")).and_modify(|v| *v *= 10).or_default(), 40);
+        assert_eq!(*cache.entry(String::from("e")).and_modify(|v| *v *= 10).or_default(), 0);
+        assert_eq!(cache.keys().map(String::as_str).collect::<Vec<_>>(), ["e", "d", "c"]);
+
+        match cache.entry(String::from("c")) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), "c");
+                assert_eq!(*entry.get(), 3);
+                *entry.get_mut() += 1;
+                assert_eq!(entry.remove_entry(), (String::from("c"), 4));
+            }
+            Entry::Vacant(_) => panic!("\"c\" is in the cache"),
+        }
+        match cache.entry(String::from("c")) {
+            Entry::Occupied(_) => panic!("\"c\" was removed"),
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), "c");
+                assert_eq!(entry.into_key(), "c");
+            }
+        }
+        match cache.entry(String::from("e")) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 0),
+            Entry::Vacant(_) => panic!("\"e\" is in the cache"),
+        }
+        let entry = cache.entry(String::from("f"));
+        assert_eq!(entry.key(), "f");
+        let Entry::Vacant(entry) = entry else { panic!("\"f\" is not in the cache") };
+        *entry.insert(5) += 1;
+        assert_eq!(cache.iter().map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(), [("f", 6), ("d", 40)]);
+        assert_eq!(cache.stats().hits, 4);
+        assert_eq!(cache.stats().misses, 5);
+        cache.assert_invariants();
+    }
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+    #[test]
+    fn test_resize_with_evicted() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        cache.put_many((0..10).map(|i| (i, i * 10)));
+        cache.get(&0);
+
+        let evicted = cache.resize_with_evicted(NonZeroUsize::new(3).unwrap());
+        assert_eq!(evicted, (1..8).map(|i| (i, i * 10)).collect::<Vec<_>>());
+        assert_eq!(cache.cap().get(), 3);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [0, 9, 8]);
+        assert!(cache.resize_with_evicted(NonZeroUsize::new(3).unwrap()).is_empty());
+        assert!(cache.resize_with_evicted(NonZeroUsize::new(5).unwrap()).is_empty());
+        assert_eq!(cache.stats().evictions, 7);
+    }
 
-        assert_opt_eq(cache.peek(&"banana"), "yellow");
-        assert_opt_eq(cache.peek(&"apple"), "red");
+    #[test]
+    fn test_nth_recent_and_nth_last() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(cache.nth_recent(0), None);
+        assert_eq!(cache.nth_last(0), None);
+        cache.put_many([("a", 1), ("b", 2), ("c", 3)]);
+
+        assert_eq!(cache.nth_recent(0), Some((&"c", &3)));
+        assert_eq!(cache.nth_recent(1), Some((&"b", &2)));
+        assert_eq!(cache.nth_recent(2), Some((&"a", &1)));
+        assert_eq!(cache.nth_recent(3), None);
+        assert_eq!(cache.nth_last(0), Some((&"a", &1)));
+        assert_eq!(cache.nth_last(2), Some((&"c", &3)));
+        assert_eq!(cache.nth_last(usize::MAX), None);
+
+        // looking does not promote
+        cache.put("d", 4);
+        assert!(!cache.contains(&"a"));
+    }
 
-        cache.put("pear", "green");
+    #[test]
+    fn test_move_to_position() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
+        cache.put_many([("e", 5), ("d", 4), ("c", 3), ("b", 2), ("a", 1)]);
+        let order = |cache: &LRUCache<&str, i32>| cache.iter().map(|(k, _)| *k).collect::<String>();
+        assert_eq!(order(&cache), "abcde");
+
+        cache.move_to_position(&"a", 2);
+        assert_eq!(order(&cache), "bcade");
+        cache.move_to_position(&"e", 0);
+        assert_eq!(order(&cache), "ebcad");
+        cache.move_to_position(&"b", 4);
+        assert_eq!(order(&cache), "ecadb");
+        cache.move_to_position(&"c", 100);
+        assert_eq!(order(&cache), "eadbc");
+        cache.move_to_position(&"d", 2);
+        assert_eq!(order(&cache), "eadbc");
+        cache.move_to_position(&"x", 0);
+        assert_eq!(order(&cache), "eadbc");
+        cache.assert_invariants();
+
+        // the least recently used entry is the next victim
+        cache.put("f", 6);
+        assert_eq!(order(&cache), "feadb");
+        cache.assert_invariants();
+    }
 
-        assert!(cache.peek(&"apple").is_none());
-        assert_opt_eq(cache.peek(&"banana"), "yellow");
-        assert_opt_eq(cache.peek(&"pear"), "green");
+    #[test]
+    fn test_get_many() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.put_many([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+
+        let values = cache.get_many([&"b", &"x", &"a", &"b"]);
+        assert_eq!(values, [Some(&2), None, Some(&1), Some(&2)]);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["b", "a", "d", "c"]);
+        assert_eq!(cache.stats().hits, 3);
+        assert_eq!(cache.stats().misses, 1);
+        assert!(cache.get_many::<&str, _>([]).is_empty());
+        cache.assert_invariants();
     }
 
     #[test]
-    fn test_peek_mut() {
+    fn test_current_size_follows_random_ops() {
+        fn recomputed(cache: &LRUCache<String, String>) -> usize { cache.iter().map(|(k, v)| k.len() + v.len()).sum() }
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(8).unwrap());
+        cache.put(String::from("a"), String::from("123"));
+        assert_eq!(cache.current_size(), 4);
+        cache.recompute_size();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..2000 {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = "k".repeat(1 + (state >> 8) as usize % 12);
+            let value = "v".repeat((state >> 16) as usize % 20);
+            match state % 9 {
+                0 | 1 => drop(cache.put(key, value)),
+                2 => drop(cache.pop(&key)),
+                3 => drop(cache.pop_last()),
+                4 => drop(cache.get_or_insert(key, || value)),
+                5 => drop(cache.replace(&key, value)),
+                6 => drop(cache.rename(&key, format!("{}!", key))),
+                7 => cache.retain(|k, _| k.len() % 5 != 0),
+                _ => drop(cache.put_many([(key, value)])),
+            }
+            assert_eq!(cache.current_size(), recomputed(&cache));
+        }
+        assert_eq!(cache.size_of_entry("missing"), None);
+        let (key, value) = cache.peek_first().map(|(k, v)| (k.clone(), v.len())).unwrap();
+        assert_eq!(cache.size_of_entry(&key), Some(key.len() + value));
+        assert_eq!(cache.clone().current_size(), cache.current_size());
+
+        // changes in place are only seen by a recount
+        let before = cache.current_size();
+        cache.peek_mut(&key).unwrap().push_str("grown");
+        assert_eq!(cache.current_size(), before);
+        cache.recompute_size();
+        assert_eq!(cache.current_size(), before + 5);
+        cache.clear();
+        assert_eq!(cache.current_size(), 0);
+    }
+
+    #[test]
+    fn test_both_limits_share_the_eviction_path() {
+        fn run(mut cache: LRUCache<&'static str, &'static str>) -> (Vec<&'static str>, Vec<&'static str>) {
+            let mut evicted = cache.put_many([("a", "1"), ("b", "22"), ("c", "333"), ("d", "4")]);
+            cache.assert_invariants();
+            evicted.extend(cache.resize_with_evicted(NonZeroUsize::new(3).unwrap()));
+            cache.assert_invariants();
+            (cache.iter().map(|(k, _)| *k).collect(), evicted.into_iter().map(|(k, _)| k).collect())
+        }
+
+        let cap = NonZeroUsize::new(4).unwrap();
+        assert_eq!(run(LRUCache::new(cap)), (vec!["d", "c", "b"], vec!["a"]));
+        assert_eq!(run(LRUCache::storage(cap)), (vec!["d"], vec!["a", "b", "c"]));
+    }
+
+    #[test]
+    fn test_put_weighted() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(10).unwrap());
+        assert!(cache.put_weighted("a", "1", 6).is_empty());
+        cache.put("b", "22");
+        assert!(cache.put_weighted("c", "3", 2).is_empty());
+        assert_eq!(cache.used_bytes(), 10);
+
+        // the weight decides what has to go, not the value's size
+        assert_eq!(cache.put_weighted("d", "4", 5), [("a", "1")]);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["d", "c", "b"]);
+        assert_eq!(cache.used_bytes(), 9);
+        assert_eq!(cache.put_weighted("e", "5", 11), [("e", "5")]);
+
+        // removals give back the stored weight
+        assert_eq!(cache.pop(&"d"), Some("4"));
+        assert_eq!(cache.used_bytes(), 4);
+        assert_eq!(cache.pop_entry(&"c"), Some(("c", "3")));
+        assert_eq!(cache.used_bytes(), 2);
+
+        // putting a key again replaces its weight
+        cache.put_weighted("b", "22", 7);
+        assert_eq!(cache.used_bytes(), 7);
+        cache.put("b", "22");
+        assert_eq!(cache.used_bytes(), 2);
+        cache.put_weighted("f", "6", 8);
+        assert_eq!(cache.used_bytes(), 10);
+        cache.assert_invariants();
+        cache.clear();
+        assert_eq!(cache.used_bytes(), 0);
+
+        // moved entries keep their weight
+        let mut other = LRUCache::storage(NonZeroUsize::new(10).unwrap());
+        other.put_weighted("a", "1", 9);
+        assert!(cache.append(&mut other).is_empty());
+        assert_eq!(cache.used_bytes(), 9);
+        let split = cache.split_off(0);
+        assert_eq!((cache.used_bytes(), split.used_bytes()), (0, 9));
+        assert_eq!(split.clone().used_bytes(), 9);
+    }
+
+    #[test]
+    fn test_put_many() {
         let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        assert_eq!(cache.put_many([("a", 1), ("b", 2), ("a", 3), ("c", 4), ("d", 5)]), [("b", 2), ("a", 3)]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"d", &5), (&"c", &4)]);
+        cache.assert_invariants();
+
+        let mut cache = LRUCache::storage(NonZeroUsize::new(4).unwrap());
+        assert_eq!(cache.put_many([("a", "1"), ("b", "2"), ("c", "3"), ("d", "4444"), ("e", "55555")]), [("a", "1"), ("b", "2"), ("c", "3"), ("e", "55555")]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"d", &"4444")]);
+        assert_eq!(cache.used_bytes(), 4);
+        cache.assert_invariants();
+    }
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+    #[test]
+    fn test_append() {
+        let mut main = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        main.put("a", 1);
+        main.put("b", 2);
+        let mut staging = LRUCache::with_hasher(CacheMode::ItemLimit, NonZeroUsize::new(5).unwrap(), BuildHasherDefault::<DefaultHasher>::default());
+        staging.put("c", 3);
+        staging.put("a", 10);
+        staging.put("d", 4);
+
+        // "c" fits, "a" is overwritten and moves to the front, "d" pushes out "b"
+        assert_eq!(main.append(&mut staging), [("b", 2)]);
+        assert!(staging.is_empty());
+        assert_eq!(staging.iter().count(), 0);
+        assert_eq!(main.iter().collect::<Vec<_>>(), [(&"d", &4), (&"a", &10), (&"c", &3)]);
+
+        staging.put("e", 5);
+        assert_eq!(main.append(&mut staging), [("c", 3)]);
+        assert_eq!(main.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["e", "d", "a"]);
+        assert_eq!(main.append(&mut staging), []);
+    }
 
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
-        assert_opt_eq_mut(cache.peek_mut(&"apple"), "red");
-        assert!(cache.peek_mut(&"pear").is_none());
+    #[test]
+    fn test_append_in_capacity_mode() {
+        let mut main = LRUCache::storage(NonZeroUsize::new(6).unwrap());
+        main.put("a", "11");
+        main.put("b", "22");
+        main.put("c", "33");
+        let mut staging = LRUCache::unbounded();
+        staging.put("too big", "1234567");
+        staging.put("d", "4444");
+
+        // every entry "d" displaces is handed back, not just the last one
+        assert_eq!(main.append(&mut staging), [("too big", "1234567"), ("a", "11"), ("b", "22")]);
+        assert_eq!(main.iter().collect::<Vec<_>>(), [(&"d", &"4444"), (&"c", &"33")]);
+        assert_eq!(main.used_bytes(), 6);
+        assert!(staging.is_empty());
+    }
 
-        cache.put("pear", "green");
+    #[test]
+    fn test_retain() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(100).unwrap());
+        for key in ["a/1", "b/1", "a/2", "b/2", "c/1"] {
+            cache.put(key, key.len());
+        }
+        let mut visited = Vec::new();
+        cache.retain(|k, v| {
+            visited.push(*k);
+            *v += 1;
+            !k.starts_with("a/")
+        });
+        assert_eq!(visited, ["c/1", "b/2", "a/2", "b/1", "a/1"]);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"c/1", &4), (&"b/2", &4), (&"b/1", &4)]);
+        assert!(!cache.contains(&"a/1"));
+        assert_eq!(cache.used_bytes(), 24);
 
-        assert!(cache.peek_mut(&"apple").is_none());
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "yellow");
-        assert_opt_eq_mut(cache.peek_mut(&"pear"), "green");
+        cache.retain(|_, _| false);
+        assert!(cache.is_empty());
+        assert_eq!(cache.used_bytes(), 0);
+        cache.assert_invariants();
+    }
 
-        {
-            let v = cache.peek_mut(&"banana").unwrap();
-            *v = "green";
+    #[test]
+    fn test_no_memory_leaks_with_retain() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
         }
 
-        assert_opt_eq_mut(cache.peek_mut(&"banana"), "green");
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..10 {
+            cache.put(i, DropCounter);
+        }
+        cache.retain(|k, _| k % 3 == 0);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 6);
+        assert_eq!(cache.len(), 4);
+        drop(cache);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 10);
     }
 
     #[test]
-    fn test_peek_lru() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_split_off() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(100).unwrap());
+        for (k, v) in [("a", "1"), ("b", "22"), ("c", "333"), ("d", "4444")] {
+            cache.put(k, v);
+        }
+        let mut cold = cache.split_off(1);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"d", &"4444")]);
+        assert_eq!(cold.iter().collect::<Vec<_>>(), [(&"c", &"333"), (&"b", &"22"), (&"a", &"1")]);
+        assert_eq!((cache.cap(), cold.cap()), (NonZeroUsize::new(100).unwrap(), NonZeroUsize::new(100).unwrap()));
+        assert_eq!((cache.used_bytes(), cold.used_bytes()), (4, 6));
+        cache.assert_invariants();
+        cold.assert_invariants();
+        assert_eq!(cold.get(&"b"), Some(&"22"));
+        assert_eq!(cold.pop_last(), Some(("a", "1")));
+
+        // nothing past the end, or everything
+        assert!(cache.split_off(1).is_empty());
+        assert!(cache.split_off(5).is_empty());
+        let all = cache.split_off(0);
+        assert!(cache.is_empty());
+        assert_eq!(cache.used_bytes(), 0);
+        assert_eq!(all.iter().collect::<Vec<_>>(), [(&"d", &"4444")]);
+        cache.assert_invariants();
+        all.assert_invariants();
+        cache.put("e", "5");
+        cache.assert_invariants();
+    }
 
-        assert!(cache.peek_last().is_none());
+    #[test]
+    fn test_no_memory_leaks_with_split_off() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-        assert_opt_eq_tuple(cache.peek_last(), ("apple", "red"));
+        struct DropCounter;
 
-        cache.get(&"apple");
-        assert_opt_eq_tuple(cache.peek_last(), ("banana", "yellow"));
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
 
-        cache.clear();
-        assert!(cache.peek_last().is_none());
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..10 {
+            cache.put(i, DropCounter);
+        }
+        let cold = cache.split_off(4);
+        assert_eq!((cache.len(), cold.len()), (4, 6));
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 0);
+        drop(cold);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 6);
+        drop(cache);
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 10);
     }
 
     #[test]
-    fn test_contains() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_no_memory_leaks_with_clear() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
-        cache.put("pear", "green");
+        struct DropCounter;
 
-        assert!(!cache.contains(&"apple"));
-        assert!(cache.contains(&"banana"));
-        assert!(cache.contains(&"pear"));
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for _ in 0..n {
+            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+            for i in 0..n {
+                cache.put(i, DropCounter {});
+            }
+            cache.clear();
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
     }
 
     #[test]
-    fn test_pop() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_no_memory_leaks_with_take_all() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+        struct DropCounter;
+
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for round in 0..n {
+            let mut cache = LRUCache::new(NonZeroUsize::new(n).unwrap());
+            for i in 0..n {
+                cache.put(i, DropCounter {});
+            }
+            let taken = cache.take_all();
+            drop(cache);
+            // the values moved out: dropping the cache drops none of them
+            assert_eq!(DROP_COUNT.load(Ordering::SeqCst), round * n);
+            assert_eq!(taken.len(), n);
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+    }
 
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+    #[test]
+    fn test_reuse_after_take_all() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        for i in 1..=3 {
+            cache.put(i, i * 10);
+        }
+        cache.get(&1);
+        assert_eq!(cache.take_all(), vec![(1, 10), (3, 30), (2, 20)]);
+        assert!(cache.is_empty());
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.cap().get(), 3);
 
-        let popped = cache.pop(&"apple");
-        assert!(popped.is_some());
-        assert_eq!(popped.unwrap(), "red");
-        assert_eq!(cache.len(), 1);
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+        for i in 4..=7 {
+            cache.put(i, i * 10);
+        }
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![7, 6, 5]);
+
+        let mut storage = LRUCache::storage(NonZeroUsize::new(2).unwrap());
+        storage.put("a", 1u8);
+        storage.put("b", 2u8);
+        assert_eq!(storage.take_all().len(), 2);
+        // the byte accounting starts over, so two fresh entries fit again
+        storage.put("c", 3u8);
+        storage.put("d", 4u8);
+        assert_eq!(storage.len(), 2);
     }
 
     #[test]
-    fn test_pop_entry() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+    fn test_map_values() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.set_clock(clock);
+        cache.put("a", vec![1u8]);
+        cache.put_with_ttl("b", vec![2u8, 2], Duration::from_secs(10));
+        cache.put("c", vec![3u8, 3, 3]);
+        cache.get(&"a");
+
+        let mut lens = cache.map_values(|_, v| v.len());
+        assert_eq!(lens.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), vec![("a", 1), ("c", 3), ("b", 2)]);
+        assert_eq!(lens.cap().get(), 4);
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(lens.get(&"b"), None);
+        for (k, v) in [("d", 4), ("e", 5), ("f", 6)] {
+            lens.put(k, v);
+        }
+        assert!(!lens.contains(&"c") && lens.contains(&"a"));
 
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+        let cache: LRUCache<&str, Vec<u8>> = LRUCache::unbounded();
+        assert!(cache.try_map_values(|_, v| Ok::<_, ()>(v.len())).unwrap().is_empty());
+    }
 
-        let popped = cache.pop_entry(&"apple");
-        assert!(popped.is_some());
-        assert_eq!(popped.unwrap(), ("apple", "red"));
-        assert_eq!(cache.len(), 1);
-        assert!(cache.get(&"apple").is_none());
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+    #[test]
+    fn test_try_map_values_stops_at_first_error() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        for i in 1..=3 {
+            cache.put(i, format!("{}", i * 10));
+        }
+        let mut seen = Vec::new();
+        let result = cache.try_map_values(|k, v| {
+            seen.push(*k);
+            v.parse::<u32>().map_err(|_| *k).and_then(|n| if n == 20 { Err(*k) } else { Ok(n) })
+        });
+        assert_eq!(result.err(), Some(2));
+        // least recently used first, nothing after the failure
+        assert_eq!(seen, vec![1, 2]);
     }
 
     #[test]
-    fn test_pop_lru() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(200).unwrap());
+    fn test_no_memory_leaks_when_map_values_panics() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        for i in 0..75 {
-            cache.put(i, "A");
-        }
-        for i in 0..75 {
-            cache.put(i + 100, "B");
-        }
-        for i in 0..75 {
-            cache.put(i + 200, "C");
-        }
-        assert_eq!(cache.len(), 200);
+        struct DropCounter;
 
-        for i in 0..75 {
-            assert_opt_eq(cache.get(&(74 - i + 100)), "B");
-        }
-        assert_opt_eq(cache.get(&25), "A");
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
 
-        for i in 26..75 {
-            assert_eq!(cache.pop_last(), Some((i, "A")));
-        }
-        for i in 0..75 {
-            assert_eq!(cache.pop_last(), Some((i + 200, "C")));
-        }
-        for i in 0..75 {
-            assert_eq!(cache.pop_last(), Some((74 - i + 100, "B")));
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
         }
-        assert_eq!(cache.pop_last(), Some((25, "A")));
-        for _ in 0..50 {
-            assert_eq!(cache.pop_last(), None);
+
+        let n = 10;
+        let mut cache = LRUCache::new(NonZeroUsize::new(n).unwrap());
+        for i in 0..n {
+            cache.put(i, DropCounter {});
         }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            cache.map_values(|k, v| {
+                assert!(*k != n / 2, "mapping failed");
+                v
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n);
     }
 
     #[test]
-    fn test_clear() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_no_memory_leaks_with_resize() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        cache.put("apple", "red");
-        cache.put("banana", "yellow");
+        struct DropCounter;
 
-        assert_eq!(cache.len(), 2);
-        assert_opt_eq(cache.get(&"apple"), "red");
-        assert_opt_eq(cache.get(&"banana"), "yellow");
+        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
 
-        cache.clear();
-        assert_eq!(cache.len(), 0);
+        impl Drop for DropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for _ in 0..n {
+            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+            for i in 0..n {
+                cache.put(i, DropCounter {});
+            }
+            cache.clear();
+        }
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
     }
 
     #[test]
-    fn test_resize_larger() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+    fn test_no_memory_leaks_with_pop() {
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        cache.put(1, "a");
-        cache.put(2, "b");
-        cache.resize(NonZeroUsize::new(4).unwrap());
-        cache.put(3, "c");
-        cache.put(4, "d");
+        #[derive(Hash, PartialEq, Eq)]
+        struct KeyDropCounter(usize);
 
-        assert_eq!(cache.len(), 4);
-        assert_eq!(cache.get(&1), Some(&"a"));
-        assert_eq!(cache.get(&2), Some(&"b"));
-        assert_eq!(cache.get(&3), Some(&"c"));
-        assert_eq!(cache.get(&4), Some(&"d"));
+        impl Drop for KeyDropCounter {
+            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        let n = 100;
+        for _ in 0..n {
+            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+
+            for i in 0..100 {
+                cache.put(KeyDropCounter(i), i);
+                cache.pop(&KeyDropCounter(i));
+            }
+        }
+
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n * 2);
     }
 
     #[test]
-    fn test_resize_smaller() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
-
-        cache.put(1, "a");
-        cache.put(2, "b");
-        cache.put(3, "c");
-        cache.put(4, "d");
+    fn test_promote_and_demote() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
+        for i in 0..5 {
+            cache.push(i, i);
+        }
+        cache.promote(&1);
+        cache.promote(&0);
+        cache.demote(&3);
+        cache.demote(&4);
+        assert_eq!(cache.pop_last(), Some((4, 4)));
+        assert_eq!(cache.pop_last(), Some((3, 3)));
+        assert_eq!(cache.pop_last(), Some((2, 2)));
+        assert_eq!(cache.pop_last(), Some((1, 1)));
+        assert_eq!(cache.pop_last(), Some((0, 0)));
+        assert_eq!(cache.pop_last(), None);
+    }
 
-        cache.resize(NonZeroUsize::new(2).unwrap());
+    #[test]
+    #[cfg(feature = "entry-timestamps")]
+    fn test_pop_older_than() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::unbounded();
+        cache.set_clock(clock);
+        for key in ["a", "b", "c"] {
+            cache.put(key, 0);
+        }
+        clock.advance(Duration::from_secs(10));
+        cache.get(&"a");
+        cache.put("d", 0);
 
+        clock.advance(Duration::from_secs(5));
+        // "b" and "c" idled 15s; the promoted "a" and the new "d" only 5s
+        assert_eq!(cache.pop_older_than(Duration::from_secs(8), clock.now()), vec![("b", 0), ("c", 0)]);
         assert_eq!(cache.len(), 2);
-        assert!(cache.get(&1).is_none());
-        assert!(cache.get(&2).is_none());
-        assert_eq!(cache.get(&3), Some(&"c"));
-        assert_eq!(cache.get(&4), Some(&"d"));
+        assert!(cache.pop_older_than(Duration::from_secs(5), clock.now()).is_empty());
+        assert_eq!(cache.pop_older_than(Duration::from_secs(4), clock.now()).len(), 2);
     }
 
     #[test]
-    fn test_send() {
-        use std::thread;
+    #[cfg(feature = "entry-timestamps")]
+    fn test_pop_older_than_stops_at_first_recent_entry() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::unbounded();
+        cache.set_clock(clock);
+        cache.put("old1", 0);
+        cache.put("old2", 0);
+        clock.advance(Duration::from_secs(10));
+        cache.put("young", 0);
+        cache.demote(&"young");
+
+        assert!(cache.pop_older_than(Duration::from_secs(5), clock.now()).is_empty());
+        assert_eq!(cache.len(), 3);
+    }
 
+    #[test]
+    fn test_dump_golden() {
+        let clock = ManualClock::leaked();
         let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
-        cache.put(1, "a");
+        cache.set_clock(clock);
+        cache.put("apple", "red");
+        cache.put_with_ttl("banana", "yellow", Duration::from_secs(30));
+        clock.advance(Duration::from_millis(1500));
+        cache.put("cherry", "a rather long description");
+        cache.get(&"apple");
 
-        let handle = thread::spawn(move || {
-            assert_eq!(cache.get(&1), Some(&"a"));
-        });
+        let dump = cache.dump(DumpOptions { include_values: true, max_value_bytes: 8 });
+        let mut golden: serde_json::Value = serde_json::from_str(include_str!("../../tests/fixtures/dump.json")).unwrap();
+        if cfg!(not(feature = "entry-timestamps")) {
+            // idle times are only kept with the feature
+            for entry in golden["entries"].as_array_mut().unwrap() {
+                entry.as_object_mut().unwrap().remove("idle_ms");
+            }
+        }
+        assert_eq!(serde_json::to_value(&dump).unwrap(), golden);
 
-        assert!(handle.join().is_ok());
+        let dump = cache.dump(DumpOptions::default());
+        assert!(dump.entries.iter().all(|e| e.value.is_none()));
+        // dumping does not promote
+        assert_eq!(cache.iter().next().map(|(k, _)| *k), Some("apple"));
     }
 
     #[test]
-    fn test_iter_forwards() {
+    fn test_eviction_skips_values_in_use() {
         let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
-        cache.put("a", 1);
-        cache.put("b", 2);
-        cache.put("c", 3);
-
-        {
-            // iter const
-            let mut iter = cache.iter();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_tuple(iter.next(), ("c", 3));
-
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_tuple(iter.next(), ("b", 2));
-
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_tuple(iter.next(), ("a", 1));
-
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next(), None);
+        cache.skip_in_use_victims(1);
+        for (k, v) in [("a", 1u32), ("b", 2), ("c", 3)] {
+            cache.put(k, Arc::new(v));
         }
-        {
-            // iter mut
-            let mut iter = cache.iter_mut();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_mut_tuple(iter.next(), ("c", 3));
+        let streaming = cache.peek(&"a").unwrap().clone();
+        cache.put("d", Arc::new(4));
+        assert!(cache.contains(&"a") && !cache.contains(&"b"));
+        assert_eq!(cache.in_use_skips(), 1);
+
+        // with "a" and "c" both held, one skip is not enough and "a" goes after all
+        let _also = cache.peek(&"c").unwrap().clone();
+        cache.put("e", Arc::new(5));
+        assert!(!cache.contains(&"a") && cache.contains(&"c"));
+        assert_eq!(*streaming, 1);
+        assert_eq!(cache.in_use_skips(), 1);
+    }
 
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_mut_tuple(iter.next(), ("b", 2));
+    #[test]
+    fn test_capacity_eviction_skips_values_in_use() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(8).unwrap());
+        cache.skip_in_use_victims(4);
+        cache.put(1, Arc::new(10u32));
+        cache.put(2, Arc::new(20u32));
+        let held = cache.peek(&1).unwrap().clone();
+        cache.put(3, Arc::new(30u32));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![3, 1]);
+        drop(held);
+        cache.put(4, Arc::new(40u32));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![4, 3]);
+    }
 
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_mut_tuple(iter.next(), ("a", 1));
+    #[test]
+    fn test_auto_shrink_after_drain() {
+        let mut cache = LRUCache::unbounded();
+        for i in 0..10_000 {
+            cache.put(i, i);
+        }
+        let grown = cache.map_capacity();
+        for i in 0..9_000 {
+            cache.pop(&i);
+        }
+        // off by default; removals only leave tombstones behind
+        assert!(cache.map_capacity() > grown / 2);
 
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next(), None);
+        cache.set_auto_shrink(Some(4));
+        while cache.len() > 100 {
+            cache.pop_last();
         }
+        assert!(cache.map_capacity() < grown / 4, "{} of {}", cache.map_capacity(), grown);
+        assert!(cache.map_capacity() >= cache.len());
+        assert_eq!(cache.iter().next(), Some((&9_999, &9_999)));
+
+        // small maps stay as they are; a fixed hasher leaves both maps with the same tombstones
+        let filled = || {
+            let hasher = BuildHasherDefault::<DefaultHasher>::default();
+            let mut cache = LRUCache::with_hasher(CacheMode::ItemLimit, NonZeroUsize::new(100).unwrap(), hasher);
+            for i in 0..100 {
+                cache.put(i, i);
+            }
+            cache
+        };
+        let (mut small, mut plain) = (filled(), filled());
+        small.set_auto_shrink(Some(4));
+        small.clear();
+        plain.clear();
+        assert_eq!(small.map_capacity(), plain.map_capacity());
     }
 
     #[test]
-    fn test_iter_backwards() {
+    fn test_peek_many() {
         let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
-        cache.put("a", 1);
-        cache.put("b", 2);
-        cache.put("c", 3);
+        for (i, key) in ["apple", "banana", "pear"].into_iter().enumerate() {
+            cache.put(key.to_string(), i);
+        }
+        assert_eq!(cache.peek_many(["pear", "kiwi", "apple", "pear"]), vec![Some(&2), None, Some(&0), Some(&2)]);
+        let keys = vec!["apple".to_string()];
+        assert_eq!(cache.peek_many(&keys), vec![Some(&0)]);
+
+        // "apple" was peeked but is still the next victim
+        assert_eq!(cache.last_key().map(String::as_str), Some("apple"));
+        cache.put("kiwi".to_string(), 3);
+        assert!(!cache.contains("apple"));
+    }
 
-        {
-            // iter const
-            let mut iter = cache.iter();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_tuple(iter.next_back(), ("a", 1));
+    #[test]
+    fn test_reserve_and_shrink_to_fit() {
+        let mut cache = LRUCache::unbounded();
+        cache.reserve(10_000);
+        let reserved = cache.map_capacity();
+        assert!(reserved >= 10_000);
+        for i in 0..10_000 {
+            cache.put(i, i);
+        }
+        // the reservation was enough for the whole batch
+        assert_eq!(cache.map_capacity(), reserved);
 
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_tuple(iter.next_back(), ("b", 2));
+        while cache.len() > 10 {
+            cache.pop_last();
+        }
+        // removals only leave tombstones behind
+        assert!(cache.map_capacity() > reserved / 2);
+        cache.shrink_to_fit();
+        assert!(cache.map_capacity() < reserved / 100, "{}", cache.map_capacity());
+        assert!(cache.map_capacity() >= 10);
+        assert_eq!(cache.iter().next(), Some((&9_999, &9_999)));
+    }
 
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_tuple(iter.next_back(), ("c", 3));
+    #[test]
+    fn test_try_reserve() {
+        let mut cache: LRUCache<u64, u64> = LRUCache::unbounded();
+        assert!(cache.try_reserve(100).is_ok());
+        assert!(cache.try_reserve(usize::MAX).is_err());
+        assert_eq!(cache.try_put(1, 10), Ok(None));
+        assert_eq!(cache.try_put(1, 11), Ok(Some(10)));
+    }
 
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next_back(), None);
-        }
+    #[test]
+    fn test_try_new() {
+        assert_eq!(LRUCache::<u32, u32>::try_new(0).err(), Some(CacheError::ZeroCapacity));
+        assert_eq!(LRUCache::<u32, u32>::try_storage(0).err(), Some(CacheError::ZeroCapacity));
+        let hasher = BuildHasherDefault::<DefaultHasher>::default();
+        assert_eq!(LRUCache::<u32, u32, _>::try_with_hasher(CacheMode::ItemLimit, 0, hasher).err(), Some(CacheError::ZeroCapacity));
+
+        let mut one = LRUCache::try_new(1).unwrap();
+        one.put(1, 1);
+        assert_eq!(one.push(2, 2), Some((1, 1)));
+        assert_eq!(one.cap().get(), 1);
+        let mut bytes = LRUCache::try_storage(1).unwrap();
+        assert_eq!(bytes.push("a", "x"), None);
+        assert_eq!(bytes.push("b", "yz"), Some(("b", "yz")));
+
+        // `new` would panic sizing the map for that many keys; nothing is allocated per byte
+        assert_eq!(LRUCache::<u32, u32>::try_new(usize::MAX).err(), Some(CacheError::AllocError));
+        let mut huge = LRUCache::try_storage(usize::MAX).unwrap();
+        huge.put("a", 1u8);
+        assert_eq!(huge.cap().get(), usize::MAX);
+    }
 
-        {
-            // iter mut
-            let mut iter = cache.iter_mut();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_mut_tuple(iter.next_back(), ("a", 1));
+    #[test]
+    fn test_try_put_leaves_cache_untouched_when_allocation_fails() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(2).unwrap());
+        cache.put("a", 1u8);
+        cache.put("b", 2u8);
+
+        super::FAIL_SLOT_ALLOC.with(|fail| fail.set(true));
+        // a new key needs a new slot, and nothing is evicted to make room for it
+        assert_eq!(cache.try_put("c", 3u8), Err(CacheError::AllocError));
+        // replacing a value reuses the existing node
+        assert_eq!(cache.try_put("a", 4u8), Ok(Some(1)));
+        super::FAIL_SLOT_ALLOC.with(|fail| fail.set(false));
 
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_mut_tuple(iter.next_back(), ("b", 2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek(&"b"), Some(&2));
+        assert_eq!(cache.try_put("c", 3u8), Ok(None));
+        assert!(!cache.contains(&"b"));
+    }
 
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_mut_tuple(iter.next_back(), ("c", 3));
+    #[test]
+    fn test_storage_evicts_until_the_value_fits() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(10).unwrap());
+        cache.put("a", "123");
+        cache.put("b", "45");
+        cache.put("c", "6789");
+        assert_eq!(cache.used_bytes(), 9);
+
+        // one put pushes out the two least recently used entries
+        assert_eq!(cache.push("d", "abcdef"), Some(("b", "45")));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["d", "c"]);
+        assert_eq!(cache.used_bytes(), 10);
+    }
+
+    #[test]
+    fn test_storage_turns_away_values_larger_than_the_budget() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(4).unwrap());
+        cache.put("a", "12");
+        assert_eq!(cache.push("b", "12345"), Some(("b", "12345")));
+        assert_eq!(cache.put("a", "12345"), None);
+        assert_eq!(cache.try_put("c", "12345"), Err(CacheError::TooLarge { size: 5, capacity: 4 }));
+        assert_eq!(cache.peek(&"a"), Some(&"12"));
+        assert_eq!(cache.used_bytes(), 2);
+    }
 
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next_back(), None);
+    #[test]
+    fn test_get_or_insert_does_not_store_values_larger_than_the_budget() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(4).unwrap());
+        cache.put("a", "1");
+        cache.put("b", "2");
+        let unchanged = |cache: &LRUCache<&str, &str>| {
+            assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["b", "a"]);
+            assert_eq!(cache.used_bytes(), 2);
+            cache.assert_invariants();
+        };
+
+        // the loaded value is handed back, and nothing is evicted for it
+        assert_eq!(*cache.get_or_insert("c", || "12345"), "12345");
+        unchanged(&cache);
+        assert_eq!(*cache.get_or_insert_with_key("c", |_| "12345"), "12345");
+        unchanged(&cache);
+        assert_eq!(*cache.get_or_insert_mut("c", || "12345"), "12345");
+        unchanged(&cache);
+        assert_eq!(*cache.get_or_insert_with_key_mut("c", |_| "12345"), "12345");
+        unchanged(&cache);
+        assert_eq!(cache.try_get_or_insert("c", || Ok::<_, ()>("12345")), Ok(&"12345"));
+        unchanged(&cache);
+        assert_eq!(cache.try_get_or_insert_mut("c", || Ok::<_, ()>("12345")).map(|v| *v), Ok("12345"));
+        unchanged(&cache);
+        match cache.entry("c") {
+            Entry::Vacant(entry) => assert_eq!(*entry.insert("12345"), "12345"),
+            Entry::Occupied(_) => panic!("c was stored"),
         }
+        unchanged(&cache);
+
+        // a value that fits is stored as before
+        assert_eq!(*cache.get_or_insert("c", || "12"), "12");
+        assert_eq!(cache.used_bytes(), 4);
     }
 
     #[test]
-    fn test_iter_forwards_and_backwards() {
+    fn test_storage_accounting() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(10).unwrap());
+        cache.put("a", "1234");
+        cache.put("b", "56");
+        cache.put("c", "7");
+
+        // replacing charges the difference, evicting others if the new value is larger
+        assert_eq!(cache.put("b", "5"), Some("56"));
+        assert_eq!(cache.used_bytes(), 6);
+        assert_eq!(cache.put("c", "7777777"), Some("7"));
+        assert_eq!(cache.used_bytes(), 8);
+        assert!(!cache.contains(&"a"));
+
+        assert_eq!(cache.pop(&"b"), Some("5"));
+        assert_eq!(cache.used_bytes(), 7);
+        cache.put("d", "dd");
+        assert_eq!(cache.pop_entry(&"d"), Some(("d", "dd")));
+        assert_eq!(cache.pop_last(), Some(("c", "7777777")));
+        assert_eq!(cache.used_bytes(), 0);
+
+        cache.put("e", "eeee");
+        cache.put("f", "ffff");
+        cache.resize(NonZeroUsize::new(5).unwrap());
+        assert_eq!((cache.len(), cache.used_bytes()), (1, 4));
+        cache.clear();
+        assert_eq!(cache.used_bytes(), 0);
+    }
+
+    #[test]
+    fn test_clone_owns_its_nodes() {
         let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.put("a".to_string(), vec![1]);
+        cache.put("b".to_string(), vec![2]);
+        cache.put("c".to_string(), vec![3]);
+        cache.get("a");
+
+        let mut clone = cache.clone();
+        drop(cache);
+        assert_eq!(clone.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["a", "c", "b"]);
+        assert_eq!(clone.get("c"), Some(&vec![3]));
+        assert_eq!(clone.pop_last(), Some(("b".to_string(), vec![2])));
+        clone.put("d".to_string(), vec![4]);
+        clone.put("e".to_string(), vec![5]);
+        assert_eq!(clone.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), ["e", "d", "c"]);
+    }
+
+    #[test]
+    fn test_on_evict_takes_dropped_entries_only() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::new_with_on_evict(NonZeroUsize::new(2).unwrap(), move |k, v| sink.lock().unwrap().push((k, v)));
+        let take = || std::mem::take(&mut *evicted.lock().unwrap());
+
         cache.put("a", 1);
         cache.put("b", 2);
         cache.put("c", 3);
+        assert_eq!(take(), [("a", 1)]);
+        // returned to the caller instead
+        assert_eq!(cache.push("d", 4), Some(("b", 2)));
+        assert_eq!(cache.pop(&"c"), Some(3));
+        assert_eq!(cache.pop_entry(&"d"), Some(("d", 4)));
+        assert!(take().is_empty());
+
+        cache.get_or_insert("e", || 5);
+        cache.get_or_insert("f", || 6);
+        cache.get_or_insert("g", || 7);
+        assert_eq!(take(), [("e", 5)]);
+        cache.resize(NonZeroUsize::new(1).unwrap());
+        assert_eq!(take(), [("f", 6)]);
+        cache.clear();
+        assert_eq!(take(), [("g", 7)]);
+    }
 
-        {
-            // iter const
-            let mut iter = cache.iter();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_tuple(iter.next(), ("c", 3));
-
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_tuple(iter.next_back(), ("a", 1));
+    #[test]
+    fn test_watermarks_evict_in_batches() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::builder()
+            .max_items(10)
+            .watermarks(4, 8)
+            .on_evict(move |k, _| sink.lock().unwrap().push(k))
+            .build()
+            .unwrap();
+
+        // a sawtooth: up to the high mark untouched, then one burst down to the low one
+        let mut lens = Vec::new();
+        for i in 0..14u32 {
+            cache.put(i, i);
+            lens.push(cache.len());
+        }
+        assert_eq!(lens, [1, 2, 3, 4, 5, 6, 7, 8, 4, 5, 6, 7, 8, 4]);
+        assert_eq!(*evicted.lock().unwrap(), (0..10).collect::<Vec<_>>());
+        assert_eq!(cache.stats().evictions, 10);
+
+        // the capacity stays the hard limit when the high mark is above it
+        cache.set_watermarks(Some((2, 20)));
+        let batch = cache.put_many((14..21).map(|i| (i, i)));
+        assert_eq!(batch.iter().map(|&(k, _)| k).collect::<Vec<_>>(), (10..19).collect::<Vec<_>>());
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [&20, &19]);
+
+        // in capacity mode the marks count bytes, and they bound an unbounded cache too
+        let mut bytes = LRUCache::builder().max_bytes(100).watermarks(4, 6).build().unwrap();
+        bytes.put_many([("a", "11"), ("b", "22"), ("c", "33")]);
+        assert_eq!(bytes.used_bytes(), 6);
+        bytes.put("d", "4");
+        assert_eq!(bytes.iter().collect::<Vec<_>>(), [(&"d", &"4"), (&"c", &"33")]);
+
+        let mut unbounded = LRUCache::unbounded();
+        unbounded.set_watermarks(Some((1, 2)));
+        unbounded.put_many((0..3u32).map(|i| (i, i)));
+        assert_eq!(unbounded.keys().collect::<Vec<_>>(), [&2]);
+        unbounded.set_watermarks(None);
+        unbounded.put_many((0..3u32).map(|i| (i, i)));
+        assert_eq!(unbounded.len(), 3);
+        cache.assert_invariants();
+        bytes.assert_invariants();
+        unbounded.assert_invariants();
+    }
 
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_tuple(iter.next(), ("b", 2));
+    #[test]
+    fn test_overwrite_past_the_high_watermark_keeps_the_entry() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::builder().max_bytes(100).watermarks(4, 6).build().unwrap();
+        cache.set_on_evict(move |k, _| sink.lock().unwrap().push(k));
+        cache.put_many([("a", "1"), ("b", "12345")]);
+
+        // growing "b" past the high mark evicts down to the low one, but never "b" itself
+        assert_eq!(cache.put("b", "1234567"), Some("12345"));
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(&"b", &"1234567")]);
+        assert_eq!(*evicted.lock().unwrap(), ["a"]);
+        assert_eq!(cache.used_bytes(), 7);
+        cache.assert_invariants();
+    }
 
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next_back(), None);
+    #[test]
+    fn test_slots_are_reused() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(10).unwrap());
+        for i in 0..10_000 {
+            cache.put(i, i);
+            if i % 3 == 0 {
+                cache.pop(&(i / 2));
+            }
         }
-        {
-            // iter mut
-            let mut iter = cache.iter_mut();
-            assert_eq!(iter.len(), 3);
-            assert_opt_eq_mut_tuple(iter.next(), ("c", 3));
-
-            assert_eq!(iter.len(), 2);
-            assert_opt_eq_mut_tuple(iter.next_back(), ("a", 1));
+        // the sigils, the entries, and the one allocated before evicting for it
+        assert!(cache.slab.len() <= 13, "{} slots", cache.slab.len());
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), (9990..10_000).rev().collect::<Vec<_>>());
+
+        let drained: Vec<_> = cache.drain().collect();
+        assert_eq!(drained.len(), 10);
+        cache.put_many((0..10).map(|i| (i, i)));
+        assert!(cache.slab.len() <= 13);
+        cache.assert_invariants();
+    }
 
-            assert_eq!(iter.len(), 1);
-            assert_opt_eq_mut_tuple(iter.next(), ("b", 2));
+    #[test]
+    fn test_slab_shrinks_after_a_drain() {
+        let mut cache = LRUCache::unbounded();
+        cache.put_many((0..1000).map(|i| (i, i)));
+        for i in 0..990 {
+            cache.pop(&i);
+        }
+        assert!(cache.slab.entries.capacity() >= 1000);
+        cache.shrink_to_fit();
+        assert_eq!(cache.slab.entries.capacity(), 12);
+        // the survivors moved down and the map follows them
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), (990..1000).rev().collect::<Vec<_>>());
+        assert_eq!(cache.get(&995), Some(&995));
+        cache.assert_invariants();
+
+        cache.put_many((0..1000).map(|i| (i, i)));
+        cache.resize(NonZeroUsize::new(5).unwrap());
+        assert_eq!(cache.slab.entries.capacity(), 7);
+        assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [999, 998, 997, 996, 995]);
+        cache.assert_invariants();
+
+        cache.put_many((0..1000).map(|i| (i, i)));
+        cache.clear();
+        assert_eq!(cache.slab.entries.capacity(), 2);
+        cache.put(1, 1);
+        assert_eq!(cache.get(&1), Some(&1));
+        cache.assert_invariants();
 
-            assert_eq!(iter.len(), 0);
-            assert_eq!(iter.next_back(), None);
+        let mut cache = LRUCache::unbounded();
+        cache.set_auto_shrink(Some(4));
+        cache.put_many((0..10_000).map(|i| (i, i)));
+        for i in 0..10_000 {
+            cache.pop(&i);
         }
+        // auto-shrink leaves maps of up to AUTO_SHRINK_MIN_CAPACITY slots alone
+        assert!(cache.slab.entries.capacity() <= AUTO_SHRINK_MIN_CAPACITY, "{} slots", cache.slab.entries.capacity());
+        cache.assert_invariants();
     }
 
     #[test]
-    fn test_iter_clone() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
-        cache.put("a", 1);
-        cache.put("b", 2);
+    fn test_oversized_put_many_keeps_the_resident_entry() {
+        let mut cache = LRUCache::storage(NonZeroUsize::new(8).unwrap());
+        cache.put("a", "1234");
+        cache.put("b", "12");
+        assert_eq!(cache.put_many([("a", "123456789")]), [("a", "123456789")]);
+        assert_eq!(cache.peek(&"a"), Some(&"1234"));
+        assert_eq!(cache.put_weighted("b", "1", 9), [("b", "1")]);
+        assert_eq!(cache.peek(&"b"), Some(&"12"));
+        assert_eq!(cache.used_bytes(), 6);
+
+        // a value that fits replaces the old one, which is not reported as evicted
+        assert!(cache.put_many([("a", "123456")]).is_empty());
+        assert_eq!(cache.peek(&"a"), Some(&"123456"));
+        cache.assert_invariants();
+    }
 
-        let mut iter = cache.iter();
-        let mut iter_clone = iter.clone();
+    #[test]
+    fn test_check_consistency_finds_corruption() {
+        use super::{HEAD, NIL, TAIL};
+        use crate::lru::error::ConsistencyError;
 
-        assert_eq!(iter.len(), 2);
-        assert_opt_eq_tuple(iter.next(), ("b", 2));
-        assert_eq!(iter_clone.len(), 2);
-        assert_opt_eq_tuple(iter_clone.next(), ("b", 2));
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.put_many([("a", 1), ("b", 2), ("c", 3)]);
+        assert_eq!(cache.check_consistency(), Ok(()));
+
+        // each corruption is undone before the next one, so that dropping the cache is sound
+        let first = cache.slab[HEAD].next;
+        let second = cache.slab[first].next;
+        cache.slab[second].prev = HEAD;
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::BrokenLink { position: 1 }));
+        cache.slab[second].prev = first;
+
+        cache.slab[HEAD].prev = second;
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::BrokenSigil));
+        cache.slab[HEAD].prev = NIL;
+
+        cache.slab[second].next = 1000;
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::BrokenSigil));
+        cache.slab[second].next = cache.slab[TAIL].prev;
+
+        let node = cache.unindex(&"b").unwrap();
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::NotInMap { position: 1 }));
+        cache.index(node);
+
+        cache.limit.add(1);
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::WeightMismatch { entries: 3, limiter: 4 }));
+        cache.limit.remove(1);
+
+        // a cycle is reported as a list longer than the map
+        let last = cache.slab[TAIL].prev;
+        cache.slab[last].next = first;
+        assert_eq!(cache.check_consistency(), Err(ConsistencyError::LengthMismatch { list: 4, map: 3 }));
+        cache.slab[last].next = TAIL;
+        cache.assert_invariants();
+    }
 
-        assert_eq!(iter.len(), 1);
-        assert_opt_eq_tuple(iter.next(), ("a", 1));
-        assert_eq!(iter_clone.len(), 1);
-        assert_opt_eq_tuple(iter_clone.next(), ("a", 1));
+    #[test]
+    #[should_panic(expected = "inconsistent LRUCache: the list holds 2 entries, the map 1")]
+    fn test_assert_invariants_panics() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(4).unwrap());
+        cache.put_many([("a", 1), ("b", 2)]);
+        // still in the list, "a" is dropped with the cache
+        cache.unindex(&"a");
+        cache.assert_invariants();
+    }
 
-        assert_eq!(iter.len(), 0);
-        assert_eq!(iter.next(), None);
-        assert_eq!(iter_clone.len(), 0);
-        assert_eq!(iter_clone.next(), None);
+    #[test]
+    fn test_on_evict_in_capacity_mode() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = evicted.clone();
+        let mut cache = LRUCache::storage(NonZeroUsize::new(4).unwrap());
+        cache.set_on_evict(move |k, v| sink.lock().unwrap().push((k, v)));
+        cache.put("a", "1");
+        cache.put("b", "2");
+        cache.put("c", "3");
+
+        // three entries make room, the last one is returned and the others go to the callback
+        assert_eq!(cache.push("d", "4444"), Some(("c", "3")));
+        assert_eq!(*evicted.lock().unwrap(), [("a", "1"), ("b", "2")]);
     }
 
     #[test]
-    fn test_into_iter() {
+    fn test_first_and_last_key() {
         let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(cache.first_key(), None);
+        assert_eq!(cache.last_key(), None);
+
         cache.put("a", 1);
+        assert_eq!((cache.first_key(), cache.last_key()), (Some(&"a"), Some(&"a")));
         cache.put("b", 2);
         cache.put("c", 3);
-
-        let mut iter = cache.into_iter();
-        assert_eq!(iter.len(), 3);
-        assert_eq!(iter.next(), Some(("a", 1)));
-
-        assert_eq!(iter.len(), 2);
-        assert_eq!(iter.next(), Some(("b", 2)));
-
-        assert_eq!(iter.len(), 1);
-        assert_eq!(iter.next(), Some(("c", 3)));
-
-        assert_eq!(iter.len(), 0);
-        assert_eq!(iter.next(), None);
+        assert_eq!((cache.first_key(), cache.last_key()), (Some(&"c"), Some(&"a")));
+
+        cache.promote(&"a");
+        assert_eq!((cache.first_key(), cache.last_key()), (Some(&"a"), Some(&"b")));
+        cache.demote(&"a");
+        assert_eq!((cache.first_key(), cache.last_key()), (Some(&"c"), Some(&"a")));
+        // reading the ends promotes nothing
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec!["c", "b", "a"]);
     }
 
     #[test]
-    fn test_that_pop_actually_detaches_node() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
-
+    fn test_touch() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
         cache.put("a", 1);
         cache.put("b", 2);
-        cache.put("c", 3);
-        cache.put("d", 4);
-        cache.put("e", 5);
+        cache.put_with_ttl("c", 3, Duration::from_secs(1));
 
-        assert_eq!(cache.pop(&"c"), Some(3));
+        assert!(cache.touch(&"a"));
+        assert!(!cache.touch(&"z"));
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec!["a", "c", "b"]);
 
-        cache.put("f", 6);
+        assert_eq!(cache.touch_many([&"b", &"z", &"c"]), 2);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec!["c", "b", "a"]);
 
-        let mut iter = cache.iter();
-        assert_opt_eq_tuple(iter.next(), ("f", 6));
-        assert_opt_eq_tuple(iter.next(), ("e", 5));
-        assert_opt_eq_tuple(iter.next(), ("d", 4));
-        assert_opt_eq_tuple(iter.next(), ("b", 2));
-        assert_opt_eq_tuple(iter.next(), ("a", 1));
-        assert!(iter.next().is_none());
+        // an expired entry is gone, not touched
+        clock.advance(Duration::from_secs(2));
+        assert!(!cache.touch(&"c"));
+        assert_eq!(cache.len(), 2);
+
+        let keys = ["a".to_string(), "x".to_string()];
+        let mut owned = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        owned.put("a".to_string(), 1);
+        assert_eq!(owned.touch_many(keys.iter().map(String::as_str)), 1);
     }
 
     #[test]
-    fn test_get_with_borrow() {
-        use alloc::string::String;
-
+    #[cfg(not(feature = "std"))]
+    fn test_ttl_needs_a_clock_without_std() {
         let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put_with_ttl("apple", "red", Duration::from_nanos(1));
+        assert_eq!(cache.ttl(&"apple"), Some(Duration::from_nanos(1)));
+        assert_opt_eq(cache.get(&"apple"), "red");
 
-        let key = String::from("apple");
-        cache.put(key, "red");
-
-        assert_opt_eq(cache.get("apple"), "red");
+        let clock = ManualClock::leaked();
+        cache.set_clock(clock);
+        clock.advance(Duration::from_nanos(1));
+        assert!(cache.get(&"apple").is_none());
     }
 
     #[test]
-    fn test_get_mut_with_borrow() {
-        use alloc::string::String;
+    fn test_put_with_ttl() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
 
-        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.put_with_ttl("apple", "red", Duration::from_secs(10));
+        cache.put("banana", "yellow");
+        assert_eq!(cache.ttl(&"apple"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.ttl(&"banana"), None);
 
-        let key = String::from("apple");
-        cache.put(key, "red");
+        clock.advance(Duration::from_secs(9));
+        assert_opt_eq(cache.get(&"apple"), "red");
 
-        assert_opt_eq_mut(cache.get_mut("apple"), "red");
+        clock.advance(Duration::from_secs(1));
+        assert!(cache.get(&"apple").is_none());
+        assert!(cache.get_mut(&"apple").is_none());
+        assert_eq!(cache.len(), 1);
+        assert_opt_eq(cache.get(&"banana"), "yellow");
+    }
+
+    // A cache whose "apple" has expired, for the get_or_insert variants to load it again.
+    fn cache_with_expired_apple() -> LRUCache<&'static str, &'static str> {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
+        cache.put_with_ttl("apple", "red", Duration::from_secs(10));
+        cache.put("banana", "yellow");
+        clock.advance(Duration::from_secs(10));
+        cache
     }
 
     #[test]
-    fn test_no_memory_leaks() {
-        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+    fn test_get_or_insert_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(*cache.get_or_insert("apple", || "green"), "green");
+        assert_eq!(cache.ttl(&"apple"), None);
+        assert_eq!(cache.stats().misses, 1);
+    }
 
-        struct DropCounter;
+    #[test]
+    fn test_get_or_insert_with_key_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(*cache.get_or_insert_with_key("apple", |_| "green"), "green");
+        assert_eq!(cache.ttl(&"apple"), None);
+    }
 
-        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+    #[test]
+    fn test_get_or_insert_mut_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(*cache.get_or_insert_mut("apple", || "green"), "green");
+        assert_eq!(cache.ttl(&"apple"), None);
+    }
 
-        impl Drop for DropCounter {
-            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
-        }
+    #[test]
+    fn test_get_or_insert_with_key_mut_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(*cache.get_or_insert_with_key_mut("apple", |_| "green"), "green");
+        assert_eq!(cache.ttl(&"apple"), None);
+    }
 
-        let n = 100;
-        for _ in 0..n {
-            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
-            for i in 0..n {
-                cache.put(i, DropCounter {});
-            }
-        }
-        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+    #[test]
+    fn test_try_get_or_insert_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(cache.try_get_or_insert("apple", || Ok::<_, ()>("green")), Ok(&"green"));
+        assert_eq!(cache.ttl(&"apple"), None);
     }
 
     #[test]
-    fn test_no_memory_leaks_with_clear() {
-        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+    fn test_try_get_or_insert_mut_reloads_expired() {
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(cache.try_get_or_insert_mut("apple", || Ok::<_, ()>("green")).map(|v| *v), Ok("green"));
+        assert_eq!(cache.ttl(&"apple"), None);
+
+        // a failing loader still drops the expired entry
+        let mut cache = cache_with_expired_apple();
+        assert_eq!(cache.try_get_or_insert_mut("apple", || Err("down")), Err("down"));
+        assert!(!cache.contains(&"apple"));
+        assert_eq!(cache.len(), 1);
+    }
 
-        struct DropCounter;
+    #[test]
+    fn test_expired_entries_until_removed() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
+        cache.put_with_ttl("apple", "red", Duration::from_secs(10));
+        cache.put_with_ttl("kiwi", "green", Duration::from_secs(10));
+        cache.put("banana", "yellow");
+        clock.advance(Duration::from_secs(10));
 
-        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+        // shared lookups skip expired entries, which still count and are still listed
+        assert!(!cache.contains(&"apple"));
+        assert_eq!(cache.peek_key_value(&"apple"), None);
+        assert_eq!(cache.peek_many([&"apple", &"banana"]), vec![None, Some(&"yellow")]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.iter().count(), 3);
+
+        // `peek_mut` removes what it finds expired; `pop_last` takes the LRU entry, expired or not
+        assert!(cache.peek(&"kiwi").is_none());
+        assert_eq!(cache.len(), 3);
+        assert!(cache.peek_mut(&"kiwi").is_none());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.pop_last(), Some(("apple", "red")));
+        assert_eq!(cache.pop_last(), Some(("banana", "yellow")));
+    }
 
-        impl Drop for DropCounter {
-            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
-        }
+    #[test]
+    fn test_put_clears_ttl() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
 
-        let n = 100;
-        for _ in 0..n {
-            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
-            for i in 0..n {
-                cache.put(i, DropCounter {});
-            }
-            cache.clear();
-        }
-        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+        cache.put_with_ttl("apple", "red", Duration::from_secs(10));
+        cache.put("apple", "green");
+        clock.advance(Duration::from_secs(60));
+        assert_opt_eq(cache.get(&"apple"), "green");
     }
 
     #[test]
-    fn test_no_memory_leaks_with_resize() {
-        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+    fn test_extend_ttl() {
+        let clock = ManualClock::leaked();
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        cache.set_clock(clock);
 
-        struct DropCounter;
+        cache.put_with_ttl("apple", "red", Duration::from_secs(10));
+        cache.put("banana", "yellow");
+        assert!(cache.extend_ttl(&"apple", Duration::from_secs(5)));
+        assert!(!cache.extend_ttl(&"banana", Duration::from_secs(5)));
+        assert!(!cache.extend_ttl(&"pear", Duration::from_secs(5)));
 
-        impl ItemSize for DropCounter { fn size_of(&self) -> usize { 1 } }
+        // extending does not promote
+        assert_opt_eq_tuple(cache.peek_last(), ("apple", "red"));
 
-        impl Drop for DropCounter {
-            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
-        }
+        clock.advance(Duration::from_secs(14));
+        assert_opt_eq(cache.get(&"apple"), "red");
+        clock.advance(Duration::from_secs(1));
+        assert!(!cache.extend_ttl(&"apple", Duration::from_secs(5)));
+        assert!(cache.get(&"apple").is_none());
+    }
 
-        let n = 100;
-        for _ in 0..n {
-            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
-            for i in 0..n {
-                cache.put(i, DropCounter {});
-            }
-            cache.clear();
-        }
-        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n);
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
     }
 
-    #[test]
-    fn test_no_memory_leaks_with_pop() {
-        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+    impl Recorder {
+        fn record(&self, event: String) { self.events.lock().unwrap().push(event); }
 
-        #[derive(Hash, PartialEq, Eq)]
-        struct KeyDropCounter(usize);
+        fn take(&self) -> Vec<String> { std::mem::take(&mut *self.events.lock().unwrap()) }
+    }
 
-        impl Drop for KeyDropCounter {
-            fn drop(&mut self) { DROP_COUNT.fetch_add(1, Ordering::SeqCst); }
-        }
+    impl CacheObserver<&'static str, u32> for Recorder {
+        fn on_hit(&self, key: &&'static str) { self.record(format!("hit {}", key)); }
 
-        let n = 100;
-        for _ in 0..n {
-            let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+        fn on_miss(&self) { self.record("miss".to_string()); }
 
-            for i in 0..100 {
-                cache.put(KeyDropCounter(i), i);
-                cache.pop(&KeyDropCounter(i));
-            }
+        fn on_insert(&self, key: &&'static str, size: usize) { self.record(format!("insert {} {}", key, size)); }
+
+        fn on_evict(&self, key: &&'static str, value: &u32, cause: EvictionCause) {
+            self.record(format!("evict {}={} {:?}", key, value, cause));
         }
+    }
 
-        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), n * n * 2);
+    #[test]
+    fn test_observer_call_sequence() {
+        let clock = ManualClock::leaked();
+        let recorder = Arc::new(Recorder::default());
+        let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+        cache.set_clock(clock);
+        cache.set_observer(recorder.clone());
+
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.get(&"a");
+        cache.get(&"z");
+        cache.put("c", 3);
+        cache.put("a", 10);
+        cache.peek(&"a");
+        cache.contains(&"c");
+        assert_eq!(recorder.take(), ["insert a 4", "insert b 4", "hit a", "miss", "evict b=2 Capacity", "insert c 4", "insert a 4"]);
+
+        cache.get_or_insert("a", || 0);
+        cache.get_or_insert("d", || 4);
+        cache.put_with_ttl("e", 5, Duration::from_secs(1));
+        clock.advance(Duration::from_secs(2));
+        cache.get_mut(&"e");
+        cache.resize(NonZeroUsize::new(1).unwrap());
+        cache.pop(&"d");
+        assert_eq!(
+            recorder.take(),
+            ["hit a", "miss", "evict c=3 Capacity", "insert d 4", "evict a=10 Capacity", "insert e 4", "evict e=5 Expired", "miss"]
+        );
+        // explicit removals are not evictions
+        assert!(recorder.take().is_empty());
     }
 
     #[test]
-    fn test_promote_and_demote() {
-        let mut cache = LRUCache::new(NonZeroUsize::new(5).unwrap());
-        for i in 0..5 {
-            cache.push(i, i);
+    fn test_counters_observer() {
+        let counters = Arc::new(CacheCounters::default());
+        let mut cache = LRUCache::new(NonZeroUsize::new(1).unwrap());
+        cache.set_observer(counters.clone());
+        cache.put(1, 1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.put(2, 2);
+        assert_eq!((counters.hits(), counters.misses(), counters.inserts(), counters.evictions()), (1, 1, 2, 1));
+    }
+
+    // A rough throughput check, run with `cargo test --release -- --ignored --nocapture
+    // test_put_get_throughput`: a million puts into a cache holding a tenth of them, then a
+    // million gets, half of them misses.
+    #[test]
+    #[ignore]
+    fn test_put_get_throughput() {
+        const N: u64 = 1_000_000;
+        let mut cache = LRUCache::new(NonZeroUsize::new(N as usize / 10).unwrap());
+        let started = std::time::Instant::now();
+        for i in 0..N {
+            cache.put(i.wrapping_mul(0x9e37_79b9_7f4a_7c15), i);
         }
-        cache.promote(&1);
-        cache.promote(&0);
-        cache.demote(&3);
-        cache.demote(&4);
-        assert_eq!(cache.pop_last(), Some((4, 4)));
-        assert_eq!(cache.pop_last(), Some((3, 3)));
-        assert_eq!(cache.pop_last(), Some((2, 2)));
-        assert_eq!(cache.pop_last(), Some((1, 1)));
-        assert_eq!(cache.pop_last(), Some((0, 0)));
-        assert_eq!(cache.pop_last(), None);
+        let puts = started.elapsed();
+        let started = std::time::Instant::now();
+        let mut hits = 0;
+        for i in 0..N {
+            let key = (N - N / 20 - 1 - i % (N / 10)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            hits += cache.get(&key).is_some() as u64;
+        }
+        let gets = started.elapsed();
+        assert_eq!(hits, N / 2);
+        println!("1M puts: {puts:?}, 1M gets: {gets:?}");
     }
 }
@@ -1,5 +1,24 @@
-use crate::lru::cache::Cache;
-
+pub mod builder;
 pub mod cache;
+#[cfg(feature = "std")]
+pub mod chunked_bytes;
+pub mod clock;
+pub mod clock_cache;
+pub mod dump;
+pub mod error;
+pub mod fifo_cache;
+pub mod in_use;
+pub mod item_size;
+pub mod lfu_cache;
+pub(crate) mod limiter;
 pub mod lru_cache;
-mod item_size;
\ No newline at end of file
+pub mod observer;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "std")]
+pub mod sharded_cache;
+pub mod slru_cache;
+#[cfg(feature = "std")]
+pub mod sync_cache;
+pub(crate) mod trace;
+pub mod ttl_cache;
\ No newline at end of file
@@ -0,0 +1,76 @@
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Why an entry left the cache without being asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionCause {
+    /// Made room for a new entry, or no longer fit after a `resize`.
+    Capacity,
+    /// Its TTL passed.
+    Expired,
+}
+
+/// Instrumentation called by `LRUCache` as it serves requests. Every method does nothing by
+/// default. Hooks run while the cache is being mutated, so they must not call back into it.
+pub trait CacheObserver<K, V>: Send + Sync {
+    /// A lookup found `key`.
+    fn on_hit(&self, _key: &K) {}
+
+    /// A lookup found nothing. The key is not passed because lookups may use any borrowed
+    /// form of it.
+    fn on_miss(&self) {}
+
+    /// `key` was stored or overwritten with a value of `size` (per `ItemSize`).
+    fn on_insert(&self, _key: &K, _size: usize) {}
+
+    /// The cache dropped an entry on its own.
+    fn on_evict(&self, _key: &K, _value: &V, _cause: EvictionCause) {}
+}
+
+/// Counts kept by every `LRUCache`, see `LRUCache::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups that found their key.
+    pub hits: u64,
+    /// Lookups that found nothing, expired entries included.
+    pub misses: u64,
+    /// Values stored, new keys and overwrites alike.
+    pub inserts: u64,
+    /// Entries dropped to make room, or that no longer fit after a `resize`.
+    pub evictions: u64,
+    /// Entries dropped because their TTL passed or they sat idle for too long.
+    pub expirations: u64,
+}
+
+/// Hit, miss, insert and eviction counts. Keep an `Arc` to read them and hand a clone to
+/// `LRUCache::set_observer`. Needs 64-bit atomics.
+#[cfg(target_has_atomic = "64")]
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl CacheCounters {
+    pub fn hits(&self) -> u64 { self.hits.load(Ordering::Relaxed) }
+
+    pub fn misses(&self) -> u64 { self.misses.load(Ordering::Relaxed) }
+
+    pub fn inserts(&self) -> u64 { self.inserts.load(Ordering::Relaxed) }
+
+    pub fn evictions(&self) -> u64 { self.evictions.load(Ordering::Relaxed) }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl<K, V> CacheObserver<K, V> for CacheCounters {
+    fn on_hit(&self, _key: &K) { self.hits.fetch_add(1, Ordering::Relaxed); }
+
+    fn on_miss(&self) { self.misses.fetch_add(1, Ordering::Relaxed); }
+
+    fn on_insert(&self, _key: &K, _size: usize) { self.inserts.fetch_add(1, Ordering::Relaxed); }
+
+    fn on_evict(&self, _key: &K, _value: &V, _cause: EvictionCause) { self.evictions.fetch_add(1, Ordering::Relaxed); }
+}
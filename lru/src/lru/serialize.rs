@@ -0,0 +1,133 @@
+//! `Serialize` and `Deserialize` for `LRUCache`, compiled in with the `serde` feature.
+//!
+//! A cache is written as its mode, its capacity (`null` when unbounded) and its entries as
+//! `[key, value]` pairs, most recently used first. Reading it back puts the entries least
+//! recently used first, so the restored cache has the same recency order. TTLs, idle times
+//! and the settings made through setters are not part of the snapshot.
+
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+use core::num::NonZeroUsize;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::lru::cache::Cache;
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+
+#[derive(Serialize)]
+struct SnapshotRef<'a, K, V> {
+    mode: &'a CacheMode,
+    capacity: Option<usize>,
+    entries: Vec<(&'a K, &'a V)>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot<K, V> {
+    mode: CacheMode,
+    capacity: Option<usize>,
+    entries: Vec<(K, V)>,
+}
+
+impl<K, V, S> Serialize for LRUCache<K, V, S>
+where
+    K: Serialize + Hash + Eq,
+    V: Serialize + ItemSize,
+    S: BuildHasher,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mode = self.cache_mode();
+        let capacity = match mode {
+            CacheMode::UnLimit => None,
+            _ => Some(self.cap().get()),
+        };
+        SnapshotRef { mode, capacity, entries: self.iter().collect() }.serialize(serializer)
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for LRUCache<K, V, S>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de> + ItemSize,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Snapshot { mode, capacity, entries } = Snapshot::deserialize(deserializer)?;
+        let cap = || capacity.and_then(NonZeroUsize::new).ok_or_else(|| D::Error::custom("a bounded cache needs a positive capacity"));
+        // the map is sized after the entries, never after a capacity read from the input
+        let mut cache = match mode {
+            CacheMode::UnLimit => LRUCache::unbounded_with_hasher(mode, S::default()),
+            CacheMode::StoreLimit => LRUCache::storage_with_hasher(cap()?, S::default()),
+            CacheMode::ItemLimit => LRUCache::try_with_hasher(mode, cap()?.get(), S::default()).map_err(D::Error::custom)?,
+        };
+        cache.try_reserve(entries.len()).map_err(D::Error::custom)?;
+        cache.extend(entries.into_iter().rev());
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    fn round_trip(cache: &LRUCache<String, u32>) -> LRUCache<String, u32> {
+        serde_json::from_str(&serde_json::to_string(cache).unwrap()).unwrap()
+    }
+
+    fn drain_order(mut cache: LRUCache<String, u32>) -> Vec<(String, u32)> { std::iter::from_fn(|| cache.pop_last()).collect() }
+
+    #[test]
+    fn test_round_trip_keeps_order_and_capacity() {
+        let mut cache = LRUCache::new(NonZeroUsize::new(3).unwrap());
+        for (k, v) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+            cache.put(k.to_string(), v);
+        }
+        cache.get("b");
+        assert_eq!(serde_json::to_string(&cache).unwrap(), r#"{"mode":"item_limit","capacity":3,"entries":[["b",2],["d",4],["c",3]]}"#);
+
+        let mut restored = round_trip(&cache);
+        assert_eq!(restored.cap(), cache.cap());
+        assert!(restored.iter().eq(cache.iter()));
+        restored.put("e".to_string(), 5);
+        assert!(!restored.contains("c"));
+        assert_eq!(drain_order(round_trip(&cache)), drain_order(cache));
+    }
+
+    #[test]
+    fn test_round_trip_unbounded() {
+        let mut cache = LRUCache::unbounded();
+        for i in 0..100 {
+            cache.put(i.to_string(), i);
+        }
+        cache.promote("10");
+        let json = serde_json::to_value(&cache).unwrap();
+        assert_eq!(json["capacity"], serde_json::Value::Null);
+
+        let restored = round_trip(&cache);
+        assert_eq!(restored.cap(), cache.cap());
+        assert!(restored.iter().eq(cache.iter()));
+        assert_eq!(drain_order(restored), drain_order(cache));
+    }
+
+    #[test]
+    fn test_bounded_snapshot_needs_a_capacity() {
+        let res = serde_json::from_str::<LRUCache<String, u32>>(r#"{"mode":"item_limit","capacity":0,"entries":[]}"#);
+        assert!(res.unwrap_err().to_string().contains("positive capacity"));
+    }
+
+    #[test]
+    fn test_huge_capacity_is_not_allocated() {
+        let json = r#"{"mode":"store_limit","capacity":18446744073709551615,"entries":[["a",1]]}"#;
+        let mut restored: LRUCache<String, u32> = serde_json::from_str(json).unwrap();
+        assert_eq!(restored.cap().get(), usize::MAX);
+        assert_eq!(restored.get("a"), Some(&1));
+
+        // an item cap is a number of keys the map is sized for: too many is an error, not a panic
+        let json = r#"{"mode":"item_limit","capacity":18446744073709551615,"entries":[]}"#;
+        assert!(serde_json::from_str::<LRUCache<String, u32>>(json).is_err());
+    }
+}
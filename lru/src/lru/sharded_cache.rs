@@ -0,0 +1,208 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+use crate::lru::observer::CacheStats;
+
+/// An LRU cache shared through `&self`, split into shards that each sit behind a `Mutex`, so
+/// that threads working on keys of different shards do not wait for each other. A key always
+/// lives in the shard its hash picks, and each shard evicts its own least recently used entry:
+/// the eviction order is LRU within a shard, not across the whole cache.
+///
+/// The capacity is divided as evenly as it goes: every shard holds `cap / shards` entries and
+/// the first `cap % shards` of them one more, so the shards add up to `cap` exactly. A capacity
+/// below the number of shards makes one shard per entry.
+pub struct ShardedLRUCache<K, V, S = DefaultHasher> {
+    shards: Box<[Mutex<LRUCache<K, V, S>>]>,
+    hasher: S,
+    cap: NonZeroUsize,
+}
+
+impl<K: Hash + Eq, V: ItemSize> ShardedLRUCache<K, V> {
+    /// Creates a cache holding at most `cap` entries, split into `shards`.
+    pub fn new(cap: NonZeroUsize, shards: NonZeroUsize) -> Self {
+        ShardedLRUCache::with_hasher(cap, shards, DefaultHasher::default())
+    }
+}
+
+impl<K, V, S> ShardedLRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher + Clone,
+{
+    /// Like `new`, hashing keys with `hasher`, both to pick a shard and within it.
+    pub fn with_hasher(cap: NonZeroUsize, shards: NonZeroUsize, hasher: S) -> Self {
+        let n = shards.min(cap).get();
+        let shards = (0..n)
+            .map(|i| {
+                let shard_cap = NonZeroUsize::new(shard_share(cap.get(), n, i)).expect("every shard holds an entry");
+                Mutex::new(LRUCache::with_hasher(CacheMode::ItemLimit, shard_cap, hasher.clone()))
+            })
+            .collect();
+        ShardedLRUCache { shards, hasher, cap }
+    }
+
+    /// How many shards the capacity was split into.
+    pub fn shard_count(&self) -> usize { self.shards.len() }
+
+    pub fn cap(&self) -> NonZeroUsize { self.cap }
+
+    /// The number of entries of all shards, each counted under its lock in turn.
+    pub fn len(&self) -> usize { self.shards.iter().map(|shard| lock(shard).len()).sum() }
+
+    pub fn is_empty(&self) -> bool { self.shards.iter().all(|shard| lock(shard).is_empty()) }
+
+    /// Puts `k` in its shard, evicting the shard's least recently used entry if it is full.
+    /// Returns the old value of `k`.
+    pub fn put(&self, k: K, v: V) -> Option<V> { self.shard(&k).put(k, v) }
+
+    /// Returns a clone of the value of `k`, promoting it within its shard.
+    pub fn get<Q>(&self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.shard(k).get(k).cloned()
+    }
+
+    /// Removes `k` and returns its value.
+    pub fn pop<Q>(&self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).pop(k)
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(k).contains(k)
+    }
+
+    /// The counts of all shards added up.
+    pub fn stats(&self) -> CacheStats {
+        self.shards.iter().map(|shard| lock(shard).stats()).fold(CacheStats::default(), |total, stats| CacheStats {
+            hits: total.hits + stats.hits,
+            misses: total.misses + stats.misses,
+            inserts: total.inserts + stats.inserts,
+            evictions: total.evictions + stats.evictions,
+            expirations: total.expirations + stats.expirations,
+        })
+    }
+
+    /// Removes every entry, one shard at a time.
+    pub fn clear(&self) { self.shards.iter().for_each(|shard| lock(shard).clear()); }
+
+    // Locks the shard `k` belongs to.
+    fn shard<Q: Hash + ?Sized>(&self, k: &Q) -> MutexGuard<'_, LRUCache<K, V, S>> {
+        lock(&self.shards[shard_of(&self.hasher, k, self.shards.len())])
+    }
+}
+
+/// The part of `total` shard `i` of `shards` holds: `total / shards`, plus one for each of the
+/// first `total % shards` shards.
+pub fn shard_share(total: usize, shards: usize, i: usize) -> usize { total / shards + usize::from(i < total % shards) }
+
+/// The shard of `shards` that `k` lives in.
+pub fn shard_of<Q: Hash + ?Sized>(hasher: &impl BuildHasher, k: &Q, shards: usize) -> usize {
+    (hasher.hash_one(k) % shards as u64) as usize
+}
+
+// A panic in another thread leaves a shard consistent: every `LRUCache` call either finished or
+// did not start touching the list, so a poisoned lock is used as it is.
+fn lock<T>(shard: &Mutex<T>) -> MutexGuard<'_, T> { shard.lock().unwrap_or_else(|e| e.into_inner()) }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ShardedLRUCache;
+    use crate::lru::cache::Cache;
+
+    fn nz(n: usize) -> NonZeroUsize { NonZeroUsize::new(n).unwrap() }
+
+    #[test]
+    fn test_capacity_split() {
+        let caps = |cache: &ShardedLRUCache<u32, u32>| cache.shards.iter().map(|shard| super::lock(shard).cap().get()).collect::<Vec<_>>();
+        assert_eq!(caps(&ShardedLRUCache::new(nz(10), nz(4))), [3, 3, 2, 2]);
+        assert_eq!(caps(&ShardedLRUCache::new(nz(2), nz(8))), [1, 1]);
+
+        let cache = ShardedLRUCache::new(nz(10), nz(4));
+        for i in 0..1_000 {
+            cache.put(i, i);
+        }
+        assert_eq!(cache.len(), 10);
+        assert_eq!(cache.stats().evictions, 990);
+    }
+
+    #[test]
+    fn test_get_put_pop_through_shared_refs() {
+        let cache = ShardedLRUCache::new(nz(64), nz(8));
+        assert_eq!(cache.put("a".to_string(), 1), None);
+        assert_eq!(cache.put("a".to_string(), 2), Some(1));
+        assert_eq!(cache.get("a"), Some(2));
+        assert_eq!(cache.get("b"), None);
+        assert!(cache.contains("a"));
+        assert_eq!(cache.pop("a"), Some(2));
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.inserts), (1, 1, 2));
+        cache.put("c".to_string(), 3);
+        cache.clear();
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_concurrent_puts_and_gets() {
+        let cap = 100;
+        let cache = Arc::new(ShardedLRUCache::new(nz(cap), nz(8)));
+        let done = Arc::new(AtomicBool::new(false));
+
+        // a watcher checks the bound while the writers run
+        let watcher = {
+            let (cache, done) = (cache.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    assert!(cache.len() <= cap);
+                }
+            })
+        };
+        let writers: Vec<_> = (0..8)
+            .map(|t| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..5_000 {
+                        let key = (t * 5_000 + i) % 700;
+                        if cache.get(&key).is_none() {
+                            cache.put(key, key);
+                        }
+                        if i % 7 == 0 {
+                            cache.pop(&((key + 1) % 700));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        watcher.join().unwrap();
+
+        assert!(cache.len() <= cap);
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 8 * 5_000);
+    }
+}
@@ -0,0 +1,417 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::num::NonZeroUsize;
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+
+/// A segmented LRU cache. New entries go to a probationary segment and move to a protected one
+/// the first time they are hit there; evictions always take the least recently used entry of
+/// probation, so a scan of keys read once cannot push out the protected ones. When protected
+/// is full, its least recently used entry goes back to the front of probation.
+///
+/// In the `Cache` methods, the protected entries come before the probationary ones: `peek_first`
+/// sees the most recently used protected entry, `peek_last` the next to be evicted.
+pub struct SLRUCache<K, V, S = DefaultHasher> {
+    probation: LRUCache<K, V, S>,
+    protected: LRUCache<K, V, S>,
+    cap: NonZeroUsize,
+    protected_ratio: f64,
+    // protected_cap is the part of `cap` protected may take, always leaving room in probation
+    protected_cap: usize,
+}
+
+impl<K: Hash + Eq, V: ItemSize> SLRUCache<K, V> {
+    /// Creates a cache holding at most `cap` entries, up to `protected_ratio` of them protected.
+    ///
+    /// Panics if `protected_ratio` is not in `0.0..1.0`.
+    pub fn new(cap: NonZeroUsize, protected_ratio: f64) -> Self {
+        SLRUCache::with_hasher(cap, protected_ratio, DefaultHasher::default())
+    }
+}
+
+impl<K, V, S> SLRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher + Clone,
+{
+    /// Like `new`, hashing keys with `hasher`.
+    pub fn with_hasher(cap: NonZeroUsize, protected_ratio: f64, hasher: S) -> Self {
+        assert!((0.0..1.0).contains(&protected_ratio), "protected_ratio must be in 0.0..1.0");
+        SLRUCache {
+            probation: LRUCache::unbounded_with_hasher(CacheMode::ItemLimit, hasher.clone()),
+            protected: LRUCache::unbounded_with_hasher(CacheMode::ItemLimit, hasher),
+            cap,
+            protected_ratio,
+            protected_cap: protected_cap(cap, protected_ratio),
+        }
+    }
+
+    /// How many entries the protected segment may hold.
+    pub fn protected_cap(&self) -> usize { self.protected_cap }
+
+    /// Whether `k` is in the protected segment.
+    pub fn is_protected<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.protected.contains(k)
+    }
+
+    /// Entries from the most recently used protected one to the next to be evicted.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> { self.protected.iter().chain(self.probation.iter()) }
+
+    // Counts a hit on `k`: moves it from probation to protected, or to the front of protected.
+    // Returns whether `k` is present.
+    fn hit<Q>(&mut self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.protected.contains(k) {
+            self.protected.promote(k);
+            return true;
+        }
+        if self.protected_cap == 0 {
+            self.probation.promote(k);
+            return self.probation.contains(k);
+        }
+        let Some((k, v)) = self.probation.pop_entry(k) else { return false };
+        self.protected.put(k, v);
+        self.demote_overflow();
+        true
+    }
+
+    // Moves the protected entries past `protected_cap` back to the front of probation.
+    fn demote_overflow(&mut self) {
+        while self.protected.len() > self.protected_cap {
+            let Some((k, v)) = self.protected.pop_last() else { break };
+            self.probation.put(k, v);
+        }
+    }
+
+    // Evicts until `extra` more entries fit.
+    fn evict_for(&mut self, extra: usize) -> Option<(K, V)> {
+        let mut evicted = None;
+        while self.len() + extra > self.cap.get() {
+            evicted = self.pop_last();
+        }
+        evicted
+    }
+
+    fn find_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.protected.contains(k) {
+            true => self.protected.peek_mut(k),
+            false => self.probation.peek_mut(k),
+        }
+    }
+}
+
+fn protected_cap(cap: NonZeroUsize, ratio: f64) -> usize { ((cap.get() as f64 * ratio) as usize).min(cap.get() - 1) }
+
+impl<K, V, S> Cache<K, V, S> for SLRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher + Clone,
+{
+    fn len(&self) -> usize { self.probation.len() + self.protected.len() }
+
+    fn cap(&self) -> NonZeroUsize { self.cap }
+
+    fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Puts `k` in probation, or counts a hit if it was already present and replaces its value.
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        match self.hit(&k) {
+            true => self.find_mut(&k).map(|old| core::mem::replace(old, v)),
+            false => {
+                self.evict_for(1);
+                self.probation.put(k, v);
+                None
+            }
+        }
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        match self.hit(&k) {
+            true => self.find_mut(&k).map(|old| core::mem::replace(old, v)).map(|old| (k, old)),
+            false => {
+                let evicted = self.evict_for(1);
+                self.probation.put(k, v);
+                evicted
+            }
+        }
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(k).map(|(_, v)| v)
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.hit(k) {
+            true => self.peek_key_value(k),
+            false => None,
+        }
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.hit(k) {
+            true => self.find_mut(k),
+            false => None,
+        }
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        if !self.hit(&k) {
+            // the loader runs first so that a failure evicts nothing
+            let v = f(&k)?;
+            self.evict_for(1);
+            return self.probation.lookup_or_insert(k, |_| Ok(v), false);
+        }
+        // the hit left the key in one of the segments, where it is found
+        let segment = match self.protected.contains(&k) {
+            true => &mut self.protected,
+            false => &mut self.probation,
+        };
+        segment.lookup_or_insert(k, f, false)
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek_key_value(k).map(|(_, v)| v)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.protected.peek_key_value(k).or_else(|| self.probation.peek_key_value(k))
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter().map(|k| self.peek(k)).collect()
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_mut(k)
+    }
+
+    /// The next entry to be evicted, the least recently used of probation if it has any.
+    fn peek_last(&self) -> Option<(&K, &V)> { self.probation.peek_last().or_else(|| self.protected.peek_last()) }
+
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> {
+        match self.probation.is_empty() {
+            true => self.protected.peek_last_mut(),
+            false => self.probation.peek_last_mut(),
+        }
+    }
+
+    /// The most recently used protected entry, or of probation if none is protected.
+    fn peek_first(&self) -> Option<(&K, &V)> { self.protected.peek_first().or_else(|| self.probation.peek_first()) }
+
+    fn first_key(&self) -> Option<&K> { self.peek_first().map(|(k, _)| k) }
+
+    fn last_key(&self) -> Option<&K> { self.peek_last().map(|(k, _)| k) }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.protected.contains(k) || self.probation.contains(k)
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.pop_entry(k).map(|(_, v)| v)
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.protected.pop_entry(k).or_else(|| self.probation.pop_entry(k))
+    }
+
+    /// Evicts the least recently used entry of probation, or of protected once probation is
+    /// empty.
+    fn pop_last(&mut self) -> Option<(K, V)> { self.probation.pop_last().or_else(|| self.protected.pop_last()) }
+
+    fn pop_first(&mut self) -> Option<(K, V)> { self.protected.pop_first().or_else(|| self.probation.pop_first()) }
+
+    /// Counts a hit on `k`, like `get`.
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.hit(k);
+    }
+
+    /// Moves `k` to the back of probation, making it the next to be evicted.
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some((k, v)) = self.protected.pop_entry(k) {
+            self.probation.put(k, v);
+        }
+        self.probation.demote(k);
+    }
+
+    /// Changes the capacity, keeping the segment ratio. Protected entries that no longer fit go
+    /// back to probation, then the least recently used ones of probation are evicted.
+    fn resize(&mut self, cap: NonZeroUsize) {
+        self.cap = cap;
+        self.protected_cap = protected_cap(cap, self.protected_ratio);
+        self.demote_overflow();
+        self.evict_for(0);
+    }
+
+    fn clear(&mut self) {
+        self.probation.clear();
+        self.protected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::SLRUCache;
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    fn cache(cap: usize, ratio: f64) -> SLRUCache<String, u32> { SLRUCache::new(NonZeroUsize::new(cap).unwrap(), ratio) }
+
+    fn keys(cache: &SLRUCache<String, u32>) -> Vec<&str> { cache.iter().map(|(k, _)| k.as_str()).collect() }
+
+    #[test]
+    fn test_segment_transitions() {
+        let mut cache = cache(4, 0.5);
+        assert_eq!(cache.protected_cap(), 2);
+        for (i, k) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.put(k.to_string(), i as u32);
+        }
+        assert!(!cache.is_protected("a"));
+
+        // a hit in probation protects the key
+        cache.get("a");
+        cache.get("b");
+        assert!(cache.is_protected("a") && cache.is_protected("b"));
+        assert_eq!(keys(&cache), ["b", "a", "d", "c"]);
+
+        // a third one pushes the least recently used protected key back to probation's front
+        cache.get_mut("c");
+        assert!(!cache.is_protected("a"));
+        assert_eq!(keys(&cache), ["c", "b", "a", "d"]);
+
+        // hits in protected only reorder it, peeks nothing
+        cache.get("b");
+        cache.peek("d");
+        assert_eq!(keys(&cache), ["b", "c", "a", "d"]);
+
+        // eviction takes probation's least recently used entry
+        assert_eq!(cache.push("e".to_string(), 4), Some(("d".to_string(), 3)));
+        assert_eq!(cache.push("f".to_string(), 5), Some(("a".to_string(), 0)));
+        assert_eq!(keys(&cache), ["b", "c", "f", "e"]);
+    }
+
+    #[test]
+    fn test_scan_cannot_evict_protected() {
+        let cap = NonZeroUsize::new(4).unwrap();
+        let mut slru = SLRUCache::new(cap, 0.5);
+        let mut lru = LRUCache::new(cap);
+        for k in ["hot1", "hot2"] {
+            slru.put(k.to_string(), 0);
+            slru.get(k);
+            lru.put(k.to_string(), 0);
+            lru.get(k);
+        }
+
+        for i in 0..100 {
+            slru.put(i.to_string(), i);
+            lru.put(i.to_string(), i);
+        }
+        // the scan only cycled through probation
+        assert!(slru.is_protected("hot1") && slru.is_protected("hot2"));
+        assert!(!lru.contains("hot1") && !lru.contains("hot2"));
+    }
+
+    #[test]
+    fn test_promote_demote_and_resize() {
+        let mut cache = cache(4, 0.5);
+        for (i, k) in ["a", "b", "c"].into_iter().enumerate() {
+            cache.put(k.to_string(), i as u32);
+        }
+        cache.promote("a");
+        assert_eq!(cache.put("a".to_string(), 10), Some(0));
+        cache.demote("a");
+        assert!(!cache.is_protected("a"));
+        assert_eq!(cache.last_key().map(String::as_str), Some("a"));
+
+        cache.promote("b");
+        cache.promote("c");
+        cache.resize(NonZeroUsize::new(2).unwrap());
+        assert_eq!(cache.protected_cap(), 1);
+        assert_eq!(keys(&cache), ["c", "b"]);
+        assert_eq!(*cache.get_or_insert("d".to_string(), || 3), 3);
+        assert_eq!(keys(&cache), ["c", "d"]);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_no_protected_segment() {
+        let mut cache = cache(2, 0.0);
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+        cache.get("a");
+        assert!(!cache.is_protected("a"));
+        // plain LRU then
+        cache.put("c".to_string(), 3);
+        assert_eq!(keys(&cache), ["c", "a"]);
+    }
+}
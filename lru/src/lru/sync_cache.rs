@@ -0,0 +1,237 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::LRUCache;
+
+/// An `LRUCache` behind its own lock, to share through `&self` (in an `Arc`, a `static`...)
+/// without wrapping it by hand. Reads promote, so every call takes the lock exclusively; each
+/// one holds it only for that call.
+pub struct SyncLRUCache<K, V, S = DefaultHasher> {
+    inner: Mutex<LRUCache<K, V, S>>,
+    // loads holds a gate per key `get_or_insert_with` is loading, for the callers of the same
+    // key to wait on instead of loading it again
+    loads: Mutex<HashMap<K, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl<K, V, S> SyncLRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// Wraps `cache`, keeping its mode, bound and settings.
+    pub fn new(cache: LRUCache<K, V, S>) -> Self { SyncLRUCache { inner: Mutex::new(cache), loads: Mutex::default() } }
+
+    /// Returns a clone of the value of `k`, promoting it.
+    pub fn get_cloned<Q>(&self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lock().get(k).cloned()
+    }
+
+    /// See `Cache::put`.
+    pub fn put(&self, k: K, v: V) -> Option<V> { self.lock().put(k, v) }
+
+    /// See `Cache::pop`.
+    pub fn pop<Q>(&self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().pop(k)
+    }
+
+    pub fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().contains(k)
+    }
+
+    pub fn len(&self) -> usize { self.lock().len() }
+
+    pub fn is_empty(&self) -> bool { self.lock().is_empty() }
+
+    /// Runs `f` on the cache under the lock, for anything the methods above do not cover or
+    /// that must happen at once.
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut LRUCache<K, V, S>) -> R) -> R { f(&mut self.lock()) }
+
+    /// Returns a clone of the value of `k`, loading it with `f` if it is missing. The lock is
+    /// not held while `f` runs; concurrent callers for the same key wait for the first load
+    /// and take its value rather than loading again. A value put by other means while `f` ran
+    /// wins over the loaded one, which is dropped.
+    pub async fn get_or_insert_with<F, Fut>(&self, k: K, f: F) -> V
+    where
+        K: Clone,
+        V: Clone,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        if let Some(v) = self.get_cloned(&k) {
+            return v;
+        }
+        let gate = self.loads.lock().unwrap_or_else(|e| e.into_inner()).entry(k.clone()).or_default().clone();
+        let load = LoadTurn { loads: &self.loads, key: &k, gate: &gate };
+        let _turn = gate.lock().await;
+        // the load this caller waited for may have put it
+        if let Some(v) = self.get_cloned(&k) {
+            return v;
+        }
+        let v = f().await;
+        let v = self.with_mut(|cache| cache.get_or_insert(k.clone(), || v).clone());
+        drop(load);
+        v
+    }
+
+    /// Takes the cache back.
+    pub fn into_inner(self) -> LRUCache<K, V, S> { self.inner.into_inner().unwrap_or_else(|e| e.into_inner()) }
+
+    // A panic in `with_mut` leaves the cache consistent, every `LRUCache` call having either
+    // finished or not started, so a poisoned lock is used as it is.
+    fn lock(&self) -> MutexGuard<'_, LRUCache<K, V, S>> { self.inner.lock().unwrap_or_else(|e| e.into_inner()) }
+}
+
+// Forgets the gate of a load once it is over, or was dropped halfway, unless a later load
+// already replaced it.
+struct LoadTurn<'a, K: Hash + Eq> {
+    loads: &'a Mutex<HashMap<K, Arc<tokio::sync::Mutex<()>>>>,
+    key: &'a K,
+    gate: &'a Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<K: Hash + Eq> Drop for LoadTurn<'_, K> {
+    fn drop(&mut self) {
+        let mut loads = self.loads.lock().unwrap_or_else(|e| e.into_inner());
+        if loads.get(self.key).is_some_and(|gate| Arc::ptr_eq(gate, self.gate)) {
+            loads.remove(self.key);
+        }
+    }
+}
+
+impl<K, V, S> From<LRUCache<K, V, S>> for SyncLRUCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    fn from(cache: LRUCache<K, V, S>) -> Self { SyncLRUCache::new(cache) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::SyncLRUCache;
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_shared_methods() {
+        assert_send_sync::<SyncLRUCache<String, Vec<u8>>>();
+
+        let cache = SyncLRUCache::new(LRUCache::new(NonZeroUsize::new(2).unwrap()));
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+        assert_eq!(cache.get_cloned("a"), Some(1));
+        cache.put("c".to_string(), 3);
+        assert!(!cache.contains("b"));
+        assert_eq!(cache.pop("a"), Some(1));
+        assert_eq!(cache.len(), 1);
+
+        let keys = cache.with_mut(|cache| {
+            cache.put("d".to_string(), 4);
+            cache.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>()
+        });
+        assert_eq!(keys, ["d", "c"]);
+        assert_eq!(cache.into_inner().peek_first(), Some((&"d".to_string(), &4)));
+    }
+
+    #[test]
+    fn test_hammered_from_threads() {
+        let cap = 50;
+        let cache = Arc::new(SyncLRUCache::from(LRUCache::new(NonZeroUsize::new(cap).unwrap())));
+        let threads: Vec<_> = (0..8u32)
+            .map(|t| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for i in 0..2_000u32 {
+                        let key = (t * 31 + i) % 200;
+                        match i % 4 {
+                            0 => {
+                                cache.put(key, key);
+                            }
+                            1 => assert!(cache.get_cloned(&key).is_none_or(|v| v == key)),
+                            2 => {
+                                cache.pop(&key);
+                            }
+                            _ => assert!(cache.with_mut(|cache| cache.len()) <= cap),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(cache.len() <= cap);
+        assert_eq!(cache.with_mut(|cache| cache.iter().count()), cache.len());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_loads_run_once() {
+        let cache = Arc::new(SyncLRUCache::new(LRUCache::new(NonZeroUsize::new(16).unwrap())));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..32u32)
+            .map(|i| {
+                let (cache, loads) = (cache.clone(), loads.clone());
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert_with(i % 2, || async move {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            i % 2 + 100
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await.unwrap(), i as u32 % 2 + 100);
+        }
+        // one load per key
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert!(cache.loads.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_load_and_put_meanwhile() {
+        let cache = SyncLRUCache::new(LRUCache::new(NonZeroUsize::new(4).unwrap()));
+        let stalled = cache.get_or_insert_with("a", std::future::pending::<u32>);
+        assert!(tokio::time::timeout(Duration::from_millis(10), stalled).await.is_err());
+        assert!(cache.loads.lock().unwrap().is_empty());
+        assert_eq!(cache.get_or_insert_with("a", || async { 1 }).await, 1);
+
+        // a put while the loader runs wins
+        let loaded = cache.get_or_insert_with("b", || async {
+            cache.put("b", 2);
+            3
+        });
+        assert_eq!(loaded.await, 2);
+        assert_eq!(cache.get_or_insert_with("b", || async { 4 }).await, 2);
+    }
+}
@@ -0,0 +1,141 @@
+//! Trace spans around the cache operations, compiled in with the `tracing` feature.
+//!
+//! `op_span!("lru.put")` opens a `TRACE` span that stays entered until the returned `OpSpan` is
+//! dropped, which records `elapsed_us`. Spans opened while a request is handled nest under the
+//! HTTP request span. Without the feature `OpSpan` is a unit struct and every call is a no-op.
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// The entered span of one cache operation.
+#[cfg(feature = "tracing")]
+pub(crate) struct OpSpan {
+    span: tracing::span::EnteredSpan,
+    started: Instant,
+}
+
+/// The entered span of one cache operation.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct OpSpan;
+
+// the disabled span must cost nothing to carry around
+#[cfg(not(feature = "tracing"))]
+const _: () = assert!(core::mem::size_of::<OpSpan>() == 0);
+
+#[cfg(feature = "tracing")]
+impl OpSpan {
+    pub(crate) fn enter(span: tracing::Span) -> Self { OpSpan { span: span.entered(), started: Instant::now() } }
+
+    /// Bytes (or items, outside capacity mode) of the value written or read.
+    pub(crate) fn value_size(&self, size: usize) { self.span.record("value_size", size); }
+
+    /// Entries evicted or removed by the operation.
+    pub(crate) fn evictions(&self, count: usize) { self.span.record("evictions", count); }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl OpSpan {
+    #[inline(always)]
+    pub(crate) fn value_size(&self, _: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn evictions(&self, _: usize) {}
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for OpSpan {
+    fn drop(&mut self) { self.span.record("elapsed_us", self.started.elapsed().as_micros() as u64); }
+}
+
+/// Opens the span of a cache operation, see the module docs.
+#[cfg(feature = "tracing")]
+macro_rules! op_span {
+    ($name:literal) => {
+        $crate::lru::trace::OpSpan::enter(tracing::trace_span!(
+            $name,
+            value_size = tracing::field::Empty,
+            evictions = tracing::field::Empty,
+            elapsed_us = tracing::field::Empty
+        ))
+    };
+}
+
+/// Opens the span of a cache operation, see the module docs.
+#[cfg(not(feature = "tracing"))]
+macro_rules! op_span {
+    ($name:literal) => {
+        $crate::lru::trace::OpSpan
+    };
+}
+
+pub(crate) use op_span;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    use crate::lru::cache::Cache;
+    use crate::lru::lru_cache::LRUCache;
+
+    #[derive(Debug, Default)]
+    struct Recorded {
+        name: &'static str,
+        parent: Option<&'static str>,
+        fields: BTreeMap<&'static str, String>,
+    }
+
+    impl Visit for Recorded {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) { self.fields.insert(field.name(), format!("{:?}", value)); }
+    }
+
+    // keeps every span with its parent and the fields recorded on it
+    #[derive(Clone, Default)]
+    struct Collector(Arc<Mutex<Vec<(Id, Recorded)>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Collector {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let parent = ctx.span(id).and_then(|span| span.parent()).map(|parent| parent.name());
+            let mut recorded = Recorded { name: attrs.metadata().name(), parent, ..Default::default() };
+            attrs.record(&mut recorded);
+            self.0.lock().unwrap().push((id.clone(), recorded));
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            if let Some((_, recorded)) = self.0.lock().unwrap().iter_mut().rev().find(|(span, _)| span == id) {
+                values.record(recorded);
+            }
+        }
+    }
+
+    #[test]
+    fn test_put_span_records_evictions_under_the_request_span() {
+        let collector = Collector::default();
+        let subscriber = tracing_subscriber::registry().with(collector.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let mut cache = LRUCache::new(NonZeroUsize::new(2).unwrap());
+            let _request = tracing::info_span!("request").entered();
+            cache.put("a", 1u32);
+            cache.put("b", 2u32);
+            cache.put("c", 3u32);
+        });
+
+        let spans = collector.0.lock().unwrap();
+        let puts: Vec<_> = spans.iter().map(|(_, span)| span).filter(|span| span.name == "lru.put").collect();
+        assert_eq!(puts.len(), 3);
+        assert!(puts.iter().all(|put| put.parent == Some("request")));
+        assert_eq!(puts[1].fields["evictions"], "0");
+        assert_eq!(puts[2].fields["evictions"], "1");
+        assert_eq!(puts[2].fields["value_size"], "4");
+        assert!(puts[2].fields.contains_key("elapsed_us"));
+    }
+}
@@ -0,0 +1,420 @@
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::num::NonZeroUsize;
+use core::time::Duration;
+
+use crate::lru::cache::{Cache, DefaultHasher, KeyRef};
+use crate::lru::error::CacheError;
+use crate::lru::clock::Clock;
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::{CacheMode, LRUCache};
+
+/// A cache whose entries live `ttl` from the time they were put, however often they are read.
+/// Reads do not reorder anything; the oldest entry is the next to expire and, once `resize`
+/// bounded the cache, the next to be evicted. Expired entries are misses and are purged by
+/// every call taking `&mut self`, unless `keep_purged` asked for them.
+///
+/// The HTTP server's `ttl` cache mode indexes its keys with one, built with `ttl_seconds`.
+pub struct TTLCache<K, V, S = DefaultHasher> {
+    // entries run from the most recently put to the oldest, all with the same TTL, so expired
+    // ones are always at the back
+    inner: LRUCache<K, V, S>,
+    ttl: Duration,
+    // the purged entries waiting for `take_purged`, once `keep_purged` was called
+    purged: Option<Vec<(K, V)>>,
+}
+
+impl<K: Hash + Eq, V: ItemSize> TTLCache<K, V> {
+    /// Creates an unbounded cache whose entries expire `ttl` after they were put.
+    pub fn new(ttl: Duration) -> Self { TTLCache::with_hasher(ttl, DefaultHasher::default()) }
+}
+
+impl<K, V, S> TTLCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// Like `new`, hashing keys with `hasher`.
+    pub fn with_hasher(ttl: Duration, hasher: S) -> Self {
+        let mut inner = LRUCache::unbounded_with_hasher(CacheMode::UnLimit, hasher);
+        inner.set_default_ttl(Some(ttl));
+        TTLCache { inner, ttl, purged: None }
+    }
+
+    /// How long entries live.
+    pub fn ttl(&self) -> Duration { self.ttl }
+
+    /// Replaces the clock deciding when entries expire.
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) { self.inner.set_clock(clock); }
+
+    /// Makes every purge keep the expired entries for `take_purged` instead of dropping them,
+    /// for owners that have to release something per entry.
+    pub fn keep_purged(&mut self) { self.purged.get_or_insert_with(Vec::new); }
+
+    /// The entries purged since the last call, oldest first. Always empty without `keep_purged`.
+    pub fn take_purged(&mut self) -> Vec<(K, V)> { self.purged.as_mut().map(core::mem::take).unwrap_or_default() }
+
+    /// Removes every expired entry and returns how many there were.
+    pub fn purge_expired(&mut self) -> usize {
+        let mut purged = 0;
+        while self.inner.peek_last().is_some_and(|(k, _)| self.expired(k)) {
+            if let (Some(entry), Some(kept)) = (self.inner.pop_last(), self.purged.as_mut()) {
+                kept.push(entry);
+            }
+            purged += 1;
+        }
+        purged
+    }
+
+    /// Returns how long `k` has left, or `None` if it is missing or expired.
+    pub fn time_to_live<Q>(&self, k: &Q) -> Option<Duration>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.ttl(k)
+    }
+
+    /// Like `resize`, but returns the entries that no longer fit, oldest first.
+    pub fn resize_with_evicted(&mut self, cap: NonZeroUsize) -> Vec<(K, V)> {
+        self.purge_expired();
+        self.inner.resize_with_evicted(cap)
+    }
+
+    /// See `LRUCache::try_reserve`.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), CacheError> { self.inner.try_reserve(additional) }
+
+    /// Entries from the most recently put to the oldest, expired ones included.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> { self.inner.iter() }
+
+    fn expired(&self, k: &K) -> bool { self.inner.ttl(k).is_none() }
+
+    fn live(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> { self.inner.iter().filter(|(k, _)| !self.expired(k)) }
+}
+
+impl<K, V, S> Cache<K, V, S> for TTLCache<K, V, S>
+where
+    K: Hash + Eq,
+    V: ItemSize,
+    S: BuildHasher,
+{
+    /// The number of unexpired entries. Calls taking `&mut self` purge the expired ones; the
+    /// ones left since are not counted.
+    fn len(&self) -> usize { self.inner.len() - self.inner.iter().rev().take_while(|(k, _)| self.expired(k)).count() }
+
+    fn cap(&self) -> NonZeroUsize { self.inner.cap() }
+
+    fn limit(&self) -> Option<NonZeroUsize> { self.inner.limit() }
+
+    fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Puts `k` to expire `ttl` from now, moving it to the front if it was already present.
+    fn put(&mut self, k: K, v: V) -> Option<V> {
+        self.purge_expired();
+        self.inner.put(k, v)
+    }
+
+    fn push(&mut self, k: K, v: V) -> Option<(K, V)> {
+        self.purge_expired();
+        self.inner.push(k, v)
+    }
+
+    fn get<'a, Q>(&'a mut self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.peek(k)
+    }
+
+    fn get_key_value<'a, Q>(&'a mut self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.peek_key_value(k)
+    }
+
+    fn get_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.peek_mut(k)
+    }
+
+    fn try_get_or_insert_with_key_mut<F, E>(&'_ mut self, k: K, f: F) -> Result<&'_ mut V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        self.purge_expired();
+        self.inner.lookup_or_insert(k, f, false)
+    }
+
+    fn peek<'a, Q>(&'a self, k: &Q) -> Option<&'a V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek(k)
+    }
+
+    fn peek_key_value<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_key_value(k)
+    }
+
+    fn peek_many<'a, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + 'a,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        self.inner.peek_many(keys)
+    }
+
+    fn peek_mut<'a, Q>(&'a mut self, k: &Q) -> Option<&'a mut V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.peek_mut(k)
+    }
+
+    /// The oldest unexpired entry, the next to expire.
+    fn peek_last(&self) -> Option<(&K, &V)> { self.live().next_back() }
+
+    /// Like `peek_last`, purging the expired entries first.
+    fn peek_last_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.purge_expired();
+        self.inner.peek_last_mut()
+    }
+
+    /// The unexpired entry put last.
+    fn peek_first(&self) -> Option<(&K, &V)> { self.live().next() }
+
+    fn first_key(&self) -> Option<&K> { self.peek_first().map(|(k, _)| k) }
+
+    fn last_key(&self) -> Option<&K> { self.peek_last().map(|(k, _)| k) }
+
+    fn contains<Q>(&self, k: &Q) -> bool
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.contains(k)
+    }
+
+    fn pop<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.pop(k)
+    }
+
+    fn pop_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.pop_entry(k)
+    }
+
+    /// Removes the oldest unexpired entry.
+    fn pop_last(&mut self) -> Option<(K, V)> {
+        self.purge_expired();
+        self.inner.pop_last()
+    }
+
+    fn pop_first(&mut self) -> Option<(K, V)> {
+        self.purge_expired();
+        self.inner.pop_first()
+    }
+
+    /// Puts `k` again, so that it expires `ttl` from now.
+    fn promote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        if let Some((k, v)) = self.inner.pop_entry(k) {
+            self.inner.put(k, v);
+        }
+    }
+
+    /// Expires `k` at once: entries only leave a `TTLCache` first by expiring.
+    fn demote<Q>(&mut self, k: &Q)
+    where
+        KeyRef<K>: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.purge_expired();
+        self.inner.pop(k);
+    }
+
+    /// Bounds the number of entries, dropping the oldest ones that no longer fit. Later puts
+    /// evict the oldest entry when the cache is full.
+    fn resize(&mut self, cap: NonZeroUsize) {
+        self.purge_expired();
+        self.inner.resize(cap);
+    }
+
+    fn clear(&mut self) { self.inner.clear(); }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use super::TTLCache;
+    use crate::lru::cache::Cache;
+    use crate::lru::clock::ManualClock;
+    use crate::lru::item_size::ItemSize;
+
+    fn cache(clock: &'static ManualClock) -> TTLCache<&'static str, u32> {
+        let mut cache = TTLCache::new(Duration::from_secs(10));
+        cache.set_clock(clock);
+        cache
+    }
+
+    #[test]
+    fn test_entries_expire_by_age() {
+        let clock = ManualClock::leaked();
+        let mut cache = cache(clock);
+        cache.put("a", 1);
+        clock.advance(Duration::from_secs(4));
+        cache.put("b", 2);
+        clock.advance(Duration::from_secs(4));
+
+        // reads neither reorder nor extend anything
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.peek_last(), Some((&"a", &1)));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.len(), 1);
+        assert!(!cache.contains(&"a"));
+        assert_eq!(cache.peek_last(), Some((&"b", &2)));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.iter().count(), 1);
+
+        // putting again starts over
+        cache.put("b", 3);
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get(&"b"), Some(&3));
+        clock.advance(Duration::from_secs(2));
+        assert!(cache.is_empty());
+        assert_eq!(cache.purge_expired(), 1);
+        assert_eq!(cache.purge_expired(), 0);
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let clock = ManualClock::leaked();
+        let mut cache = cache(clock);
+        for (i, k) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            cache.put(k, i as u32);
+            clock.advance(Duration::from_secs(3));
+        }
+        // a, b and c were put 12, 9 and 6 seconds ago
+        clock.advance(Duration::from_secs(1));
+        assert_eq!((cache.len(), cache.iter().count()), (2, 4));
+        assert_eq!(cache.purge_expired(), 2);
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["d", "c"]);
+    }
+
+    #[test]
+    fn test_promote_demote_and_resize() {
+        let clock = ManualClock::leaked();
+        let mut cache = cache(clock);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+        clock.advance(Duration::from_secs(5));
+
+        cache.promote(&"a");
+        cache.demote(&"b");
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["a", "c"]);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.len(), 1);
+
+        cache.put("d", 4);
+        cache.put("e", 5);
+        cache.resize(NonZeroUsize::new(2).unwrap());
+        assert_eq!(cache.iter().map(|(k, _)| *k).collect::<Vec<_>>(), ["e", "d"]);
+        assert_eq!(cache.push("f", 6), Some(("d", 4)));
+        assert_eq!(*cache.get_or_insert("g", || 7), 7);
+        assert_eq!(*cache.get_or_insert("g", || 8), 7);
+        assert_eq!(cache.pop_last(), Some(("f", 6)));
+    }
+
+    #[test]
+    fn test_get_or_insert_reloads_expired_entries() {
+        let clock = ManualClock::leaked();
+        let mut cache = cache(clock);
+        cache.put("a", 1);
+        clock.advance(Duration::from_secs(10));
+
+        assert_eq!(*cache.get_or_insert_with_key("a", |k| k.len() as u32 + 1), 2);
+        assert_eq!(cache.try_get_or_insert("a", || Err("down")), Ok(&2));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.try_get_or_insert_mut("a", || Err("down")), Err("down"));
+        assert!(cache.is_empty());
+        // the reloaded entry lives a full `ttl` again
+        assert_eq!(*cache.get_or_insert_with_key_mut("a", |_| 3), 3);
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(cache.get(&"a"), Some(&3));
+    }
+
+    #[test]
+    fn test_keep_purged() {
+        let clock = ManualClock::leaked();
+        let mut cache = cache(clock);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        clock.advance(Duration::from_secs(10));
+        cache.put("c", 3);
+        assert_eq!(cache.take_purged(), []);
+
+        cache.keep_purged();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.get(&"c"), None);
+        cache.put("d", 4);
+        assert_eq!(cache.take_purged(), [("c", 3)]);
+        assert_eq!(cache.take_purged(), []);
+        assert_eq!(cache.time_to_live(&"d"), Some(Duration::from_secs(10)));
+        assert_eq!(cache.time_to_live(&"c"), None);
+    }
+
+    #[test]
+    fn test_usable_as_a_cache() {
+        fn fill<C: Cache<String, u32>>(cache: &mut C) -> Option<u32> {
+            for i in 0..5 {
+                cache.put(i.to_string(), i);
+            }
+            *cache.get_or_insert_mut("2".to_string(), || 0) += 10;
+            cache.get("2").copied()
+        }
+
+        let clock = ManualClock::leaked();
+        let mut cache = TTLCache::new(Duration::from_secs(1));
+        cache.set_clock(clock);
+        assert_eq!(fill(&mut cache), Some(12));
+        assert_eq!(cache.peek_first().map(|(k, v)| (k.as_str(), v.size_of())), Some(("4", 4)));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(fill(&mut cache), Some(12));
+        assert_eq!(cache.len(), 5);
+    }
+}
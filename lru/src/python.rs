@@ -0,0 +1,186 @@
+//! Python bindings (feature `python`), built with maturin as the `see_lru` module.
+//!
+//! Keys and values are `bytes` or `str` and come back with the type they went in with.
+//! The cache sits behind a mutex so that the GIL can be released while it is locked.
+
+use crate::lru::cache::Cache;
+use crate::lru::item_size::ItemSize;
+use crate::lru::lru_cache::LRUCache;
+use crate::lru::observer::CacheCounters;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Item {
+    Bytes(Vec<u8>),
+    Str(String),
+}
+
+impl Item {
+    fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Item> {
+        if let Ok(b) = obj.cast::<PyBytes>() {
+            Ok(Item::Bytes(b.as_bytes().to_vec()))
+        } else if let Ok(s) = obj.cast::<PyString>() {
+            Ok(Item::Str(s.to_str()?.to_string()))
+        } else {
+            Err(PyTypeError::new_err("keys and values must be bytes or str"))
+        }
+    }
+
+    fn to_py<'py>(&self, py: Python<'py>) -> Bound<'py, PyAny> {
+        match self {
+            Item::Bytes(b) => PyBytes::new(py, b).into_any(),
+            Item::Str(s) => PyString::new(py, s).into_any(),
+        }
+    }
+}
+
+impl ItemSize for Item {
+    fn size_of(&self) -> usize {
+        match self {
+            Item::Bytes(b) => b.len(),
+            Item::Str(s) => s.len(),
+        }
+    }
+}
+
+// The index never evicts on its own: both limits are enforced here so that the byte count
+// covers keys as well as values.
+struct Inner {
+    cache: LRUCache<Item, Item>,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    bytes: usize,
+    // hits and misses, counted by the cache itself
+    counters: Arc<CacheCounters>,
+    evictions: u64,
+}
+
+impl Inner {
+    fn put(&mut self, key: Item, value: Item) -> Result<(), String> {
+        let size = key.size_of() + value.size_of();
+        if let Some(max) = self.max_bytes.filter(|&max| size > max) {
+            return Err(format!("entry of {} bytes exceeds max_bytes={}", size, max));
+        }
+        if let Some(old) = self.cache.pop(&key) {
+            self.bytes -= key.size_of() + old.size_of();
+        }
+        self.cache.put(key, value);
+        self.bytes += size;
+        while self.max_entries.is_some_and(|max| self.cache.len() > max) || self.max_bytes.is_some_and(|max| self.bytes > max) {
+            match self.cache.pop_last() {
+                Some((k, v)) => {
+                    self.bytes -= k.size_of() + v.size_of();
+                    self.evictions += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&mut self, key: &Item) -> Option<Item> { self.cache.get(key).cloned() }
+
+    fn pop(&mut self, key: &Item) -> Option<Item> {
+        let value = self.cache.pop(key)?;
+        self.bytes -= key.size_of() + value.size_of();
+        Some(value)
+    }
+
+    fn clear(&mut self) {
+        while self.cache.pop_last().is_some() {}
+        self.bytes = 0;
+    }
+}
+
+/// An LRU cache bounded by entry count, total bytes of keys and values, or both.
+#[pyclass(name = "LRUCache", module = "see_lru")]
+pub struct PyLruCache {
+    inner: Mutex<Inner>,
+}
+
+impl PyLruCache {
+    fn lock(&self) -> MutexGuard<'_, Inner> { self.inner.lock().unwrap_or_else(|e| e.into_inner()) }
+}
+
+#[pymethods]
+impl PyLruCache {
+    #[new]
+    #[pyo3(signature = (max_entries = None, max_bytes = None))]
+    fn new(max_entries: Option<usize>, max_bytes: Option<usize>) -> PyResult<Self> {
+        if max_entries == Some(0) || max_bytes == Some(0) {
+            return Err(PyValueError::new_err("limits must be positive"));
+        }
+        let counters = Arc::new(CacheCounters::default());
+        let mut cache = LRUCache::unbounded();
+        cache.set_observer(counters.clone());
+        let inner = Inner {
+            cache,
+            max_entries,
+            max_bytes,
+            bytes: 0,
+            counters,
+            evictions: 0,
+        };
+        Ok(PyLruCache { inner: Mutex::new(inner) })
+    }
+
+    /// Stores `value` under `key`, evicting least recently used entries to stay within limits.
+    fn put(&self, py: Python<'_>, key: &Bound<'_, PyAny>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let (key, value) = (Item::extract(key)?, Item::extract(value)?);
+        py.detach(|| self.lock().put(key, value)).map_err(PyValueError::new_err)
+    }
+
+    /// Returns the value of `key`, marking it most recently used, or `default`.
+    #[pyo3(signature = (key, default = None))]
+    fn get<'py>(&self, py: Python<'py>, key: &Bound<'py, PyAny>, default: Option<Bound<'py, PyAny>>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let key = Item::extract(key)?;
+        let value = py.detach(|| self.lock().get(&key));
+        Ok(value.map(|v| v.to_py(py)).or(default))
+    }
+
+    /// Removes `key` and returns its value, or `default`.
+    #[pyo3(signature = (key, default = None))]
+    fn pop<'py>(&self, py: Python<'py>, key: &Bound<'py, PyAny>, default: Option<Bound<'py, PyAny>>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let key = Item::extract(key)?;
+        let value = py.detach(|| self.lock().pop(&key));
+        Ok(value.map(|v| v.to_py(py)).or(default))
+    }
+
+    /// Membership test; does not change the recency order.
+    fn __contains__(&self, key: &Bound<'_, PyAny>) -> PyResult<bool> {
+        let key = Item::extract(key)?;
+        Ok(self.lock().cache.contains(&key))
+    }
+
+    fn __len__(&self) -> usize { self.lock().cache.len() }
+
+    /// Keys from most to least recently used.
+    fn keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let inner = self.lock();
+        PyList::new(py, inner.cache.iter().map(|(k, _)| k.to_py(py)))
+    }
+
+    fn clear(&self, py: Python<'_>) { py.detach(|| self.lock().clear()) }
+
+    /// Counters and sizes as a dict.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let inner = self.lock();
+        let stats = PyDict::new(py);
+        stats.set_item("entries", inner.cache.len())?;
+        stats.set_item("bytes", inner.bytes)?;
+        stats.set_item("hits", inner.counters.hits())?;
+        stats.set_item("misses", inner.counters.misses())?;
+        stats.set_item("evictions", inner.evictions)?;
+        stats.set_item("max_entries", inner.max_entries)?;
+        stats.set_item("max_bytes", inner.max_bytes)?;
+        Ok(stats)
+    }
+}
+
+#[pymodule]
+fn see_lru(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyLruCache>()
+}
@@ -0,0 +1,501 @@
+use crate::http::access_log::DEFAULT_ACCESS_LOG_FORMAT;
+use crate::http::stats::DEFAULT_SIZE_BUCKETS;
+use crate::http::store::{OnLimit, PrefixQuota};
+use crate::lru::chunked_bytes::DEFAULT_CHUNK_SIZE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Placeholder written in place of secret values.
+pub const REDACTED: &str = "***";
+
+/// The values `cache_mode` may take; `default` is item mode.
+pub const CACHE_MODES: &[&str] = &["default", "item", "fifo", "lfu", "capacity", "unlimited", "ttl"];
+
+/// The values the `cache_mode` of a namespace may take. A namespace has `default_ttl_secs`
+/// instead of a ttl mode.
+pub const NAMESPACE_CACHE_MODES: &[&str] = &["default", "item", "fifo", "lfu", "capacity", "unlimited"];
+
+/// Typed server configuration, deserialized from the config file.
+/// Keys missing from the file take the values of `ServerConfig::default()`; unknown keys are
+/// an error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub server_port: u16,
+    /// One of `CACHE_MODES`.
+    pub cache_mode: String,
+    pub cache_size: usize,
+    /// Splits the stores into this many shards by key, each behind its own lock. Every bound,
+    /// `cache_size` and those of the namespaces, is divided across them: a key is evicted when
+    /// its own shard is full. More than one shard does not go with the key limits or prefix
+    /// quotas, which count across the whole store.
+    pub cache_shards: usize,
+    /// Lifetime of every key in `ttl` mode, which needs it, counted from the upload whatever
+    /// the reads. A TTL of the upload's own only expires the key sooner.
+    pub ttl_seconds: Option<u64>,
+    /// Bearer token required on every API route when set.
+    pub api_token: Option<String>,
+    /// HMAC key used to sign and verify pre-signed download URLs.
+    pub signing_key: Option<String>,
+    /// How far past `exp` a pre-signed URL is still accepted, to absorb clock skew.
+    pub presign_clock_skew_secs: u64,
+    /// Upper bound for any entry's TTL, including sliding extensions.
+    pub max_ttl_secs: u64,
+    /// Segment size used to store uploaded values.
+    pub chunk_size_bytes: usize,
+    /// Upper bounds, in bytes, of the value-size histogram buckets.
+    pub size_buckets_bytes: Vec<usize>,
+    /// How long a graceful shutdown waits for in-flight requests by default.
+    pub shutdown_drain_secs: u64,
+    /// Writes one access log line per request when set.
+    pub access_log: bool,
+    /// File the access log is appended to; stdout when unset.
+    pub access_log_path: Option<String>,
+    /// Access log line format, see `LogFormat`.
+    pub access_log_format: String,
+    /// Ceiling on the number of keys across all namespaces.
+    pub max_keys: Option<usize>,
+    /// Ceiling on the number of keys in each namespace (the key prefix before the first `/`).
+    pub max_keys_per_namespace: Option<usize>,
+    /// Whether a write at a key ceiling evicts (`evict`) or is refused (`reject`).
+    pub on_limit: OnLimit,
+    /// Namespaces with a cache of their own, outside `cache_mode`, `cache_size` and the key limits.
+    pub namespaces: BTreeMap<String, NamespaceConfig>,
+    /// Keys not read or written for this long are removed by a background sweep.
+    pub max_idle_secs: Option<u64>,
+    /// Refuse to start unless systemd passed a listening socket, instead of binding `server_port`.
+    pub require_socket_activation: bool,
+    /// `[[prefix_quotas]]` tables bounding the keys that start with a prefix, in every store.
+    pub prefix_quotas: Vec<PrefixQuota>,
+    /// How long a request waits for the store write lock before it is answered `503 LOCK_BUSY`;
+    /// unset waits for as long as it takes.
+    pub lock_wait_ms: Option<u64>,
+    /// The same for requests that only read, usually set longer than `lock_wait_ms`.
+    pub read_lock_wait_ms: Option<u64>,
+    /// Name of the multipart field an upload must carry its value in.
+    pub upload_field_name: String,
+    /// Also serves HTTP/2 over cleartext (h2c, prior knowledge) next to HTTP/1.1.
+    pub http2_enabled: bool,
+    /// Closes an HTTP/1 connection that sends no complete request headers for this long,
+    /// which covers idle keep-alive connections. Unset keeps them open.
+    pub http1_keepalive_timeout_secs: Option<u64>,
+    /// Cap on concurrent streams per HTTP/2 connection; hyper's default when unset.
+    pub http2_max_concurrent_streams: Option<u32>,
+    /// Sets `TCP_NODELAY` on accepted connections.
+    pub tcp_nodelay: bool,
+    /// Checks the config file this often and applies the changes that need no restart (cache
+    /// sizes and namespace TTLs). Off when unset, or when the server was not given the file.
+    pub config_watch_interval_secs: Option<u64>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            server_port: 2345,
+            cache_mode: "default".to_string(),
+            cache_size: 5,
+            cache_shards: 1,
+            ttl_seconds: None,
+            api_token: None,
+            signing_key: None,
+            presign_clock_skew_secs: 30,
+            max_ttl_secs: 7 * 24 * 3600,
+            chunk_size_bytes: DEFAULT_CHUNK_SIZE,
+            size_buckets_bytes: DEFAULT_SIZE_BUCKETS.to_vec(),
+            shutdown_drain_secs: 30,
+            access_log: false,
+            access_log_path: None,
+            access_log_format: DEFAULT_ACCESS_LOG_FORMAT.to_string(),
+            max_keys: None,
+            max_keys_per_namespace: None,
+            on_limit: OnLimit::Evict,
+            namespaces: BTreeMap::new(),
+            max_idle_secs: None,
+            require_socket_activation: false,
+            prefix_quotas: Vec::new(),
+            lock_wait_ms: None,
+            read_lock_wait_ms: None,
+            upload_field_name: "file".to_string(),
+            http2_enabled: false,
+            http1_keepalive_timeout_secs: None,
+            http2_max_concurrent_streams: None,
+            tcp_nodelay: false,
+            config_watch_interval_secs: None,
+        }
+    }
+}
+
+/// A `[namespaces.<name>]` table: the cache holding the keys prefixed with `<name>/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceConfig {
+    /// One of `NAMESPACE_CACHE_MODES`.
+    pub cache_mode: String,
+    /// Maximum number of keys in item, fifo and lfu modes.
+    pub cache_size: usize,
+    /// Byte budget in capacity mode (defaults to `cache_size`); an extra bound in the others.
+    pub cache_bytes: Option<usize>,
+    /// TTL of uploads that do not ask for one.
+    pub default_ttl_secs: Option<u64>,
+    pub hasher: KeyHasher,
+}
+
+impl Default for NamespaceConfig {
+    fn default() -> Self {
+        NamespaceConfig {
+            cache_mode: "item".to_string(),
+            cache_size: 5,
+            cache_bytes: None,
+            default_ttl_secs: None,
+            hasher: KeyHasher::Sip,
+        }
+    }
+}
+
+/// Hash function of a cache's key index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyHasher {
+    /// Randomly keyed SipHash, the only one available so far.
+    #[default]
+    Sip,
+}
+
+impl ServerConfig {
+    /// Refuses a `cache_mode`, of the server or of a namespace, that no store is built for,
+    /// naming its key.
+    pub fn check_cache_modes(&self) -> Result<(), String> {
+        check_cache_mode("cache_mode", &self.cache_mode, CACHE_MODES)?;
+        for (name, ns) in &self.namespaces {
+            check_cache_mode(&format!("namespaces.{}.cache_mode", name), &ns.cache_mode, NAMESPACE_CACHE_MODES)?;
+        }
+        Ok(())
+    }
+
+    /// Refuses a `cache_shards` the stores cannot be split into: none, more than a bound has room
+    /// for, or several along with the key limits or prefix quotas.
+    pub fn check_cache_shards(&self) -> Result<(), String> {
+        let shards = self.cache_shards;
+        if shards == 0 {
+            return Err("cache_shards must be positive".to_string());
+        }
+        if shards == 1 {
+            return Ok(());
+        }
+        if self.max_keys.is_some() || self.max_keys_per_namespace.is_some() || !self.prefix_quotas.is_empty() {
+            return Err("cache_shards: max_keys, max_keys_per_namespace and prefix_quotas need a single shard".to_string());
+        }
+        let fits = |key: String, size: usize| match size >= shards {
+            true => Ok(()),
+            false => Err(format!("{}: {} is less than one per shard of {}", key, size, shards)),
+        };
+        if self.cache_mode != "unlimited" && self.cache_mode != "ttl" {
+            fits("cache_size".to_string(), self.cache_size)?;
+        }
+        for (name, ns) in &self.namespaces {
+            if ns.cache_mode != "unlimited" && !(ns.cache_mode == "capacity" && ns.cache_bytes.is_some()) {
+                fits(format!("namespaces.{}.cache_size", name), ns.cache_size)?;
+            }
+            if let Some(bytes) = ns.cache_bytes {
+                fits(format!("namespaces.{}.cache_bytes", name), bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuses a `ttl` cache mode without a positive `ttl_seconds`.
+    pub fn check_ttl_mode(&self) -> Result<(), String> {
+        match (self.cache_mode.as_str(), self.ttl_seconds) {
+            ("ttl", None | Some(0)) => Err("ttl mode needs a positive ttl_seconds".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses a `chunk_size_bytes` of 0, which no upload could be split into.
+    pub fn check_chunk_size(&self) -> Result<(), String> {
+        match self.chunk_size_bytes {
+            0 => Err("chunk_size_bytes must be positive".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the effective configuration as JSON with every secret value redacted.
+    pub fn redacted(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or(Value::Null);
+        redact(&mut value);
+        value
+    }
+}
+
+fn check_cache_mode(key: &str, mode: &str, known: &[&str]) -> Result<(), String> {
+    match known.contains(&mode) {
+        true => Ok(()),
+        false => Err(format!("{}: unknown mode {:?}, expected one of {}", key, mode, known.join(", "))),
+    }
+}
+
+/// Why a config file could not be loaded. Every variant names the file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io { path: PathBuf, source: std::io::Error },
+    /// The extension is not one of `toml`, `yaml`, `yml` or `json`.
+    UnsupportedFormat { path: PathBuf },
+    /// The file is malformed, has an unknown key or a value of the wrong type.
+    Parse { path: PathBuf, line: Option<usize>, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::UnsupportedFormat { path } => {
+                write!(f, "{}: unsupported config format, expected .toml, .yaml, .yml or .json", path.display())
+            }
+            ConfigError::Parse { path, line: Some(line), message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ConfigError::Parse { path, line: None, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a TOML, YAML or JSON config file, picked by extension, into a `ServerConfig`.
+pub fn load_config(path: impl AsRef<Path>) -> Result<ServerConfig, ConfigError> {
+    let path = path.as_ref();
+    let parse_error = |line, message| ConfigError::Parse { path: path.to_path_buf(), line, message };
+    let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    let extension = match extension.as_deref() {
+        Some(ext @ ("toml" | "yaml" | "yml" | "json")) => ext.to_string(),
+        _ => return Err(ConfigError::UnsupportedFormat { path: path.to_path_buf() }),
+    };
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+
+    match extension.as_str() {
+        "toml" => toml::from_str(&text).map_err(|e| {
+            let line = e.span().map(|span| line_of(&text, span.start));
+            parse_error(line, e.message().to_string())
+        }),
+        "json" => serde_json::from_str(&text).map_err(|e| {
+            let line = (e.line() > 0).then_some(e.line());
+            parse_error(line, without_position(&e.to_string()))
+        }),
+        // an empty YAML document is a config without keys
+        _ if text.trim().is_empty() => Ok(ServerConfig::default()),
+        _ => serde_yaml::from_str(&text).map_err(|e| {
+            let line = e.location().map(|l| l.line());
+            parse_error(line, without_position(&e.to_string()))
+        }),
+    }
+}
+
+// 1-based line of the byte `offset` in `text`
+fn line_of(text: &str, offset: usize) -> usize { text[..offset.min(text.len())].matches('\n').count() + 1 }
+
+// serde_json and serde_yaml append " at line L column C"; the line is reported separately
+fn without_position(message: &str) -> String {
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Returns true when a config key names a secret (tokens, passwords, keys and key paths).
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["token", "secret", "password", "signing_key", "private_key", "key_path"]
+        .iter()
+        .any(|needle| key.contains(needle))
+}
+
+/// Replaces the values of secret keys, recursively, with `REDACTED`.
+/// Secret keys holding `null` stay `null` so it is still visible that they are unset.
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if is_secret_key(key) {
+                    if !v.is_null() {
+                        *v = Value::String(REDACTED.to_string());
+                    }
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_secret_key, load_config, redact, ConfigError, NamespaceConfig, ServerConfig, REDACTED};
+    use crate::http::store::OnLimit;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf { PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/config").join(name) }
+
+    fn parse_error(name: &str) -> (Option<usize>, String) {
+        match load_config(fixture(name)) {
+            Err(ConfigError::Parse { line, message, .. }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_secret_key_detection() {
+        assert!(is_secret_key("api_token"));
+        assert!(is_secret_key("ADMIN_TOKEN"));
+        assert!(is_secret_key("signing_key"));
+        assert!(is_secret_key("tls_key_path"));
+        assert!(is_secret_key("db_password"));
+        assert!(is_secret_key("client_secret"));
+        assert!(!is_secret_key("cache_size"));
+        assert!(!is_secret_key("server_port"));
+        assert!(!is_secret_key("key"));
+    }
+
+    #[test]
+    fn test_redact_nested() {
+        let mut value = json!({
+            "api_token": "abc",
+            "cache_size": 5,
+            "tls": { "tls_key_path": "/etc/key.pem", "tls_cert_path": "/etc/cert.pem" },
+            "tenants": [{ "name": "a", "token": "t1" }, { "name": "b", "token": null }],
+        });
+        redact(&mut value);
+        assert_eq!(value["api_token"], REDACTED);
+        assert_eq!(value["cache_size"], 5);
+        assert_eq!(value["tls"]["tls_key_path"], REDACTED);
+        assert_eq!(value["tls"]["tls_cert_path"], "/etc/cert.pem");
+        assert_eq!(value["tenants"][0]["token"], REDACTED);
+        assert_eq!(value["tenants"][0]["name"], "a");
+        assert!(value["tenants"][1]["token"].is_null());
+    }
+
+    #[test]
+    fn test_redacted_never_leaks_secret_values() {
+        let mut value = json!({ "token": { "inner": "t0p-s3cret" }, "list": ["plain"] });
+        redact(&mut value);
+        assert!(!value.to_string().contains("t0p-s3cret"));
+        assert_eq!(value["list"][0], "plain");
+    }
+
+    #[test]
+    fn test_server_config_redacted() {
+        let config = ServerConfig {
+            api_token: Some("t0ken".to_string()),
+            signing_key: Some("k3y".to_string()),
+            ..ServerConfig::default()
+        };
+        let value = config.redacted();
+        assert_eq!(value["api_token"], REDACTED);
+        assert_eq!(value["signing_key"], REDACTED);
+        assert!(!value.to_string().contains("t0ken"));
+        assert!(!value.to_string().contains("k3y"));
+        assert_eq!(value["server_port"], 2345);
+        assert_eq!(value["cache_mode"], "default");
+        assert_eq!(value["cache_size"], 5);
+    }
+
+    #[test]
+    fn test_check_cache_shards() {
+        let sharded = |cache_shards| ServerConfig { cache_size: 4, cache_shards, ..ServerConfig::default() };
+        assert_eq!(sharded(1).check_cache_shards(), Ok(()));
+        assert_eq!(sharded(4).check_cache_shards(), Ok(()));
+        assert_eq!(sharded(0).check_cache_shards(), Err("cache_shards must be positive".to_string()));
+        assert_eq!(sharded(5).check_cache_shards(), Err("cache_size: 4 is less than one per shard of 5".to_string()));
+        let unlimited = ServerConfig { cache_mode: "unlimited".to_string(), ..sharded(5) };
+        assert_eq!(unlimited.check_cache_shards(), Ok(()));
+
+        let limited = ServerConfig { max_keys: Some(100), ..sharded(2) };
+        assert!(limited.check_cache_shards().unwrap_err().contains("need a single shard"));
+        let ns = NamespaceConfig { cache_mode: "capacity".to_string(), cache_bytes: Some(1), ..NamespaceConfig::default() };
+        let config = ServerConfig { namespaces: [("meta".to_string(), ns)].into(), ..sharded(2) };
+        assert_eq!(config.check_cache_shards(), Err("namespaces.meta.cache_bytes: 1 is less than one per shard of 2".to_string()));
+    }
+
+    #[test]
+    fn test_check_ttl_mode() {
+        let ttl = ServerConfig { cache_mode: "ttl".to_string(), ..ServerConfig::default() };
+        assert_eq!(ttl.check_ttl_mode(), Err("ttl mode needs a positive ttl_seconds".to_string()));
+        assert!(ServerConfig { ttl_seconds: Some(0), ..ttl.clone() }.check_ttl_mode().is_err());
+        assert_eq!(ServerConfig { ttl_seconds: Some(60), ..ttl }.check_ttl_mode(), Ok(()));
+        assert_eq!(ServerConfig::default().check_ttl_mode(), Ok(()));
+    }
+
+    #[test]
+    fn test_check_chunk_size() {
+        assert_eq!(ServerConfig::default().check_chunk_size(), Ok(()));
+        let config = ServerConfig { chunk_size_bytes: 0, ..ServerConfig::default() };
+        assert_eq!(config.check_chunk_size(), Err("chunk_size_bytes must be positive".to_string()));
+    }
+
+    #[test]
+    fn test_load_each_format() {
+        for name in ["valid.toml", "valid.yaml", "valid.json"] {
+            let config = load_config(fixture(name)).unwrap();
+            assert_eq!(config.server_port, 8080, "{}", name);
+            assert_eq!(config.cache_mode, "capacity", "{}", name);
+            assert_eq!(config.cache_size, 1024, "{}", name);
+            assert_eq!(config.on_limit, OnLimit::Reject, "{}", name);
+            // keys missing from the file keep their defaults
+            assert_eq!(config.max_ttl_secs, ServerConfig::default().max_ttl_secs, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_load_namespace_tables() {
+        let config = load_config(fixture("namespaces.toml")).unwrap();
+        let blob = &config.namespaces["blob"];
+        assert_eq!((blob.cache_mode.as_str(), blob.cache_bytes), ("capacity", Some(1 << 20)));
+        let meta = &config.namespaces["meta"];
+        assert_eq!((meta.cache_mode.as_str(), meta.cache_size, meta.default_ttl_secs), ("lfu", 100, Some(60)));
+
+        let (line, message) = parse_error("namespace_typo.toml");
+        assert_eq!(line, Some(3));
+        assert!(message.contains("unknown field `cache_byte`"), "{}", message);
+    }
+
+    #[test]
+    fn test_unknown_key_is_rejected() {
+        for (name, line) in [("typo.toml", 3), ("typo.yaml", 3), ("typo.json", 4)] {
+            let (err_line, message) = parse_error(name);
+            assert!(message.contains("unknown field `cache_sizee`"), "{}: {}", name, message);
+            assert_eq!(err_line, Some(line), "{}", name);
+        }
+        let err = load_config(fixture("typo.toml")).unwrap_err().to_string();
+        assert!(err.contains("typo.toml:3: unknown field `cache_sizee`"), "{}", err);
+    }
+
+    #[test]
+    fn test_malformed_and_mistyped_values() {
+        let (line, _) = parse_error("bad_syntax.json");
+        assert_eq!(line, Some(3));
+        let (line, message) = parse_error("bad_type.toml");
+        assert_eq!(line, Some(2));
+        assert!(message.contains("invalid type"), "{}", message);
+    }
+
+    #[test]
+    fn test_missing_file_and_unknown_extension() {
+        let err = load_config(fixture("missing.toml")).unwrap_err();
+        assert!(matches!(err, ConfigError::Io { .. }));
+        assert!(err.to_string().contains("missing.toml"));
+
+        let err = load_config(fixture("config.ini")).unwrap_err();
+        assert!(matches!(err, ConfigError::UnsupportedFormat { .. }));
+    }
+}
@@ -0,0 +1,134 @@
+//! Compiles `tests/capi/see_lru_test.c` against `include/see_lru.h` and the cdylib, then runs it.
+#![cfg(feature = "capi")]
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// the cdylib is built next to this test binary, in `target/<profile>/deps`
+fn cdylib_dir() -> PathBuf { std::env::current_exe().unwrap().parent().unwrap().to_path_buf() }
+
+#[test]
+#[cfg(unix)]
+fn test_c_abi() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = cdylib_dir();
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join("see_lru_test");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let status = Command::new(cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg(manifest.join("tests/capi/see_lru_test.c"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-llru")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("a C compiler is required for the capi tests");
+    assert!(status.success(), "compiling the C test failed");
+
+    let status = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "the C test failed");
+}
+
+// The C type of a Rust type of the bindings.
+fn c_type(rust: &str) -> String {
+    let rust = rust.trim();
+    if let Some(pointee) = rust.strip_prefix("*const ") {
+        return format!("const {} *", c_type(pointee));
+    }
+    if let Some(pointee) = rust.strip_prefix("*mut ") {
+        let pointee = c_type(pointee);
+        return match pointee.ends_with('*') {
+            true => format!("{}*", pointee),
+            false => format!("{} *", pointee),
+        };
+    }
+    match rust {
+        "u8" => "uint8_t",
+        "i64" => "int64_t",
+        "usize" => "size_t",
+        "SeeLru" => "see_lru_t",
+        other => panic!("no C type for `{}`", other),
+    }
+    .to_string()
+}
+
+// The C prototype of every `extern "C" fn` of src/ffi.rs, keyed by name.
+fn rust_prototypes(source: &str) -> BTreeMap<String, String> {
+    let mut prototypes = BTreeMap::new();
+    for (i, m) in source.match_indices("extern \"C\" fn ") {
+        let rest = &source[i + m.len()..];
+        let (name, rest) = rest.split_once('(').unwrap();
+        let (params, rest) = rest.split_once(')').unwrap();
+        let ret = rest[..rest.find('{').unwrap()].trim().strip_prefix("-> ").map_or("void".to_string(), c_type);
+        let params: Vec<String> = params
+            .split(',')
+            .filter(|param| !param.trim().is_empty())
+            .map(|param| {
+                let (name, ty) = param.split_once(':').unwrap();
+                let ty = c_type(ty);
+                match ty.ends_with('*') {
+                    true => format!("{}{}", ty, name.trim()),
+                    false => format!("{} {}", ty, name.trim()),
+                }
+            })
+            .collect();
+        let ret = match ret.ends_with('*') {
+            true => ret,
+            false => format!("{} ", ret),
+        };
+        prototypes.insert(name.to_string(), format!("{}{}({});", ret, name, params.join(", ")));
+    }
+    prototypes
+}
+
+// The function declarations of the header, comments and line breaks removed, keyed by name.
+fn header_prototypes(header: &str) -> BTreeMap<String, String> {
+    let mut code = String::new();
+    let mut rest = header;
+    while let Some(start) = rest.find("/*") {
+        code.push_str(&rest[..start]);
+        rest = &rest[start + rest[start..].find("*/").unwrap() + 2..];
+    }
+    code.push_str(rest);
+    code.lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split(';')
+        .map(|decl| decl.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|decl| decl.contains("see_lru_") && decl.contains('('))
+        .map(|decl| {
+            let name = decl[..decl.find('(').unwrap()].rsplit([' ', '*']).next().unwrap().to_string();
+            (name, format!("{};", decl))
+        })
+        .collect()
+}
+
+// The header is written by hand: every binding must be declared in it with the same types, and
+// every error code defined with the same value.
+#[test]
+fn test_header_matches_the_bindings() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = std::fs::read_to_string(manifest.join("src/ffi.rs")).unwrap();
+    let header = std::fs::read_to_string(manifest.join("include/see_lru.h")).unwrap();
+    assert_eq!(header_prototypes(&header), rust_prototypes(&source));
+
+    let constants: Vec<_> = source.lines().filter_map(|line| line.strip_prefix("pub const ")).collect();
+    assert!(!constants.is_empty());
+    for constant in constants {
+        let (name, value) = constant.split_once(": i64 = ").unwrap();
+        let define = format!("#define {} ({})", name, value.trim_end_matches(';'));
+        assert!(header.contains(&define), "the header lacks `{}`", define);
+    }
+}
@@ -0,0 +1,59 @@
+/* ABI check for include/see_lru.h, built and run by tests/capi.rs. */
+#include <stdio.h>
+#include <string.h>
+
+#include "see_lru.h"
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                 \
+        }                                                             \
+    } while (0)
+
+#define BYTES(s) (const uint8_t *)(s), strlen(s)
+
+int main(void) {
+    CHECK(see_lru_new(0) == NULL);
+    CHECK(see_lru_new(SIZE_MAX) == NULL);
+
+    see_lru_t *lru = see_lru_new(2);
+    CHECK(lru != NULL);
+    CHECK(see_lru_put(lru, BYTES("a"), BYTES("apple")) == 0);
+    CHECK(see_lru_put(lru, BYTES("b"), BYTES("banana")) == 0);
+    CHECK(see_lru_len(lru) == 2);
+
+    uint8_t buf[16];
+    CHECK(see_lru_get(lru, BYTES("a"), NULL, 0) == 5);
+    CHECK(see_lru_get(lru, BYTES("a"), buf, sizeof buf) == 5);
+    CHECK(memcmp(buf, "apple", 5) == 0);
+
+    /* "b" is the least recently used entry and gets evicted */
+    CHECK(see_lru_put(lru, BYTES("c"), BYTES("cherry")) == 0);
+    CHECK(see_lru_get(lru, BYTES("b"), buf, sizeof buf) == SEE_LRU_ENOTFOUND);
+
+    uint8_t *owned = NULL;
+    size_t owned_len = 0;
+    CHECK(see_lru_get_owned(lru, BYTES("c"), &owned, &owned_len) == 0);
+    CHECK(owned_len == 6 && memcmp(owned, "cherry", 6) == 0);
+    see_lru_buf_free(owned, owned_len);
+
+    /* binary keys with embedded NUL bytes */
+    const uint8_t key[] = {0, 1, 0, 2};
+    CHECK(see_lru_put(lru, key, sizeof key, (const uint8_t *)"\0x", 2) == 0);
+    CHECK(see_lru_get(lru, key, sizeof key, buf, sizeof buf) == 2);
+    CHECK(buf[0] == 0 && buf[1] == 'x');
+
+    CHECK(see_lru_pop(lru, key, sizeof key) == 1);
+    CHECK(see_lru_pop(lru, key, sizeof key) == 0);
+
+    CHECK(see_lru_put(NULL, BYTES("a"), BYTES("x")) == SEE_LRU_EINVAL);
+    CHECK(see_lru_put(lru, NULL, 3, BYTES("x")) == SEE_LRU_EINVAL);
+    CHECK(see_lru_get(lru, BYTES("a"), NULL, 8) == SEE_LRU_EINVAL);
+    CHECK(see_lru_len(NULL) == 0);
+
+    see_lru_free(lru);
+    see_lru_free(NULL);
+    return 0;
+}
@@ -0,0 +1,75 @@
+# Run with `maturin develop && pytest tests/python` from the lru directory.
+import pytest
+
+from see_lru import LRUCache
+
+
+def test_put_get_round_trips_types():
+    cache = LRUCache(max_entries=4)
+    cache.put(b"k", b"bytes")
+    cache.put("s", "text")
+    assert cache.get(b"k") == b"bytes"
+    assert cache.get("s") == "text"
+    # bytes and str keys are distinct
+    assert cache.get(b"s") is None
+    assert cache.get("missing", "fallback") == "fallback"
+
+
+def test_eviction_order_by_entries():
+    cache = LRUCache(max_entries=2)
+    cache.put("a", "1")
+    cache.put("b", "2")
+    cache.get("a")
+    cache.put("c", "3")
+    assert "b" not in cache
+    assert cache.keys() == ["c", "a"]
+    assert len(cache) == 2
+
+
+def test_eviction_order_by_bytes():
+    cache = LRUCache(max_bytes=10)
+    cache.put(b"a", b"1234")  # 5 bytes with the key
+    cache.put(b"b", b"1234")
+    cache.put(b"c", b"12")  # 3 more: "a" has to go
+    assert cache.keys() == [b"c", b"b"]
+    assert cache.stats()["bytes"] == 8
+    with pytest.raises(ValueError):
+        cache.put(b"big", b"x" * 10)
+
+
+def test_contains_does_not_promote():
+    cache = LRUCache(max_entries=2)
+    cache.put("a", "1")
+    cache.put("b", "2")
+    assert "a" in cache
+    cache.put("c", "3")
+    assert "a" not in cache
+
+
+def test_pop_clear_and_stats():
+    cache = LRUCache(max_entries=2, max_bytes=100)
+    cache.put("a", "1")
+    assert cache.pop("a") == "1"
+    assert cache.pop("a", 0) == 0
+    cache.put("a", "1")
+    cache.put("b", "2")
+    cache.put("c", "3")
+    cache.get("c")
+    cache.get("a")
+    stats = cache.stats()
+    assert stats["entries"] == 2
+    assert stats["hits"] == 1
+    assert stats["misses"] == 1
+    assert stats["evictions"] == 1
+    assert stats["max_entries"] == 2
+    cache.clear()
+    assert len(cache) == 0
+    assert cache.stats()["bytes"] == 0
+
+
+def test_rejects_other_types_and_zero_limits():
+    cache = LRUCache()
+    with pytest.raises(TypeError):
+        cache.put(1, b"x")
+    with pytest.raises(ValueError):
+        LRUCache(max_entries=0)